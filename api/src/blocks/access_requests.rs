@@ -0,0 +1,286 @@
+use super::{
+	block::PermLevel,
+	perms::{set_block_perms, PermLists},
+};
+use crate::{graphql::ContextData, users::user::UserObject};
+use async_graphql::*;
+use block_tools::{
+	auth::{
+		permissions::{self, has_perm_level},
+		require_token, validate_token,
+	},
+	models::{self, AccessRequest, Block, NewAccessRequest, NewNotification, User},
+	NoAccessSubject, PgConnect, UserError,
+};
+use block_types::delegation::display::delegate_block_name;
+use chrono::{DateTime, Utc};
+use std::time::SystemTime;
+
+/// A user asking for access to a block they can't use yet
+pub struct AccessRequestObject {
+	pub id: i64,
+	pub block_id: i64,
+	pub requester_id: i32,
+	pub level: permissions::PermLevel,
+	pub message: Option<String>,
+	pub status: models::AccessRequestStatus,
+	pub granted_level: Option<permissions::PermLevel>,
+	pub decided_by: Option<i32>,
+	pub created_at: SystemTime,
+	pub decided_at: Option<SystemTime>,
+}
+
+#[Object]
+impl AccessRequestObject {
+	async fn id(&self) -> i64 {
+		self.id
+	}
+
+	/// The ID of the block that access was requested for. The block itself
+	/// may not be viewable by the requester.
+	async fn block_id(&self) -> i64 {
+		self.block_id
+	}
+
+	async fn requester(&self, context: &Context<'_>) -> Result<Option<UserObject>> {
+		let (_, conn) = &ContextData::parse(context)?;
+		Ok(User::by_id(self.requester_id, conn)?.map(UserObject::from))
+	}
+
+	/// The level that was asked for
+	async fn level(&self) -> PermLevel {
+		self.level.into()
+	}
+
+	async fn message(&self) -> Option<String> {
+		self.message.clone()
+	}
+
+	async fn status(&self) -> AccessRequestStatus {
+		self.status.into()
+	}
+
+	/// The level that was given, which can be lower than the one asked for
+	async fn granted_level(&self) -> Option<PermLevel> {
+		self.granted_level.map(PermLevel::from)
+	}
+
+	/// The user that approved or denied the request
+	async fn decided_by(&self, context: &Context<'_>) -> Result<Option<UserObject>> {
+		let (_, conn) = &ContextData::parse(context)?;
+		Ok(match self.decided_by {
+			Some(user_id) => User::by_id(user_id, conn)?.map(UserObject::from),
+			None => None,
+		})
+	}
+
+	async fn created_at(&self) -> DateTime<Utc> {
+		self.created_at.into()
+	}
+
+	async fn decided_at(&self) -> Option<DateTime<Utc>> {
+		self.decided_at.map(DateTime::from)
+	}
+}
+
+impl From<AccessRequest> for AccessRequestObject {
+	fn from(request: AccessRequest) -> Self {
+		AccessRequestObject {
+			level: request.level(),
+			status: request.status(),
+			granted_level: request.granted(),
+			id: request.id,
+			block_id: request.block_id,
+			requester_id: request.requester_id,
+			message: request.message,
+			decided_by: request.decided_by,
+			created_at: request.created_at,
+			decided_at: request.decided_at,
+		}
+	}
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum AccessRequestStatus {
+	Pending,
+	Approved,
+	Denied,
+}
+
+impl From<models::AccessRequestStatus> for AccessRequestStatus {
+	fn from(status: models::AccessRequestStatus) -> Self {
+		match status {
+			models::AccessRequestStatus::Pending => AccessRequestStatus::Pending,
+			models::AccessRequestStatus::Approved => AccessRequestStatus::Approved,
+			models::AccessRequestStatus::Denied => AccessRequestStatus::Denied,
+		}
+	}
+}
+
+#[derive(Default)]
+pub struct AccessRequestQueries;
+
+#[Object]
+impl AccessRequestQueries {
+	/// The requests for access to a block, oldest first. The user must have
+	/// full permissions or higher.
+	async fn access_requests(
+		&self,
+		context: &Context<'_>,
+		block_id: i64,
+		#[graphql(default, desc = "Include requests that were approved or denied")] decided: bool,
+	) -> Result<Vec<AccessRequestObject>> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let access_err: Error =
+			UserError::NoAccess(NoAccessSubject::UpdatePermissions(block_id)).into();
+
+		match Block::by_id(block_id, conn)? {
+			Some(block) if has_perm_level(user_id, &block, permissions::PermLevel::Full) => {}
+			_ => return Err(access_err),
+		}
+
+		Ok(AccessRequest::of_block(block_id, decided, conn)?
+			.into_iter()
+			.map(AccessRequestObject::from)
+			.collect())
+	}
+}
+
+#[derive(Default)]
+pub struct AccessRequestMutations;
+
+#[Object]
+impl AccessRequestMutations {
+	/// Asks for access to a block. Everyone with full permissions on the block
+	/// is notified. Asking again replaces the user's pending request.
+	async fn request_access(
+		&self,
+		context: &Context<'_>,
+		block_id: i64,
+		level: PermLevel,
+		message: Option<String>,
+	) -> Result<AccessRequestObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+
+		let block = match Block::by_id(block_id, conn)? {
+			Some(block) => block,
+			None => return Err(UserError::NoAccess(NoAccessSubject::ViewBlock(block_id)).into()),
+		};
+		let level = permissions::PermLevel::from(level);
+		if has_perm_level(user_id, &block, level) {
+			return Err(UserError::AlreadyHasAccess(block_id).into());
+		}
+
+		let request = NewAccessRequest::new(block_id, user_id, level, message).insert(conn)?;
+
+		let user_name = display_name(user_id, conn)?;
+		let block_name = delegate_block_name(context, &block.block_type, &block)?;
+		NewNotification::new(
+			format!("{} requested {} access", user_name, level.as_str()),
+			format!(
+				"{} asked for {} access to \"{}\".",
+				user_name,
+				level.as_str(),
+				block_name
+			),
+		)
+		.recipients(block.full_user_ids())
+		.link(block_id)
+		.send(conn)?;
+
+		Ok(request.into())
+	}
+
+	/// Gives the requester access to the block. A lower level than the one
+	/// requested can be given instead. The user must have full permissions
+	/// or higher.
+	async fn approve_access_request(
+		&self,
+		context: &Context<'_>,
+		request_id: i64,
+		#[graphql(desc = "A lower level to give than the one requested")] level: Option<PermLevel>,
+	) -> Result<AccessRequestObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let (request, block) = pending_request(request_id, user_id, conn)?;
+
+		let requested = request.level();
+		let level = match level.map(permissions::PermLevel::from) {
+			Some(level) if level < requested => level,
+			_ => requested,
+		};
+
+		let requester_id = request.requester_id;
+		let mut perms = PermLists::of_block(&block);
+		let list = match level {
+			permissions::PermLevel::View => &mut perms.view,
+			permissions::PermLevel::Comment => &mut perms.comment,
+			permissions::PermLevel::Edit => &mut perms.edit,
+			_ => &mut perms.full,
+		};
+		if !list.contains(&requester_id) {
+			list.push(requester_id);
+		}
+		// This also tells the requester about their new access
+		set_block_perms(context, user_id, block.id, perms, false)?;
+
+		Ok(request.approve(level, user_id, conn)?.into())
+	}
+
+	/// Turns down a request for access. The user must have full permissions or higher.
+	async fn deny_access_request(
+		&self,
+		context: &Context<'_>,
+		request_id: i64,
+	) -> Result<AccessRequestObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let (request, _) = pending_request(request_id, user_id, conn)?;
+
+		let request = request.deny(user_id, conn)?;
+
+		NewNotification::new(
+			"Your access request was denied",
+			format!(
+				"Your request for access to block {} was denied.",
+				request.block_id
+			),
+		)
+		.recipients(vec![request.requester_id])
+		.send(conn)?;
+
+		Ok(request.into())
+	}
+}
+
+/// Finds a request that hasn't been decided, along with its block,
+/// if the user has full permissions on the block
+fn pending_request(
+	request_id: i64,
+	user_id: i32,
+	conn: &PgConnect,
+) -> Result<(AccessRequest, Block), Error> {
+	let request = match AccessRequest::by_id(request_id, conn)? {
+		Some(request) => request,
+		None => return Err(UserError::NoAccess(NoAccessSubject::AccessRequest(request_id)).into()),
+	};
+	let access_err: Error =
+		UserError::NoAccess(NoAccessSubject::UpdatePermissions(request.block_id)).into();
+	let block = match Block::by_id(request.block_id, conn)? {
+		Some(block) if has_perm_level(user_id, &block, permissions::PermLevel::Full) => block,
+		_ => return Err(access_err),
+	};
+	if request.status() != models::AccessRequestStatus::Pending {
+		return Err(UserError::AccessRequestDecided(request_id).into());
+	}
+	Ok((request, block))
+}
+
+/// The user's display name, or their username if they don't have one
+fn display_name(user_id: i32, conn: &PgConnect) -> Result<String, Error> {
+	Ok(User::by_id(user_id, conn)?
+		.map(|user| user.display_name.unwrap_or(user.username))
+		.unwrap_or_default())
+}
@@ -0,0 +1,236 @@
+use crate::{
+	graphql::{pagination::limit_size, ContextData},
+	users::user::UserObject,
+};
+use async_graphql::*;
+use block_tools::{
+	auth::{
+		permissions::{has_perm_level, PermLevel},
+		require_token, validate_token,
+	},
+	models::{self, AuditEvent, Block, PermSnapshot, User},
+	NoAccessSubject, UserError,
+};
+use chrono::{DateTime, Utc};
+use std::time::SystemTime;
+
+/// A change to who can access a block
+pub struct AuditEventObject {
+	pub id: i64,
+	pub block_id: i64,
+	pub actor_id: Option<i32>,
+	pub source: models::AuditSource,
+	pub share_link_id: Option<i64>,
+	pub action: models::AuditAction,
+	pub old_public: bool,
+	pub new_public: bool,
+	pub old_perms: PermSnapshot,
+	pub new_perms: PermSnapshot,
+	pub created_at: SystemTime,
+}
+
+#[Object]
+impl AuditEventObject {
+	async fn id(&self) -> i64 {
+		self.id
+	}
+
+	/// The ID of the block that changed. The block may have been deleted since.
+	async fn block_id(&self) -> i64 {
+		self.block_id
+	}
+
+	/// The user that made the change, if it is known
+	async fn actor(&self, context: &Context<'_>) -> Result<Option<UserObject>> {
+		let (_, conn) = &ContextData::parse(context)?;
+		Ok(match self.actor_id {
+			Some(user_id) => User::by_id(user_id, conn)?.map(UserObject::from),
+			None => None,
+		})
+	}
+
+	/// Where the access to make the change came from
+	async fn source(&self) -> AuditSource {
+		self.source.into()
+	}
+
+	/// The ID of the share link the change was made with or about
+	async fn share_link_id(&self) -> Option<i64> {
+		self.share_link_id
+	}
+
+	async fn action(&self) -> AuditAction {
+		self.action.into()
+	}
+
+	async fn old_public(&self) -> bool {
+		self.old_public
+	}
+
+	async fn new_public(&self) -> bool {
+		self.new_public
+	}
+
+	/// The block's permissions before the change
+	async fn old_perms(&self) -> PermSnapshotObject {
+		self.old_perms.clone().into()
+	}
+
+	/// The block's permissions after the change
+	async fn new_perms(&self) -> PermSnapshotObject {
+		self.new_perms.clone().into()
+	}
+
+	async fn created_at(&self) -> DateTime<Utc> {
+		self.created_at.into()
+	}
+}
+
+impl From<AuditEvent> for AuditEventObject {
+	fn from(event: AuditEvent) -> Self {
+		AuditEventObject {
+			source: event.source(),
+			action: event.action(),
+			old_perms: event.old_snapshot(),
+			new_perms: event.new_snapshot(),
+			id: event.id,
+			block_id: event.block_id,
+			actor_id: event.actor_id,
+			share_link_id: event.share_link_id,
+			old_public: event.old_public,
+			new_public: event.new_public,
+			created_at: event.created_at,
+		}
+	}
+}
+
+#[derive(SimpleObject)]
+/// The IDs of the users and teams with access to a block at one point
+pub struct PermSnapshotObject {
+	pub owner_id: i32,
+	pub co_owners: Vec<i32>,
+	pub perm_full: Vec<i32>,
+	pub perm_edit: Vec<i32>,
+	pub perm_comment: Vec<i32>,
+	pub perm_view: Vec<i32>,
+	pub team_full: Vec<i64>,
+	pub team_edit: Vec<i64>,
+	pub team_comment: Vec<i64>,
+	pub team_view: Vec<i64>,
+	pub inherit_perms: bool,
+}
+
+impl From<PermSnapshot> for PermSnapshotObject {
+	fn from(perms: PermSnapshot) -> Self {
+		PermSnapshotObject {
+			owner_id: perms.owner_id,
+			co_owners: perms.co_owners,
+			perm_full: perms.perm_full,
+			perm_edit: perms.perm_edit,
+			perm_comment: perms.perm_comment,
+			perm_view: perms.perm_view,
+			team_full: perms.team_full,
+			team_edit: perms.team_edit,
+			team_comment: perms.team_comment,
+			team_view: perms.team_view,
+			inherit_perms: perms.inherit_perms,
+		}
+	}
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum AuditAction {
+	Visibility,
+	Permissions,
+	TeamPermissions,
+	Inheritance,
+	CoOwners,
+	Owner,
+	Revert,
+	ShareLinkCreated,
+	ShareLinkRevoked,
+	ShareLinkRedeemed,
+}
+
+impl From<models::AuditAction> for AuditAction {
+	fn from(action: models::AuditAction) -> Self {
+		match action {
+			models::AuditAction::Visibility => AuditAction::Visibility,
+			models::AuditAction::Permissions => AuditAction::Permissions,
+			models::AuditAction::TeamPermissions => AuditAction::TeamPermissions,
+			models::AuditAction::Inheritance => AuditAction::Inheritance,
+			models::AuditAction::CoOwners => AuditAction::CoOwners,
+			models::AuditAction::Owner => AuditAction::Owner,
+			models::AuditAction::Revert => AuditAction::Revert,
+			models::AuditAction::ShareLinkCreated => AuditAction::ShareLinkCreated,
+			models::AuditAction::ShareLinkRevoked => AuditAction::ShareLinkRevoked,
+			models::AuditAction::ShareLinkRedeemed => AuditAction::ShareLinkRedeemed,
+		}
+	}
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+/// Where the access to make a change came from
+pub enum AuditSource {
+	User,
+	ShareLink,
+}
+
+impl From<models::AuditSource> for AuditSource {
+	fn from(source: models::AuditSource) -> Self {
+		match source {
+			models::AuditSource::User => AuditSource::User,
+			models::AuditSource::ShareLink => AuditSource::ShareLink,
+		}
+	}
+}
+
+#[derive(Default)]
+pub struct AuditQueries;
+
+#[Object]
+impl AuditQueries {
+	/// The changes to who can access a block, newest first. The user must
+	/// have full permissions or higher.
+	async fn audit_log(
+		&self,
+		context: &Context<'_>,
+		block_id: i64,
+		#[graphql(desc = "At most 100 events, 20 by default")] limit: Option<i32>,
+	) -> Result<Vec<AuditEventObject>> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+
+		match Block::by_id(block_id, conn)? {
+			Some(block) if has_perm_level(user_id, &block, PermLevel::Full) => {}
+			_ => return Err(UserError::NoAccess(NoAccessSubject::BlockAudit(block_id)).into()),
+		}
+
+		Ok(AuditEvent::of_block(block_id, limit_size(limit)?, conn)?
+			.into_iter()
+			.map(AuditEventObject::from)
+			.collect())
+	}
+
+	/// The changes a user made to who can access blocks, newest first.
+	/// Only admins can see these.
+	async fn audit_log_by_actor(
+		&self,
+		context: &Context<'_>,
+		actor_id: i32,
+		#[graphql(desc = "At most 100 events, 20 by default")] limit: Option<i32>,
+	) -> Result<Vec<AuditEventObject>> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+
+		let is_admin = User::by_id(user_id, conn)?.map_or(false, |user| user.admin);
+		if !is_admin {
+			return Err(UserError::NoAccess(NoAccessSubject::UserAudit(actor_id)).into());
+		}
+
+		Ok(AuditEvent::by_actor(actor_id, limit_size(limit)?, conn)?
+			.into_iter()
+			.map(AuditEventObject::from)
+			.collect())
+	}
+}
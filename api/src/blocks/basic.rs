@@ -1,14 +1,23 @@
-use super::block::BlockObject;
+use super::{block::BlockObject, search::index_block};
 use crate::graphql::ContextData;
 use async_graphql::{Context, Error, Object};
 use block_tools::{
-	auth::{permissions::maybe_use_view, require_token, validate_token},
+	auth::{
+		permissions::{
+			can_view, has_perm_level, maybe_use_view, request_has_perm_level, PermLevel,
+		},
+		require_token, validate_token,
+	},
+	blocks::Context as ToolsContext,
 	dsl::prelude::*,
-	models::Block,
-	schema::blocks,
-	NoAccessSubject, UserError,
+	models::{Block, BlockReference, Property},
+	schema::properties,
+	BlockError, NoAccessSubject, UserError,
+};
+use block_types::delegation::{
+	display::delegate_block_name,
+	methods::{delegate_method, method_level},
 };
-use block_types::delegation::methods::delegate_method;
 
 #[derive(Default)]
 pub struct BasicBlockMutations;
@@ -16,7 +25,8 @@ pub struct BasicBlockMutations;
 #[Object]
 impl BasicBlockMutations {
 	/// Executes a specific method created by a block type. Takes a method name, block type,
-	/// block id, and arguments for the method. Method name and args determine on the block type
+	/// block id, and arguments for the method. Method name and args determine on the block type.
+	/// The user needs the level the block type declares for the method, which is edit by default.
 	pub async fn block_method(
 		&self,
 		context: &Context<'_>,
@@ -26,7 +36,9 @@ impl BasicBlockMutations {
 		#[graphql(desc = "ID of the block to act on")] block_id: i64,
 	) -> Result<BlockObject, Error> {
 		let context = &context.data::<ContextData>()?.other();
-		Ok(delegate_method(context, r#type, args, method_name, block_id)?.into())
+		let block = run_method(context, &r#type, args, method_name, block_id)?;
+
+		Ok(block.into())
 	}
 
 	/// Deletes a block from the database. Currently does not delete everything,
@@ -36,21 +48,62 @@ impl BasicBlockMutations {
 
 		let user_id = validate_token(&require_token(context)?)?;
 
-		// Delete the block (only if the owner is authenticated)
-		let rows = block_tools::dsl::delete(
-			blocks::dsl::blocks
-				.filter(blocks::id.eq(block_id))
-				.filter(blocks::owner_id.eq(user_id)),
-		)
-		.execute(conn)?;
-
-		// If 0 rows are deleted, permissions were not there
-		match rows {
-			0 => Err(Error::from(UserError::NoAccess(
-				NoAccessSubject::DeleteBlock(block_id),
-			))),
-			_ => Ok(block_id),
+		// Only the owner and co-owners can delete the block
+		let access_err = Error::from(UserError::NoAccess(NoAccessSubject::DeleteBlock(block_id)));
+		let block = match Block::by_id(block_id, conn)? {
+			Some(block) if has_perm_level(user_id, &block, PermLevel::Owner) => block,
+			_ => return Err(access_err),
+		};
+
+		block.delete(conn)?;
+
+		Ok(block_id)
+	}
+
+	/// Chooses which parent a block's breadcrumb goes through when it has more than one.
+	/// The primary parent is cleared if no parent is provided.
+	pub async fn set_primary_parent(
+		&self,
+		context: &Context<'_>,
+		block_id: i64,
+		parent_id: Option<i64>,
+	) -> Result<BlockObject, Error> {
+		let (context, conn) = &ContextData::parse(context)?;
+
+		let user_id = validate_token(&require_token(context)?)?;
+		let access_err: Error = UserError::NoAccess(NoAccessSubject::EditParents(block_id)).into();
+		let block = match Block::by_id(block_id, conn)? {
+			Some(block) => block,
+			None => return Err(access_err),
+		};
+		if !has_perm_level(user_id, &block, PermLevel::Edit) {
+			return Err(access_err);
+		}
+
+		if let Some(parent_id) = parent_id {
+			let access_err: Error =
+				UserError::NoAccess(NoAccessSubject::ViewBlock(parent_id)).into();
+			let parent = match Block::by_id(parent_id, conn)? {
+				Some(parent) => parent,
+				None => return Err(access_err),
+			};
+			if !can_view(Some(user_id), &parent) {
+				return Err(access_err);
+			}
+			let is_parent: bool = block_tools::dsl::select(block_tools::dsl::exists(
+				properties::dsl::properties
+					.filter(properties::parent_id.eq(parent_id))
+					.filter(properties::value_id.eq(block_id)),
+			))
+			.get_result(conn)?;
+			if !is_parent {
+				return Err(BlockError::NotParent(parent_id, block_id).into());
+			}
 		}
+
+		Property::set_primary_parent(block_id, parent_id, conn)?;
+
+		Ok(block.into())
 	}
 }
 
@@ -71,3 +124,187 @@ impl BasicBlockQueries {
 		Ok(maybe_use_view(context, Block::by_id(id, conn)?)?.map(BlockObject::from))
 	}
 }
+
+/// Checks that a block method can be called before it is delegated to the
+/// block type. The block has to have the type the caller expects, and the
+/// request needs the level the type declares for the method. Returns the block.
+pub fn authorize_method(
+	context: &ToolsContext,
+	r#type: &str,
+	method_name: &str,
+	block_id: i64,
+) -> Result<Block, Error> {
+	let access_err: Error = UserError::NoAccess(NoAccessSubject::BlockMethod(
+		block_id,
+		method_name.to_string(),
+	))
+	.into();
+
+	let block = match Block::by_id(block_id, &context.shared_conn()?)? {
+		Some(block) => block,
+		None => return Err(access_err),
+	};
+	// Users that can't see the block shouldn't learn its type
+	if !request_has_perm_level(context, &block, PermLevel::View)? {
+		return Err(access_err);
+	}
+
+	let level = required_method_level(&block, r#type, method_name)?;
+	if !request_has_perm_level(context, &block, level)? {
+		return Err(access_err);
+	}
+	Ok(block)
+}
+
+/// Checks that a block method can be called, then calls it. The method may
+/// rename the block, which outdates the links that showed its old name and
+/// the name it is found by.
+pub fn run_method(
+	context: &ToolsContext,
+	r#type: &str,
+	args: String,
+	method_name: String,
+	block_id: i64,
+) -> Result<Block, Error> {
+	let before = authorize_method(context, r#type, &method_name, block_id)?;
+	let old_name = delegate_block_name(context, &before.block_type, &before)?;
+
+	let block = delegate_method(context, r#type.to_string(), args, method_name, block_id)?;
+
+	let name = delegate_block_name(context, &block.block_type, &block)?;
+	BlockReference::mark_renamed(block.id, &old_name, &name, &context.shared_conn()?)?;
+	index_block(context, &block)?;
+	Ok(block)
+}
+
+/// The level needed to call a method on a block, if the block has the type
+pub fn required_method_level(
+	block: &Block,
+	r#type: &str,
+	method_name: &str,
+) -> Result<PermLevel, BlockError> {
+	if block.block_type != r#type {
+		return Err(BlockError::TypeMismatch(block.id, r#type.to_string()));
+	}
+	Ok(method_level(r#type, method_name))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use block_tools::auth::inheritance::InheritedPerms;
+	use block_types::delegation::methods::{registered_methods, DEFAULT_METHOD_LEVEL};
+	use std::time::SystemTime;
+
+	const OWNER: i32 = 1;
+	/// A user with each level, and one without any access
+	const USERS: [(i32, Option<PermLevel>); 5] = [
+		(2, Some(PermLevel::Full)),
+		(3, Some(PermLevel::Edit)),
+		(4, Some(PermLevel::Comment)),
+		(5, Some(PermLevel::View)),
+		(6, None),
+	];
+
+	fn block_of_type(block_type: &str) -> Block {
+		Block {
+			id: 1,
+			block_type: block_type.to_string(),
+			created_at: SystemTime::now(),
+			updated_at: SystemTime::now(),
+			block_data: None,
+			owner_id: OWNER,
+			public: false,
+			perm_full: vec![2],
+			perm_edit: vec![3],
+			perm_comment: vec![4],
+			perm_view: vec![5],
+			stars: vec![],
+			notif_enabled: vec![],
+			color: None,
+			inherit_perms: false,
+			inherited: InheritedPerms::default(),
+			team_full: vec![],
+			team_edit: vec![],
+			team_comment: vec![],
+			team_view: vec![],
+			co_owners: vec![],
+		}
+	}
+
+	#[test]
+	fn every_registered_method_is_guarded() {
+		let methods = registered_methods();
+		assert!(!methods.is_empty());
+		for (block_type, name, level) in methods {
+			let block = block_of_type(&block_type);
+			assert_eq!(
+				required_method_level(&block, &block_type, &name).unwrap(),
+				level
+			);
+			assert!(has_perm_level(OWNER, &block, level));
+			for (user_id, user_level) in USERS.iter() {
+				assert_eq!(
+					has_perm_level(*user_id, &block, level),
+					matches!(user_level, Some(user_level) if *user_level >= level),
+					"user {} calling {} on a {} block",
+					user_id,
+					name,
+					block_type
+				);
+			}
+		}
+	}
+
+	#[test]
+	fn every_type_declares_its_methods_once() {
+		let methods = registered_methods();
+		for (block_type, name, _) in &methods {
+			let declared = methods
+				.iter()
+				.filter(|(other_type, other_name, _)| {
+					other_type == block_type && other_name == name
+				})
+				.count();
+			assert_eq!(
+				declared, 1,
+				"{} declares {} more than once",
+				block_type, name
+			);
+		}
+	}
+
+	#[test]
+	fn listed_methods_resolve_to_their_level() {
+		let block = block_of_type("collection");
+		assert_eq!(
+			required_method_level(&block, "collection", "set_collection").unwrap(),
+			PermLevel::Edit
+		);
+		let block = block_of_type("text");
+		assert_eq!(
+			required_method_level(&block, "text", "edit").unwrap(),
+			DEFAULT_METHOD_LEVEL
+		);
+	}
+
+	#[test]
+	fn unlisted_methods_need_edit() {
+		let block = block_of_type("text");
+		assert_eq!(
+			required_method_level(&block, "text", "not_a_real_method").unwrap(),
+			DEFAULT_METHOD_LEVEL
+		);
+		assert!(has_perm_level(3, &block, DEFAULT_METHOD_LEVEL));
+		assert!(!has_perm_level(4, &block, DEFAULT_METHOD_LEVEL));
+	}
+
+	#[test]
+	fn type_has_to_match() {
+		let block = block_of_type("text");
+		assert!(matches!(
+			required_method_level(&block, "data", "edit"),
+			Err(BlockError::TypeMismatch(1, _))
+		));
+	}
+}
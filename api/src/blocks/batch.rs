@@ -0,0 +1,237 @@
+use super::{
+	basic::run_method,
+	block::BlockObject,
+	create::create_for_user,
+	perms::{set_block_perms, PermLists},
+};
+use crate::graphql::ContextData;
+use async_graphql::{Context, Error, InputObject, Object};
+use block_tools::{
+	auth::{require_token, validate_token},
+	blocks::Context as ToolsContext,
+	models::Block,
+	BlockError, SharedTransaction,
+};
+use regex::Regex;
+
+#[derive(InputObject)]
+/// One step of a batch. Exactly one of the fields should be provided.
+pub struct BatchOperation {
+	/// Creates a block, like the `createBlock` mutation
+	create_block: Option<BatchCreateBlock>,
+	/// Executes a block method, like the `blockMethod` mutation
+	block_method: Option<BatchBlockMethod>,
+	/// Sets the permissions of a block, like the `setPerms` mutation
+	set_perms: Option<BatchSetPerms>,
+}
+
+#[derive(InputObject)]
+pub struct BatchCreateBlock {
+	/// Name of the block type to create
+	r#type: String,
+	/// JSON string to specify what to create. `$ref:N` is replaced with
+	/// the ID of the block returned by operation N.
+	input: String,
+}
+
+#[derive(InputObject)]
+pub struct BatchBlockMethod {
+	/// The block type that determines the method
+	r#type: String,
+	/// Arguments to help the method. `$ref:N` is replaced with the ID of
+	/// the block returned by operation N.
+	args: String,
+	/// Name of the method on the block type
+	method_name: String,
+	/// ID of the block to act on
+	block_id: Option<i64>,
+	/// Index of an earlier operation whose block to act on, instead of `blockId`
+	block_ref: Option<usize>,
+}
+
+#[derive(InputObject)]
+pub struct BatchSetPerms {
+	#[graphql(default)]
+	perm_full: Vec<i32>,
+	#[graphql(default)]
+	perm_edit: Vec<i32>,
+	#[graphql(default)]
+	perm_comment: Vec<i32>,
+	#[graphql(default)]
+	perm_view: Vec<i32>,
+	/// Also notify the users that lose their access
+	#[graphql(default)]
+	notify_removed: bool,
+	/// ID of the block to act on
+	block_id: Option<i64>,
+	/// Index of an earlier operation whose block to act on, instead of `blockId`
+	block_ref: Option<usize>,
+}
+
+#[derive(Default)]
+pub struct BatchMutations;
+
+#[Object]
+impl BatchMutations {
+	/// Runs a list of operations in order inside of one transaction. If any
+	/// of them fail, none of the changes are kept. Block types only take part
+	/// in the transaction where they use the context's shared connection.
+	/// Returns the block from each operation, in the same order.
+	pub async fn batch(
+		&self,
+		context: &Context<'_>,
+		operations: Vec<BatchOperation>,
+	) -> Result<Vec<BlockObject>, Error> {
+		let data = context.data::<ContextData>()?;
+		let context = &data.other();
+
+		let user_id = validate_token(&require_token(context)?)?;
+
+		for (index, operation) in operations.iter().enumerate() {
+			validate_operation(index, operation)?;
+		}
+
+		// Every query made with the context's shared connection is part of the transaction
+		let mut batch_context = ToolsContext {
+			transaction: Some(SharedTransaction::begin(&data.pool)?),
+			..data.other()
+		};
+
+		match run_operations(&batch_context, user_id, operations) {
+			Ok(blocks) => {
+				batch_context.end_transaction(true)?;
+				Ok(blocks.into_iter().map(BlockObject::from).collect())
+			}
+			Err(err) => {
+				batch_context.end_transaction(false)?;
+				Err(err)
+			}
+		}
+	}
+}
+
+fn run_operations(
+	context: &ToolsContext,
+	user_id: i32,
+	operations: Vec<BatchOperation>,
+) -> Result<Vec<Block>, Error> {
+	let mut blocks: Vec<Block> = vec![];
+
+	for operation in operations {
+		let ids: Vec<i64> = blocks.iter().map(|block| block.id).collect();
+		let block = if let Some(create) = operation.create_block {
+			create_for_user(
+				context,
+				user_id,
+				&create.r#type,
+				fill_refs(&create.input, &ids),
+			)?
+		} else if let Some(method) = operation.block_method {
+			let block_id = target_id(method.block_id, method.block_ref, &ids);
+			run_method(
+				context,
+				&method.r#type,
+				fill_refs(&method.args, &ids),
+				method.method_name,
+				block_id,
+			)?
+		} else if let Some(perms) = operation.set_perms {
+			let block_id = target_id(perms.block_id, perms.block_ref, &ids);
+			let lists = PermLists {
+				full: perms.perm_full,
+				edit: perms.perm_edit,
+				comment: perms.perm_comment,
+				view: perms.perm_view,
+			};
+			set_block_perms(context, user_id, block_id, lists, perms.notify_removed)?
+		} else {
+			return Err(BlockError::InvalidBatchOperation(blocks.len()).into());
+		};
+		blocks.push(block);
+	}
+
+	Ok(blocks)
+}
+
+/// Makes sure an operation has exactly one action, and that it
+/// only references operations that come before it
+fn validate_operation(index: usize, operation: &BatchOperation) -> Result<(), BlockError> {
+	let invalid = BlockError::InvalidBatchOperation(index);
+	let (block_id, block_ref, text) = match (
+		&operation.create_block,
+		&operation.block_method,
+		&operation.set_perms,
+	) {
+		(Some(create), None, None) => (None, None, Some(&create.input)),
+		(None, Some(method), None) => (method.block_id, method.block_ref, Some(&method.args)),
+		(None, None, Some(perms)) => (perms.block_id, perms.block_ref, None),
+		_ => return Err(invalid),
+	};
+
+	// Operations that act on a block need exactly one way to find it
+	if operation.create_block.is_none() && block_id.is_some() == block_ref.is_some() {
+		return Err(invalid);
+	}
+	if let Some(block_ref) = block_ref {
+		if block_ref >= index {
+			return Err(invalid);
+		}
+	}
+	if let Some(text) = text {
+		if text_refs(text)
+			.into_iter()
+			.any(|text_ref| text_ref >= index)
+		{
+			return Err(invalid);
+		}
+	}
+
+	Ok(())
+}
+
+/// The block ID an operation acts on, either given directly or from an earlier operation
+fn target_id(block_id: Option<i64>, block_ref: Option<usize>, ids: &[i64]) -> i64 {
+	match block_ref {
+		Some(index) => ids[index],
+		None => block_id.unwrap_or_default(),
+	}
+}
+
+/// All the operation indexes referenced with `$ref:N` in a string
+fn text_refs(text: &str) -> Vec<usize> {
+	let re = Regex::new(r"\$ref:(\d+)").unwrap();
+	re.captures_iter(text)
+		.filter_map(|capture| capture[1].parse().ok())
+		.collect()
+}
+
+/// Replaces `$ref:N` in a string with the ID of the block from operation N
+fn fill_refs(text: &str, ids: &[i64]) -> String {
+	let mut text = text.to_string();
+	// Start from the end so that `$ref:1` doesn't replace the start of `$ref:12`
+	for (index, id) in ids.iter().enumerate().rev() {
+		text = text.replace(&format!("$ref:{}", index), &id.to_string());
+	}
+	text
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn fill_single_ref() {
+		assert_eq!(r#"{"item":"42"}"#, fill_refs(r#"{"item":"$ref:0"}"#, &[42]));
+	}
+
+	#[test]
+	fn fill_double_digit_ref() {
+		let ids: Vec<i64> = (100..112).collect();
+		assert_eq!("111 101", fill_refs("$ref:11 $ref:1", &ids));
+	}
+
+	#[test]
+	fn find_text_refs() {
+		assert_eq!(vec![0, 3], text_refs(r#"{"a":"$ref:0","b":$ref:3}"#));
+	}
+}
@@ -1,12 +1,20 @@
-use crate::{graphql::ContextData, users::user::UserObject};
+use crate::{
+	graphql::{
+		pagination::{connection, ListConnection, PageArgs},
+		ContextData,
+	},
+	users::{teams::TeamObject, user::UserObject},
+};
 use async_graphql::*;
 use block_tools::{
 	auth::{
-		optional_token, optional_validate_token, permissions::can_view, require_token,
-		validate_token,
+		inheritance::InheritedPerms,
+		optional_token, optional_validate_token,
+		permissions::{self, can_view},
+		require_token, validate_token,
 	},
 	dsl::prelude::*,
-	models::{Block, Comment, User},
+	models::{Block, BlockReference, BlockRevision, Comment, Formula, Property, Tag, Team, User},
 	schema::comments,
 };
 use block_types::delegation::display::{delegate_embed_display, delegate_page_display};
@@ -16,6 +24,12 @@ use std::time::SystemTime;
 use super::{
 	breadcrumb::{gen_breadcrumb, BreadCrumb},
 	comments::CommentObject,
+	formulas::FormulaObject,
+	properties::{InlinePropertyObject, PropertyObject},
+	references::BlockReferenceObject,
+	relations::{relations_of, RelationObject},
+	revisions::RevisionObject,
+	tags::TagObject,
 };
 
 /// A block on Loop. Currently the best documentation is in this schema.
@@ -31,9 +45,17 @@ pub struct BlockObject {
 	// Permissions
 	pub owner_id: i32,
 	pub perm_edit: Vec<i32>,
+	pub perm_comment: Vec<i32>,
 	pub perm_full: Vec<i32>,
 	pub perm_view: Vec<i32>,
 	pub public: bool,
+	pub inherit_perms: bool,
+	pub inherited: InheritedPerms,
+	pub team_full: Vec<i64>,
+	pub team_edit: Vec<i64>,
+	pub team_comment: Vec<i64>,
+	pub team_view: Vec<i64>,
+	pub co_owners: Vec<i32>,
 	// Notifications
 	pub notif_enabled: Vec<i32>,
 	pub stars: Vec<i32>,
@@ -113,6 +135,7 @@ impl BlockObject {
 		let mut users: Vec<UserObject> = vec![];
 		let id_list = match level {
 			PermLevel::View => self.perm_view.clone(),
+			PermLevel::Comment => self.perm_comment.clone(),
 			PermLevel::Edit => self.perm_edit.clone(),
 			PermLevel::Full => self.perm_full.clone(),
 		};
@@ -127,10 +150,93 @@ impl BlockObject {
 		Ok(users)
 	}
 
+	/// The users that can do everything the owner can
+	async fn co_owners(&self, context: &Context<'_>) -> Result<Vec<UserObject>> {
+		let (_, conn) = &ContextData::parse(context)?;
+
+		let mut users: Vec<UserObject> = vec![];
+		for id in &self.co_owners {
+			if let Some(user) = User::by_id(*id, conn)? {
+				users.push(user.into());
+			}
+		}
+
+		Ok(users)
+	}
+
+	/// Whether the block gets the permissions of the blocks it is a property of
+	async fn inherit_perms(&self) -> bool {
+		self.inherit_perms
+	}
+
+	/// Whether the block is public because a parent it inherits from is public
+	async fn inherited_public(&self) -> bool {
+		self.inherited.public
+	}
+
+	/// The users that have a specific level on the block because of its parents.
+	/// This is empty unless the block inherits permissions.
+	async fn inherited_perms(
+		&self,
+		context: &Context<'_>,
+		level: PermLevel,
+	) -> Result<Vec<UserObject>> {
+		let (_, conn) = &ContextData::parse(context)?;
+
+		let mut users: Vec<UserObject> = vec![];
+		let id_list = match level {
+			PermLevel::View => &self.inherited.view,
+			PermLevel::Comment => &self.inherited.comment,
+			PermLevel::Edit => &self.inherited.edit,
+			PermLevel::Full => &self.inherited.full,
+		};
+
+		for id in id_list {
+			if let Some(user) = User::by_id(*id, conn)? {
+				users.push(user.into());
+			}
+		}
+
+		Ok(users)
+	}
+
+	/// The teams whose members have a specific level on the block
+	async fn team_perms(&self, context: &Context<'_>, level: PermLevel) -> Result<Vec<TeamObject>> {
+		let (_, conn) = &ContextData::parse(context)?;
+
+		let id_list = match level {
+			PermLevel::View => &self.team_view,
+			PermLevel::Comment => &self.team_comment,
+			PermLevel::Edit => &self.team_edit,
+			PermLevel::Full => &self.team_full,
+		};
+
+		let mut teams: Vec<TeamObject> = vec![];
+		for id in id_list {
+			if let Some(team) = Team::by_id(*id, conn)? {
+				teams.push(team.into());
+			}
+		}
+
+		Ok(teams)
+	}
+
 	/// The JSON string for page display, a DisplayObject
 	async fn page_display(&self, context: &Context<'_>) -> Result<String> {
 		let context = &context.data::<ContextData>()?.other();
-		let display = delegate_page_display(&self.other(), context)?;
+		let mut display = delegate_page_display(&self.other(), context)?;
+
+		// Every block's menu has a section for the user's tags
+		let user_id = optional_validate_token(optional_token(context))?;
+		let menu = display
+			.meta
+			.as_mut()
+			.and_then(|meta| meta.page.as_mut())
+			.and_then(|page| page.menu.as_mut());
+		if let (Some(user_id), Some(menu)) = (user_id, menu) {
+			menu.load_tags(user_id, &context.conn()?)?;
+		}
+
 		Ok(serde_json::to_string(&display)?)
 	}
 
@@ -148,24 +254,30 @@ impl BlockObject {
 	}
 
 	/// All the comments that the user can see on this block
-	async fn comments(&self, context: &Context<'_>) -> Result<Vec<CommentObject>> {
+	async fn comments(
+		&self,
+		context: &Context<'_>,
+		after: Option<String>,
+		before: Option<String>,
+		first: Option<i32>,
+		last: Option<i32>,
+	) -> Result<ListConnection<CommentObject>> {
 		let (context, conn) = &ContextData::parse(context)?;
 		let user_id = optional_validate_token(optional_token(context))?;
-		let comments: Vec<Comment> = comments::dsl::comments
-			.order_by(comments::dsl::created_at.desc())
-			.filter(comments::block_id.eq(self.id))
-			.get_results(conn)?;
-		let mut comment_objects: Vec<CommentObject> = vec![];
-
-		for comment in comments {
-			if let Some(block) = Block::by_id(comment.content_id, conn)? {
-				if can_view(user_id, &block) {
-					comment_objects.push(comment.into())
-				}
-			}
+		let page = PageArgs {
+			after,
+			before,
+			first,
+			last,
 		}
+		.query()?;
 
-		Ok(comment_objects)
+		let comments = Comment::page_on(self.id, user_id, &page, conn)?;
+		Ok(connection(
+			comments,
+			|comment| comment.id,
+			CommentObject::from,
+		))
 	}
 
 	/// The total number of comments for this block. This includes blocks that the user doesn't
@@ -179,6 +291,104 @@ impl BlockObject {
 
 		Ok(count)
 	}
+
+	/// Blocks that the user can see which link to this block in their content
+	async fn backlinks(&self, context: &Context<'_>) -> Result<Vec<BlockObject>> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = optional_validate_token(optional_token(context))?;
+
+		let mut source_ids: Vec<i64> = BlockReference::to_block(self.id, conn)?
+			.into_iter()
+			.map(|reference| reference.source_id)
+			.collect();
+		source_ids.sort_unstable();
+		source_ids.dedup();
+
+		let mut backlinks: Vec<BlockObject> = vec![];
+		for source_id in source_ids {
+			if let Some(block) = Block::by_id(source_id, conn)? {
+				if can_view(user_id, &block) {
+					backlinks.push(block.into())
+				}
+			}
+		}
+
+		Ok(backlinks)
+	}
+
+	/// The links to other blocks inside of this block's content
+	async fn references(&self, context: &Context<'_>) -> Result<Vec<BlockReferenceObject>> {
+		let (_, conn) = &ContextData::parse(context)?;
+		Ok(BlockReference::from_block(self.id, conn)?
+			.into_iter()
+			.map(BlockReferenceObject::from)
+			.collect())
+	}
+
+	/// The tags that the authenticated user has put on this block
+	async fn tags(&self, context: &Context<'_>) -> Result<Vec<TagObject>> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = match optional_validate_token(optional_token(context))? {
+			Some(id) => id,
+			None => return Ok(vec![]),
+		};
+		Ok(Tag::on_block(self.id, user_id, conn)?
+			.into_iter()
+			.map(TagObject::from)
+			.collect())
+	}
+
+	/// The properties of this block, in order
+	async fn properties(&self, context: &Context<'_>) -> Result<Vec<PropertyObject>> {
+		let (_, conn) = &ContextData::parse(context)?;
+		Ok(Property::children_of(self.id, conn)?
+			.into_iter()
+			.map(PropertyObject::from)
+			.collect())
+	}
+
+	/// Relations from this block to other blocks the user can view
+	async fn relations(&self, context: &Context<'_>) -> Result<Vec<RelationObject>> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = optional_validate_token(optional_token(context))?;
+		relations_of(self.id, false, user_id, conn)
+	}
+
+	/// Relations to this block from other blocks the user can view, named from this block's side
+	async fn inverse_relations(&self, context: &Context<'_>) -> Result<Vec<RelationObject>> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = optional_validate_token(optional_token(context))?;
+		relations_of(self.id, true, user_id, conn)
+	}
+
+	/// The properties of this block with primitive values, sorted by name
+	async fn inline_properties(&self, context: &Context<'_>) -> Result<Vec<InlinePropertyObject>> {
+		let (_, conn) = &ContextData::parse(context)?;
+		Ok(self
+			.other()
+			.inline_properties(conn)?
+			.into_iter()
+			.map(InlinePropertyObject::from)
+			.collect())
+	}
+
+	/// The formulas that compute this block's values, sorted by name
+	async fn formulas(&self, context: &Context<'_>) -> Result<Vec<FormulaObject>> {
+		let (_, conn) = &ContextData::parse(context)?;
+		Ok(Formula::of_block(self.id, conn)?
+			.into_iter()
+			.map(FormulaObject::from)
+			.collect())
+	}
+
+	/// The history of changes made to this block, newest first
+	async fn revisions(&self, context: &Context<'_>) -> Result<Vec<RevisionObject>> {
+		let (_, conn) = &ContextData::parse(context)?;
+		Ok(BlockRevision::of_block(self.id, conn)?
+			.into_iter()
+			.map(RevisionObject::from)
+			.collect())
+	}
 }
 
 #[derive(Enum, Copy, Clone, Eq, PartialEq)]
@@ -186,14 +396,40 @@ impl BlockObject {
 pub enum PermLevel {
 	/// Users with this level can view the block, and nothing more.
 	View,
+	/// Users with this level can comment on the block as well as everything that
+	/// comes with the `VIEW` level
+	Comment,
 	/// Users with this level are able to edit the blocks with certain methods as well as
-	/// everything that comes with the `VIEW` level
+	/// everything that comes with the `COMMENT` level
 	Edit,
 	/// Users with this level are able to edit the block permissions as well as everything
 	/// that comes with the `EDIT` level
 	Full,
 }
 
+impl From<PermLevel> for permissions::PermLevel {
+	fn from(level: PermLevel) -> Self {
+		match level {
+			PermLevel::View => permissions::PermLevel::View,
+			PermLevel::Comment => permissions::PermLevel::Comment,
+			PermLevel::Edit => permissions::PermLevel::Edit,
+			PermLevel::Full => permissions::PermLevel::Full,
+		}
+	}
+}
+
+/// Owners are shown with the `FULL` level, since there is only one owner
+impl From<permissions::PermLevel> for PermLevel {
+	fn from(level: permissions::PermLevel) -> Self {
+		match level {
+			permissions::PermLevel::View => PermLevel::View,
+			permissions::PermLevel::Comment => PermLevel::Comment,
+			permissions::PermLevel::Edit => PermLevel::Edit,
+			_ => PermLevel::Full,
+		}
+	}
+}
+
 impl BlockObject {
 	pub fn other(&self) -> Block {
 		Block {
@@ -207,7 +443,15 @@ impl BlockObject {
 			public: self.public,
 			perm_full: self.perm_full.clone(),
 			perm_edit: self.perm_edit.clone(),
+			perm_comment: self.perm_comment.clone(),
 			perm_view: self.perm_view.clone(),
+			inherit_perms: self.inherit_perms,
+			inherited: self.inherited.clone(),
+			team_full: self.team_full.clone(),
+			team_edit: self.team_edit.clone(),
+			team_comment: self.team_comment.clone(),
+			team_view: self.team_view.clone(),
+			co_owners: self.co_owners.clone(),
 			stars: self.stars.clone(),
 			notif_enabled: self.notif_enabled.clone(),
 		}
@@ -227,7 +471,15 @@ impl From<Block> for BlockObject {
 			public: blockd.public,
 			perm_full: blockd.perm_full,
 			perm_edit: blockd.perm_edit,
+			perm_comment: blockd.perm_comment,
 			perm_view: blockd.perm_view,
+			inherit_perms: blockd.inherit_perms,
+			inherited: blockd.inherited,
+			team_full: blockd.team_full,
+			team_edit: blockd.team_edit,
+			team_comment: blockd.team_comment,
+			team_view: blockd.team_view,
+			co_owners: blockd.co_owners,
 			stars: blockd.stars,
 			notif_enabled: blockd.notif_enabled,
 		}
@@ -247,7 +499,15 @@ impl From<&Block> for BlockObject {
 			public: blockd.public,
 			perm_full: blockd.perm_full.clone(),
 			perm_edit: blockd.perm_edit.clone(),
+			perm_comment: blockd.perm_comment.clone(),
 			perm_view: blockd.perm_view.clone(),
+			inherit_perms: blockd.inherit_perms,
+			inherited: blockd.inherited.clone(),
+			team_full: blockd.team_full.clone(),
+			team_edit: blockd.team_edit.clone(),
+			team_comment: blockd.team_comment.clone(),
+			team_view: blockd.team_view.clone(),
+			co_owners: blockd.co_owners.clone(),
 			stars: blockd.stars.clone(),
 			notif_enabled: blockd.notif_enabled.clone(),
 		}
@@ -32,5 +32,6 @@ pub fn type_list() -> Vec<BlockType> {
 		habit_block::HabitBlock::info().into(),
 		group_block::GroupBlock::info().into(),
 		text_block::TextBlock::info().into(),
+		collection_block::CollectionBlock::info().into(),
 	]
 }
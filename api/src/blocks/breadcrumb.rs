@@ -1,12 +1,12 @@
 use async_graphql::*;
-use block_tools::{auth::permissions::maybe_use_view, use_diesel::prelude::*};
 use block_tools::{
+	auth::permissions::use_view,
 	blocks::Context,
 	models::{Block, Property},
-	schema::properties,
 	LoopError,
 };
 use block_types::delegation::display::delegate_block_name;
+use std::collections::HashMap;
 
 #[derive(SimpleObject, Clone)]
 /// One section of a block's breadcrumb. There should be multiple of these
@@ -21,91 +21,100 @@ pub struct BreadCrumb {
 
 /// Generates a vector of crumbs for a single block
 pub fn gen_breadcrumb(context: &Context, block: &Block) -> Result<Vec<BreadCrumb>, LoopError> {
-	// Generate the breadcrumbs (it's recursive)
-	let mut crumbs = cycle(context, block, vec![], vec![])?.0;
-	// Reverse the order of the breadcrumbs b/c it would otherwise
-	// be often reversed on the frontend anyways.
-	crumbs.reverse();
-	Ok(crumbs)
+	Ancestry::load(context, &[block.id])?.breadcrumb(context, block)
 }
 
-/// A recursive function that builds a breadcrumb. There may or may not be
-/// performance issues to solve
-fn cycle(
-	context: &Context,
-	block: &Block,
-	mut crumbs: Vec<BreadCrumb>,
-	mut blocks_added: Vec<i64>,
-) -> Result<(Vec<BreadCrumb>, Vec<i64>), LoopError> {
-	let conn = &context.conn()?;
+/// The ancestors of a set of blocks, loaded all at once so that any
+/// number of breadcrumbs can be made without querying for each parent
+pub struct Ancestry {
+	/// The properties that point to each block, with the primary parent
+	/// first and the rest in the order they were made
+	parents: HashMap<i64, Vec<Property>>,
+	/// The ancestors that the user has access to
+	viewable: HashMap<i64, Block>,
+}
+
+impl Ancestry {
+	pub fn load(context: &Context, block_ids: &[i64]) -> Result<Self, LoopError> {
+		let conn = &context.conn()?;
 
-	let parent_props: Vec<Property> = properties::dsl::properties
-		.filter(properties::value_id.eq(block.id))
-		.get_results(conn)?
-		.into_iter()
-		// Remove the parents that have already been added
-		.filter(|prop: &Property| !blocks_added.contains(&prop.parent_id))
-		.collect();
-	let (parent_prop, parent) = allowed_parent(parent_props, context)?;
+		let props = Property::ancestors_of(block_ids, conn)?;
+		let mut parent_ids: Vec<i64> = props.iter().map(|prop| prop.parent_id).collect();
+		parent_ids.sort_unstable();
+		parent_ids.dedup();
+		// The share link of the request can give access to ancestors too
+		let mut viewable = HashMap::new();
+		for block in Block::by_ids(&parent_ids, conn)? {
+			if let Some(block) = use_view(context, block)? {
+				viewable.insert(block.id, block);
+			}
+		}
 
-	// Helper function for when there are no parents
-	let add_this = |mut crumbs: Vec<BreadCrumb>, blocks_added: Vec<i64>| {
-		// Add the final block that its on
-		crumbs.push(BreadCrumb {
-			block_id: block.id,
-			name: delegate_block_name(context, &block.block_type, block)?,
-		});
-		Ok((crumbs, blocks_added))
-	};
-	let parent = match parent {
-		Some(parent) => parent,
-		None => return add_this(crumbs, blocks_added),
-	};
-	let parent_prop = match parent_prop {
-		Some(prop) => prop,
-		None => return add_this(crumbs, blocks_added),
-	};
-	// If the property is an item (like in a group) then the name should
-	// be the name of the block
-	if parent_prop.property_name == "item" {
-		crumbs.push(BreadCrumb {
-			block_id: block.id,
-			name: delegate_block_name(context, &block.block_type, block)?,
-		})
-	// If not, then the name of the block displayed should be the property name
-	} else {
-		crumbs.push(BreadCrumb {
-			block_id: block.id,
-			name: parent_prop.property_name,
-		})
+		let mut parents: HashMap<i64, Vec<Property>> = HashMap::new();
+		for prop in props {
+			parents.entry(prop.value_id).or_default().push(prop);
+		}
+		for props in parents.values_mut() {
+			props.sort_by_key(|prop| (!prop.is_primary, prop.id));
+		}
+
+		Ok(Ancestry { parents, viewable })
 	}
-	// Add the block to the list of blocks added
-	blocks_added.push(block.id);
-	// Cycle to further in the crumb cycle
-	let next_cycle = cycle(context, &parent, crumbs, blocks_added)?;
-	Ok((next_cycle.0, next_cycle.1))
-}
 
-/// From a list of properties, this returns the property & block that
-/// the user has access to (if any)
-fn allowed_parent(
-	parent_props: Vec<Property>,
-	context: &Context,
-) -> Result<(Option<Property>, Option<Block>), LoopError> {
-	let conn = &context.conn()?;
+	/// Builds a block's breadcrumb by following its first parent that
+	/// the user has access to, until there are none left
+	pub fn breadcrumb(
+		&self,
+		context: &Context,
+		block: &Block,
+	) -> Result<Vec<BreadCrumb>, LoopError> {
+		let mut crumbs: Vec<BreadCrumb> = vec![];
+		let mut blocks_added: Vec<i64> = vec![];
+		let mut current = block;
 
-	let mut parent: Option<Block> = None;
-	let mut parent_prop: Option<Property> = None;
+		loop {
+			let parent = self.parents.get(&current.id).and_then(|props| {
+				props
+					.iter()
+					// Skip the parents that have already been added
+					.filter(|prop| !blocks_added.contains(&prop.parent_id))
+					.find_map(|prop| {
+						self.viewable
+							.get(&prop.parent_id)
+							.map(|parent| (prop, parent))
+					})
+			});
+			let (parent_prop, parent) = match parent {
+				Some(parent) => parent,
+				None => {
+					// Add the final block that its on
+					crumbs.push(BreadCrumb {
+						block_id: current.id,
+						name: delegate_block_name(context, &current.block_type, current)?,
+					});
+					break;
+				}
+			};
 
-	for candidate in parent_props {
-		let block = Block::by_id(candidate.parent_id, conn)?;
-		let block = maybe_use_view(context, block)?;
-		if let Some(block) = block {
-			parent = Some(block);
-			parent_prop = Some(candidate);
-			break;
+			// If the property is an item (like in a group) then the name should
+			// be the name of the block. If not, then the name of the block
+			// displayed should be the property name
+			let name = if parent_prop.property_name == "item" {
+				delegate_block_name(context, &current.block_type, current)?
+			} else {
+				parent_prop.property_name.clone()
+			};
+			crumbs.push(BreadCrumb {
+				block_id: current.id,
+				name,
+			});
+			blocks_added.push(current.id);
+			current = parent;
 		}
-	}
 
-	Ok((parent_prop, parent))
+		// Reverse the order of the breadcrumbs b/c it would otherwise
+		// be often reversed on the frontend anyways.
+		crumbs.reverse();
+		Ok(crumbs)
+	}
 }
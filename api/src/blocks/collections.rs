@@ -0,0 +1,349 @@
+use super::{
+	block::{BlockObject, PermLevel},
+	search::{BlockSearchFilters, BlockSortType, TagMatch},
+};
+use crate::graphql::{
+	pagination::{connection, ListConnection, PageArgs},
+	ContextData,
+};
+use async_graphql::*;
+use block_tools::{
+	auth::{require_token, validate_token},
+	blocks::Context as ToolsContext,
+	dsl::prelude::*,
+	models::{
+		Block, Collection, CollectionChanges, NewCollection, NewNotification, SearchFilters,
+		SearchSort,
+	},
+	schema::properties,
+	NoAccessSubject, PgConnect, UserError,
+};
+use block_types::delegation::display::delegate_block_name;
+use chrono::{DateTime, TimeZone, Utc};
+use std::collections::HashMap;
+
+/// A search that a user saved with a name, so that its results can be
+/// listed again or shown in a `collection` block. Collections can only be
+/// seen by the user that made them.
+pub struct CollectionObject {
+	collection: Collection,
+}
+
+#[Object]
+impl CollectionObject {
+	/// A unique identifier for the collection
+	async fn id(&self) -> i64 {
+		self.collection.id
+	}
+
+	async fn name(&self) -> String {
+		self.collection.name.clone()
+	}
+
+	/// The text that blocks are searched for
+	async fn query(&self) -> String {
+		self.collection.query.clone()
+	}
+
+	/// The filters the search was saved with
+	async fn filters(&self) -> SavedSearchFilters {
+		self.collection.filters().into()
+	}
+
+	/// Whether data blocks are included with the results
+	async fn with_data(&self) -> bool {
+		self.collection.with_data
+	}
+
+	async fn sort_by(&self) -> BlockSortType {
+		self.collection.sort().into()
+	}
+
+	/// Whether the user is notified about new blocks that match
+	async fn subscribed(&self) -> bool {
+		self.collection.subscribed_at.is_some()
+	}
+
+	/// When was the collection created?
+	async fn created_at(&self) -> DateTime<Utc> {
+		self.collection.created_at.into()
+	}
+
+	/// When was the search last changed?
+	async fn updated_at(&self) -> DateTime<Utc> {
+		self.collection.updated_at.into()
+	}
+
+	/// Runs the search again and returns the blocks it finds, in the order it
+	/// was saved with. Results are paged like a Relay connection.
+	async fn results(
+		&self,
+		context: &Context<'_>,
+		after: Option<String>,
+		before: Option<String>,
+		first: Option<i32>,
+		last: Option<i32>,
+	) -> Result<ListConnection<BlockObject>> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let page = PageArgs {
+			after,
+			before,
+			first,
+			last,
+		}
+		.query()?;
+
+		let search = self.collection.search(Some(user_id));
+		let mut hits = Block::search(&search, &page, conn)?;
+
+		let ids: Vec<i64> = hits.items.iter().map(|hit| hit.id).collect();
+		let mut blocks: HashMap<i64, Block> = Block::by_ids(&ids, conn)?
+			.into_iter()
+			.map(|block| (block.id, block))
+			.collect();
+		// Blocks deleted since they were found are left out
+		hits.items.retain(|hit| blocks.contains_key(&hit.id));
+		let results = hits.map(|hit| (hit.key(), blocks.remove(&hit.id).unwrap()));
+
+		Ok(connection(
+			results,
+			|(key, _)| *key,
+			|(_, block)| block.into(),
+		))
+	}
+}
+
+impl From<Collection> for CollectionObject {
+	fn from(collection: Collection) -> Self {
+		CollectionObject { collection }
+	}
+}
+
+#[derive(SimpleObject)]
+/// The filters that a collection's search was saved with, in the same
+/// shape as `BlockSearchFilters`
+pub struct SavedSearchFilters {
+	starred: bool,
+	block_type: Option<String>,
+	owner_id: Option<i32>,
+	tags: Option<Vec<i64>>,
+	tag_match: TagMatch,
+	created_after: Option<DateTime<Utc>>,
+	created_before: Option<DateTime<Utc>>,
+	updated_after: Option<DateTime<Utc>>,
+	updated_before: Option<DateTime<Utc>>,
+	color: Option<String>,
+	public: Option<bool>,
+	perm_level: Option<PermLevel>,
+	has_comments: Option<bool>,
+	parent_id: Option<i64>,
+	notif_enabled: Option<bool>,
+	all: Vec<SavedSearchFilters>,
+	any: Vec<SavedSearchFilters>,
+	none: Vec<SavedSearchFilters>,
+}
+
+impl From<SearchFilters> for SavedSearchFilters {
+	fn from(filters: SearchFilters) -> Self {
+		let time = |seconds: Option<i64>| seconds.map(|seconds| Utc.timestamp(seconds, 0));
+		let groups = |groups: Vec<SearchFilters>| -> Vec<SavedSearchFilters> {
+			groups.into_iter().map(SavedSearchFilters::from).collect()
+		};
+		SavedSearchFilters {
+			starred: filters.starred,
+			block_type: filters.block_type,
+			owner_id: filters.owner_id,
+			tags: filters.tags,
+			tag_match: filters.tag_match.into(),
+			created_after: time(filters.created_after),
+			created_before: time(filters.created_before),
+			updated_after: time(filters.updated_after),
+			updated_before: time(filters.updated_before),
+			color: filters.color,
+			public: filters.public,
+			perm_level: filters.perm_level.map(Into::into),
+			has_comments: filters.has_comments,
+			parent_id: filters.parent_id,
+			notif_enabled: filters.notif_enabled,
+			all: groups(filters.all),
+			any: groups(filters.any),
+			none: groups(filters.none),
+		}
+	}
+}
+
+#[derive(Default)]
+pub struct CollectionQueries;
+
+#[Object]
+impl CollectionQueries {
+	/// All the collections the authenticated user has saved, sorted by name
+	async fn collections(&self, context: &Context<'_>) -> Result<Vec<CollectionObject>> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+
+		Ok(Collection::of_user(user_id, conn)?
+			.into_iter()
+			.map(CollectionObject::from)
+			.collect())
+	}
+
+	/// One of the user's collections
+	async fn collection(
+		&self,
+		context: &Context<'_>,
+		collection_id: i64,
+	) -> Result<CollectionObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+
+		Ok(owned_collection(collection_id, user_id, conn)?.into())
+	}
+}
+
+#[derive(Default)]
+pub struct CollectionMutations;
+
+#[Object]
+impl CollectionMutations {
+	/// Saves a search as a new collection, with the same arguments as the
+	/// `searchBlocks` query
+	async fn create_collection(
+		&self,
+		context: &Context<'_>,
+		name: String,
+		query: String,
+		filters: Option<BlockSearchFilters>,
+		#[graphql(desc = "Include data blocks with results?")] with_data: Option<bool>,
+		sort_by: Option<BlockSortType>,
+	) -> Result<CollectionObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+
+		let filters: SearchFilters = filters.unwrap_or_default().into();
+		filters.check()?;
+		let sort = SearchSort::from(sort_by.unwrap_or_default());
+		let collection = NewCollection {
+			filters: filters.to_json(),
+			with_data: with_data.unwrap_or_default(),
+			sort: sort.as_str().to_string(),
+			..NewCollection::new(user_id, name, query)
+		}
+		.insert(conn)?;
+
+		Ok(collection.into())
+	}
+
+	/// Changes one of the user's collections. Only the arguments that are
+	/// provided are changed, and `filters` replaces all of the filters.
+	async fn update_collection(
+		&self,
+		context: &Context<'_>,
+		collection_id: i64,
+		name: Option<String>,
+		query: Option<String>,
+		filters: Option<BlockSearchFilters>,
+		with_data: Option<bool>,
+		sort_by: Option<BlockSortType>,
+	) -> Result<CollectionObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let collection = owned_collection(collection_id, user_id, conn)?;
+
+		let filters = filters.map(SearchFilters::from);
+		if let Some(filters) = &filters {
+			filters.check()?;
+		}
+		let changes = CollectionChanges {
+			name,
+			query,
+			filters: filters.map(|filters| filters.to_json()),
+			with_data,
+			sort: sort_by.map(|sort| SearchSort::from(sort).as_str().to_string()),
+		};
+
+		Ok(collection.update(changes, conn)?.into())
+	}
+
+	/// Starts or stops notifying the user when blocks created from now on
+	/// match one of their collections
+	async fn set_collection_subscribed(
+		&self,
+		context: &Context<'_>,
+		collection_id: i64,
+		subscribed: bool,
+	) -> Result<CollectionObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let collection = owned_collection(collection_id, user_id, conn)?;
+
+		Ok(collection.set_subscribed(subscribed, conn)?.into())
+	}
+
+	/// Deletes one of the user's collections. Collection blocks that showed
+	/// it are left, and say that it was deleted.
+	async fn delete_collection(&self, context: &Context<'_>, collection_id: i64) -> Result<i64> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let collection = owned_collection(collection_id, user_id, conn)?;
+
+		collection.delete(conn)?;
+
+		Ok(collection_id)
+	}
+}
+
+/// Finds a collection, but only if the user owns it
+fn owned_collection(
+	collection_id: i64,
+	user_id: i32,
+	conn: &PgConnect,
+) -> Result<Collection, Error> {
+	match Collection::by_id(collection_id, conn)? {
+		Some(collection) if collection.owner_id == user_id => Ok(collection),
+		_ => Err(UserError::NoAccess(NoAccessSubject::EditCollection(collection_id)).into()),
+	}
+}
+
+/// Notifies the owners of subscribed collections that a block is a new
+/// match. The text of data blocks is searched as part of their parents,
+/// so the parents of a data block are checked too. Users aren't notified
+/// about their own blocks. The connection is only held while querying,
+/// so that block types can use their own.
+pub fn notify_collections(context: &ToolsContext, block: &Block) -> Result<(), Error> {
+	let mut candidates = vec![block.clone()];
+	if block.block_type == "data" {
+		let conn: &PgConnect = &context.shared_conn()?;
+		let parent_ids: Vec<i64> = properties::table
+			.filter(properties::value_id.eq(block.id))
+			.select(properties::parent_id)
+			.load(conn)?;
+		candidates.extend(Block::by_ids(&parent_ids, conn)?);
+	}
+
+	for candidate in &candidates {
+		for collection in Collection::watching(candidate, &context.shared_conn()?)? {
+			if !collection.is_new_match(candidate, &context.shared_conn()?)? {
+				continue;
+			}
+			if !collection.mark_notified(candidate.id, &context.shared_conn()?)? {
+				continue;
+			}
+
+			let block_name = delegate_block_name(context, &candidate.block_type, candidate)?;
+			NewNotification::new(
+				format!("New in \"{}\"", collection.name),
+				format!(
+					"\"{}\" matches your collection \"{}\".",
+					block_name, collection.name
+				),
+			)
+			.recipients(vec![collection.owner_id])
+			.link(candidate.id)
+			.send(&context.shared_conn()?)?;
+		}
+	}
+
+	Ok(())
+}
@@ -37,7 +37,7 @@ impl BlockColorMutations {
 			return Err(access_err);
 		}
 
-		let block = block.update_color(Some(rgb), conn)?;
+		let block = block.update_color_by(Some(rgb), Some(user_id), conn)?;
 
 		Ok(block.into())
 	}
@@ -63,7 +63,7 @@ impl BlockColorMutations {
 			return Err(access_err);
 		}
 
-		let block = block.update_color(None, conn)?;
+		let block = block.update_color_by(None, Some(user_id), conn)?;
 
 		Ok(block.into())
 	}
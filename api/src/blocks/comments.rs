@@ -1,7 +1,10 @@
 use crate::{graphql::ContextData, users::user::UserObject};
 use async_graphql::*;
 use block_tools::{
-	auth::{permissions::can_view, require_token, validate_token},
+	auth::{
+		permissions::{can_comment, can_view},
+		require_token, validate_token,
+	},
 	dsl,
 	dsl::prelude::*,
 	models::{Block, Comment, NewComment, User},
@@ -81,6 +84,7 @@ pub struct CommentMutations;
 #[Object]
 impl CommentMutations {
 	/// This mutation creates a comment for a block, given a comment content ID and the block to comment on.
+	/// The block must be public, or the user must have comment permissions or higher on it.
 	pub async fn create_comment(
 		&self,
 		context: &Context<'_>,
@@ -91,13 +95,13 @@ impl CommentMutations {
 
 		let user_id = validate_token(&require_token(context)?)?;
 
-		let access_err = Err(UserError::NoAccess(NoAccessSubject::ViewBlock(block_id)).into());
+		let access_err = Err(UserError::NoAccess(NoAccessSubject::CommentBlock(block_id)).into());
 		let block_on = if let Some(block) = Block::by_id(block_id, conn)? {
 			block
 		} else {
 			return access_err;
 		};
-		if !can_view(Some(user_id), &block_on) {
+		if !can_comment(user_id, &block_on) {
 			return access_err;
 		}
 		let access_err = Err(UserError::NoAccess(NoAccessSubject::ViewBlock(content_id)).into());
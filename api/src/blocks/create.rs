@@ -1,9 +1,10 @@
-use super::block::BlockObject;
+use super::{block::BlockObject, search::index_block};
 use crate::graphql::ContextData;
 use async_graphql::{Context, Error, Object};
-use block_tools::models::User;
+use block_tools::models::{Block, User};
 use block_tools::{
 	auth::{require_token, validate_token},
+	blocks::Context as ToolsContext,
 	UserError,
 };
 use block_types::{
@@ -26,24 +27,38 @@ impl BlockCreationMutation {
 		#[graphql(desc = "Name of the block type to create.")] r#type: String,
 		#[graphql(desc = "JSON string to specify what to create.")] input: String,
 	) -> Result<BlockObject, Error> {
-		let (context, conn) = &ContextData::parse(context)?;
+		let context = &context.data::<ContextData>()?.other();
 
 		let user_id = validate_token(&require_token(context)?)?;
-		let user = match User::by_id(user_id, conn)? {
-			Some(user) => user,
-			None => return Err(UserError::JwtGeneric.into()),
-		};
 
-		let mut block =
-			BlockObject::from(delegate_create(r#type.as_str(), input, context, user_id)?);
+		Ok(create_for_user(context, user_id, &r#type, input)?.into())
+	}
+}
 
-		// If the user has no root, create one
-		if user.root_id.is_none() {
-			block = create_root(context, user, block.id)?.into();
-		}
+/// Creates a block of a type for a user. If the user doesn't have a root
+/// block yet, one is created as well.
+pub fn create_for_user(
+	context: &ToolsContext,
+	user_id: i32,
+	block_type: &str,
+	input: String,
+) -> Result<Block, Error> {
+	let user = match User::by_id(user_id, &context.shared_conn()?)? {
+		Some(user) => user,
+		None => return Err(UserError::JwtGeneric.into()),
+	};
 
-		Ok(block)
+	let block = delegate_create(block_type, input, context, user_id)?;
+	index_block(context, &block)?;
+
+	// If the user has no root, create one
+	if user.root_id.is_none() {
+		let root = create_root(context, user, block.id)?;
+		index_block(context, &root)?;
+		return Ok(root);
 	}
+
+	Ok(block)
 }
 
 #[derive(Default)]
@@ -0,0 +1,91 @@
+use super::block::BlockObject;
+use crate::graphql::{pagination::limit_size, ContextData};
+use async_graphql::*;
+use block_tools::{
+	auth::{optional_token, optional_validate_token},
+	models::{Block, DataFilter, DataOp},
+};
+use serde_json::Value;
+
+#[derive(Enum, Copy, Clone, PartialEq, Eq)]
+/// How to compare the value inside of block data
+pub enum DataFilterOp {
+	Equals,
+	NotEquals,
+	/// The value is text that includes the filter's value, ignoring case
+	Contains,
+	GreaterThan,
+	LessThan,
+	/// There is any value at the path
+	Exists,
+}
+
+#[derive(InputObject)]
+/// A condition on a value inside of block data
+pub struct DataFilterInput {
+	/// The keys to follow into the data, like `["status"]`
+	path: Vec<String>,
+	op: DataFilterOp,
+	/// JSON to compare with, like `"done"` or `3`. Text that isn't valid JSON is used as a string.
+	value: Option<String>,
+	/// Compare the inline property or formula named by the first key
+	/// of the path, instead of the block's data
+	#[graphql(default)]
+	inline: bool,
+}
+
+impl From<DataFilterOp> for DataOp {
+	fn from(op: DataFilterOp) -> Self {
+		match op {
+			DataFilterOp::Equals => DataOp::Equals,
+			DataFilterOp::NotEquals => DataOp::NotEquals,
+			DataFilterOp::Contains => DataOp::Contains,
+			DataFilterOp::GreaterThan => DataOp::GreaterThan,
+			DataFilterOp::LessThan => DataOp::LessThan,
+			DataFilterOp::Exists => DataOp::Exists,
+		}
+	}
+}
+
+impl From<DataFilterInput> for DataFilter {
+	fn from(input: DataFilterInput) -> Self {
+		let value = match input.value {
+			Some(value) => serde_json::from_str(&value).unwrap_or(Value::String(value)),
+			None => Value::Null,
+		};
+		DataFilter {
+			path: input.path,
+			op: input.op.into(),
+			value,
+			inline: input.inline,
+		}
+	}
+}
+
+#[derive(Default)]
+pub struct BlockDataQueries;
+
+#[Object]
+impl BlockDataQueries {
+	/// Finds the blocks whose data matches every filter, newest first. For example,
+	/// tasks whose `status` equals `"done"`. Only blocks the user can view are returned.
+	async fn query_blocks(
+		&self,
+		context: &Context<'_>,
+		#[graphql(desc = "Only include blocks of this type")] r#type: Option<String>,
+		#[graphql(default)] filters: Vec<DataFilterInput>,
+		#[graphql(desc = "At most 100 blocks, 20 by default")] limit: Option<i32>,
+	) -> Result<Vec<BlockObject>> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = optional_validate_token(optional_token(context))?;
+
+		let filters: Vec<DataFilter> = filters.into_iter().map(DataFilter::from).collect();
+		let limit = limit_size(limit)?;
+		let blocks = Block::query_data(r#type.as_deref(), &filters, user_id, limit, conn)?
+			.into_iter()
+			.map(BlockObject::from)
+			.collect();
+
+		Ok(blocks)
+	}
+}
@@ -0,0 +1,82 @@
+use super::block::BlockObject;
+use crate::graphql::ContextData;
+use async_graphql::*;
+use block_tools::{
+	auth::{
+		permissions::{has_perm_level, PermLevel},
+		require_token, validate_token,
+	},
+	models::{Block, Formula},
+	NoAccessSubject, PgConnect, UserError,
+};
+use chrono::{DateTime, Utc};
+
+#[derive(SimpleObject, Clone)]
+/// A value of a block that is computed from its other values and children.
+/// Its result can be read from the block's inline property with the same name.
+pub struct FormulaObject {
+	pub name: String,
+	/// The formula as it was written, like `price * quantity`
+	pub expression: String,
+	/// Why the formula couldn't be computed, if it couldn't
+	pub error: Option<String>,
+	pub updated_at: DateTime<Utc>,
+}
+
+impl From<Formula> for FormulaObject {
+	fn from(formula: Formula) -> Self {
+		FormulaObject {
+			name: formula.name,
+			expression: formula.expression,
+			error: formula.error,
+			updated_at: formula.updated_at.into(),
+		}
+	}
+}
+
+#[derive(Default)]
+pub struct FormulaMutations;
+
+#[Object]
+impl FormulaMutations {
+	/// Adds a formula to a block, or changes the one with the same name. Formulas can
+	/// use arithmetic, `+` to join text, the block's other values by name, and the
+	/// functions `count(property)`, `sum(property, field)`, `days(start, end)`,
+	/// `concat(...)` and `now()`.
+	async fn set_formula(
+		&self,
+		context: &Context<'_>,
+		block_id: i64,
+		name: String,
+		expression: String,
+	) -> Result<FormulaObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		editable_block(block_id, user_id, conn)?;
+
+		Ok(Formula::define(block_id, &name, &expression, Some(user_id), conn)?.into())
+	}
+
+	/// Removes a formula from a block, along with its value
+	async fn remove_formula(
+		&self,
+		context: &Context<'_>,
+		block_id: i64,
+		name: String,
+	) -> Result<BlockObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let block = editable_block(block_id, user_id, conn)?;
+
+		Formula::remove(block_id, &name, conn)?;
+
+		Ok(block.into())
+	}
+}
+
+fn editable_block(block_id: i64, user_id: i32, conn: &PgConnect) -> Result<Block, Error> {
+	match Block::by_id(block_id, conn)? {
+		Some(block) if has_perm_level(user_id, &block, PermLevel::Edit) => Ok(block),
+		_ => Err(UserError::NoAccess(NoAccessSubject::EditParents(block_id)).into()),
+	}
+}
@@ -0,0 +1,123 @@
+use crate::graphql::ContextData;
+use async_graphql::*;
+use block_tools::{
+	auth::{optional_token, optional_validate_token, permissions::can_view},
+	models::{Block, Property},
+	NoAccessSubject, UserError,
+};
+use block_types::delegation::display::{delegate_block_icon, delegate_block_name};
+use std::collections::{HashMap, HashSet};
+
+/// The furthest a graph can reach from its root block
+const MAX_GRAPH_DEPTH: i32 = 10;
+
+#[derive(SimpleObject)]
+/// The blocks reachable from a block, and the properties that connect them
+pub struct BlockGraph {
+	/// Every block in the graph, including the root
+	pub nodes: Vec<BlockGraphNode>,
+	/// Every property between two blocks in the graph
+	pub edges: Vec<BlockGraphEdge>,
+}
+
+#[derive(SimpleObject)]
+pub struct BlockGraphNode {
+	pub block_id: i64,
+	pub block_type: String,
+	pub name: String,
+	pub icon: Option<String>,
+	pub color: Option<String>,
+}
+
+#[derive(SimpleObject)]
+pub struct BlockGraphEdge {
+	/// The block that has the property
+	pub parent_id: i64,
+	/// The block the property points to
+	pub value_id: i64,
+	pub property_name: String,
+	pub annotation: Option<String>,
+}
+
+#[derive(Default)]
+pub struct BlockGraphQueries;
+
+#[Object]
+impl BlockGraphQueries {
+	/// The blocks that can be reached from a block by following its properties.
+	/// Blocks the user can't view are left out, along with anything only
+	/// reachable through them.
+	async fn block_graph(
+		&self,
+		context: &Context<'_>,
+		root_id: i64,
+		#[graphql(desc = "How many properties to follow from the root, up to 10")] depth: i32,
+		#[graphql(desc = "Only follow properties with these names")] property_names: Option<
+			Vec<String>,
+		>,
+	) -> Result<BlockGraph> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = optional_validate_token(optional_token(context))?;
+
+		let access_err: Error = UserError::NoAccess(NoAccessSubject::ViewBlock(root_id)).into();
+		let root = match Block::by_id(root_id, conn)? {
+			Some(root) => root,
+			None => return Err(access_err),
+		};
+		if !can_view(user_id, &root) {
+			return Err(access_err);
+		}
+
+		let depth = depth.clamp(0, MAX_GRAPH_DEPTH);
+		let props = Property::reachable_from(root_id, depth, property_names, conn)?;
+
+		let mut ids: Vec<i64> = props.iter().map(|prop| prop.value_id).collect();
+		ids.sort_unstable();
+		ids.dedup();
+		let viewable: HashMap<i64, Block> = Block::by_ids(&ids, conn)?
+			.into_iter()
+			.filter(|block| can_view(user_id, block))
+			.map(|block| (block.id, block))
+			.chain(std::iter::once((root.id, root)))
+			.collect();
+
+		// Walk the properties again from the root so that blocks behind
+		// a block the user can't view stay hidden
+		let mut reached: HashSet<i64> = HashSet::new();
+		reached.insert(root_id);
+		let mut frontier = vec![root_id];
+		let mut edges = vec![];
+		while let Some(parent_id) = frontier.pop() {
+			for prop in props.iter().filter(|prop| prop.parent_id == parent_id) {
+				if !viewable.contains_key(&prop.value_id) {
+					continue;
+				}
+				if reached.insert(prop.value_id) {
+					frontier.push(prop.value_id);
+				}
+				edges.push(BlockGraphEdge {
+					parent_id: prop.parent_id,
+					value_id: prop.value_id,
+					property_name: prop.property_name.clone(),
+					annotation: prop.annotation.clone(),
+				});
+			}
+		}
+
+		let mut nodes = vec![];
+		let mut reached: Vec<i64> = reached.into_iter().collect();
+		reached.sort_unstable();
+		for block_id in reached {
+			let block = &viewable[&block_id];
+			nodes.push(BlockGraphNode {
+				block_id,
+				block_type: block.block_type.clone(),
+				name: delegate_block_name(context, &block.block_type, block)?,
+				icon: delegate_block_icon(&block.block_type).map(String::from),
+				color: block.color.clone(),
+			});
+		}
+
+		Ok(BlockGraph { nodes, edges })
+	}
+}
@@ -1,9 +1,23 @@
+pub mod access_requests;
+pub mod audit;
 pub mod basic;
+pub mod batch;
 pub mod block;
 pub mod block_types;
 pub mod breadcrumb;
+pub mod collections;
 pub mod colors;
 pub mod comments;
 pub mod create;
+pub mod data_query;
+pub mod formulas;
+pub mod graph;
+pub mod ownership;
 pub mod perms;
+pub mod properties;
+pub mod references;
+pub mod relations;
+pub mod revisions;
 pub mod search;
+pub mod share_links;
+pub mod tags;
@@ -0,0 +1,292 @@
+use super::block::BlockObject;
+use crate::{graphql::ContextData, users::user::UserObject};
+use async_graphql::*;
+use block_tools::{
+	auth::{require_token, validate_token},
+	dsl::Connection,
+	models::{
+		self, AuditAction, Block, NewAuditEvent, NewNotification, NewOwnershipTransfer,
+		OwnershipTransfer, User,
+	},
+	LoopError, NoAccessSubject, PgConnect, UserError,
+};
+use block_types::delegation::display::delegate_block_name;
+use chrono::{DateTime, Utc};
+use std::time::SystemTime;
+
+/// An offer to make another user the owner of a block
+pub struct OwnershipTransferObject {
+	pub id: i64,
+	pub block_id: i64,
+	pub from_id: i32,
+	pub to_id: i32,
+	pub subtree: bool,
+	pub status: models::TransferStatus,
+	pub created_at: SystemTime,
+	pub decided_at: Option<SystemTime>,
+}
+
+#[Object]
+impl OwnershipTransferObject {
+	async fn id(&self) -> i64 {
+		self.id
+	}
+
+	/// The block being transferred. The recipient can see it even if they
+	/// don't have access to it yet.
+	async fn block(&self, context: &Context<'_>) -> Result<Option<BlockObject>> {
+		let (_, conn) = &ContextData::parse(context)?;
+		Ok(Block::by_id(self.block_id, conn)?.map(BlockObject::from))
+	}
+
+	/// The owner that offered the block
+	async fn from(&self, context: &Context<'_>) -> Result<Option<UserObject>> {
+		let (_, conn) = &ContextData::parse(context)?;
+		Ok(User::by_id(self.from_id, conn)?.map(UserObject::from))
+	}
+
+	/// The user that would become the owner
+	async fn to(&self, context: &Context<'_>) -> Result<Option<UserObject>> {
+		let (_, conn) = &ContextData::parse(context)?;
+		Ok(User::by_id(self.to_id, conn)?.map(UserObject::from))
+	}
+
+	/// Whether the blocks under the block with the same owner are transferred too
+	async fn subtree(&self) -> bool {
+		self.subtree
+	}
+
+	async fn status(&self) -> TransferStatus {
+		self.status.into()
+	}
+
+	async fn created_at(&self) -> DateTime<Utc> {
+		self.created_at.into()
+	}
+
+	async fn decided_at(&self) -> Option<DateTime<Utc>> {
+		self.decided_at.map(DateTime::from)
+	}
+}
+
+impl From<OwnershipTransfer> for OwnershipTransferObject {
+	fn from(transfer: OwnershipTransfer) -> Self {
+		OwnershipTransferObject {
+			status: transfer.status(),
+			id: transfer.id,
+			block_id: transfer.block_id,
+			from_id: transfer.from_id,
+			to_id: transfer.to_id,
+			subtree: transfer.subtree,
+			created_at: transfer.created_at,
+			decided_at: transfer.decided_at,
+		}
+	}
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum TransferStatus {
+	Pending,
+	Accepted,
+	Declined,
+	Cancelled,
+}
+
+impl From<models::TransferStatus> for TransferStatus {
+	fn from(status: models::TransferStatus) -> Self {
+		match status {
+			models::TransferStatus::Pending => TransferStatus::Pending,
+			models::TransferStatus::Accepted => TransferStatus::Accepted,
+			models::TransferStatus::Declined => TransferStatus::Declined,
+			models::TransferStatus::Cancelled => TransferStatus::Cancelled,
+		}
+	}
+}
+
+#[derive(Default)]
+pub struct OwnershipQueries;
+
+#[Object]
+impl OwnershipQueries {
+	/// Transfers offered to the authenticated user that they haven't answered yet
+	async fn ownership_transfers(
+		&self,
+		context: &Context<'_>,
+	) -> Result<Vec<OwnershipTransferObject>> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+
+		Ok(OwnershipTransfer::pending_to(user_id, conn)?
+			.into_iter()
+			.map(OwnershipTransferObject::from)
+			.collect())
+	}
+}
+
+#[derive(Default)]
+pub struct OwnershipMutations;
+
+#[Object]
+impl OwnershipMutations {
+	/// Offers to make another user the owner of a block. Nothing changes until
+	/// they accept, and then the current owner keeps full permissions. Only the
+	/// owner can do this; co-owners can't give the block away.
+	async fn transfer_ownership(
+		&self,
+		context: &Context<'_>,
+		block_id: i64,
+		new_owner_id: i32,
+		#[graphql(
+			default,
+			desc = "Also transfer the blocks under this one that have the same owner"
+		)]
+		subtree: bool,
+	) -> Result<OwnershipTransferObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let block = owned_block(block_id, user_id, conn)?;
+
+		if User::by_id(new_owner_id, conn)?.is_none() {
+			return Err(UserError::IdNonexist(new_owner_id).into());
+		}
+
+		let transfer = NewOwnershipTransfer::new(&block, new_owner_id, subtree).insert(conn)?;
+
+		let user_name = display_name(user_id, conn)?;
+		let block_name = delegate_block_name(context, &block.block_type, &block)?;
+		NewNotification::new(
+			format!("{} wants to give you a block", user_name),
+			format!("{} offered you ownership of \"{}\".", user_name, block_name),
+		)
+		.recipients(vec![new_owner_id])
+		.link(block_id)
+		.send(conn)?;
+
+		Ok(transfer.into())
+	}
+
+	/// Accepts a transfer offered to the authenticated user, making them the owner
+	async fn accept_ownership_transfer(
+		&self,
+		context: &Context<'_>,
+		transfer_id: i64,
+	) -> Result<BlockObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let transfer = pending_transfer(transfer_id, conn)?;
+		if transfer.to_id != user_id {
+			return Err(
+				UserError::NoAccess(NoAccessSubject::TransferOwnership(transfer_id)).into(),
+			);
+		}
+
+		// The block may have changed owners since the transfer was offered
+		let still_owned = matches!(
+			Block::by_id(transfer.block_id, conn)?,
+			Some(block) if block.owner_id == transfer.from_id
+		);
+		if !still_owned {
+			transfer.cancel(conn)?;
+			return Err(UserError::TransferDecided(transfer_id).into());
+		}
+
+		let block = transfer.accept(conn)?;
+
+		let user_name = display_name(user_id, conn)?;
+		NewNotification::new(
+			format!("{} accepted your block", user_name),
+			format!("{} is now the owner of a block you gave them.", user_name),
+		)
+		.recipients(vec![transfer.from_id])
+		.link(block.id)
+		.send(conn)?;
+
+		Ok(block.into())
+	}
+
+	/// Turns down a transfer offered to the authenticated user
+	async fn decline_ownership_transfer(
+		&self,
+		context: &Context<'_>,
+		transfer_id: i64,
+	) -> Result<OwnershipTransferObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let transfer = pending_transfer(transfer_id, conn)?;
+		if transfer.to_id != user_id {
+			return Err(
+				UserError::NoAccess(NoAccessSubject::TransferOwnership(transfer_id)).into(),
+			);
+		}
+
+		Ok(transfer.decline(conn)?.into())
+	}
+
+	/// Takes back a transfer before it is answered. The user must be
+	/// the owner of the block.
+	async fn cancel_ownership_transfer(
+		&self,
+		context: &Context<'_>,
+		transfer_id: i64,
+	) -> Result<OwnershipTransferObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let transfer = pending_transfer(transfer_id, conn)?;
+		owned_block(transfer.block_id, user_id, conn)?;
+
+		Ok(transfer.cancel(conn)?.into())
+	}
+
+	/// Sets the users that can do everything the owner can, including deleting
+	/// the block. Only the owner can change who the co-owners are.
+	async fn set_co_owners(
+		&self,
+		context: &Context<'_>,
+		block_id: i64,
+		co_owners: Vec<i32>,
+	) -> Result<BlockObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let block = owned_block(block_id, user_id, conn)?;
+
+		for co_owner in &co_owners {
+			if User::by_id(*co_owner, conn)?.is_none() {
+				return Err(UserError::IdNonexist(*co_owner).into());
+			}
+		}
+
+		let updated = conn.transaction::<_, LoopError, _>(|| {
+			let updated = block.update_co_owners_by(co_owners, Some(user_id), conn)?;
+			NewAuditEvent::new(AuditAction::CoOwners, &block, &updated, Some(user_id))
+				.insert(conn)?;
+			Ok(updated)
+		})?;
+
+		Ok(updated.into())
+	}
+}
+
+/// Finds a block, but only if the user is its owner. Co-owners have the
+/// owner's permissions on the block, but can't change who owns it.
+fn owned_block(block_id: i64, user_id: i32, conn: &PgConnect) -> Result<Block, Error> {
+	match Block::by_id(block_id, conn)? {
+		Some(block) if block.owner_id == user_id => Ok(block),
+		_ => Err(UserError::NoAccess(NoAccessSubject::UpdatePermissions(block_id)).into()),
+	}
+}
+
+/// Finds a transfer that hasn't been answered yet
+fn pending_transfer(transfer_id: i64, conn: &PgConnect) -> Result<OwnershipTransfer, Error> {
+	match OwnershipTransfer::by_id(transfer_id, conn)? {
+		Some(transfer) if transfer.status() == models::TransferStatus::Pending => Ok(transfer),
+		Some(_) => Err(UserError::TransferDecided(transfer_id).into()),
+		None => Err(UserError::NoAccess(NoAccessSubject::TransferOwnership(transfer_id)).into()),
+	}
+}
+
+/// The user's display name, or their username if they don't have one
+fn display_name(user_id: i32, conn: &PgConnect) -> Result<String, Error> {
+	Ok(User::by_id(user_id, conn)?
+		.map(|user| user.display_name.unwrap_or(user.username))
+		.unwrap_or_default())
+}
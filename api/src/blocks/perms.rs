@@ -6,13 +6,18 @@ use block_tools::{
 		permissions::{has_perm_level, PermLevel},
 		require_token, validate_token,
 	},
-	models::{Block, NewNotification, User},
-	NoAccessSubject, UserError,
+	blocks::Context as ToolsContext,
+	dsl::Connection,
+	models::{AuditAction, Block, NewAuditEvent, NewNotification, Team, User},
+	LoopError, NoAccessSubject, UserError,
 };
 use block_types::delegation::{
 	display::delegate_block_name,
-	methods::{delegate_general_perm_update, delegate_visibility_update},
+	methods::{
+		delegate_comment_perm_update, delegate_general_perm_update, delegate_visibility_update,
+	},
 };
+use std::collections::HashMap;
 
 #[derive(Default)]
 pub struct BlockPermMutations;
@@ -43,7 +48,13 @@ impl BlockPermMutations {
 			return Err(access_err);
 		}
 
-		let block = block.update_public(public, conn)?;
+		let before = block;
+		let block = conn.transaction::<_, LoopError, _>(|| {
+			let block = before.update_public_by(public, Some(user_id), conn)?;
+			NewAuditEvent::new(AuditAction::Visibility, &before, &block, Some(user_id))
+				.insert(conn)?;
+			Ok(block)
+		})?;
 
 		delegate_visibility_update(context, &block.block_type, block.id, public)?;
 
@@ -70,14 +81,42 @@ impl BlockPermMutations {
 		Ok(block.into())
 	}
 
-	/// Set the permissions for the users with full permissions, edit permissions, and view permissions.
+	/// Set the permissions for the users with full permissions, edit permissions, comment permissions,
+	/// and view permissions. Users that get a higher level than before are notified. Every user ID
+	/// has to exist.
 	pub async fn set_perms(
 		&self,
 		context: &Context<'_>,
 		#[graphql(default)] perm_full: Vec<i32>,
 		#[graphql(default)] perm_edit: Vec<i32>,
+		#[graphql(default)] perm_comment: Vec<i32>,
 		#[graphql(default)] perm_view: Vec<i32>,
 		block_id: i64,
+		#[graphql(default, desc = "Also notify the users that lose their access")]
+		notify_removed: bool,
+	) -> Result<BlockObject, Error> {
+		let context = &context.data::<ContextData>()?.other();
+
+		let user_id = validate_token(&require_token(context)?)?;
+
+		let perms = PermLists {
+			full: perm_full,
+			edit: perm_edit,
+			comment: perm_comment,
+			view: perm_view,
+		};
+		Ok(set_block_perms(context, user_id, block_id, perms, notify_removed)?.into())
+	}
+
+	/// Turns inheriting permissions from the block's parents on or off. While it
+	/// is on, anyone with access to a parent has at least the same access to the
+	/// block, on top of the block's own permissions. The user must have full
+	/// permissions or higher.
+	pub async fn set_perm_inheritance(
+		&self,
+		context: &Context<'_>,
+		block_id: i64,
+		inherit: bool,
 	) -> Result<BlockObject, Error> {
 		let (context, conn) = &ContextData::parse(context)?;
 
@@ -95,38 +134,306 @@ impl BlockPermMutations {
 			return Err(access_err);
 		}
 
-		let block = block.update_perms(
-			perm_full.clone(),
-			perm_edit.clone(),
-			perm_view.clone(),
-			conn,
-		)?;
-
-		delegate_general_perm_update(
-			context,
-			&block.block_type,
-			block.id,
-			perm_full,
-			perm_edit,
-			perm_view,
-		)?;
-
-		// If the user is not the owner, send a notification
-		if user_id != block.owner_id {
-			let user_name = User::by_id(user_id, conn)?
-				.and_then(|user| user.display_name.or(Some(user.username)))
-				.unwrap();
-			let block_name = delegate_block_name(context, &block.block_type, &block)?;
+		let updated = conn.transaction::<_, LoopError, _>(|| {
+			let updated = block.set_inherit_perms(inherit, conn)?;
+			NewAuditEvent::new(AuditAction::Inheritance, &block, &updated, Some(user_id))
+				.insert(conn)?;
+			Ok(updated)
+		})?;
 
-			let notif = NewNotification::new(
-				format!("{} updated the permissions of your block", user_name),
-				format!("{} updated \"{}\".", user_name, block_name),
+		Ok(updated.into())
+	}
+
+	/// Sets which teams have full, edit, comment and view permissions on the block. The
+	/// user must have full permissions or higher, and be a member of every team
+	/// they add.
+	pub async fn set_team_perms(
+		&self,
+		context: &Context<'_>,
+		#[graphql(default)] team_full: Vec<i64>,
+		#[graphql(default)] team_edit: Vec<i64>,
+		#[graphql(default)] team_comment: Vec<i64>,
+		#[graphql(default)] team_view: Vec<i64>,
+		block_id: i64,
+	) -> Result<BlockObject, Error> {
+		let (context, conn) = &ContextData::parse(context)?;
+
+		let user_id = validate_token(&require_token(context)?)?;
+
+		let access_err: Error =
+			UserError::NoAccess(NoAccessSubject::UpdatePermissions(block_id)).into();
+
+		let block = match Block::by_id(block_id, conn)? {
+			Some(block) => block,
+			None => return Err(access_err),
+		};
+
+		if !has_perm_level(user_id, &block, PermLevel::Full) {
+			return Err(access_err);
+		}
+
+		// Teams that already had access can stay without the user being in them
+		let already_shared: Vec<i64> = block
+			.team_full
+			.iter()
+			.chain(&block.team_edit)
+			.chain(&block.team_comment)
+			.chain(&block.team_view)
+			.copied()
+			.collect();
+		let new_teams = team_full
+			.iter()
+			.chain(&team_edit)
+			.chain(&team_comment)
+			.chain(&team_view);
+		for team_id in new_teams {
+			if already_shared.contains(team_id) {
+				continue;
+			}
+			let is_member = match Team::by_id(*team_id, conn)? {
+				Some(team) => team.role_of(user_id, conn)?.is_some(),
+				None => false,
+			};
+			if !is_member {
+				return Err(UserError::NoAccess(NoAccessSubject::ViewTeam(*team_id)).into());
+			}
+		}
+
+		let updated = conn.transaction::<_, LoopError, _>(|| {
+			let updated = block.update_team_perms_by(
+				team_full,
+				team_edit,
+				team_comment,
+				team_view,
+				Some(user_id),
+				conn,
+			)?;
+			NewAuditEvent::new(
+				AuditAction::TeamPermissions,
+				&block,
+				&updated,
+				Some(user_id),
 			)
-			.recipients(vec![block.owner_id])
-			.link(block_id);
-			notif.send(conn)?;
+			.insert(conn)?;
+			Ok(updated)
+		})?;
+
+		Ok(updated.into())
+	}
+}
+
+/// The users with each level on a block, as they are set together
+pub struct PermLists {
+	pub full: Vec<i32>,
+	pub edit: Vec<i32>,
+	pub comment: Vec<i32>,
+	pub view: Vec<i32>,
+}
+
+impl PermLists {
+	pub fn of_block(block: &Block) -> Self {
+		PermLists {
+			full: block.perm_full.clone(),
+			edit: block.perm_edit.clone(),
+			comment: block.perm_comment.clone(),
+			view: block.perm_view.clone(),
+		}
+	}
+
+	/// The highest level each user in the lists has
+	fn levels(&self) -> HashMap<i32, PermLevel> {
+		let mut levels = HashMap::new();
+		let lists = [
+			(&self.view, PermLevel::View),
+			(&self.comment, PermLevel::Comment),
+			(&self.edit, PermLevel::Edit),
+			(&self.full, PermLevel::Full),
+		];
+		for (ids, level) in lists.iter() {
+			for id in ids.iter() {
+				levels.insert(*id, *level);
+			}
 		}
+		levels
+	}
 
-		Ok(block.into())
+	fn ids(&self) -> Vec<i32> {
+		let mut ids: Vec<i32> = self.levels().keys().copied().collect();
+		ids.sort_unstable();
+		ids
+	}
+}
+
+/// Compares the lists before and after a change. Returns the users that got a
+/// higher level than before (with their new level), and the users that lost
+/// all of their access, both ordered by ID.
+pub fn access_changes(old: &PermLists, new: &PermLists) -> (Vec<(i32, PermLevel)>, Vec<i32>) {
+	let old_levels = old.levels();
+	let new_levels = new.levels();
+
+	let mut granted: Vec<(i32, PermLevel)> = new_levels
+		.iter()
+		.filter(|(id, level)| old_levels.get(*id).map_or(true, |old| old < *level))
+		.map(|(id, level)| (*id, *level))
+		.collect();
+	granted.sort_unstable();
+
+	let mut removed: Vec<i32> = old_levels
+		.keys()
+		.filter(|id| !new_levels.contains_key(*id))
+		.copied()
+		.collect();
+	removed.sort_unstable();
+
+	(granted, removed)
+}
+
+/// Sets the permission lists of a block, if the user has full access to it. Every
+/// user that gets a higher level is notified, and so are users that lose their
+/// access if `notify_removed` is true. The connection is only held while
+/// querying, so that block types can use their own.
+pub fn set_block_perms(
+	context: &ToolsContext,
+	user_id: i32,
+	block_id: i64,
+	perms: PermLists,
+	notify_removed: bool,
+) -> Result<Block, Error> {
+	let access_err: Error =
+		UserError::NoAccess(NoAccessSubject::UpdatePermissions(block_id)).into();
+
+	let block = match Block::by_id(block_id, &context.shared_conn()?)? {
+		Some(block) => block,
+		None => return Err(access_err),
+	};
+
+	if !has_perm_level(user_id, &block, PermLevel::Full) {
+		return Err(access_err);
+	}
+
+	let ids = perms.ids();
+	let existing = User::existing_ids(&ids, &context.shared_conn()?)?;
+	if let Some(missing) = ids.into_iter().find(|id| !existing.contains(id)) {
+		return Err(UserError::IdNonexist(missing).into());
+	}
+
+	let before = block;
+	let block = {
+		let conn = &context.shared_conn()?;
+		conn.transaction::<_, LoopError, _>(|| {
+			let block = before.update_perms_by(
+				perms.full.clone(),
+				perms.edit.clone(),
+				perms.comment.clone(),
+				perms.view.clone(),
+				Some(user_id),
+				conn,
+			)?;
+			NewAuditEvent::new(AuditAction::Permissions, &before, &block, Some(user_id))
+				.insert(conn)?;
+			Ok(block)
+		})?
+	};
+
+	delegate_general_perm_update(
+		context,
+		&block.block_type,
+		block.id,
+		perms.full.clone(),
+		perms.edit.clone(),
+		perms.view.clone(),
+	)?;
+	delegate_comment_perm_update(context, &block.block_type, block.id, perms.comment.clone())?;
+
+	let user_name = User::by_id(user_id, &context.shared_conn()?)?
+		.and_then(|user| user.display_name.or(Some(user.username)))
+		.unwrap();
+	let block_name = delegate_block_name(context, &block.block_type, &block)?;
+
+	// If the user is not the owner, send a notification
+	if user_id != block.owner_id {
+		let notif = NewNotification::new(
+			format!("{} updated the permissions of your block", user_name),
+			format!("{} updated \"{}\".", user_name, block_name),
+		)
+		.recipients(vec![block.owner_id])
+		.link(block_id);
+		notif.send(&context.shared_conn()?)?;
+	}
+
+	let (granted, removed) = access_changes(&PermLists::of_block(&before), &perms);
+	for (granted_id, level) in granted {
+		if granted_id == user_id {
+			continue;
+		}
+		NewNotification::new(
+			format!("{} gave you {} access", user_name, level.as_str()),
+			format!(
+				"{} gave you {} access to \"{}\".",
+				user_name,
+				level.as_str(),
+				block_name
+			),
+		)
+		.recipients(vec![granted_id])
+		.link(block_id)
+		.send(&context.shared_conn()?)?;
+	}
+	let removed: Vec<i32> = removed.into_iter().filter(|id| *id != user_id).collect();
+	if notify_removed && !removed.is_empty() {
+		NewNotification::new(
+			format!("{} removed your access", user_name),
+			format!("{} removed your access to \"{}\".", user_name, block_name),
+		)
+		.recipients(removed)
+		.send(&context.shared_conn()?)?;
+	}
+
+	Ok(block)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn lists(full: &[i32], edit: &[i32], comment: &[i32], view: &[i32]) -> PermLists {
+		PermLists {
+			full: full.to_vec(),
+			edit: edit.to_vec(),
+			comment: comment.to_vec(),
+			view: view.to_vec(),
+		}
+	}
+
+	#[test]
+	fn new_users_are_granted() {
+		let (granted, removed) =
+			access_changes(&lists(&[], &[], &[], &[1]), &lists(&[2], &[], &[3], &[1]));
+		assert_eq!(granted, vec![(2, PermLevel::Full), (3, PermLevel::Comment)]);
+		assert!(removed.is_empty());
+	}
+
+	#[test]
+	fn upgrades_are_granted_but_downgrades_are_not() {
+		let (granted, removed) =
+			access_changes(&lists(&[1], &[], &[], &[2]), &lists(&[], &[2], &[], &[1]));
+		assert_eq!(granted, vec![(2, PermLevel::Edit)]);
+		assert!(removed.is_empty());
+	}
+
+	#[test]
+	fn highest_level_counts() {
+		// Being in a lower list as well doesn't make it a change
+		let (granted, _) =
+			access_changes(&lists(&[1], &[], &[], &[]), &lists(&[1], &[], &[], &[1]));
+		assert!(granted.is_empty());
+	}
+
+	#[test]
+	fn removed_users() {
+		let (granted, removed) =
+			access_changes(&lists(&[3], &[1], &[], &[2]), &lists(&[3], &[], &[], &[]));
+		assert!(granted.is_empty());
+		assert_eq!(removed, vec![1, 2]);
 	}
 }
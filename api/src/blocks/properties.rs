@@ -0,0 +1,159 @@
+use super::block::BlockObject;
+use crate::graphql::ContextData;
+use async_graphql::*;
+use block_tools::{
+	auth::{
+		permissions::{has_perm_level, PermLevel},
+		require_token, validate_token,
+	},
+	models::{Block, InlineProperty, InlineValue, Property},
+	BlockError, NoAccessSubject, UserError,
+};
+use chrono::{DateTime, Utc};
+
+#[derive(SimpleObject, Clone)]
+/// A link from a block to one of its children
+pub struct PropertyObject {
+	pub id: i64,
+	/// The name of the property, like `item`
+	pub name: String,
+	/// The ID of the block that has the property
+	pub parent_id: i64,
+	/// The ID of the child block
+	pub value_id: i64,
+	pub annotation: Option<String>,
+	/// Sorts the property among its siblings
+	pub position: Option<String>,
+}
+
+impl From<Property> for PropertyObject {
+	fn from(prop: Property) -> Self {
+		PropertyObject {
+			id: prop.id,
+			name: prop.property_name,
+			parent_id: prop.parent_id,
+			value_id: prop.value_id,
+			annotation: prop.annotation,
+			position: prop.position,
+		}
+	}
+}
+
+#[derive(SimpleObject, Clone)]
+/// A property with a primitive value stored right on the block. Only
+/// the field matching its type is set.
+pub struct InlinePropertyObject {
+	pub name: String,
+	/// One of `text`, `number`, `boolean`, `timestamp` or `user`
+	pub value_type: String,
+	pub text: Option<String>,
+	pub number: Option<f64>,
+	pub boolean: Option<bool>,
+	pub timestamp: Option<DateTime<Utc>>,
+	pub user_id: Option<i32>,
+}
+
+impl From<InlineProperty> for InlinePropertyObject {
+	fn from(prop: InlineProperty) -> Self {
+		InlinePropertyObject {
+			name: prop.property_name,
+			value_type: prop.value_type,
+			text: prop.text_value,
+			number: prop.number_value,
+			boolean: prop.bool_value,
+			timestamp: prop.time_value.map(DateTime::from),
+			user_id: prop.user_value,
+		}
+	}
+}
+
+#[derive(InputObject)]
+/// A primitive value for a property. Exactly one field should be provided.
+pub struct InlineValueInput {
+	text: Option<String>,
+	number: Option<f64>,
+	boolean: Option<bool>,
+	timestamp: Option<DateTime<Utc>>,
+	user_id: Option<i32>,
+}
+
+impl InlineValueInput {
+	fn value(self) -> Option<InlineValue> {
+		let values = vec![
+			self.text.map(InlineValue::Text),
+			self.number.map(InlineValue::Number),
+			self.boolean.map(InlineValue::Boolean),
+			self.timestamp
+				.map(|timestamp| InlineValue::Timestamp(timestamp.into())),
+			self.user_id.map(InlineValue::User),
+		];
+		let mut values = values.into_iter().flatten();
+		match (values.next(), values.next()) {
+			(Some(value), None) => Some(value),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Default)]
+pub struct PropertyMutations;
+
+#[Object]
+impl PropertyMutations {
+	/// Changes the order of a block's properties. The properties listed go
+	/// first in the order given, and the rest keep their order after them.
+	async fn reorder_properties(
+		&self,
+		context: &Context<'_>,
+		parent_id: i64,
+		property_ids: Vec<i64>,
+	) -> Result<BlockObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+
+		let access_err: Error = UserError::NoAccess(NoAccessSubject::EditParents(parent_id)).into();
+		let parent = match Block::by_id(parent_id, conn)? {
+			Some(parent) => parent,
+			None => return Err(access_err),
+		};
+		if !has_perm_level(user_id, &parent, PermLevel::Edit) {
+			return Err(access_err);
+		}
+
+		Property::reorder(parent_id, &property_ids, Some(user_id), conn)?;
+
+		Ok(parent.into())
+	}
+
+	/// Sets a property with a primitive value on a block, or removes it if no value is provided
+	async fn set_inline_value(
+		&self,
+		context: &Context<'_>,
+		block_id: i64,
+		name: String,
+		value: Option<InlineValueInput>,
+	) -> Result<BlockObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+
+		let access_err: Error = UserError::NoAccess(NoAccessSubject::EditParents(block_id)).into();
+		let block = match Block::by_id(block_id, conn)? {
+			Some(block) => block,
+			None => return Err(access_err),
+		};
+		if !has_perm_level(user_id, &block, PermLevel::Edit) {
+			return Err(access_err);
+		}
+
+		let value = match value {
+			Some(input) => match input.value() {
+				Some(value) => Some(value),
+				None => return Err(BlockError::InputParse.into()),
+			},
+			None => None,
+		};
+		block.set_inline_value(&name, value, Some(user_id), conn)?;
+
+		Ok(block.into())
+	}
+}
@@ -0,0 +1,26 @@
+use async_graphql::*;
+use block_tools::models::BlockReference;
+
+#[derive(SimpleObject, Clone)]
+/// A link from inside a block's content to another block
+pub struct BlockReferenceObject {
+	/// The ID of the block that has the link in its content
+	pub source_id: i64,
+	/// The ID of the block that is linked to
+	pub target_id: i64,
+	/// The text that is shown for the link
+	pub link_text: Option<String>,
+	/// True if the linked block was deleted, or renamed since the link was made
+	pub dangling: bool,
+}
+
+impl From<BlockReference> for BlockReferenceObject {
+	fn from(reference: BlockReference) -> Self {
+		BlockReferenceObject {
+			source_id: reference.source_id,
+			target_id: reference.target_id,
+			link_text: reference.link_text,
+			dangling: reference.dangling,
+		}
+	}
+}
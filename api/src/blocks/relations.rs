@@ -0,0 +1,238 @@
+use super::block::BlockObject;
+use crate::graphql::ContextData;
+use async_graphql::*;
+use block_tools::{
+	auth::{
+		permissions::{can_view, has_perm_level, PermLevel},
+		require_token, validate_token,
+	},
+	models::{Block, BlockRelation, NewBlockRelation, NewRelationType, RelationType},
+	NoAccessSubject, PgConnect, UserError,
+};
+
+#[derive(SimpleObject, Clone)]
+/// A kind of link between blocks, with a name for each direction
+pub struct RelationTypeObject {
+	pub id: i64,
+	/// What the source block is to the target, like "blocks"
+	pub name: String,
+	/// What the target block is to the source, like "blocked by"
+	pub inverse_name: String,
+	/// Built in relation types can be used by everyone
+	pub built_in: bool,
+}
+
+impl From<RelationType> for RelationTypeObject {
+	fn from(relation_type: RelationType) -> Self {
+		RelationTypeObject {
+			id: relation_type.id,
+			name: relation_type.name,
+			inverse_name: relation_type.inverse_name,
+			built_in: relation_type.owner_id.is_none(),
+		}
+	}
+}
+
+/// A relation seen from one of its blocks
+pub struct RelationObject {
+	pub id: i64,
+	/// The name of the relation from this side, like "blocked by"
+	pub name: String,
+	pub relation_type: RelationType,
+	pub source_id: i64,
+	pub target_id: i64,
+	/// The block on the other side of the relation
+	pub other: Block,
+}
+
+#[Object]
+impl RelationObject {
+	/// A unique identifier for the relation
+	async fn id(&self) -> i64 {
+		self.id
+	}
+
+	/// What the other block is to this one, like "blocked by"
+	async fn name(&self) -> String {
+		self.name.clone()
+	}
+
+	async fn relation_type(&self) -> RelationTypeObject {
+		self.relation_type.clone().into()
+	}
+
+	/// The block the relation starts from
+	async fn source_id(&self) -> i64 {
+		self.source_id
+	}
+
+	/// The block the relation points to
+	async fn target_id(&self) -> i64 {
+		self.target_id
+	}
+
+	/// The block on the other side of the relation
+	async fn block(&self) -> BlockObject {
+		self.other.clone().into()
+	}
+}
+
+/// Gets the relations on either side of a block, leaving out the ones
+/// whose other block the user can't view
+pub fn relations_of(
+	block_id: i64,
+	inverse: bool,
+	user_id: Option<i32>,
+	conn: &PgConnect,
+) -> Result<Vec<RelationObject>, Error> {
+	let relations = if inverse {
+		BlockRelation::to_block(block_id, conn)?
+	} else {
+		BlockRelation::from_block(block_id, conn)?
+	};
+
+	let mut objects = vec![];
+	for relation in relations {
+		let other_id = if inverse {
+			relation.source_id
+		} else {
+			relation.target_id
+		};
+		let other = match Block::by_id(other_id, conn)? {
+			Some(other) if can_view(user_id, &other) => other,
+			_ => continue,
+		};
+		let relation_type = match RelationType::by_id(relation.relation_type_id, conn)? {
+			Some(relation_type) => relation_type,
+			None => continue,
+		};
+		objects.push(RelationObject {
+			id: relation.id,
+			name: if inverse {
+				relation_type.inverse_name.clone()
+			} else {
+				relation_type.name.clone()
+			},
+			relation_type,
+			source_id: relation.source_id,
+			target_id: relation.target_id,
+			other,
+		});
+	}
+	Ok(objects)
+}
+
+#[derive(Default)]
+pub struct RelationQueries;
+
+#[Object]
+impl RelationQueries {
+	/// The relation types the user can use, including the built in ones
+	async fn relation_types(&self, context: &Context<'_>) -> Result<Vec<RelationTypeObject>> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+
+		Ok(RelationType::available_to(user_id, conn)?
+			.into_iter()
+			.map(RelationTypeObject::from)
+			.collect())
+	}
+}
+
+#[derive(Default)]
+pub struct RelationMutations;
+
+#[Object]
+impl RelationMutations {
+	/// Makes a new relation type that only the user can use
+	async fn create_relation_type(
+		&self,
+		context: &Context<'_>,
+		#[graphql(desc = "What the source is to the target, like \"blocks\"")] name: String,
+		#[graphql(desc = "What the target is to the source, like \"blocked by\"")]
+		inverse_name: String,
+	) -> Result<RelationTypeObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+
+		if RelationType::by_name(user_id, &name, conn)?.is_some() {
+			return Err(UserError::RelationTypeConflict(name).into());
+		}
+
+		Ok(NewRelationType::new(user_id, name, inverse_name)
+			.insert(conn)?
+			.into())
+	}
+
+	/// Deletes one of the user's relation types, along with every relation of that type
+	async fn delete_relation_type(&self, context: &Context<'_>, type_id: i64) -> Result<i64> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+
+		match RelationType::by_id(type_id, conn)? {
+			Some(relation_type) if relation_type.owner_id == Some(user_id) => {
+				relation_type.delete(conn)?
+			}
+			_ => {
+				return Err(UserError::NoAccess(NoAccessSubject::EditRelationType(type_id)).into())
+			}
+		}
+
+		Ok(type_id)
+	}
+
+	/// Relates one block to another. Needs edit access to the source
+	/// and view access to the target.
+	async fn add_relation(
+		&self,
+		context: &Context<'_>,
+		source_id: i64,
+		target_id: i64,
+		type_id: i64,
+	) -> Result<BlockObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+
+		match RelationType::by_id(type_id, conn)? {
+			Some(relation_type) if relation_type.usable_by(user_id) => {}
+			_ => {
+				return Err(UserError::NoAccess(NoAccessSubject::EditRelationType(type_id)).into())
+			}
+		}
+		let source = editable_source(source_id, user_id, conn)?;
+		let access_err: Error = UserError::NoAccess(NoAccessSubject::ViewBlock(target_id)).into();
+		match Block::by_id(target_id, conn)? {
+			Some(target) if can_view(Some(user_id), &target) => {}
+			_ => return Err(access_err),
+		}
+
+		NewBlockRelation {
+			author_id: Some(user_id),
+			..NewBlockRelation::new(type_id, source_id, target_id)
+		}
+		.insert(conn)?;
+
+		Ok(source.into())
+	}
+
+	/// Removes a relation. Needs edit access to its source block.
+	async fn remove_relation(&self, context: &Context<'_>, relation_id: i64) -> Result<i64> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+
+		if let Some(relation) = BlockRelation::by_id(relation_id, conn)? {
+			editable_source(relation.source_id, user_id, conn)?;
+			relation.delete(conn)?;
+		}
+
+		Ok(relation_id)
+	}
+}
+
+/// Finds the source block of a relation, but only if the user can edit it
+fn editable_source(source_id: i64, user_id: i32, conn: &PgConnect) -> Result<Block, Error> {
+	match Block::by_id(source_id, conn)? {
+		Some(source) if has_perm_level(user_id, &source, PermLevel::Edit) => Ok(source),
+		_ => Err(UserError::NoAccess(NoAccessSubject::EditRelations(source_id)).into()),
+	}
+}
@@ -0,0 +1,205 @@
+use super::{block::BlockObject, search::index_block};
+use crate::{graphql::ContextData, users::user::UserObject};
+use async_graphql::*;
+use block_tools::{
+	auth::{
+		optional_token, optional_validate_token,
+		permissions::{can_view, has_perm_level, PermLevel},
+		require_token, validate_token,
+	},
+	dsl::Connection,
+	models::{
+		AuditAction, Block, BlockRevision, NewAuditEvent, PermSnapshot, RevisionFieldChange, User,
+	},
+	LoopError, NoAccessSubject, UserError,
+};
+use chrono::{DateTime, Utc};
+use std::time::SystemTime;
+
+/// A snapshot of a block, saved whenever its data, color, visibility,
+/// permissions or properties were changed
+pub struct RevisionObject {
+	pub id: i64,
+	pub block_id: i64,
+	pub author_id: Option<i32>,
+	pub created_at: SystemTime,
+	pub change: String,
+	pub block_data: Option<String>,
+	pub color: Option<String>,
+	pub public: bool,
+}
+
+#[Object]
+impl RevisionObject {
+	/// A unique identifier for the revision
+	async fn id(&self) -> i64 {
+		self.id
+	}
+
+	/// The ID of the block this is a revision of
+	async fn block_id(&self) -> i64 {
+		self.block_id
+	}
+
+	/// When the change was made
+	async fn created_at(&self) -> DateTime<Utc> {
+		self.created_at.into()
+	}
+
+	/// What kind of change created this revision, such as `data` or `permissions`
+	async fn change(&self) -> String {
+		self.change.clone()
+	}
+
+	/// The block's data at the time of the revision
+	async fn data(&self) -> Option<String> {
+		self.block_data.clone()
+	}
+
+	/// The block's color at the time of the revision
+	async fn color(&self) -> Option<String> {
+		self.color.clone()
+	}
+
+	/// Whether the block was public at the time of the revision
+	async fn public(&self) -> bool {
+		self.public
+	}
+
+	/// The user that made the change. Null if it is unknown.
+	async fn author(&self, context: &Context<'_>) -> Result<Option<UserObject>> {
+		let author_id = match self.author_id {
+			Some(id) => id,
+			None => return Ok(None),
+		};
+		let (_, conn) = &ContextData::parse(context)?;
+		Ok(User::by_id(author_id, conn)?.map(UserObject::from))
+	}
+}
+
+impl From<BlockRevision> for RevisionObject {
+	fn from(revision: BlockRevision) -> Self {
+		RevisionObject {
+			id: revision.id,
+			block_id: revision.block_id,
+			author_id: revision.author_id,
+			created_at: revision.created_at,
+			change: revision.change,
+			block_data: revision.block_data,
+			color: revision.color,
+			public: revision.public,
+		}
+	}
+}
+
+#[derive(SimpleObject)]
+/// One field that is different between two revisions
+pub struct RevisionChangeObject {
+	/// The name of the field, like `data`, `color` or `property`
+	pub field: String,
+	/// The value in the older revision (null if it was added)
+	pub old: Option<String>,
+	/// The value in the newer revision (null if it was removed)
+	pub new: Option<String>,
+}
+
+impl From<RevisionFieldChange> for RevisionChangeObject {
+	fn from(change: RevisionFieldChange) -> Self {
+		RevisionChangeObject {
+			field: change.field,
+			old: change.old,
+			new: change.new,
+		}
+	}
+}
+
+#[derive(Default)]
+pub struct BlockRevisionQueries;
+
+#[Object]
+impl BlockRevisionQueries {
+	/// Lists the fields that changed between two revisions of the same block.
+	/// Both revisions have to exist and be of the same block.
+	async fn revision_diff(
+		&self,
+		context: &Context<'_>,
+		#[graphql(desc = "ID of the older revision")] from_id: i64,
+		#[graphql(desc = "ID of the newer revision")] to_id: i64,
+	) -> Result<Vec<RevisionChangeObject>, Error> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = optional_validate_token(optional_token(context))?;
+
+		let from =
+			BlockRevision::by_id(from_id, conn)?.ok_or(UserError::RevisionNonexist(from_id))?;
+
+		let access_err: Error =
+			UserError::NoAccess(NoAccessSubject::ViewBlock(from.block_id)).into();
+		match Block::by_id(from.block_id, conn)? {
+			Some(block) if can_view(user_id, &block) => {}
+			_ => return Err(access_err),
+		};
+
+		let to = BlockRevision::by_id(to_id, conn)?.ok_or(UserError::RevisionNonexist(to_id))?;
+		if to.block_id != from.block_id {
+			return Err(UserError::RevisionMismatch(from_id, to_id).into());
+		}
+
+		Ok(from
+			.diff(&to)
+			.into_iter()
+			.map(RevisionChangeObject::from)
+			.collect())
+	}
+}
+
+#[derive(Default)]
+pub struct BlockRevisionMutations;
+
+#[Object]
+impl BlockRevisionMutations {
+	/// Restores a block to how it was at a revision. The user must have edit
+	/// access to the block, and visibility and permissions are only restored
+	/// for users with full access.
+	async fn revert_block(
+		&self,
+		context: &Context<'_>,
+		block_id: i64,
+		revision_id: i64,
+	) -> Result<BlockObject, Error> {
+		let (context, conn) = &ContextData::parse(context)?;
+
+		let user_id = validate_token(&require_token(context)?)?;
+
+		let access_err: Error = UserError::NoAccess(NoAccessSubject::RevertBlock(block_id)).into();
+
+		let block = match Block::by_id(block_id, conn)? {
+			Some(block) => block,
+			None => return Err(access_err),
+		};
+
+		if !has_perm_level(user_id, &block, PermLevel::Edit) {
+			return Err(access_err);
+		}
+
+		let revision = match BlockRevision::by_id(revision_id, conn)? {
+			Some(revision) if revision.block_id == block.id => revision,
+			_ => return Err(access_err),
+		};
+
+		let restore_perms = has_perm_level(user_id, &block, PermLevel::Full);
+		let before = block;
+		let block = conn.transaction::<_, LoopError, _>(|| {
+			let block = before.revert_to(&revision, restore_perms, Some(user_id), conn)?;
+			let perms_changed = before.public != block.public
+				|| PermSnapshot::from(&before) != PermSnapshot::from(&block);
+			if restore_perms && perms_changed {
+				NewAuditEvent::new(AuditAction::Revert, &before, &block, Some(user_id))
+					.insert(conn)?;
+			}
+			Ok(block)
+		})?;
+		index_block(context, &block)?;
+
+		Ok(block.into())
+	}
+}
@@ -1,18 +1,27 @@
 use super::{
+	block::PermLevel,
 	block_types::{type_list, BlockType},
-	breadcrumb::{gen_breadcrumb, BreadCrumb},
+	breadcrumb::{Ancestry, BreadCrumb},
+	collections::notify_collections,
+};
+use crate::graphql::{
+	pagination::{connection, ListConnection, PageArgs},
+	ContextData,
 };
-use crate::graphql::ContextData;
 use async_graphql::{Context, Enum, Error, InputObject, Object, SimpleObject};
 use block_tools::{
-	auth::{optional_token, optional_validate_token, permissions::can_view},
+	auth::{optional_token, optional_validate_token, require_token, validate_token},
+	blocks::Context as ToolsContext,
 	dsl::prelude::*,
-	models::Block,
+	models::{
+		Block, BlockSearch, SearchFilters, SearchSort, Snippet, TagMatch as ToolsTagMatch, User,
+	},
 	schema::blocks,
+	NoAccessSubject, UserError,
 };
 use block_types::delegation::display::{delegate_block_icon, delegate_block_name};
-use std::time::SystemTime;
-use strsim::normalized_levenshtein;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
 
 #[derive(Default)]
 pub struct BlockSearchQueries;
@@ -24,9 +33,10 @@ impl BlockSearchQueries {
 		type_list()
 	}
 
-	/// Finds blocks that are similar to the query provided. Matches against
-	/// block breadcrumbs and sorts them by similarity. Does not include `data` blocks
-	/// by default.
+	/// Finds blocks that match the query provided. Matches against block names,
+	/// the text inside of them and their comments, and sorts them by how well
+	/// they match. Words in double quotes only match as a phrase. Does not
+	/// include `data` blocks by default. Results are paged like a Relay connection.
 	async fn search_blocks(
 		&self,
 		context: &Context<'_>,
@@ -34,115 +44,100 @@ impl BlockSearchQueries {
 		filters: Option<BlockSearchFilters>,
 		#[graphql(desc = "Include data blocks with results?")] with_data: Option<bool>,
 		sort_by: Option<BlockSortType>,
-	) -> Result<Vec<BlockResult>, Error> {
+		after: Option<String>,
+		before: Option<String>,
+		first: Option<i32>,
+		last: Option<i32>,
+	) -> Result<ListConnection<BlockResult>, Error> {
 		let (context, conn) = &ContextData::parse(context)?;
 
 		let user_id = optional_validate_token(optional_token(context))?;
-		let with_data = with_data.unwrap_or_default();
-		let sort_by = sort_by.unwrap_or_default();
+		let filters: SearchFilters = filters.unwrap_or_default().into();
+		let page = PageArgs {
+			after,
+			before,
+			first,
+			last,
+		}
+		.query()?;
 
-		let mut helpers = blocks::dsl::blocks
-			.load::<Block>(conn)?
+		let search = BlockSearch {
+			with_data: with_data.unwrap_or_default(),
+			sort: sort_by.unwrap_or_default().into(),
+			..BlockSearch::filtered(query, user_id, user_id, &filters)
+		};
+		let mut hits = Block::search(&search, &page, conn)?;
+
+		let ids: Vec<i64> = hits.items.iter().map(|hit| hit.id).collect();
+		let mut blocks: HashMap<i64, Block> = Block::by_ids(&ids, conn)?
 			.into_iter()
-			// Only blocks user has access to
-			.filter(|block| {
-				if !with_data && block.block_type == "data" {
-					return false;
-				}
-				if let Some(filters) = &filters {
-					if let Some(block_type) = &filters.block_type {
-						if &block.block_type != block_type {
-							return false;
-						}
-					}
-					if let Some(owner_id) = filters.owner_id {
-						if block.owner_id != owner_id {
-							return false;
-						}
-					}
-					if let Some(user_id) = user_id {
-						if let Some(only_show_starred) = filters.starred {
-							if only_show_starred && !block.stars.contains(&user_id) {
-								return false;
-							}
-						}
-					}
-				}
-				can_view(user_id, block)
-			})
-			.map(|block| {
-				let name =
-					delegate_block_name(context, &block.block_type, &block).unwrap_or_default();
-				let mut sim = normalized_levenshtein(&name, &query);
-
-				// If the block is data, make it less influencial
-				if block.block_type == "data" {
-					sim /= 2.;
-				}
-				let result = PreBlockResult {
-					block: block.clone(),
-					icon: delegate_block_icon(block.block_type).map(String::from),
-					color: block.color,
-					id: block.id,
-				};
-				BlockSortHelper {
-					result,
-					strsim: sim,
-					star_count: block.stars.len(),
-					updated_at: block.updated_at,
-					created_at: block.created_at,
-				}
-			})
-			.filter(|helper| helper.strsim > 0.01)
-			.collect::<Vec<BlockSortHelper>>();
-
-		match sort_by {
-			BlockSortType::Default => {
-				helpers.sort_by(|a, b| b.strsim.partial_cmp(&a.strsim).unwrap());
-			}
-			BlockSortType::StarCount => {
-				helpers.sort_by(|a, b| b.star_count.partial_cmp(&a.star_count).unwrap());
-			}
-			BlockSortType::Updated => {
-				helpers.sort_by(|a, b| b.updated_at.partial_cmp(&a.updated_at).unwrap());
-			}
-			BlockSortType::Created => {
-				helpers.sort_by(|a, b| b.created_at.partial_cmp(&a.created_at).unwrap());
-			}
+			.map(|block| (block.id, block))
+			.collect();
+		// Blocks deleted since they were found are left out
+		hits.items.retain(|hit| blocks.contains_key(&hit.id));
+		let mut results = hits.map(|hit| {
+			let block = blocks.remove(&hit.id).unwrap();
+			(hit.key(), hit.snippets(), block, vec![])
+		});
+
+		// Load the parents of every result together, instead of for each breadcrumb
+		let ancestry = Ancestry::load(context, &ids)?;
+		for (_, _, block, crumbs) in &mut results.items {
+			*crumbs = ancestry.breadcrumb(context, block)?;
 		}
 
-		Ok(helpers
-			.into_iter()
-			.map(|helper| {
-				let block = helper.result.block;
-				let crumbs = gen_breadcrumb(context, &block).unwrap_or_default();
-				BlockResult {
-					icon: helper.result.icon,
-					color: helper.result.color,
-					id: helper.result.id,
-					crumbs,
-				}
-			})
-			.collect())
+		Ok(connection(
+			results,
+			|(key, _, _, _)| *key,
+			|(_, snippets, block, crumbs)| BlockResult {
+				crumbs,
+				icon: delegate_block_icon(block.block_type.clone()).map(String::from),
+				color: block.color,
+				id: block.id,
+				snippets: snippets.into_iter().map(SnippetObject::from).collect(),
+			},
+		))
 	}
 }
 
-struct BlockSortHelper {
-	result: PreBlockResult,
-	strsim: f64,
-	star_count: usize,
-	updated_at: SystemTime,
-	created_at: SystemTime,
+#[derive(Default)]
+pub struct BlockSearchMutations;
+
+#[Object]
+impl BlockSearchMutations {
+	/// Updates the name every block is found by in searches. Names are kept
+	/// up to date as blocks change, so this is only needed after block types
+	/// change how they name blocks. Only admins can do this. Returns how many
+	/// blocks were indexed.
+	async fn reindex_search(&self, context: &Context<'_>) -> Result<i64, Error> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+
+		let is_admin = User::by_id(user_id, conn)?.map_or(false, |user| user.admin);
+		if !is_admin {
+			return Err(UserError::NoAccess(NoAccessSubject::ReindexSearch).into());
+		}
+
+		let blocks: Vec<Block> = blocks::table
+			.filter(blocks::block_type.ne("data"))
+			.load(conn)?;
+		for block in &blocks {
+			let name = delegate_block_name(context, &block.block_type, block)?;
+			Block::set_search_name(block.id, &name, conn)?;
+		}
+		Ok(blocks.len() as i64)
+	}
 }
 
-struct PreBlockResult {
-	block: Block,
-	/// Icon to show to represent the block
-	icon: Option<String>,
-	/// Color of the block
-	color: Option<String>,
-	/// The ID of the block that was searched
-	id: i64,
+/// Updates the name a block is found by in searches. This is needed after
+/// anything that can rename the block, since the name comes from its block type.
+/// The text of the block's data children is kept up to date by the database.
+/// Users subscribed to collections that the block now matches are notified.
+pub fn index_block(context: &ToolsContext, block: &Block) -> Result<(), Error> {
+	let name = delegate_block_name(context, &block.block_type, block)?;
+	Block::set_search_name(block.id, &name, &context.shared_conn()?)?;
+	notify_collections(context, block)?;
+	Ok(())
 }
 
 #[derive(SimpleObject)]
@@ -155,11 +150,46 @@ struct BlockResult {
 	color: Option<String>,
 	/// The ID of the block that was searched
 	id: i64,
+	/// The parts of the block's content and comments that match the query
+	snippets: Vec<SnippetObject>,
+}
+
+#[derive(SimpleObject)]
+/// A part of a block's text that matches a search
+struct SnippetObject {
+	text: String,
+	/// The parts of the text to highlight
+	highlights: Vec<HighlightRange>,
+}
+
+#[derive(SimpleObject)]
+/// A range of characters in a snippet. Counts characters, not bytes.
+struct HighlightRange {
+	start: i32,
+	/// The character after the range
+	end: i32,
+}
+
+impl From<Snippet> for SnippetObject {
+	fn from(snippet: Snippet) -> Self {
+		SnippetObject {
+			text: snippet.text,
+			highlights: snippet
+				.highlights
+				.into_iter()
+				.map(|(start, end)| HighlightRange {
+					start: start as i32,
+					end: end as i32,
+				})
+				.collect(),
+		}
+	}
 }
 
 #[derive(InputObject, Default, Clone)]
-/// Filters to help find a specific block
-struct BlockSearchFilters {
+/// Filters to help find a specific block. Blocks have to pass every filter
+/// that is provided, including the groups in `all`, `any` and `none`.
+pub struct BlockSearchFilters {
 	/// If true, will only include results that the user has starred
 	starred: Option<bool>,
 	/// Will only include blocks of the type provided. Note that data blocks
@@ -167,11 +197,107 @@ struct BlockSearchFilters {
 	block_type: Option<String>,
 	/// Will only include blocks owned by this user
 	owner_id: Option<i32>,
+	/// Will only include blocks that the user has put these tags on
+	tags: Option<Vec<i64>>,
+	/// Whether blocks need all of the `tags` or any of them. Defaults to all.
+	tag_match: Option<TagMatch>,
+	/// Will only include blocks created at or after this time
+	created_after: Option<DateTime<Utc>>,
+	/// Will only include blocks created before this time
+	created_before: Option<DateTime<Utc>>,
+	/// Will only include blocks updated at or after this time
+	updated_after: Option<DateTime<Utc>>,
+	/// Will only include blocks updated before this time
+	updated_before: Option<DateTime<Utc>>,
+	/// Will only include blocks with this color, like `rgb(123,123,123)`
+	color: Option<String>,
+	/// If true, will only include public blocks. If false, only private ones.
+	public: Option<bool>,
+	/// Will only include blocks that the user has at least this level on
+	perm_level: Option<PermLevel>,
+	/// If true, will only include blocks with comments. If false, only blocks without.
+	has_comments: Option<bool>,
+	/// Will only include blocks that are under this block, at any depth
+	parent_id: Option<i64>,
+	/// If true, will only include blocks that the user gets notifications
+	/// for. If false, only blocks they don't.
+	notif_enabled: Option<bool>,
+	/// Will only include blocks that pass every one of these filters
+	all: Option<Vec<BlockSearchFilters>>,
+	/// Will only include blocks that pass at least one of these filters
+	any: Option<Vec<BlockSearchFilters>>,
+	/// Will only include blocks that pass none of these filters
+	none: Option<Vec<BlockSearchFilters>>,
+}
+
+#[derive(Enum, Copy, Clone, PartialEq, Eq)]
+/// How to combine multiple tags in a filter
+pub enum TagMatch {
+	/// Blocks need to have every tag
+	All,
+	/// Blocks need to have at least one of the tags
+	Any,
+}
+
+impl Default for TagMatch {
+	fn default() -> Self {
+		Self::All
+	}
+}
+
+impl From<BlockSearchFilters> for SearchFilters {
+	fn from(filters: BlockSearchFilters) -> Self {
+		let groups = |groups: Option<Vec<BlockSearchFilters>>| -> Vec<SearchFilters> {
+			groups
+				.unwrap_or_default()
+				.into_iter()
+				.map(SearchFilters::from)
+				.collect()
+		};
+		SearchFilters {
+			starred: filters.starred.unwrap_or_default(),
+			block_type: filters.block_type,
+			owner_id: filters.owner_id,
+			tags: filters.tags,
+			tag_match: filters.tag_match.unwrap_or_default().into(),
+			created_after: filters.created_after.map(|time| time.timestamp()),
+			created_before: filters.created_before.map(|time| time.timestamp()),
+			updated_after: filters.updated_after.map(|time| time.timestamp()),
+			updated_before: filters.updated_before.map(|time| time.timestamp()),
+			color: filters.color,
+			public: filters.public,
+			perm_level: filters.perm_level.map(Into::into),
+			has_comments: filters.has_comments,
+			parent_id: filters.parent_id,
+			notif_enabled: filters.notif_enabled,
+			all: groups(filters.all),
+			any: groups(filters.any),
+			none: groups(filters.none),
+		}
+	}
+}
+
+impl From<TagMatch> for ToolsTagMatch {
+	fn from(tag_match: TagMatch) -> Self {
+		match tag_match {
+			TagMatch::All => ToolsTagMatch::All,
+			TagMatch::Any => ToolsTagMatch::Any,
+		}
+	}
+}
+
+impl From<ToolsTagMatch> for TagMatch {
+	fn from(tag_match: ToolsTagMatch) -> Self {
+		match tag_match {
+			ToolsTagMatch::All => TagMatch::All,
+			ToolsTagMatch::Any => TagMatch::Any,
+		}
+	}
 }
 
 #[derive(Enum, Copy, Clone, PartialEq, Eq)]
 /// Custom ways to sort the results
-enum BlockSortType {
+pub enum BlockSortType {
 	Default,
 	StarCount,
 	Updated,
@@ -183,3 +309,25 @@ impl Default for BlockSortType {
 		Self::Default
 	}
 }
+
+impl From<BlockSortType> for SearchSort {
+	fn from(sort: BlockSortType) -> Self {
+		match sort {
+			BlockSortType::Default => SearchSort::Rank,
+			BlockSortType::StarCount => SearchSort::Stars,
+			BlockSortType::Updated => SearchSort::Updated,
+			BlockSortType::Created => SearchSort::Created,
+		}
+	}
+}
+
+impl From<SearchSort> for BlockSortType {
+	fn from(sort: SearchSort) -> Self {
+		match sort {
+			SearchSort::Rank => BlockSortType::Default,
+			SearchSort::Stars => BlockSortType::StarCount,
+			SearchSort::Updated => BlockSortType::Updated,
+			SearchSort::Created => BlockSortType::Created,
+		}
+	}
+}
@@ -0,0 +1,285 @@
+use super::block::BlockObject;
+use crate::{graphql::ContextData, users::user::UserObject};
+use async_graphql::*;
+use block_tools::{
+	auth::{
+		permissions::{has_perm_level, PermLevel},
+		require_token, validate_token,
+	},
+	dsl::Connection,
+	models::{self, AuditAction, Block, NewAuditEvent, NewShareLink, ShareLink, User},
+	LoopError, NoAccessSubject, PgConnect, UserError,
+};
+use chrono::{DateTime, Utc};
+use std::time::SystemTime;
+
+/// A link that gives access to a block to anyone with its token, even
+/// people without an account. The token is sent in the `X-Share-Token` header.
+pub struct ShareLinkObject {
+	pub id: i64,
+	pub block_id: i64,
+	pub token: String,
+	pub level: models::ShareLevel,
+	pub expires_at: Option<SystemTime>,
+	pub single_use: bool,
+	pub used_by: Option<i32>,
+	pub used_at: Option<SystemTime>,
+	pub revoked: bool,
+	pub usable: bool,
+	pub created_by: i32,
+	pub created_at: SystemTime,
+}
+
+#[Object]
+impl ShareLinkObject {
+	async fn id(&self) -> i64 {
+		self.id
+	}
+
+	/// The secret part of the link
+	async fn token(&self) -> String {
+		self.token.clone()
+	}
+
+	/// The access the link gives
+	async fn level(&self) -> ShareLevel {
+		self.level.into()
+	}
+
+	/// When the link stops working, if it ever does
+	async fn expires_at(&self) -> Option<DateTime<Utc>> {
+		self.expires_at.map(DateTime::from)
+	}
+
+	/// Single use links have to be redeemed by a user, who then keeps the access
+	async fn single_use(&self) -> bool {
+		self.single_use
+	}
+
+	/// The last user that redeemed the link
+	async fn used_by(&self, context: &Context<'_>) -> Result<Option<UserObject>> {
+		let (_, conn) = &ContextData::parse(context)?;
+		Ok(match self.used_by {
+			Some(user_id) => User::by_id(user_id, conn)?.map(UserObject::from),
+			None => None,
+		})
+	}
+
+	async fn used_at(&self) -> Option<DateTime<Utc>> {
+		self.used_at.map(DateTime::from)
+	}
+
+	async fn revoked(&self) -> bool {
+		self.revoked
+	}
+
+	/// False if the link was revoked, has expired or was already used up
+	async fn usable(&self) -> bool {
+		self.usable
+	}
+
+	async fn created_by(&self, context: &Context<'_>) -> Result<Option<UserObject>> {
+		let (_, conn) = &ContextData::parse(context)?;
+		Ok(User::by_id(self.created_by, conn)?.map(UserObject::from))
+	}
+
+	async fn created_at(&self) -> DateTime<Utc> {
+		self.created_at.into()
+	}
+
+	/// The block the link gives access to
+	async fn block(&self, context: &Context<'_>) -> Result<Option<BlockObject>> {
+		let (_, conn) = &ContextData::parse(context)?;
+		Ok(Block::by_id(self.block_id, conn)?.map(BlockObject::from))
+	}
+}
+
+impl From<ShareLink> for ShareLinkObject {
+	fn from(link: ShareLink) -> Self {
+		ShareLinkObject {
+			level: link.level(),
+			usable: link.is_usable(),
+			id: link.id,
+			block_id: link.block_id,
+			token: link.token,
+			expires_at: link.expires_at,
+			single_use: link.single_use,
+			used_by: link.used_by,
+			used_at: link.used_at,
+			revoked: link.revoked,
+			created_by: link.created_by,
+			created_at: link.created_at,
+		}
+	}
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+/// The access a share link gives
+pub enum ShareLevel {
+	View,
+	Edit,
+}
+
+impl From<models::ShareLevel> for ShareLevel {
+	fn from(level: models::ShareLevel) -> Self {
+		match level {
+			models::ShareLevel::View => ShareLevel::View,
+			models::ShareLevel::Edit => ShareLevel::Edit,
+		}
+	}
+}
+
+impl From<ShareLevel> for models::ShareLevel {
+	fn from(level: ShareLevel) -> Self {
+		match level {
+			ShareLevel::View => models::ShareLevel::View,
+			ShareLevel::Edit => models::ShareLevel::Edit,
+		}
+	}
+}
+
+#[derive(Default)]
+pub struct ShareLinkQueries;
+
+#[Object]
+impl ShareLinkQueries {
+	/// All the share links to a block, newest first. The user must have
+	/// full permissions or higher.
+	async fn share_links(
+		&self,
+		context: &Context<'_>,
+		block_id: i64,
+	) -> Result<Vec<ShareLinkObject>> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		full_block(block_id, user_id, conn)?;
+
+		Ok(ShareLink::of_block(block_id, conn)?
+			.into_iter()
+			.map(ShareLinkObject::from)
+			.collect())
+	}
+}
+
+#[derive(Default)]
+pub struct ShareLinkMutations;
+
+#[Object]
+impl ShareLinkMutations {
+	/// Makes a new link to a block. The user must have full permissions or higher.
+	async fn create_share_link(
+		&self,
+		context: &Context<'_>,
+		block_id: i64,
+		level: ShareLevel,
+		#[graphql(desc = "When the link stops working")] expires_at: Option<DateTime<Utc>>,
+		#[graphql(default)] single_use: bool,
+	) -> Result<ShareLinkObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let block = full_block(block_id, user_id, conn)?;
+
+		let link = conn.transaction::<_, LoopError, _>(|| {
+			let link = NewShareLink {
+				expires_at: expires_at.map(SystemTime::from),
+				single_use,
+				..NewShareLink::new(block_id, level.into(), user_id)
+			}
+			.insert(conn)?;
+			NewAuditEvent::new(AuditAction::ShareLinkCreated, &block, &block, Some(user_id))
+				.share_link(link.id)
+				.insert(conn)?;
+			Ok(link)
+		})?;
+
+		Ok(link.into())
+	}
+
+	/// Stops a link from working. The user must have full permissions
+	/// or higher on the link's block.
+	async fn revoke_share_link(
+		&self,
+		context: &Context<'_>,
+		link_id: i64,
+	) -> Result<ShareLinkObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+
+		let link = match ShareLink::by_id(link_id, conn)? {
+			Some(link) => link,
+			None => return Err(UserError::ShareLinkInvalid.into()),
+		};
+		let block = full_block(link.block_id, user_id, conn)?;
+
+		let link = conn.transaction::<_, LoopError, _>(|| {
+			let link = link.revoke(conn)?;
+			NewAuditEvent::new(AuditAction::ShareLinkRevoked, &block, &block, Some(user_id))
+				.share_link(link.id)
+				.insert(conn)?;
+			Ok(link)
+		})?;
+
+		Ok(link.into())
+	}
+
+	/// Adds the authenticated user to the permissions of a link's block, with
+	/// the level the link gives. This is the only way to use single use links.
+	async fn redeem_share_link(&self, context: &Context<'_>, token: String) -> Result<BlockObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+
+		let link = match ShareLink::by_token(&token, conn)? {
+			Some(link) if link.is_usable() => link,
+			_ => return Err(UserError::ShareLinkInvalid.into()),
+		};
+		let block = match Block::by_id(link.block_id, conn)? {
+			Some(block) => block,
+			None => return Err(UserError::ShareLinkInvalid.into()),
+		};
+
+		// Users that already have the access keep single use links usable
+		let level = link.level();
+		if has_perm_level(user_id, &block, level.perm_level()) {
+			return Ok(block.into());
+		}
+
+		let updated = conn.transaction::<_, LoopError, _>(|| {
+			let link = link
+				.claim(user_id, conn)?
+				.ok_or(UserError::ShareLinkInvalid)?;
+			let mut perm_edit = block.perm_edit.clone();
+			let mut perm_view = block.perm_view.clone();
+			match level {
+				models::ShareLevel::Edit => perm_edit.push(user_id),
+				models::ShareLevel::View => perm_view.push(user_id),
+			}
+			let updated = block.update_perms_by(
+				block.perm_full.clone(),
+				perm_edit,
+				block.perm_comment.clone(),
+				perm_view,
+				Some(user_id),
+				conn,
+			)?;
+			NewAuditEvent::new(
+				AuditAction::ShareLinkRedeemed,
+				&block,
+				&updated,
+				Some(user_id),
+			)
+			.via_share_link(link.id)
+			.insert(conn)?;
+			Ok(updated)
+		})?;
+
+		Ok(updated.into())
+	}
+}
+
+/// Finds a block, but only if the user has full permissions on it
+fn full_block(block_id: i64, user_id: i32, conn: &PgConnect) -> Result<Block, Error> {
+	match Block::by_id(block_id, conn)? {
+		Some(block) if has_perm_level(user_id, &block, PermLevel::Full) => Ok(block),
+		_ => Err(UserError::NoAccess(NoAccessSubject::UpdatePermissions(block_id)).into()),
+	}
+}
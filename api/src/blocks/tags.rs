@@ -0,0 +1,223 @@
+use super::block::BlockObject;
+use crate::graphql::ContextData;
+use async_graphql::*;
+use block_tools::{
+	auth::{permissions::can_view, require_token, validate_token},
+	models::{Block, NewTag, Tag},
+	NoAccessSubject, PgConnect, UserError,
+};
+use chrono::{DateTime, Utc};
+use std::time::SystemTime;
+
+/// A label that a user puts on blocks to organize them. Tags can only
+/// be seen by the user that made them.
+pub struct TagObject {
+	pub id: i64,
+	pub name: String,
+	pub color: Option<String>,
+	pub created_at: SystemTime,
+}
+
+#[Object]
+impl TagObject {
+	/// A unique identifier for the tag
+	async fn id(&self) -> i64 {
+		self.id
+	}
+
+	/// The name of the tag, unique among the user's tags
+	async fn name(&self) -> String {
+		self.name.clone()
+	}
+
+	/// The tag's color as an RGB string `rgb(123,123,123)`
+	async fn color(&self) -> Option<String> {
+		self.color.clone()
+	}
+
+	/// When was the tag created?
+	async fn created_at(&self) -> DateTime<Utc> {
+		self.created_at.into()
+	}
+
+	/// All the blocks with this tag that the user can still view
+	async fn blocks(&self, context: &Context<'_>) -> Result<Vec<BlockObject>> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let tag = owned_tag(self.id, user_id, conn)?;
+
+		let mut blocks: Vec<BlockObject> = vec![];
+		for block_id in tag.block_ids(conn)? {
+			if let Some(block) = Block::by_id(block_id, conn)? {
+				if can_view(Some(user_id), &block) {
+					blocks.push(block.into());
+				}
+			}
+		}
+
+		Ok(blocks)
+	}
+}
+
+impl From<Tag> for TagObject {
+	fn from(tag: Tag) -> Self {
+		TagObject {
+			id: tag.id,
+			name: tag.name,
+			color: tag.color,
+			created_at: tag.created_at,
+		}
+	}
+}
+
+#[derive(Default)]
+pub struct TagQueries;
+
+#[Object]
+impl TagQueries {
+	/// All the tags the authenticated user has made
+	async fn tags(&self, context: &Context<'_>) -> Result<Vec<TagObject>> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+
+		Ok(Tag::of_user(user_id, conn)?
+			.into_iter()
+			.map(TagObject::from)
+			.collect())
+	}
+}
+
+#[derive(Default)]
+pub struct TagMutations;
+
+#[Object]
+impl TagMutations {
+	/// Creates a new tag for the user. The name can't be used by another of the user's tags.
+	async fn create_tag(
+		&self,
+		context: &Context<'_>,
+		name: String,
+		#[graphql(desc = "An RGB string like `rgb(123,123,123)`")] color: Option<String>,
+	) -> Result<TagObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+
+		Tag::check_name(&name)?;
+		Tag::check_color(color.as_deref())?;
+		if Tag::by_name(user_id, &name, conn)?.is_some() {
+			return Err(UserError::TagConflict(name).into());
+		}
+
+		let tag = NewTag {
+			color,
+			..NewTag::new(user_id, name)
+		}
+		.insert(conn)?;
+
+		Ok(tag.into())
+	}
+
+	/// Changes the name of one of the user's tags
+	async fn rename_tag(
+		&self,
+		context: &Context<'_>,
+		tag_id: i64,
+		name: String,
+	) -> Result<TagObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let tag = owned_tag(tag_id, user_id, conn)?;
+
+		Tag::check_name(&name)?;
+		if let Some(other) = Tag::by_name(user_id, &name, conn)? {
+			if other.id != tag.id {
+				return Err(UserError::TagConflict(name).into());
+			}
+		}
+
+		Ok(tag.rename(&name, conn)?.into())
+	}
+
+	/// Changes the color of one of the user's tags, or resets it if no color is provided
+	async fn set_tag_color(
+		&self,
+		context: &Context<'_>,
+		tag_id: i64,
+		color: Option<String>,
+	) -> Result<TagObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let tag = owned_tag(tag_id, user_id, conn)?;
+
+		Tag::check_color(color.as_deref())?;
+		Ok(tag.update_color(color, conn)?.into())
+	}
+
+	/// Moves every block from one tag to another, then deletes the first tag.
+	async fn merge_tags(
+		&self,
+		context: &Context<'_>,
+		#[graphql(desc = "The tag that will be removed")] source_id: i64,
+		#[graphql(desc = "The tag that will have the blocks of both")] target_id: i64,
+	) -> Result<TagObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let source = owned_tag(source_id, user_id, conn)?;
+		let target = owned_tag(target_id, user_id, conn)?;
+
+		if source.id == target.id {
+			return Ok(target.into());
+		}
+
+		Ok(source.merge_into(&target, conn)?.into())
+	}
+
+	/// Deletes one of the user's tags, removing it from all blocks
+	async fn delete_tag(&self, context: &Context<'_>, tag_id: i64) -> Result<i64> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let tag = owned_tag(tag_id, user_id, conn)?;
+
+		tag.delete(conn)?;
+
+		Ok(tag_id)
+	}
+
+	/// Adds or removes one of the user's tags on a block that the user can view
+	async fn set_block_tagged(
+		&self,
+		context: &Context<'_>,
+		block_id: i64,
+		tag_id: i64,
+		tagged: bool,
+	) -> Result<BlockObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let tag = owned_tag(tag_id, user_id, conn)?;
+
+		let access_err: Error = UserError::NoAccess(NoAccessSubject::ViewBlock(block_id)).into();
+		let block = match Block::by_id(block_id, conn)? {
+			Some(block) => block,
+			None => return Err(access_err),
+		};
+		if !can_view(Some(user_id), &block) {
+			return Err(access_err);
+		}
+
+		if tagged {
+			tag.attach(block.id, conn)?;
+		} else {
+			tag.detach(block.id, conn)?;
+		}
+
+		Ok(block.into())
+	}
+}
+
+/// Finds a tag, but only if the user owns it
+fn owned_tag(tag_id: i64, user_id: i32, conn: &PgConnect) -> Result<Tag, Error> {
+	match Tag::by_id(tag_id, conn)? {
+		Some(tag) if tag.owner_id == user_id => Ok(tag),
+		_ => Err(UserError::NoAccess(NoAccessSubject::EditTag(tag_id)).into()),
+	}
+}
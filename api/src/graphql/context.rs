@@ -6,6 +6,8 @@ pub struct ContextData {
 	pub pool: PostgresPool,
 	/// A JWT for authenticating a user with a request
 	pub auth_token: Option<String>,
+	/// The token of a share link, for requests from people that aren't users
+	pub share_token: Option<String>,
 }
 
 impl ContextData {
@@ -14,6 +16,8 @@ impl ContextData {
 		ToolsContext {
 			pool: self.pool.clone(),
 			auth_token: self.auth_token.clone(),
+			share_token: self.share_token.clone(),
+			transaction: None,
 		}
 	}
 
@@ -22,7 +26,7 @@ impl ContextData {
 		context: &async_graphql::Context<'_>,
 	) -> async_graphql::Result<(ToolsContext, PgConnect)> {
 		let context = context.data::<ContextData>()?.other();
-		let conn = context.conn()?;
+		let conn = context.pool.get()?;
 		Ok((context, conn))
 	}
 }
@@ -3,3 +3,4 @@ pub use context::*;
 pub mod schema;
 pub use schema::*;
 pub mod misc_queries;
+pub mod pagination;
@@ -0,0 +1,191 @@
+use async_graphql::{
+	connection::{Connection, Edge, EmptyFields},
+	OutputValueType, SimpleObject,
+};
+use block_tools::{Page, PageQuery, SortKey, UserError};
+
+/// How many items a page has when neither `first` nor `last` is given
+pub const DEFAULT_PAGE_SIZE: i32 = 20;
+/// The most items a page can have
+pub const MAX_PAGE_SIZE: i32 = 100;
+
+#[derive(SimpleObject)]
+pub struct ListFields {
+	/// How many items the whole list has, not only this page
+	pub total_count: i64,
+}
+
+/// A page of a list, in the shape of a Relay connection
+pub type ListConnection<T> = Connection<String, T, ListFields, EmptyFields>;
+
+/// Relay's arguments for which part of a list to load
+pub struct PageArgs {
+	pub after: Option<String>,
+	pub before: Option<String>,
+	pub first: Option<i32>,
+	pub last: Option<i32>,
+}
+
+/// A key that a list is ordered by, which is what a cursor points to
+pub trait Cursor: Sized {
+	fn encode(&self) -> String;
+	fn decode(cursor: &str) -> Option<Self>;
+}
+
+impl Cursor for i64 {
+	fn encode(&self) -> String {
+		self.to_string()
+	}
+
+	fn decode(cursor: &str) -> Option<Self> {
+		cursor.parse().ok()
+	}
+}
+
+impl Cursor for i32 {
+	fn encode(&self) -> String {
+		self.to_string()
+	}
+
+	fn decode(cursor: &str) -> Option<Self> {
+		cursor.parse().ok()
+	}
+}
+
+impl Cursor for SortKey {
+	/// The value and the ID, like `0.75:42`. Floats are written so that they
+	/// are read back as the exact same number.
+	fn encode(&self) -> String {
+		format!("{}:{}", self.value, self.id)
+	}
+
+	fn decode(cursor: &str) -> Option<Self> {
+		let mut parts = cursor.splitn(2, ':');
+		let value: f64 = parts.next()?.parse().ok()?;
+		let id = parts.next()?.parse().ok()?;
+		if !value.is_finite() {
+			return None;
+		}
+		Some(SortKey { value, id })
+	}
+}
+
+impl PageArgs {
+	/// Reads the cursors and size of the page. Pages have `DEFAULT_PAGE_SIZE`
+	/// items if no size is given, and never more than `MAX_PAGE_SIZE`.
+	pub fn query<K: Cursor>(self) -> Result<PageQuery<K>, UserError> {
+		let (size, from_end) = match (self.first, self.last) {
+			(Some(_), Some(_)) => return Err(UserError::InvalidPage),
+			(Some(first), None) => (first, false),
+			(None, Some(last)) => (last, true),
+			(None, None) => (DEFAULT_PAGE_SIZE, false),
+		};
+		if size < 0 {
+			return Err(UserError::InvalidPage);
+		}
+		Ok(PageQuery {
+			after: decode(self.after)?,
+			before: decode(self.before)?,
+			limit: size.min(MAX_PAGE_SIZE) as i64,
+			from_end,
+		})
+	}
+}
+
+/// The size of a list that takes a plain `limit` instead of `PageArgs`, with
+/// the same default and cap as a page
+pub fn limit_size(limit: Option<i32>) -> Result<i64, UserError> {
+	match limit {
+		Some(limit) if limit < 0 => Err(UserError::InvalidPage),
+		Some(limit) => Ok(limit.min(MAX_PAGE_SIZE) as i64),
+		None => Ok(DEFAULT_PAGE_SIZE as i64),
+	}
+}
+
+fn decode<K: Cursor>(cursor: Option<String>) -> Result<Option<K>, UserError> {
+	match cursor {
+		Some(cursor) => match K::decode(&cursor) {
+			Some(key) => Ok(Some(key)),
+			None => Err(UserError::InvalidCursor(cursor)),
+		},
+		None => Ok(None),
+	}
+}
+
+/// Turns a page into a connection. `key` gives the key each item's cursor
+/// points to, and `node` turns each item into what is shown for it.
+pub fn connection<T, K, N>(
+	page: Page<T>,
+	key: impl Fn(&T) -> K,
+	node: impl Fn(T) -> N,
+) -> ListConnection<N>
+where
+	K: Cursor,
+	N: OutputValueType + Send + Sync,
+{
+	let mut connection = Connection::with_additional_fields(
+		page.has_previous,
+		page.has_next,
+		ListFields {
+			total_count: page.total_count,
+		},
+	);
+	connection.append(
+		page.items
+			.into_iter()
+			.map(|item| Edge::new(key(&item).encode(), node(item))),
+	);
+	connection
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn args(first: Option<i32>, last: Option<i32>) -> PageArgs {
+		PageArgs {
+			after: None,
+			before: None,
+			first,
+			last,
+		}
+	}
+
+	#[test]
+	fn sort_key_cursor_round_trips() {
+		let key = SortKey {
+			value: 0.1 + 0.2,
+			id: 42,
+		};
+		assert_eq!(Some(key), SortKey::decode(&key.encode()));
+	}
+
+	#[test]
+	fn bad_cursors_are_rejected() {
+		assert_eq!(None, SortKey::decode("42"));
+		assert_eq!(None, SortKey::decode("NaN:42"));
+		assert_eq!(None, i64::decode("abc"));
+	}
+
+	#[test]
+	fn page_size_is_capped() {
+		let query: PageQuery<i64> = args(Some(10_000), None).query().unwrap();
+		assert_eq!(MAX_PAGE_SIZE as i64, query.limit);
+		let query: PageQuery<i64> = args(None, None).query().unwrap();
+		assert_eq!(DEFAULT_PAGE_SIZE as i64, query.limit);
+	}
+
+	#[test]
+	fn limits_are_capped() {
+		assert_eq!(DEFAULT_PAGE_SIZE as i64, limit_size(None).unwrap());
+		assert_eq!(5, limit_size(Some(5)).unwrap());
+		assert_eq!(MAX_PAGE_SIZE as i64, limit_size(Some(10_000)).unwrap());
+		assert!(limit_size(Some(-1)).is_err());
+	}
+
+	#[test]
+	fn first_and_last_together_are_rejected() {
+		assert!(args(Some(1), Some(1)).query::<i64>().is_err());
+		assert!(args(Some(-1), None).query::<i64>().is_err());
+	}
+}
@@ -1,11 +1,24 @@
 use super::misc_queries::MiscQueries;
 use crate::{
 	blocks::{
+		access_requests::{AccessRequestMutations, AccessRequestQueries},
+		audit::AuditQueries,
 		basic::{BasicBlockMutations, BasicBlockQueries},
+		batch::BatchMutations,
+		collections::{CollectionMutations, CollectionQueries},
 		comments::CommentMutations,
 		create::{BlockCreationMutation, BlockCreationQuery},
+		data_query::BlockDataQueries,
+		formulas::FormulaMutations,
+		graph::BlockGraphQueries,
+		ownership::{OwnershipMutations, OwnershipQueries},
 		perms::BlockPermMutations,
-		search::BlockSearchQueries,
+		properties::PropertyMutations,
+		relations::{RelationMutations, RelationQueries},
+		revisions::{BlockRevisionMutations, BlockRevisionQueries},
+		search::{BlockSearchMutations, BlockSearchQueries},
+		share_links::{ShareLinkMutations, ShareLinkQueries},
+		tags::{TagMutations, TagQueries},
 	},
 	notifications::{
 		queries::NotificationQueries,
@@ -22,6 +35,7 @@ use crate::{
 		search::UserSearchQueries,
 		selecting::UserSelectingQueries,
 		special::SpecialBlockMutations,
+		teams::{TeamMutations, TeamQueries},
 		user::UserQueries,
 	},
 };
@@ -29,11 +43,22 @@ use async_graphql::{MergedObject, Schema as GraphQLSchema};
 
 #[derive(MergedObject, Default)]
 pub struct Query(
+	AccessRequestQueries,
+	AuditQueries,
 	BasicBlockQueries,
 	BlockCreationQuery,
+	BlockDataQueries,
+	BlockGraphQueries,
+	BlockRevisionQueries,
 	BlockSearchQueries,
+	CollectionQueries,
 	MiscQueries,
 	NotificationQueries,
+	OwnershipQueries,
+	RelationQueries,
+	ShareLinkQueries,
+	TagQueries,
+	TeamQueries,
 	UpdateQueries,
 	UserQueries,
 	UserSearchQueries,
@@ -42,16 +67,28 @@ pub struct Query(
 
 #[derive(MergedObject, Default)]
 pub struct Mutation(
+	AccessRequestMutations,
 	BasicBlockMutations,
+	BatchMutations,
 	BlockCreationMutation,
 	BlockPermMutations,
+	BlockRevisionMutations,
+	BlockSearchMutations,
+	CollectionMutations,
 	CommentMutations,
 	ConfirmEmailMutation,
 	ForgotPasswordMutations,
+	FormulaMutations,
 	LoginMutations,
 	NotificationMutations,
+	OwnershipMutations,
+	PropertyMutations,
+	RelationMutations,
+	ShareLinkMutations,
 	SignupMutations,
 	SpecialBlockMutations,
+	TagMutations,
+	TeamMutations,
 	UpdateEmailMutation,
 	UpdateMutations,
 	UserInfoMutations,
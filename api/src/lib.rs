@@ -62,6 +62,7 @@ pub mod tests {
 		request = request.data(ContextData {
 			pool,
 			auth_token: token,
+			share_token: None,
 		});
 		request
 	}
@@ -39,9 +39,11 @@ async fn main() {
 
 	// The route for GraphQL Requests
 	let graphql_post = warp::header::optional::<String>("authorization")
+		.and(warp::header::optional::<String>("x-share-token"))
 		.and(async_graphql_warp::graphql(schema.clone()))
 		.and_then(
 			move |token: Option<String>,
+			      share_token: Option<String>,
 			      (schema, mut request): (Schema, async_graphql::Request)| {
 				// Add the database to the GraphQL Context
 				let pool = pool.clone();
@@ -50,6 +52,7 @@ async fn main() {
 						request = request.data(ContextData {
 							pool,
 							auth_token: token,
+							share_token,
 						});
 					}
 					// Execute the request & return it
@@ -94,5 +97,9 @@ fn build_cors() -> warp::filters::cors::Builder {
 	warp::cors()
 		.allow_methods(&[Method::GET, Method::POST, Method::OPTIONS])
 		.allow_any_origin()
-		.allow_headers(&[header::CONTENT_TYPE, header::AUTHORIZATION])
+		.allow_headers(vec![
+			header::CONTENT_TYPE.as_str(),
+			header::AUTHORIZATION.as_str(),
+			"x-share-token",
+		])
 }
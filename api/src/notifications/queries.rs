@@ -1,10 +1,11 @@
-use crate::graphql::ContextData;
+use crate::graphql::{
+	pagination::{connection, ListConnection, PageArgs},
+	ContextData,
+};
 use async_graphql::*;
-use block_tools::use_diesel::*;
 use block_tools::{
 	auth::{require_token, validate_token},
 	models::Notification,
-	schema::notifications,
 };
 
 use super::NotificationObject;
@@ -14,16 +15,31 @@ pub struct NotificationQueries {}
 
 #[Object]
 impl NotificationQueries {
-	/// All the notifications that the user has not cleared
-	async fn notifications(&self, context: &Context<'_>) -> Result<Vec<NotificationObject>> {
+	/// The notifications that the user has not cleared, newest first
+	async fn notifications(
+		&self,
+		context: &Context<'_>,
+		after: Option<String>,
+		before: Option<String>,
+		first: Option<i32>,
+		last: Option<i32>,
+	) -> Result<ListConnection<NotificationObject>> {
 		let (context, conn) = &ContextData::parse(context)?;
 
 		let user_id = validate_token(&require_token(context)?)?;
+		let page = PageArgs {
+			after,
+			before,
+			first,
+			last,
+		}
+		.query()?;
 
-		let notifs: Vec<Notification> = notifications::dsl::notifications
-			.filter(notifications::dsl::recipients.contains(vec![user_id]))
-			.load::<Notification>(conn)?;
-
-		Ok(notifs.into_iter().map(|notif| notif.into()).collect())
+		let notifs = Notification::page_for(user_id, &page, conn)?;
+		Ok(connection(
+			notifs,
+			|notif| notif.id,
+			NotificationObject::from,
+		))
 	}
 }
@@ -1,7 +1,6 @@
 use async_graphql::*;
 use block_tools::{
 	auth::{optional_token, optional_validate_token, require_token, validate_token},
-	db::schema::updates,
 	dsl,
 	dsl::*,
 	models::{update_models::UpdateModel, User},
@@ -9,7 +8,13 @@ use block_tools::{
 };
 use chrono::{DateTime, Utc};
 
-use crate::{graphql::ContextData, users::user::UserObject};
+use crate::{
+	graphql::{
+		pagination::{connection, ListConnection, PageArgs},
+		ContextData,
+	},
+	users::user::UserObject,
+};
 
 #[derive(Clone)]
 /// A Loop notification
@@ -58,11 +63,26 @@ pub struct UpdateQueries {}
 
 #[Object]
 impl UpdateQueries {
-	/// All the updates that have been published
-	async fn all_updates(&self, context: &Context<'_>) -> Result<Vec<Update>> {
+	/// The updates that have been published, newest first
+	async fn all_updates(
+		&self,
+		context: &Context<'_>,
+		after: Option<String>,
+		before: Option<String>,
+		first: Option<i32>,
+		last: Option<i32>,
+	) -> Result<ListConnection<Update>> {
 		let (_, conn) = &ContextData::parse(context)?;
-		let updates: Vec<UpdateModel> = updates::dsl::updates.load::<UpdateModel>(conn)?;
-		Ok(updates.into_iter().map(Update::from).collect())
+		let page = PageArgs {
+			after,
+			before,
+			first,
+			last,
+		}
+		.query()?;
+
+		let updates = UpdateModel::page(&page, conn)?;
+		Ok(connection(updates, |update| update.id, Update::from))
 	}
 }
 
@@ -3,4 +3,5 @@ pub mod info;
 pub mod search;
 pub mod selecting;
 pub mod special;
+pub mod teams;
 pub mod user;
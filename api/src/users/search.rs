@@ -1,7 +1,10 @@
-use crate::graphql::ContextData;
+use crate::graphql::{
+	pagination::{connection, ListConnection, PageArgs},
+	ContextData,
+};
 use async_graphql::*;
-use block_tools::{dsl::prelude::*, models::User, schema::users};
-use strsim::jaro_winkler;
+use block_tools::models::User;
+use std::collections::HashMap;
 
 use super::user::UserObject;
 
@@ -11,39 +14,42 @@ pub struct UserSearchQueries;
 #[Object]
 impl UserSearchQueries {
 	/// Finds users that are similar to the query provided. Matches against
-	/// both username and display name and sorts them by similarity.
+	/// both username and display name and sorts them by similarity. Results
+	/// are paged like a Relay connection.
 	async fn search_users(
 		&self,
 		context: &Context<'_>,
 		query: String,
-	) -> Result<Vec<UserObject>, Error> {
+		after: Option<String>,
+		before: Option<String>,
+		first: Option<i32>,
+		last: Option<i32>,
+	) -> Result<ListConnection<UserObject>, Error> {
 		let (_, conn) = &ContextData::parse(context)?;
+		let page = PageArgs {
+			after,
+			before,
+			first,
+			last,
+		}
+		.query()?;
 
-		// Vector of user & their score pairs
-		let mut helpers: Vec<UserSortHelper> = users::dsl::users
-			.load::<User>(conn)?
+		let mut hits = User::search(&query, &page, conn)?;
+
+		let ids: Vec<i32> = hits.items.iter().map(|hit| hit.id as i32).collect();
+		let mut users: HashMap<i32, User> = User::by_ids(&ids, conn)?
 			.into_iter()
-			// Generate the similarity scores for each user
-			.map(|user| {
-				// Similarity scores for both username and display name
-				let username_sim = jaro_winkler(&user.username, &query);
-				let display_sim = user
-					.display_name
-					.clone()
-					.map(|name| jaro_winkler(&name, &query))
-					.unwrap_or_default();
-				// Helper pair
-				(user, username_sim.max(display_sim))
-			})
-			// Remove all the names with a similarity of 0
-			.filter(|helper| helper.1 != 0.)
+			.map(|user| (user.id, user))
 			.collect();
-		// Sort the user results
-		helpers.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+		// Users deleted since they were found are left out
+		hits.items
+			.retain(|hit| users.contains_key(&(hit.id as i32)));
+		let results = hits.map(|hit| (hit.key(), users.remove(&(hit.id as i32)).unwrap()));
 
-		// Turn the helpers back into the single users
-		Ok(helpers.into_iter().map(|helper| helper.0.into()).collect())
+		Ok(connection(
+			results,
+			|(key, _)| *key,
+			|(_, user)| user.into(),
+		))
 	}
 }
-
-type UserSortHelper = (User, f64);
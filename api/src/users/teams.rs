@@ -0,0 +1,398 @@
+use super::user::UserObject;
+use crate::graphql::ContextData;
+use async_graphql::*;
+use block_tools::{
+	auth::{require_token, validate_token},
+	models::{self, NewNotification, NewTeam, Team, TeamMember, User},
+	NoAccessSubject, PgConnect, UserError,
+};
+use chrono::{DateTime, Utc};
+use std::time::SystemTime;
+
+/// A named group of users. Blocks can be shared with a team
+/// instead of listing each of its members.
+pub struct TeamObject {
+	pub id: i64,
+	pub name: String,
+	pub owner_id: i32,
+	pub created_at: SystemTime,
+}
+
+#[Object]
+impl TeamObject {
+	/// A unique identifier for the team
+	async fn id(&self) -> i64 {
+		self.id
+	}
+
+	async fn name(&self) -> String {
+		self.name.clone()
+	}
+
+	/// When was the team created?
+	async fn created_at(&self) -> DateTime<Utc> {
+		self.created_at.into()
+	}
+
+	/// The user that owns the team
+	async fn owner(&self, context: &Context<'_>) -> Result<Option<UserObject>> {
+		let (_, conn) = &ContextData::parse(context)?;
+		Ok(User::by_id(self.owner_id, conn)?.map(UserObject::from))
+	}
+
+	/// Everyone in the team, including users that haven't accepted their invitation yet
+	async fn members(&self, context: &Context<'_>) -> Result<Vec<TeamMemberObject>> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let team = team_with_role(self.id, user_id, models::TeamRole::Member, conn)?;
+
+		Ok(team
+			.members(conn)?
+			.into_iter()
+			.map(TeamMemberObject::from)
+			.collect())
+	}
+
+	/// The authenticated user's role in the team
+	async fn role(&self, context: &Context<'_>) -> Result<Option<TeamRole>> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let team = match Team::by_id(self.id, conn)? {
+			Some(team) => team,
+			None => return Ok(None),
+		};
+
+		Ok(team.role_of(user_id, conn)?.map(TeamRole::from))
+	}
+}
+
+impl From<Team> for TeamObject {
+	fn from(team: Team) -> Self {
+		TeamObject {
+			id: team.id,
+			name: team.name,
+			owner_id: team.owner_id,
+			created_at: team.created_at,
+		}
+	}
+}
+
+/// A user's membership in a team, or their invitation to it
+pub struct TeamMemberObject {
+	pub team_id: i64,
+	pub user_id: i32,
+	pub role: models::TeamRole,
+	pub accepted: bool,
+	pub invited_by: Option<i32>,
+}
+
+#[Object]
+impl TeamMemberObject {
+	async fn team(&self, context: &Context<'_>) -> Result<Option<TeamObject>> {
+		let (_, conn) = &ContextData::parse(context)?;
+		Ok(Team::by_id(self.team_id, conn)?.map(TeamObject::from))
+	}
+
+	async fn user(&self, context: &Context<'_>) -> Result<Option<UserObject>> {
+		let (_, conn) = &ContextData::parse(context)?;
+		Ok(User::by_id(self.user_id, conn)?.map(UserObject::from))
+	}
+
+	async fn role(&self) -> TeamRole {
+		self.role.into()
+	}
+
+	/// False while the user hasn't accepted their invitation. Until then
+	/// they don't get access to what is shared with the team.
+	async fn accepted(&self) -> bool {
+		self.accepted
+	}
+
+	/// The user that sent the invitation
+	async fn invited_by(&self, context: &Context<'_>) -> Result<Option<UserObject>> {
+		let (_, conn) = &ContextData::parse(context)?;
+		Ok(match self.invited_by {
+			Some(user_id) => User::by_id(user_id, conn)?.map(UserObject::from),
+			None => None,
+		})
+	}
+}
+
+impl From<TeamMember> for TeamMemberObject {
+	fn from(member: TeamMember) -> Self {
+		TeamMemberObject {
+			team_id: member.team_id,
+			user_id: member.user_id,
+			role: member.role(),
+			accepted: member.accepted,
+			invited_by: member.invited_by,
+		}
+	}
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+/// What a member can do in a team
+pub enum TeamRole {
+	/// Members get access to everything shared with the team
+	Member,
+	/// Admins can also rename the team, and invite or remove members
+	Admin,
+	/// The owner can also change roles and delete the team
+	Owner,
+}
+
+impl From<models::TeamRole> for TeamRole {
+	fn from(role: models::TeamRole) -> Self {
+		match role {
+			models::TeamRole::Member => TeamRole::Member,
+			models::TeamRole::Admin => TeamRole::Admin,
+			models::TeamRole::Owner => TeamRole::Owner,
+		}
+	}
+}
+
+impl From<TeamRole> for models::TeamRole {
+	fn from(role: TeamRole) -> Self {
+		match role {
+			TeamRole::Member => models::TeamRole::Member,
+			TeamRole::Admin => models::TeamRole::Admin,
+			TeamRole::Owner => models::TeamRole::Owner,
+		}
+	}
+}
+
+#[derive(Default)]
+pub struct TeamQueries;
+
+#[Object]
+impl TeamQueries {
+	/// The teams the authenticated user is a member of
+	async fn teams(&self, context: &Context<'_>) -> Result<Vec<TeamObject>> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+
+		Ok(Team::of_user(user_id, conn)?
+			.into_iter()
+			.map(TeamObject::from)
+			.collect())
+	}
+
+	/// A team that the authenticated user is a member of
+	async fn team_by_id(&self, context: &Context<'_>, team_id: i64) -> Result<TeamObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+
+		Ok(team_with_role(team_id, user_id, models::TeamRole::Member, conn)?.into())
+	}
+
+	/// Invitations to teams that the authenticated user hasn't answered yet
+	async fn team_invitations(&self, context: &Context<'_>) -> Result<Vec<TeamMemberObject>> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+
+		Ok(TeamMember::pending_for(user_id, conn)?
+			.into_iter()
+			.map(TeamMemberObject::from)
+			.collect())
+	}
+}
+
+#[derive(Default)]
+pub struct TeamMutations;
+
+#[Object]
+impl TeamMutations {
+	/// Creates a team owned by the authenticated user
+	async fn create_team(&self, context: &Context<'_>, name: String) -> Result<TeamObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+
+		Ok(NewTeam::new(user_id, name).insert(conn)?.into())
+	}
+
+	/// Changes the name of a team. The user must be an admin or the owner.
+	async fn rename_team(
+		&self,
+		context: &Context<'_>,
+		team_id: i64,
+		name: String,
+	) -> Result<TeamObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let team = team_with_role(team_id, user_id, models::TeamRole::Admin, conn)?;
+
+		Ok(team.rename(&name, conn)?.into())
+	}
+
+	/// Invites a user to a team. They get access to what is shared with the
+	/// team once they accept. Admins can invite members, and only the owner
+	/// can invite admins.
+	async fn invite_to_team(
+		&self,
+		context: &Context<'_>,
+		team_id: i64,
+		user_id: i32,
+		#[graphql(default_with = "TeamRole::Member")] role: TeamRole,
+	) -> Result<TeamMemberObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let inviter_id = validate_token(&require_token(context)?)?;
+		let role = models::TeamRole::from(role);
+		let needed = match role {
+			models::TeamRole::Member => models::TeamRole::Admin,
+			_ => models::TeamRole::Owner,
+		};
+		let team = team_with_role(team_id, inviter_id, needed, conn)?;
+		if role == models::TeamRole::Owner {
+			return Err(UserError::NoAccess(NoAccessSubject::EditTeam(team_id)).into());
+		}
+		if User::by_id(user_id, conn)?.is_none() {
+			return Err(UserError::IdNonexist(user_id).into());
+		}
+
+		let member = team.invite(user_id, role, inviter_id, conn)?;
+
+		if !member.accepted {
+			let inviter_name = User::by_id(inviter_id, conn)?
+				.and_then(|user| user.display_name.or(Some(user.username)))
+				.unwrap();
+			NewNotification::new(
+				format!("{} invited you to {}", inviter_name, team.name),
+				format!(
+					"{} invited you to join the team \"{}\".",
+					inviter_name, team.name
+				),
+			)
+			.recipients(vec![user_id])
+			.send(conn)?;
+		}
+
+		Ok(member.into())
+	}
+
+	/// Accepts an invitation to a team
+	async fn accept_team_invite(
+		&self,
+		context: &Context<'_>,
+		team_id: i64,
+	) -> Result<TeamMemberObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let member = invitation(team_id, user_id, conn)?;
+
+		Ok(member.accept(conn)?.into())
+	}
+
+	/// Declines an invitation to a team
+	async fn decline_team_invite(&self, context: &Context<'_>, team_id: i64) -> Result<i64> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		invitation(team_id, user_id, conn)?;
+
+		if let Some(team) = Team::by_id(team_id, conn)? {
+			team.remove_member(user_id, conn)?;
+		}
+
+		Ok(team_id)
+	}
+
+	/// Removes a user from a team, or cancels their invitation. Admins can
+	/// remove members, only the owner can remove admins, and anyone but the
+	/// owner can leave a team by removing themselves.
+	async fn remove_team_member(
+		&self,
+		context: &Context<'_>,
+		team_id: i64,
+		user_id: i32,
+	) -> Result<i64> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let remover_id = validate_token(&require_token(context)?)?;
+		let access_err: Error = UserError::NoAccess(NoAccessSubject::EditTeam(team_id)).into();
+
+		let team = team_with_role(team_id, remover_id, models::TeamRole::Member, conn)?;
+		let member = match team.member(user_id, conn)? {
+			Some(member) => member,
+			None => return Ok(team_id),
+		};
+		let removed_role = member.role();
+		if removed_role == models::TeamRole::Owner {
+			return Err(access_err);
+		}
+		if remover_id != user_id {
+			let needed = match removed_role {
+				models::TeamRole::Member => models::TeamRole::Admin,
+				_ => models::TeamRole::Owner,
+			};
+			team_with_role(team_id, remover_id, needed, conn)?;
+		}
+
+		team.remove_member(user_id, conn)?;
+
+		Ok(team_id)
+	}
+
+	/// Makes a member an admin, or an admin a member. Only the owner can change roles.
+	async fn set_team_role(
+		&self,
+		context: &Context<'_>,
+		team_id: i64,
+		user_id: i32,
+		role: TeamRole,
+	) -> Result<TeamMemberObject> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let owner_id = validate_token(&require_token(context)?)?;
+		let access_err: Error = UserError::NoAccess(NoAccessSubject::EditTeam(team_id)).into();
+
+		let team = team_with_role(team_id, owner_id, models::TeamRole::Owner, conn)?;
+		let role = models::TeamRole::from(role);
+		let member = match team.member(user_id, conn)? {
+			Some(member) => member,
+			None => return Err(access_err),
+		};
+		if role == models::TeamRole::Owner || member.role() == models::TeamRole::Owner {
+			return Err(access_err);
+		}
+
+		Ok(member.set_role(role, conn)?.into())
+	}
+
+	/// Deletes a team. Its members lose the access they had through it.
+	async fn delete_team(&self, context: &Context<'_>, team_id: i64) -> Result<i64> {
+		let (context, conn) = &ContextData::parse(context)?;
+		let user_id = validate_token(&require_token(context)?)?;
+		let team = team_with_role(team_id, user_id, models::TeamRole::Owner, conn)?;
+
+		team.delete(conn)?;
+
+		Ok(team_id)
+	}
+}
+
+/// Finds a team, but only if the user has at least a role in it
+pub fn team_with_role(
+	team_id: i64,
+	user_id: i32,
+	role: models::TeamRole,
+	conn: &PgConnect,
+) -> Result<Team, Error> {
+	let subject = match role {
+		models::TeamRole::Member => NoAccessSubject::ViewTeam(team_id),
+		_ => NoAccessSubject::EditTeam(team_id),
+	};
+	let team = match Team::by_id(team_id, conn)? {
+		Some(team) => team,
+		None => return Err(UserError::NoAccess(subject).into()),
+	};
+	match team.role_of(user_id, conn)? {
+		Some(user_role) if user_role >= role => Ok(team),
+		_ => Err(UserError::NoAccess(subject).into()),
+	}
+}
+
+/// Finds the user's unanswered invitation to a team
+fn invitation(team_id: i64, user_id: i32, conn: &PgConnect) -> Result<TeamMember, Error> {
+	let team = Team::by_id(team_id, conn)?;
+	match team.map(|team| team.member(user_id, conn)).transpose()? {
+		Some(Some(member)) if !member.accepted => Ok(member),
+		_ => Err(UserError::NoAccess(NoAccessSubject::ViewTeam(team_id)).into()),
+	}
+}
@@ -1,14 +1,19 @@
-use crate::{blocks::block::BlockObject, graphql::ContextData};
+use crate::{
+	blocks::block::BlockObject,
+	graphql::{
+		pagination::{connection, ListConnection, PageArgs},
+		ContextData,
+	},
+};
 use async_graphql::*;
 use block_tools::{
 	auth::{
-		optional_token, optional_validate_token,
-		permissions::{can_view, maybe_use_view},
-		require_token, validate_token,
+		optional_token, optional_validate_token, permissions::maybe_use_view, require_token,
+		validate_token,
 	},
 	dsl::prelude::*,
 	models::{Block, User},
-	schema::{blocks, users},
+	schema::users,
 };
 
 /// GraphQL object for Loop users
@@ -68,21 +73,27 @@ impl UserObject {
 		self.display_name.clone()
 	}
 
-	/// All blocks that a user owns
-	async fn blocks(&self, context: &Context<'_>) -> Result<Vec<BlockObject>, Error> {
+	/// The blocks that a user owns and that the viewer can see, newest first
+	async fn blocks(
+		&self,
+		context: &Context<'_>,
+		after: Option<String>,
+		before: Option<String>,
+		first: Option<i32>,
+		last: Option<i32>,
+	) -> Result<ListConnection<BlockObject>, Error> {
 		let (context, conn) = &ContextData::parse(context)?;
 		let user_id = optional_validate_token(optional_token(context))?;
+		let page = PageArgs {
+			after,
+			before,
+			first,
+			last,
+		}
+		.query()?;
 
-		let blocks: Vec<BlockObject> = blocks::dsl::blocks
-			.filter(blocks::dsl::owner_id.eq(self.id))
-			.load::<Block>(conn)?
-			.iter()
-			// Filter out the blocks that the user has no access to
-			.filter(|block| can_view(user_id, block))
-			.map(BlockObject::from)
-			.collect();
-
-		Ok(blocks)
+		let blocks = Block::page_owned_by(self.id, user_id, &page, conn)?;
+		Ok(connection(blocks, |block| block.id, BlockObject::from))
 	}
 
 	/// The user's root block. This block is what is shown to the user on their home page,
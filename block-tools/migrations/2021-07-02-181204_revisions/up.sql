@@ -0,0 +1,17 @@
+CREATE TABLE block_revisions (
+	id BIGSERIAL PRIMARY KEY,
+	block_id BIGINT NOT NULL,
+	author_id INT,
+	created_at TIMESTAMP NOT NULL,
+	change VARCHAR(24) NOT NULL,
+	block_data TEXT,
+	color VARCHAR(17),
+	public BOOLEAN NOT NULL,
+	perm_full INT[] NOT NULL,
+	perm_edit INT[] NOT NULL,
+	perm_view INT[] NOT NULL,
+	properties TEXT NOT NULL,
+	CONSTRAINT fk_block_id FOREIGN KEY (block_id) REFERENCES blocks (id) ON DELETE CASCADE,
+	CONSTRAINT fk_author_id FOREIGN KEY (author_id) REFERENCES users (id)
+);
+CREATE INDEX block_revisions_block_id ON block_revisions (block_id);
@@ -0,0 +1,9 @@
+CREATE TABLE block_references (
+	id BIGSERIAL PRIMARY KEY,
+	source_id BIGINT NOT NULL,
+	target_id BIGINT NOT NULL,
+	link_text TEXT,
+	dangling BOOLEAN NOT NULL DEFAULT false,
+	CONSTRAINT fk_source_id FOREIGN KEY (source_id) REFERENCES blocks (id) ON DELETE CASCADE
+);
+CREATE INDEX block_references_target_id ON block_references (target_id);
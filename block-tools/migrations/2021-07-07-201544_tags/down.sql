@@ -0,0 +1,2 @@
+DROP TABLE block_tags;
+DROP TABLE tags;
@@ -0,0 +1,18 @@
+CREATE TABLE tags (
+	id BIGSERIAL PRIMARY KEY,
+	owner_id INT NOT NULL,
+	name VARCHAR(64) NOT NULL,
+	color VARCHAR(17),
+	created_at TIMESTAMP NOT NULL,
+	CONSTRAINT fk_owner_id FOREIGN KEY (owner_id) REFERENCES users (id),
+	CONSTRAINT unique_tag_name UNIQUE (owner_id, name)
+);
+
+CREATE TABLE block_tags (
+	id BIGSERIAL PRIMARY KEY,
+	tag_id BIGINT NOT NULL,
+	block_id BIGINT NOT NULL,
+	CONSTRAINT fk_tag_id FOREIGN KEY (tag_id) REFERENCES tags (id) ON DELETE CASCADE,
+	CONSTRAINT fk_block_id FOREIGN KEY (block_id) REFERENCES blocks (id) ON DELETE CASCADE,
+	CONSTRAINT unique_block_tag UNIQUE (tag_id, block_id)
+);
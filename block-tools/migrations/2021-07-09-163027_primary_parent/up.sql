@@ -0,0 +1 @@
+ALTER TABLE properties ADD COLUMN is_primary BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,2 @@
+DROP INDEX properties_parent_position;
+ALTER TABLE properties DROP COLUMN position;
@@ -0,0 +1,11 @@
+ALTER TABLE properties ADD COLUMN position VARCHAR;
+
+-- Keep the order that existing children were added in
+UPDATE properties SET position = ranked.position
+FROM (
+	SELECT id, lpad(to_hex(row_number() OVER (PARTITION BY parent_id ORDER BY id)), 8, '0') AS position
+	FROM properties
+) AS ranked
+WHERE properties.id = ranked.id;
+
+CREATE INDEX properties_parent_position ON properties (parent_id, position);
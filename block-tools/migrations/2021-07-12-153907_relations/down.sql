@@ -0,0 +1,2 @@
+DROP TABLE block_relations;
+DROP TABLE relation_types;
@@ -0,0 +1,30 @@
+CREATE TABLE relation_types (
+	id BIGSERIAL PRIMARY KEY,
+	-- Relation types without an owner are built in and can be used by anyone
+	owner_id INT,
+	name VARCHAR(64) NOT NULL,
+	inverse_name VARCHAR(64) NOT NULL,
+	created_at TIMESTAMP NOT NULL,
+	CONSTRAINT fk_owner_id FOREIGN KEY (owner_id) REFERENCES users (id) ON DELETE CASCADE,
+	CONSTRAINT unique_relation_type_name UNIQUE (owner_id, name)
+);
+
+INSERT INTO relation_types (owner_id, name, inverse_name, created_at) VALUES
+	(NULL, 'related to', 'related to', now()),
+	(NULL, 'blocks', 'blocked by', now()),
+	(NULL, 'duplicates', 'duplicated by', now());
+
+CREATE TABLE block_relations (
+	id BIGSERIAL PRIMARY KEY,
+	relation_type_id BIGINT NOT NULL,
+	source_id BIGINT NOT NULL,
+	target_id BIGINT NOT NULL,
+	author_id INT,
+	created_at TIMESTAMP NOT NULL,
+	CONSTRAINT fk_relation_type_id FOREIGN KEY (relation_type_id) REFERENCES relation_types (id) ON DELETE CASCADE,
+	CONSTRAINT fk_source_id FOREIGN KEY (source_id) REFERENCES blocks (id) ON DELETE CASCADE,
+	CONSTRAINT fk_target_id FOREIGN KEY (target_id) REFERENCES blocks (id) ON DELETE CASCADE,
+	CONSTRAINT unique_block_relation UNIQUE (relation_type_id, source_id, target_id)
+);
+
+CREATE INDEX block_relations_target ON block_relations (target_id);
@@ -0,0 +1,9 @@
+DROP INDEX blocks_block_type;
+DROP INDEX blocks_block_data;
+
+ALTER TABLE blocks ALTER COLUMN block_data TYPE TEXT USING (
+	CASE WHEN jsonb_typeof(block_data) = 'string'
+		THEN block_data #>> '{}'
+		ELSE block_data::text
+	END
+);
@@ -0,0 +1,22 @@
+-- Data that is a JSON object or list is kept as JSON, and
+-- anything else (like the text of a data block) becomes a JSON string
+CREATE FUNCTION pg_temp.block_data_json(data TEXT) RETURNS JSONB AS $$
+BEGIN
+	IF data IS NULL THEN
+		RETURN NULL;
+	END IF;
+	IF left(ltrim(data), 1) IN ('{', '[') THEN
+		BEGIN
+			RETURN data::jsonb;
+		EXCEPTION WHEN others THEN
+			RETURN to_jsonb(data);
+		END;
+	END IF;
+	RETURN to_jsonb(data);
+END;
+$$ LANGUAGE plpgsql;
+
+ALTER TABLE blocks ALTER COLUMN block_data TYPE JSONB USING pg_temp.block_data_json(block_data);
+
+CREATE INDEX blocks_block_data ON blocks USING GIN (block_data jsonb_path_ops);
+CREATE INDEX blocks_block_type ON blocks (block_type);
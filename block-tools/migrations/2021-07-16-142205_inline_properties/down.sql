@@ -0,0 +1 @@
+DROP TABLE inline_properties;
@@ -0,0 +1,14 @@
+CREATE TABLE inline_properties (
+	id BIGSERIAL PRIMARY KEY,
+	parent_id BIGINT NOT NULL,
+	property_name VARCHAR(36) NOT NULL,
+	value_type VARCHAR(16) NOT NULL,
+	text_value TEXT,
+	number_value DOUBLE PRECISION,
+	bool_value BOOLEAN,
+	time_value TIMESTAMP,
+	user_value INT,
+	CONSTRAINT fk_parent_id FOREIGN KEY (parent_id) REFERENCES blocks (id) ON DELETE CASCADE,
+	CONSTRAINT fk_user_value FOREIGN KEY (user_value) REFERENCES users (id) ON DELETE SET NULL,
+	CONSTRAINT unique_inline_property UNIQUE (parent_id, property_name)
+);
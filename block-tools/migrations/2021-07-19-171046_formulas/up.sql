@@ -0,0 +1,11 @@
+CREATE TABLE formulas (
+	id BIGSERIAL PRIMARY KEY,
+	block_id BIGINT NOT NULL,
+	name VARCHAR(36) NOT NULL,
+	expression TEXT NOT NULL,
+	error TEXT,
+	author_id INT,
+	updated_at TIMESTAMP NOT NULL,
+	CONSTRAINT fk_block_id FOREIGN KEY (block_id) REFERENCES blocks (id) ON DELETE CASCADE,
+	CONSTRAINT unique_formula_name UNIQUE (block_id, name)
+);
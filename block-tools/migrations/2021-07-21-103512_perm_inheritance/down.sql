@@ -0,0 +1,5 @@
+ALTER TABLE blocks DROP COLUMN inherited_view;
+ALTER TABLE blocks DROP COLUMN inherited_edit;
+ALTER TABLE blocks DROP COLUMN inherited_full;
+ALTER TABLE blocks DROP COLUMN inherited_public;
+ALTER TABLE blocks DROP COLUMN inherit_perms;
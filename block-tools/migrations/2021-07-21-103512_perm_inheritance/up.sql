@@ -0,0 +1,7 @@
+-- Blocks can opt in to getting the permissions of the blocks they are a
+-- property of. What they inherit is stored so that checking access is cheap.
+ALTER TABLE blocks ADD COLUMN inherit_perms BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE blocks ADD COLUMN inherited_public BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE blocks ADD COLUMN inherited_full INTEGER[] NOT NULL DEFAULT '{}';
+ALTER TABLE blocks ADD COLUMN inherited_edit INTEGER[] NOT NULL DEFAULT '{}';
+ALTER TABLE blocks ADD COLUMN inherited_view INTEGER[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,5 @@
+ALTER TABLE blocks DROP COLUMN team_view;
+ALTER TABLE blocks DROP COLUMN team_edit;
+ALTER TABLE blocks DROP COLUMN team_full;
+DROP TABLE team_members;
+DROP TABLE teams;
@@ -0,0 +1,28 @@
+CREATE TABLE teams (
+	id BIGSERIAL PRIMARY KEY,
+	name VARCHAR(64) NOT NULL,
+	owner_id INT NOT NULL,
+	created_at TIMESTAMP NOT NULL,
+	CONSTRAINT fk_owner_id FOREIGN KEY (owner_id) REFERENCES users (id)
+);
+
+-- Members that haven't accepted their invitation yet don't get any access
+CREATE TABLE team_members (
+	id BIGSERIAL PRIMARY KEY,
+	team_id BIGINT NOT NULL,
+	user_id INT NOT NULL,
+	role VARCHAR(16) NOT NULL,
+	accepted BOOLEAN NOT NULL DEFAULT false,
+	invited_by INT,
+	created_at TIMESTAMP NOT NULL,
+	CONSTRAINT fk_team_id FOREIGN KEY (team_id) REFERENCES teams (id) ON DELETE CASCADE,
+	CONSTRAINT fk_user_id FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+	CONSTRAINT fk_invited_by FOREIGN KEY (invited_by) REFERENCES users (id) ON DELETE SET NULL,
+	CONSTRAINT unique_team_member UNIQUE (team_id, user_id)
+);
+
+CREATE INDEX team_members_user_id ON team_members (user_id);
+
+ALTER TABLE blocks ADD COLUMN team_full BIGINT[] NOT NULL DEFAULT '{}';
+ALTER TABLE blocks ADD COLUMN team_edit BIGINT[] NOT NULL DEFAULT '{}';
+ALTER TABLE blocks ADD COLUMN team_view BIGINT[] NOT NULL DEFAULT '{}';
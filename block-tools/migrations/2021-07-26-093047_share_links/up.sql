@@ -0,0 +1,19 @@
+CREATE TABLE share_links (
+	id BIGSERIAL PRIMARY KEY,
+	block_id BIGINT NOT NULL,
+	token VARCHAR(64) NOT NULL,
+	perm_level VARCHAR(16) NOT NULL,
+	expires_at TIMESTAMP,
+	single_use BOOLEAN NOT NULL DEFAULT false,
+	used_by INT,
+	used_at TIMESTAMP,
+	revoked BOOLEAN NOT NULL DEFAULT false,
+	created_by INT NOT NULL,
+	created_at TIMESTAMP NOT NULL,
+	CONSTRAINT fk_block_id FOREIGN KEY (block_id) REFERENCES blocks (id) ON DELETE CASCADE,
+	CONSTRAINT fk_used_by FOREIGN KEY (used_by) REFERENCES users (id) ON DELETE SET NULL,
+	CONSTRAINT fk_created_by FOREIGN KEY (created_by) REFERENCES users (id),
+	CONSTRAINT unique_share_token UNIQUE (token)
+);
+
+CREATE INDEX share_links_block_id ON share_links (block_id);
@@ -0,0 +1,17 @@
+CREATE TABLE access_requests (
+	id BIGSERIAL PRIMARY KEY,
+	block_id BIGINT NOT NULL,
+	requester_id INT NOT NULL,
+	perm_level VARCHAR(16) NOT NULL,
+	message TEXT,
+	status VARCHAR(16) NOT NULL DEFAULT 'pending',
+	granted_level VARCHAR(16),
+	decided_by INT,
+	created_at TIMESTAMP NOT NULL,
+	decided_at TIMESTAMP,
+	CONSTRAINT fk_block_id FOREIGN KEY (block_id) REFERENCES blocks (id) ON DELETE CASCADE,
+	CONSTRAINT fk_requester_id FOREIGN KEY (requester_id) REFERENCES users (id) ON DELETE CASCADE,
+	CONSTRAINT fk_decided_by FOREIGN KEY (decided_by) REFERENCES users (id) ON DELETE SET NULL
+);
+
+CREATE INDEX access_requests_block_id ON access_requests (block_id, status);
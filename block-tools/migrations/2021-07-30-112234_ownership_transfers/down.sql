@@ -0,0 +1,2 @@
+DROP TABLE ownership_transfers;
+ALTER TABLE blocks DROP COLUMN co_owners;
@@ -0,0 +1,19 @@
+-- Co-owners can do everything the owner can, so blocks stay manageable
+-- when their owner leaves
+ALTER TABLE blocks ADD COLUMN co_owners INTEGER[] NOT NULL DEFAULT '{}';
+
+CREATE TABLE ownership_transfers (
+	id BIGSERIAL PRIMARY KEY,
+	block_id BIGINT NOT NULL,
+	from_id INT NOT NULL,
+	to_id INT NOT NULL,
+	subtree BOOLEAN NOT NULL DEFAULT false,
+	status VARCHAR(16) NOT NULL DEFAULT 'pending',
+	created_at TIMESTAMP NOT NULL,
+	decided_at TIMESTAMP,
+	CONSTRAINT fk_block_id FOREIGN KEY (block_id) REFERENCES blocks (id) ON DELETE CASCADE,
+	CONSTRAINT fk_from_id FOREIGN KEY (from_id) REFERENCES users (id) ON DELETE CASCADE,
+	CONSTRAINT fk_to_id FOREIGN KEY (to_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE INDEX ownership_transfers_to_id ON ownership_transfers (to_id, status);
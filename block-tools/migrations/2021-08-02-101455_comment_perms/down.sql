@@ -0,0 +1,9 @@
+UPDATE blocks SET
+	perm_view = perm_view || perm_comment,
+	inherited_view = inherited_view || inherited_comment,
+	team_view = team_view || team_comment;
+
+ALTER TABLE block_revisions DROP COLUMN perm_comment;
+ALTER TABLE blocks DROP COLUMN team_comment;
+ALTER TABLE blocks DROP COLUMN inherited_comment;
+ALTER TABLE blocks DROP COLUMN perm_comment;
@@ -0,0 +1,14 @@
+-- Commenters can view a block and comment on it, but not edit it
+ALTER TABLE blocks ADD COLUMN perm_comment INTEGER[] NOT NULL DEFAULT '{}';
+ALTER TABLE blocks ADD COLUMN inherited_comment INTEGER[] NOT NULL DEFAULT '{}';
+ALTER TABLE blocks ADD COLUMN team_comment BIGINT[] NOT NULL DEFAULT '{}';
+ALTER TABLE block_revisions ADD COLUMN perm_comment INTEGER[] NOT NULL DEFAULT '{}';
+
+-- Viewers could comment before, so they keep being able to
+UPDATE blocks SET
+	perm_comment = perm_view,
+	perm_view = '{}',
+	inherited_comment = inherited_view,
+	inherited_view = '{}',
+	team_comment = team_view,
+	team_view = '{}';
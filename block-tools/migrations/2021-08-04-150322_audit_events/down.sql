@@ -0,0 +1,4 @@
+DROP TRIGGER audit_events_append_only ON audit_events;
+DROP FUNCTION audit_events_append_only();
+DROP TABLE audit_events;
+ALTER TABLE users DROP COLUMN admin;
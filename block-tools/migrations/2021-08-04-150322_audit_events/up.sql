@@ -0,0 +1,32 @@
+ALTER TABLE users ADD COLUMN admin BOOLEAN NOT NULL DEFAULT false;
+
+-- Every change to who can access a block. Rows are kept after the block or
+-- the actor is deleted, so there are no foreign keys.
+CREATE TABLE audit_events (
+	id BIGSERIAL PRIMARY KEY,
+	block_id BIGINT NOT NULL,
+	actor_id INT,
+	-- Where the access to make the change came from: 'user' or 'share_link'
+	source VARCHAR(16) NOT NULL,
+	share_link_id BIGINT,
+	action VARCHAR(32) NOT NULL,
+	old_public BOOLEAN NOT NULL,
+	new_public BOOLEAN NOT NULL,
+	-- JSON of the permission lists before and after the change
+	old_perms TEXT NOT NULL,
+	new_perms TEXT NOT NULL,
+	created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX audit_events_block_id ON audit_events (block_id, created_at);
+CREATE INDEX audit_events_actor_id ON audit_events (actor_id, created_at);
+
+CREATE FUNCTION audit_events_append_only() RETURNS trigger AS $$
+BEGIN
+	RAISE EXCEPTION 'audit_events can only be appended to';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_append_only
+	BEFORE UPDATE OR DELETE ON audit_events
+	FOR EACH ROW EXECUTE PROCEDURE audit_events_append_only();
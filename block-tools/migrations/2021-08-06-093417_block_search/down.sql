@@ -0,0 +1,12 @@
+DROP TRIGGER property_search_content ON properties;
+DROP FUNCTION property_search_content();
+DROP TRIGGER data_search_content ON blocks;
+DROP FUNCTION data_search_content();
+DROP TRIGGER data_search_name ON blocks;
+DROP FUNCTION data_search_name();
+DROP FUNCTION block_search_content(BIGINT);
+DROP INDEX blocks_search_name;
+DROP INDEX blocks_search_document;
+ALTER TABLE blocks DROP COLUMN search_document;
+ALTER TABLE blocks DROP COLUMN search_content;
+ALTER TABLE blocks DROP COLUMN search_name;
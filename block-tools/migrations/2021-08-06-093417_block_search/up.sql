@@ -0,0 +1,70 @@
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- The name comes from the block type, so the API keeps it up to date. The
+-- content is the text of the block's data children, kept up to date by the
+-- triggers below. These columns are only used by the search query in
+-- search_models.rs, so they aren't in the diesel schema.
+ALTER TABLE blocks ADD COLUMN search_name TEXT NOT NULL DEFAULT '';
+ALTER TABLE blocks ADD COLUMN search_content TEXT NOT NULL DEFAULT '';
+ALTER TABLE blocks ADD COLUMN search_document tsvector GENERATED ALWAYS AS (
+	setweight(to_tsvector('simple', search_name), 'A') ||
+	setweight(to_tsvector('simple', search_content), 'B')
+) STORED;
+
+CREATE INDEX blocks_search_document ON blocks USING GIN (search_document);
+CREATE INDEX blocks_search_name ON blocks USING GIN (search_name gin_trgm_ops);
+
+-- The text of a block's data children, in the order they were added
+CREATE FUNCTION block_search_content(target BIGINT) RETURNS TEXT AS $$
+	SELECT left(COALESCE(string_agg(c.block_data #>> '{}', ' ' ORDER BY p.id), ''), 100000)
+	FROM properties p
+	INNER JOIN blocks c ON c.id = p.value_id
+	WHERE p.parent_id = target AND c.block_type = 'data' AND c.block_data IS NOT NULL
+$$ LANGUAGE SQL STABLE;
+
+-- Data blocks are named by their data
+CREATE FUNCTION data_search_name() RETURNS trigger AS $$
+BEGIN
+	NEW.search_name := COALESCE(NEW.block_data #>> '{}', '');
+	RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER data_search_name
+	BEFORE INSERT OR UPDATE OF block_data ON blocks
+	FOR EACH ROW WHEN (NEW.block_type = 'data')
+	EXECUTE PROCEDURE data_search_name();
+
+CREATE FUNCTION data_search_content() RETURNS trigger AS $$
+BEGIN
+	UPDATE blocks SET search_content = block_search_content(id)
+	WHERE id IN (SELECT parent_id FROM properties WHERE value_id = NEW.id);
+	RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER data_search_content
+	AFTER UPDATE OF block_data ON blocks
+	FOR EACH ROW WHEN (NEW.block_type = 'data')
+	EXECUTE PROCEDURE data_search_content();
+
+CREATE FUNCTION property_search_content() RETURNS trigger AS $$
+BEGIN
+	IF TG_OP <> 'INSERT' THEN
+		UPDATE blocks SET search_content = block_search_content(id) WHERE id = OLD.parent_id;
+	END IF;
+	IF TG_OP <> 'DELETE' THEN
+		UPDATE blocks SET search_content = block_search_content(id) WHERE id = NEW.parent_id;
+	END IF;
+	RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER property_search_content
+	AFTER INSERT OR UPDATE OF parent_id, value_id OR DELETE ON properties
+	FOR EACH ROW EXECUTE PROCEDURE property_search_content();
+
+-- Fill in what can be found without the block types. Other names are filled
+-- in as blocks change, or all at once with the `reindexSearch` mutation.
+UPDATE blocks SET search_name = COALESCE(block_data #>> '{}', '') WHERE block_type = 'data';
+UPDATE blocks SET search_content = block_search_content(id) WHERE block_type <> 'data';
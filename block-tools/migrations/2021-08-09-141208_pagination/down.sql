@@ -0,0 +1,5 @@
+DROP INDEX users_display_name_trgm;
+DROP INDEX users_username_trgm;
+DROP INDEX notifications_recipients;
+DROP INDEX comments_block_id;
+DROP INDEX blocks_owner_id;
@@ -0,0 +1,9 @@
+-- Lists are loaded a page at a time, newest first, so they need indexes
+-- that start where the page starts instead of loading every row
+CREATE INDEX blocks_owner_id ON blocks (owner_id, id);
+CREATE INDEX comments_block_id ON comments (block_id, id);
+CREATE INDEX notifications_recipients ON notifications USING GIN (recipients);
+
+-- Users are searched by how similar their names are to the query
+CREATE INDEX users_username_trgm ON users USING GIN (username gin_trgm_ops);
+CREATE INDEX users_display_name_trgm ON users USING GIN (display_name gin_trgm_ops);
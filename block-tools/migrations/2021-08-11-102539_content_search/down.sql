@@ -0,0 +1,30 @@
+DROP TRIGGER content_search_comments ON blocks;
+DROP FUNCTION content_search_comments();
+DROP TRIGGER comment_search_comments ON comments;
+DROP FUNCTION comment_search_comments();
+DROP INDEX comments_content_id;
+DROP INDEX blocks_search_document;
+ALTER TABLE blocks DROP COLUMN search_document;
+ALTER TABLE blocks DROP COLUMN search_comments;
+ALTER TABLE blocks ADD COLUMN search_document tsvector GENERATED ALWAYS AS (
+	setweight(to_tsvector('simple', search_name), 'A') ||
+	setweight(to_tsvector('simple', search_content), 'B')
+) STORED;
+CREATE INDEX blocks_search_document ON blocks USING GIN (search_document);
+DROP FUNCTION block_search_comments(BIGINT);
+
+CREATE OR REPLACE FUNCTION data_search_name() RETURNS trigger AS $$
+BEGIN
+	NEW.search_name := COALESCE(NEW.block_data #>> '{}', '');
+	RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION block_search_content(target BIGINT) RETURNS TEXT AS $$
+	SELECT left(COALESCE(string_agg(c.block_data #>> '{}', ' ' ORDER BY p.id), ''), 100000)
+	FROM properties p
+	INNER JOIN blocks c ON c.id = p.value_id
+	WHERE p.parent_id = target AND c.block_type = 'data' AND c.block_data IS NOT NULL
+$$ LANGUAGE SQL STABLE;
+
+DROP FUNCTION json_text(JSONB);
@@ -0,0 +1,85 @@
+-- The text of a JSON value. Strings are kept as they are, and the strings
+-- inside of objects and lists are joined, which covers rich text.
+CREATE FUNCTION json_text(data JSONB) RETURNS TEXT AS $$
+	SELECT COALESCE(string_agg(value #>> '{}', ' '), '')
+	FROM jsonb_path_query(data, 'strict $.** ? (@.type() == "string")') AS value
+$$ LANGUAGE SQL IMMUTABLE;
+
+CREATE OR REPLACE FUNCTION block_search_content(target BIGINT) RETURNS TEXT AS $$
+	SELECT left(COALESCE(string_agg(json_text(c.block_data), ' ' ORDER BY p.id), ''), 100000)
+	FROM properties p
+	INNER JOIN blocks c ON c.id = p.value_id
+	WHERE p.parent_id = target AND c.block_type = 'data' AND c.block_data IS NOT NULL
+$$ LANGUAGE SQL STABLE;
+
+CREATE OR REPLACE FUNCTION data_search_name() RETURNS trigger AS $$
+BEGIN
+	NEW.search_name := json_text(NEW.block_data);
+	RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+-- The text of the comments on a block, oldest first. A comment's text is
+-- the data of its content block and of that block's data children.
+CREATE FUNCTION block_search_comments(target BIGINT) RETURNS TEXT AS $$
+	SELECT left(
+		COALESCE(
+			string_agg(concat_ws(' ', json_text(c.block_data), c.search_content), ' ' ORDER BY m.id),
+			''
+		),
+		100000
+	)
+	FROM comments m
+	INNER JOIN blocks c ON c.id = m.content_id
+	WHERE m.block_id = target
+$$ LANGUAGE SQL STABLE;
+
+-- Comments weigh less than the block's own content
+ALTER TABLE blocks ADD COLUMN search_comments TEXT NOT NULL DEFAULT '';
+DROP INDEX blocks_search_document;
+ALTER TABLE blocks DROP COLUMN search_document;
+ALTER TABLE blocks ADD COLUMN search_document tsvector GENERATED ALWAYS AS (
+	setweight(to_tsvector('simple', search_name), 'A') ||
+	setweight(to_tsvector('simple', search_content), 'B') ||
+	setweight(to_tsvector('simple', search_comments), 'C')
+) STORED;
+CREATE INDEX blocks_search_document ON blocks USING GIN (search_document);
+CREATE INDEX comments_content_id ON comments (content_id);
+
+UPDATE blocks SET search_name = json_text(block_data) WHERE block_type = 'data';
+UPDATE blocks SET search_content = block_search_content(id) WHERE block_type <> 'data';
+UPDATE blocks SET search_comments = block_search_comments(id)
+WHERE id IN (SELECT block_id FROM comments);
+
+CREATE FUNCTION comment_search_comments() RETURNS trigger AS $$
+BEGIN
+	IF TG_OP <> 'INSERT' THEN
+		UPDATE blocks SET search_comments = block_search_comments(id) WHERE id = OLD.block_id;
+	END IF;
+	IF TG_OP <> 'DELETE' THEN
+		UPDATE blocks SET search_comments = block_search_comments(id) WHERE id = NEW.block_id;
+	END IF;
+	RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER comment_search_comments
+	AFTER INSERT OR UPDATE OF block_id, content_id OR DELETE ON comments
+	FOR EACH ROW EXECUTE PROCEDURE comment_search_comments();
+
+-- Comments are edited by changing their content block
+CREATE FUNCTION content_search_comments() RETURNS trigger AS $$
+BEGIN
+	UPDATE blocks SET search_comments = block_search_comments(id)
+	WHERE id IN (SELECT block_id FROM comments WHERE content_id = NEW.id);
+	RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER content_search_comments
+	AFTER UPDATE OF block_data, search_content ON blocks
+	FOR EACH ROW WHEN (
+		OLD.block_data IS DISTINCT FROM NEW.block_data
+		OR OLD.search_content IS DISTINCT FROM NEW.search_content
+	)
+	EXECUTE PROCEDURE content_search_comments();
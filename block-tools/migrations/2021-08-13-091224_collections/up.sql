@@ -0,0 +1,17 @@
+CREATE TABLE collections (
+	id BIGSERIAL PRIMARY KEY,
+	owner_id INT NOT NULL,
+	name VARCHAR(128) NOT NULL,
+	query TEXT NOT NULL,
+	filters JSONB NOT NULL DEFAULT '{}',
+	with_data BOOLEAN NOT NULL DEFAULT FALSE,
+	sort VARCHAR(16) NOT NULL DEFAULT 'rank',
+	subscribed_at TIMESTAMP,
+	notified_ids BIGINT[] NOT NULL DEFAULT '{}',
+	created_at TIMESTAMP NOT NULL,
+	updated_at TIMESTAMP NOT NULL,
+	CONSTRAINT fk_owner_id FOREIGN KEY (owner_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE INDEX collections_owner_id ON collections (owner_id);
+CREATE INDEX collections_subscribed ON collections (subscribed_at) WHERE subscribed_at IS NOT NULL;
@@ -0,0 +1 @@
+DROP INDEX block_tags_block_id;
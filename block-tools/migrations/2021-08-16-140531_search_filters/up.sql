@@ -0,0 +1,2 @@
+-- Tag filters look up the tags of each block that matches
+CREATE INDEX block_tags_block_id ON block_tags (block_id);
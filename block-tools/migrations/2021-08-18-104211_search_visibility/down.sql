@@ -0,0 +1,29 @@
+DROP TRIGGER perms_search_content ON blocks;
+DROP FUNCTION perms_search_content();
+
+CREATE OR REPLACE FUNCTION block_search_comments(target BIGINT) RETURNS TEXT AS $$
+	SELECT left(
+		COALESCE(
+			string_agg(concat_ws(' ', json_text(c.block_data), c.search_content), ' ' ORDER BY m.id),
+			''
+		),
+		100000
+	)
+	FROM comments m
+	INNER JOIN blocks c ON c.id = m.content_id
+	WHERE m.block_id = target
+$$ LANGUAGE SQL STABLE;
+
+CREATE OR REPLACE FUNCTION block_search_content(target BIGINT) RETURNS TEXT AS $$
+	SELECT left(COALESCE(string_agg(json_text(c.block_data), ' ' ORDER BY p.id), ''), 100000)
+	FROM properties p
+	INNER JOIN blocks c ON c.id = p.value_id
+	WHERE p.parent_id = target AND c.block_type = 'data' AND c.block_data IS NOT NULL
+$$ LANGUAGE SQL STABLE;
+
+DROP FUNCTION visible_with(blocks, blocks);
+DROP FUNCTION block_viewers(blocks);
+
+UPDATE blocks SET search_content = block_search_content(id) WHERE block_type <> 'data';
+UPDATE blocks SET search_comments = block_search_comments(id)
+WHERE id IN (SELECT block_id FROM comments);
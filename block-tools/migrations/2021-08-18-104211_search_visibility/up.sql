@@ -0,0 +1,64 @@
+-- Everyone that can view a block when it isn't public
+CREATE FUNCTION block_viewers(b blocks) RETURNS INT[] AS $$
+	SELECT ARRAY[b.owner_id] || b.co_owners
+		|| b.perm_full || b.perm_edit || b.perm_comment || b.perm_view
+		|| b.inherited_full || b.inherited_edit || b.inherited_comment || b.inherited_view
+$$ LANGUAGE SQL IMMUTABLE;
+
+-- Whether everyone that can view `target` can view `source` too. The text of
+-- other blocks is only searched and shown as part of a block when it is, so
+-- that search results don't reveal text the searcher can't view.
+CREATE FUNCTION visible_with(source blocks, target blocks) RETURNS BOOLEAN AS $$
+	SELECT source.public OR source.inherited_public OR (
+		NOT (target.public OR target.inherited_public)
+		AND block_viewers(source) @> block_viewers(target)
+	)
+$$ LANGUAGE SQL IMMUTABLE;
+
+CREATE OR REPLACE FUNCTION block_search_content(target BIGINT) RETURNS TEXT AS $$
+	SELECT left(COALESCE(string_agg(json_text(c.block_data), ' ' ORDER BY p.id), ''), 100000)
+	FROM properties p
+	INNER JOIN blocks t ON t.id = p.parent_id
+	INNER JOIN blocks c ON c.id = p.value_id
+	WHERE p.parent_id = target AND c.block_type = 'data' AND c.block_data IS NOT NULL
+	AND visible_with(c, t)
+$$ LANGUAGE SQL STABLE;
+
+CREATE OR REPLACE FUNCTION block_search_comments(target BIGINT) RETURNS TEXT AS $$
+	SELECT left(
+		COALESCE(
+			string_agg(concat_ws(' ', json_text(c.block_data), c.search_content), ' ' ORDER BY m.id),
+			''
+		),
+		100000
+	)
+	FROM comments m
+	INNER JOIN blocks t ON t.id = m.block_id
+	INNER JOIN blocks c ON c.id = m.content_id
+	WHERE m.block_id = target AND visible_with(c, t)
+$$ LANGUAGE SQL STABLE;
+
+-- Who can view a block decides whether its text is part of its parents' and
+-- of the blocks it comments on, and whether their text is part of its own
+CREATE FUNCTION perms_search_content() RETURNS trigger AS $$
+BEGIN
+	UPDATE blocks SET search_content = block_search_content(id)
+	WHERE id = NEW.id OR id IN (SELECT parent_id FROM properties WHERE value_id = NEW.id);
+	UPDATE blocks SET search_comments = block_search_comments(id)
+	WHERE id = NEW.id OR id IN (SELECT block_id FROM comments WHERE content_id = NEW.id);
+	RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER perms_search_content
+	AFTER UPDATE ON blocks
+	FOR EACH ROW WHEN (
+		OLD.public IS DISTINCT FROM NEW.public
+		OR OLD.inherited_public IS DISTINCT FROM NEW.inherited_public
+		OR block_viewers(OLD) IS DISTINCT FROM block_viewers(NEW)
+	)
+	EXECUTE PROCEDURE perms_search_content();
+
+UPDATE blocks SET search_content = block_search_content(id) WHERE block_type <> 'data';
+UPDATE blocks SET search_comments = block_search_comments(id)
+WHERE id IN (SELECT block_id FROM comments);
@@ -0,0 +1,4 @@
+DROP INDEX audit_events_block_id;
+DROP INDEX audit_events_actor_id;
+CREATE INDEX audit_events_block_id ON audit_events (block_id, created_at);
+CREATE INDEX audit_events_actor_id ON audit_events (actor_id, created_at);
@@ -0,0 +1,6 @@
+-- Audit logs are listed newest first by ID, so that is what the indexes
+-- are ordered by
+DROP INDEX audit_events_block_id;
+DROP INDEX audit_events_actor_id;
+CREATE INDEX audit_events_block_id ON audit_events (block_id, id);
+CREATE INDEX audit_events_actor_id ON audit_events (actor_id, id);
@@ -0,0 +1 @@
+ALTER TABLE blocks DROP COLUMN block_data_text;
@@ -0,0 +1,4 @@
+-- JSON doesn't keep how block data was written, so the text of data that is
+-- stored as a JSON object or list is kept as well. Data stored before this
+-- can't be written differently from its JSON anymore, so it has no text.
+ALTER TABLE blocks ADD COLUMN block_data_text TEXT;
@@ -0,0 +1,11 @@
+ALTER TABLE collections ADD COLUMN notified_ids BIGINT[] NOT NULL DEFAULT '{}';
+
+UPDATE collections c SET notified_ids = n.block_ids
+FROM (
+	SELECT collection_id, array_agg(block_id ORDER BY id) AS block_ids
+	FROM collection_notified
+	GROUP BY collection_id
+) n
+WHERE n.collection_id = c.id;
+
+DROP TABLE collection_notified;
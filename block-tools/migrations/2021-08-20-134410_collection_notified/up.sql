@@ -0,0 +1,19 @@
+-- The blocks the owner of a collection was notified about, one row each so
+-- that marking a block is a single insert instead of rewriting a list
+CREATE TABLE collection_notified (
+	id BIGSERIAL PRIMARY KEY,
+	collection_id BIGINT NOT NULL,
+	block_id BIGINT NOT NULL,
+	CONSTRAINT fk_collection_id FOREIGN KEY (collection_id) REFERENCES collections (id) ON DELETE CASCADE,
+	CONSTRAINT fk_block_id FOREIGN KEY (block_id) REFERENCES blocks (id) ON DELETE CASCADE,
+	CONSTRAINT collection_notified_unique UNIQUE (collection_id, block_id)
+);
+
+CREATE INDEX collection_notified_block_id ON collection_notified (block_id);
+
+INSERT INTO collection_notified (collection_id, block_id)
+SELECT DISTINCT c.id, n.block_id
+FROM collections c, unnest(c.notified_ids) AS n(block_id)
+WHERE EXISTS (SELECT 1 FROM blocks b WHERE b.id = n.block_id);
+
+ALTER TABLE collections DROP COLUMN notified_ids;
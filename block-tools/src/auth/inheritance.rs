@@ -0,0 +1,301 @@
+use std::collections::{HashMap, HashSet};
+
+/// The permissions a block gets from the blocks it is a property of and from
+/// the teams it is shared with. They are stored on the block so that checking
+/// access doesn't need to walk its parents or load team members.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InheritedPerms {
+	pub public: bool,
+	pub full: Vec<i32>,
+	pub edit: Vec<i32>,
+	pub comment: Vec<i32>,
+	pub view: Vec<i32>,
+}
+
+impl InheritedPerms {
+	/// The permissions a block grants by itself. The owners of a parent get
+	/// full permissions on the children, since a block has its own owners.
+	pub fn granted(
+		owner_ids: &[i32],
+		public: bool,
+		perm_full: &[i32],
+		perm_edit: &[i32],
+		perm_comment: &[i32],
+		perm_view: &[i32],
+	) -> Self {
+		let mut full = perm_full.to_vec();
+		full.extend(owner_ids);
+		InheritedPerms {
+			public,
+			full,
+			edit: perm_edit.to_vec(),
+			comment: perm_comment.to_vec(),
+			view: perm_view.to_vec(),
+		}
+		.normalized()
+	}
+
+	/// Adds every permission from `other`
+	pub fn merge(&mut self, other: &InheritedPerms) {
+		self.public |= other.public;
+		self.full.extend(&other.full);
+		self.edit.extend(&other.edit);
+		self.comment.extend(&other.comment);
+		self.view.extend(&other.view);
+		*self = self.clone().normalized();
+	}
+
+	/// Sorts the lists and removes duplicates, so that the same
+	/// permissions are always equal
+	fn normalized(mut self) -> Self {
+		for list in [
+			&mut self.full,
+			&mut self.edit,
+			&mut self.comment,
+			&mut self.view,
+		]
+		.iter_mut()
+		{
+			list.sort_unstable();
+			list.dedup();
+		}
+		self
+	}
+}
+
+/// What is needed about a block to work out what it inherits
+#[derive(Debug, Clone, Default)]
+pub struct PermNode {
+	/// Whether the block opted in to inheriting from its parents
+	pub inherit: bool,
+	/// IDs of the blocks this block is a property of
+	pub parents: Vec<i64>,
+	/// The permissions the block grants by itself
+	pub own: InheritedPerms,
+	/// The permissions the block grants to members of its teams. These are kept
+	/// whether or not the block inherits from its parents.
+	pub teams: InheritedPerms,
+	/// What the block inherited the last time it was resolved
+	pub inherited: InheritedPerms,
+}
+
+impl PermNode {
+	fn effective(&self, inherited: Option<&InheritedPerms>) -> InheritedPerms {
+		let mut perms = self.own.clone();
+		perms.merge(inherited.unwrap_or(&self.inherited));
+		perms
+	}
+}
+
+/// Works out what each of the `dirty` blocks inherits. Blocks that aren't
+/// dirty keep what they inherited before. Dirty blocks start with only their
+/// team permissions and only gain more, so parents that form a cycle settle on the
+/// permissions granted from outside of the cycle instead of keeping stale ones.
+pub fn resolve_inheritance(
+	nodes: &HashMap<i64, PermNode>,
+	dirty: &HashSet<i64>,
+) -> HashMap<i64, InheritedPerms> {
+	let mut order: Vec<i64> = dirty.iter().copied().collect();
+	order.sort_unstable();
+	let mut resolved: HashMap<i64, InheritedPerms> = order
+		.iter()
+		.map(|id| {
+			let teams = nodes.get(id).map(|node| node.teams.clone());
+			(*id, teams.unwrap_or_default())
+		})
+		.collect();
+
+	loop {
+		let mut changed = false;
+		for id in &order {
+			let node = match nodes.get(id) {
+				Some(node) if node.inherit => node,
+				_ => continue,
+			};
+			let mut inherited = node.teams.clone();
+			for parent_id in &node.parents {
+				if let Some(parent) = nodes.get(parent_id) {
+					inherited.merge(&parent.effective(resolved.get(parent_id)));
+				}
+			}
+			if resolved.get(id) != Some(&inherited) {
+				resolved.insert(*id, inherited);
+				changed = true;
+			}
+		}
+		if !changed {
+			return resolved;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn node(inherit: bool, parents: Vec<i64>, own: InheritedPerms) -> PermNode {
+		PermNode {
+			inherit,
+			parents,
+			own,
+			..PermNode::default()
+		}
+	}
+
+	fn owned_by(owner_id: i32) -> InheritedPerms {
+		InheritedPerms::granted(&[owner_id], false, &[], &[], &[], &[])
+	}
+
+	fn all(nodes: &HashMap<i64, PermNode>) -> HashSet<i64> {
+		nodes.keys().copied().collect()
+	}
+
+	#[test]
+	fn child_gets_parent_perms() {
+		let mut nodes = HashMap::new();
+		nodes.insert(
+			1,
+			node(
+				false,
+				vec![],
+				InheritedPerms::granted(&[10], true, &[11], &[12], &[14], &[13]),
+			),
+		);
+		nodes.insert(2, node(true, vec![1], owned_by(20)));
+		let resolved = resolve_inheritance(&nodes, &all(&nodes));
+		assert_eq!(
+			resolved[&2],
+			InheritedPerms {
+				public: true,
+				full: vec![10, 11],
+				edit: vec![12],
+				comment: vec![14],
+				view: vec![13],
+			}
+		);
+		assert_eq!(resolved[&1], InheritedPerms::default());
+	}
+
+	#[test]
+	fn opted_out_child_inherits_nothing() {
+		let mut nodes = HashMap::new();
+		nodes.insert(1, node(false, vec![], owned_by(10)));
+		nodes.insert(2, node(false, vec![1], owned_by(20)));
+		nodes.insert(3, node(true, vec![2], owned_by(30)));
+		let resolved = resolve_inheritance(&nodes, &all(&nodes));
+		assert_eq!(resolved[&2], InheritedPerms::default());
+		// The grandchild still gets the perms the child grants by itself
+		assert_eq!(resolved[&3].full, vec![20]);
+	}
+
+	#[test]
+	fn own_perms_override_inherited_ones() {
+		let mut nodes = HashMap::new();
+		nodes.insert(
+			1,
+			node(
+				false,
+				vec![],
+				InheritedPerms::granted(&[10], false, &[], &[], &[], &[5]),
+			),
+		);
+		nodes.insert(
+			2,
+			node(
+				true,
+				vec![1],
+				InheritedPerms::granted(&[20], false, &[5], &[], &[], &[]),
+			),
+		);
+		nodes.insert(3, node(true, vec![2], owned_by(30)));
+		let resolved = resolve_inheritance(&nodes, &all(&nodes));
+		assert_eq!(resolved[&3].full, vec![5, 10, 20]);
+		assert_eq!(resolved[&3].view, vec![5]);
+	}
+
+	#[test]
+	fn deep_tree() {
+		let depth = 500;
+		let mut nodes = HashMap::new();
+		nodes.insert(
+			0,
+			node(
+				false,
+				vec![],
+				InheritedPerms::granted(&[1], false, &[], &[], &[], &[7]),
+			),
+		);
+		for id in 1..depth {
+			nodes.insert(id, node(true, vec![id - 1], owned_by(id as i32 + 1)));
+		}
+		let resolved = resolve_inheritance(&nodes, &all(&nodes));
+		let leaf = &resolved[&(depth - 1)];
+		assert_eq!(leaf.view, vec![7]);
+		assert_eq!(leaf.full, (1..depth as i32).collect::<Vec<i32>>());
+	}
+
+	#[test]
+	fn only_dirty_blocks_are_resolved() {
+		let mut nodes = HashMap::new();
+		nodes.insert(1, node(false, vec![], owned_by(10)));
+		let mut middle = node(true, vec![1], owned_by(20));
+		middle.inherited = owned_by(99);
+		nodes.insert(2, middle);
+		nodes.insert(3, node(true, vec![2], owned_by(30)));
+		let dirty: HashSet<i64> = vec![3].into_iter().collect();
+		let resolved = resolve_inheritance(&nodes, &dirty);
+		assert_eq!(resolved.len(), 1);
+		assert_eq!(resolved[&3].full, vec![20, 99]);
+	}
+
+	#[test]
+	fn cycle_shares_perms() {
+		let mut nodes = HashMap::new();
+		nodes.insert(1, node(true, vec![3], owned_by(10)));
+		nodes.insert(2, node(true, vec![1], owned_by(20)));
+		nodes.insert(3, node(true, vec![2], owned_by(30)));
+		let resolved = resolve_inheritance(&nodes, &all(&nodes));
+		for id in 1..=3 {
+			assert_eq!(resolved[&id].full, vec![10, 20, 30]);
+		}
+	}
+
+	#[test]
+	fn cycle_drops_revoked_perms() {
+		let mut nodes = HashMap::new();
+		// Block 1 used to share with user 5, which spread around the cycle
+		let mut first = node(true, vec![2], owned_by(10));
+		first.inherited = InheritedPerms::granted(&[20], false, &[], &[], &[], &[5]);
+		let mut second = node(true, vec![1], owned_by(20));
+		second.inherited = InheritedPerms::granted(&[10], false, &[], &[], &[], &[5]);
+		nodes.insert(1, first);
+		nodes.insert(2, second);
+		let resolved = resolve_inheritance(&nodes, &all(&nodes));
+		assert!(resolved[&1].view.is_empty());
+		assert!(resolved[&2].view.is_empty());
+	}
+
+	#[test]
+	fn team_perms_without_inheriting() {
+		let mut nodes = HashMap::new();
+		nodes.insert(1, node(false, vec![], owned_by(10)));
+		let mut child = node(false, vec![1], owned_by(20));
+		child.teams = InheritedPerms::granted(&[30], false, &[], &[31, 32], &[], &[]);
+		nodes.insert(2, child);
+		nodes.insert(3, node(true, vec![2], owned_by(40)));
+		let resolved = resolve_inheritance(&nodes, &all(&nodes));
+		assert_eq!(resolved[&2].edit, vec![31, 32]);
+		assert_eq!(resolved[&2].full, vec![30]);
+		// Team members of a parent reach the children that inherit
+		assert_eq!(resolved[&3].edit, vec![31, 32]);
+		assert_eq!(resolved[&3].full, vec![20, 30]);
+	}
+
+	#[test]
+	fn self_parent() {
+		let mut nodes = HashMap::new();
+		nodes.insert(1, node(true, vec![1], owned_by(10)));
+		let resolved = resolve_inheritance(&nodes, &all(&nodes));
+		assert_eq!(resolved[&1].full, vec![10]);
+	}
+}
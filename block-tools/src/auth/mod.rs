@@ -3,6 +3,7 @@ use chrono::{prelude::*, Duration};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use log::error;
 use serde::{Deserialize, Serialize};
+pub mod inheritance;
 pub mod permissions;
 
 pub fn require_token(context: &Context) -> Result<String, UserError> {
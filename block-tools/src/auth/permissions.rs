@@ -1,9 +1,14 @@
-use crate::{blocks::Context, models::Block, UserError};
+use crate::{
+	blocks::Context,
+	models::{Block, ShareLink},
+	LoopError,
+};
 
 use super::{optional_token, optional_validate_token};
+use serde::{Deserialize, Serialize};
 
 pub fn can_view(user_id: Option<i32>, block: &Block) -> bool {
-	let mut allowed = block.public;
+	let mut allowed = block.public || block.inherited.public;
 	if !allowed {
 		if let Some(user_id) = user_id {
 			if has_perm_level(user_id, block, PermLevel::View) {
@@ -14,51 +19,127 @@ pub fn can_view(user_id: Option<i32>, block: &Block) -> bool {
 	allowed
 }
 
-pub fn use_view(context: &Context, block: Block) -> Result<Option<Block>, UserError> {
+/// Whether the user can comment on the block. Any signed in user can comment
+/// on public blocks, but sharing a block with view access doesn't allow comments.
+pub fn can_comment(user_id: i32, block: &Block) -> bool {
+	block.public || block.inherited.public || has_perm_level(user_id, block, PermLevel::Comment)
+}
+
+pub fn use_view(context: &Context, block: Block) -> Result<Option<Block>, LoopError> {
 	let token = optional_token(context);
 	let user_id = optional_validate_token(token)?;
-	if can_view(user_id, &block) {
+	if can_view(user_id, &block) || link_has_perm_level(context, &block, PermLevel::View)? {
 		Ok(Some(block))
 	} else {
 		Ok(None)
 	}
 }
 
-pub fn maybe_use_view(context: &Context, block: Option<Block>) -> Result<Option<Block>, UserError> {
+pub fn maybe_use_view(context: &Context, block: Option<Block>) -> Result<Option<Block>, LoopError> {
 	match block {
 		Some(block) => use_view(context, block),
 		None => Ok(None),
 	}
 }
 
+/// Whether the user has at least a level of permissions on the block. Members
+/// of teams the block is shared with get the team's level, and blocks that
+/// inherit permissions also grant what their parents grant. Both are stored
+/// on the block so that teams and parents don't have to be loaded.
 pub fn has_perm_level(user_id: i32, block: &Block, level: PermLevel) -> bool {
-	if block.owner_id == user_id {
+	let inherited = &block.inherited;
+	if block.owner_id == user_id || block.co_owners.contains(&user_id) {
 		return true;
 	}
 	if let PermLevel::Owner = level {
 		return false;
 	}
-	if block.perm_full.contains(&user_id) {
+	if block.perm_full.contains(&user_id) || inherited.full.contains(&user_id) {
 		return true;
 	}
 	if let PermLevel::Full = level {
 		return false;
 	}
-	if block.perm_edit.contains(&user_id) {
+	if block.perm_edit.contains(&user_id) || inherited.edit.contains(&user_id) {
 		return true;
 	}
 	if let PermLevel::Edit = level {
 		return false;
 	}
-	if block.public || block.perm_view.contains(&user_id) {
+	if block.perm_comment.contains(&user_id) || inherited.comment.contains(&user_id) {
+		return true;
+	}
+	if let PermLevel::Comment = level {
+		return false;
+	}
+	if block.public
+		|| inherited.public
+		|| block.perm_view.contains(&user_id)
+		|| inherited.view.contains(&user_id)
+	{
 		return true;
 	}
 	false
 }
 
+/// Whether a request has at least a level of permissions on the block, either
+/// as the authenticated user or through the share link it carries
+pub fn request_has_perm_level(
+	context: &Context,
+	block: &Block,
+	level: PermLevel,
+) -> Result<bool, LoopError> {
+	if let Some(user_id) = optional_validate_token(optional_token(context))? {
+		if has_perm_level(user_id, block, level) {
+			return Ok(true);
+		}
+	}
+	link_has_perm_level(context, block, level)
+}
+
+/// Whether the share link a request carries gives at least a level on the block
+pub fn link_has_perm_level(
+	context: &Context,
+	block: &Block,
+	level: PermLevel,
+) -> Result<bool, LoopError> {
+	let token = match &context.share_token {
+		Some(token) => token,
+		None => return Ok(false),
+	};
+	let granted =
+		ShareLink::by_token(token, &context.shared_conn()?)?.and_then(|link| link.grants(block.id));
+	Ok(matches!(granted, Some(granted) if granted >= level))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
 pub enum PermLevel {
 	View,
+	Comment,
 	Edit,
 	Full,
 	Owner,
 }
+
+impl PermLevel {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::View => "view",
+			Self::Comment => "comment",
+			Self::Edit => "edit",
+			Self::Full => "full",
+			Self::Owner => "owner",
+		}
+	}
+
+	pub fn from_name(level: &str) -> Self {
+		match level {
+			"comment" => Self::Comment,
+			"edit" => Self::Edit,
+			"full" => Self::Full,
+			"owner" => Self::Owner,
+			_ => Self::View,
+		}
+	}
+}
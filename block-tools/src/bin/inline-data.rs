@@ -0,0 +1,29 @@
+//! Moves the `data` children of a block type's property into inline values.
+//!
+//! Usage: `cargo run -p block-tools --bin inline-data -- <block type> <property name>`
+use block_tools::{env_db, get_pool, models::InlineProperty};
+use std::env;
+
+fn main() {
+	let args: Vec<String> = env::args().skip(1).collect();
+	let (block_type, property_name) = match args.as_slice() {
+		[block_type, property_name] => (block_type, property_name),
+		_ => {
+			eprintln!("Usage: inline-data <block type> <property name>");
+			std::process::exit(1);
+		}
+	};
+
+	let pool = get_pool(&env_db());
+	let conn = pool.get().expect("could not connect to the database");
+	match InlineProperty::inline_data_children(block_type, property_name, &conn) {
+		Ok(changed) => println!(
+			"Moved {} `{}` properties of {} blocks inline.",
+			changed, property_name, block_type
+		),
+		Err(err) => {
+			eprintln!("{}", err);
+			std::process::exit(1);
+		}
+	}
+}
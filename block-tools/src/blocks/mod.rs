@@ -1,10 +1,11 @@
 use crate::{
+	auth::{optional_token, optional_validate_token, permissions::PermLevel},
 	display_api::{
 		component::{atomic::icon::Icon, DisplayComponent},
 		CreationObject, DisplayObject,
 	},
-	models::Block,
-	BlockError, LoopError, PgConnect, PostgresPool,
+	models::{Block, BlockRevision},
+	BlockError, ContextConn, LoopError, PgConnect, PostgresPool, SharedTransaction,
 };
 
 /// The context to share among GraphQL requests
@@ -12,12 +13,45 @@ pub struct Context {
 	/// Gives the GraphQL operations access to the DB
 	pub pool: PostgresPool,
 	pub auth_token: Option<String>,
+	/// The token of a share link, which gives access to one block without a user
+	pub share_token: Option<String>,
+	/// The transaction of a batch, which `shared_conn` gives out instead of the pool
+	pub transaction: Option<SharedTransaction>,
 }
 
 impl Context {
 	pub fn conn(&self) -> Result<PgConnect, r2d2::Error> {
 		self.pool.get()
 	}
+
+	/// The connection of the batch the context runs, or one from the pool
+	/// outside of a batch. Changes made with `conn` aren't part of a batch.
+	pub fn shared_conn(&self) -> Result<ContextConn, r2d2::Error> {
+		Ok(match &self.transaction {
+			Some(transaction) => ContextConn::Shared(transaction.conn()),
+			None => ContextConn::Pooled(self.pool.get()?),
+		})
+	}
+
+	/// The user the context's token is for, if it has one
+	pub fn user_id(&self) -> Result<Option<i32>, LoopError> {
+		Ok(optional_validate_token(optional_token(self))?)
+	}
+
+	/// Runs block type code, recording the changes it makes without an
+	/// author as made by the context's user
+	pub fn acting<T>(&self, f: impl FnOnce() -> Result<T, LoopError>) -> Result<T, LoopError> {
+		BlockRevision::act_as(self.user_id()?, f)
+	}
+
+	/// Commits the context's transaction, or rolls it back
+	pub fn end_transaction(&mut self, commit: bool) -> Result<(), LoopError> {
+		match self.transaction.take() {
+			Some(transaction) if commit => transaction.commit(),
+			Some(transaction) => transaction.rollback(),
+			None => Ok(()),
+		}
+	}
 }
 
 pub struct TypeInfo {
@@ -40,6 +74,12 @@ pub trait BlockType {
 	) -> Result<Block, LoopError> {
 		Err(BlockError::MethodExist(name, Self::name()).into())
 	}
+	/// The methods that `method_delegate` handles, with the level needed to
+	/// call each one. Access is checked before `method_delegate` runs, and
+	/// methods that aren't listed need edit permissions.
+	fn methods() -> Vec<(String, PermLevel)> {
+		vec![]
+	}
 	fn info() -> TypeInfo;
 	fn block_name(block: &Block, context: &Context) -> Result<String, LoopError>;
 	fn visibility_update(
@@ -58,4 +98,13 @@ pub trait BlockType {
 	) -> Result<(), LoopError> {
 		Ok(())
 	}
+	/// Runs after the users with comment permissions change, alongside
+	/// `general_perm_update`
+	fn comment_perm_update(
+		_context: &Context,
+		_block_id: i64,
+		_perm_comment: Vec<i32>,
+	) -> Result<(), LoopError> {
+		Ok(())
+	}
 }
@@ -1,6 +1,8 @@
 pub mod pool;
 pub use pool::*;
 pub mod models;
+pub mod pagination;
+pub use pagination::*;
 pub mod schema;
 pub mod use_diesel;
 pub use diesel::result::Error as DieselError;
@@ -0,0 +1,182 @@
+use super::super::schema::access_requests;
+use crate::{auth::permissions::PermLevel, LoopError};
+use diesel::prelude::*;
+use std::time::SystemTime;
+
+/// A request from a user for access to a block they can't use yet. Users
+/// with full permissions on the block approve or deny it.
+#[derive(Queryable, Clone)]
+pub struct AccessRequest {
+	pub id: i64,
+	pub block_id: i64,
+	pub requester_id: i32,
+	/// The level that was asked for
+	pub perm_level: String,
+	pub message: Option<String>,
+	/// One of `pending`, `approved` or `denied`
+	pub status: String,
+	/// The level that was given, which can be lower than the one asked for
+	pub granted_level: Option<String>,
+	pub decided_by: Option<i32>,
+	pub created_at: SystemTime,
+	pub decided_at: Option<SystemTime>,
+}
+
+#[derive(Insertable)]
+#[table_name = "access_requests"]
+pub struct NewAccessRequest {
+	pub block_id: i64,
+	pub requester_id: i32,
+	pub perm_level: String,
+	pub message: Option<String>,
+	pub created_at: SystemTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessRequestStatus {
+	Pending,
+	Approved,
+	Denied,
+}
+
+impl AccessRequestStatus {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Pending => "pending",
+			Self::Approved => "approved",
+			Self::Denied => "denied",
+		}
+	}
+
+	pub fn from_name(status: &str) -> Self {
+		match status {
+			"approved" => Self::Approved,
+			"denied" => Self::Denied,
+			_ => Self::Pending,
+		}
+	}
+}
+
+impl NewAccessRequest {
+	pub fn new(
+		block_id: i64,
+		requester_id: i32,
+		level: PermLevel,
+		message: Option<String>,
+	) -> Self {
+		NewAccessRequest {
+			block_id,
+			requester_id,
+			perm_level: level.as_str().to_string(),
+			message,
+			created_at: SystemTime::now(),
+		}
+	}
+
+	/// Inserts the request. If the user already has a pending request for the
+	/// block, that one is updated with the new level and message instead.
+	pub fn insert(self, conn: &PgConnection) -> Result<AccessRequest, LoopError> {
+		if let Some(pending) = AccessRequest::pending_by(self.block_id, self.requester_id, conn)? {
+			return Ok(diesel::update(
+				access_requests::dsl::access_requests.filter(access_requests::id.eq(pending.id)),
+			)
+			.set((
+				access_requests::perm_level.eq(self.perm_level),
+				access_requests::message.eq(self.message),
+				access_requests::created_at.eq(self.created_at),
+			))
+			.get_result(conn)?);
+		}
+		Ok(diesel::insert_into(access_requests::table)
+			.values(self)
+			.get_result(conn)?)
+	}
+}
+
+impl AccessRequest {
+	pub fn by_id(request_id: i64, conn: &PgConnection) -> Result<Option<Self>, LoopError> {
+		Ok(access_requests::dsl::access_requests
+			.filter(access_requests::id.eq(request_id))
+			.limit(1)
+			.get_result(conn)
+			.optional()?)
+	}
+
+	/// The requests for a block, oldest first. Requests that were
+	/// already decided are only included if `decided` is true.
+	pub fn of_block(
+		block_id: i64,
+		decided: bool,
+		conn: &PgConnection,
+	) -> Result<Vec<Self>, LoopError> {
+		let mut query = access_requests::dsl::access_requests
+			.filter(access_requests::block_id.eq(block_id))
+			.order_by(access_requests::id)
+			.into_boxed();
+		if !decided {
+			query = query.filter(access_requests::status.eq(AccessRequestStatus::Pending.as_str()));
+		}
+		Ok(query.load(conn)?)
+	}
+
+	/// The request a user is still waiting on for a block
+	pub fn pending_by(
+		block_id: i64,
+		requester_id: i32,
+		conn: &PgConnection,
+	) -> Result<Option<Self>, LoopError> {
+		Ok(access_requests::dsl::access_requests
+			.filter(access_requests::block_id.eq(block_id))
+			.filter(access_requests::requester_id.eq(requester_id))
+			.filter(access_requests::status.eq(AccessRequestStatus::Pending.as_str()))
+			.limit(1)
+			.get_result(conn)
+			.optional()?)
+	}
+
+	pub fn level(&self) -> PermLevel {
+		PermLevel::from_name(&self.perm_level)
+	}
+
+	pub fn granted(&self) -> Option<PermLevel> {
+		self.granted_level.as_deref().map(PermLevel::from_name)
+	}
+
+	pub fn status(&self) -> AccessRequestStatus {
+		AccessRequestStatus::from_name(&self.status)
+	}
+
+	/// Marks the request as approved with a level. This doesn't change
+	/// the block's permissions.
+	pub fn approve(
+		&self,
+		level: PermLevel,
+		decided_by: i32,
+		conn: &PgConnection,
+	) -> Result<AccessRequest, LoopError> {
+		self.decide(AccessRequestStatus::Approved, Some(level), decided_by, conn)
+	}
+
+	pub fn deny(&self, decided_by: i32, conn: &PgConnection) -> Result<AccessRequest, LoopError> {
+		self.decide(AccessRequestStatus::Denied, None, decided_by, conn)
+	}
+
+	fn decide(
+		&self,
+		status: AccessRequestStatus,
+		level: Option<PermLevel>,
+		decided_by: i32,
+		conn: &PgConnection,
+	) -> Result<AccessRequest, LoopError> {
+		Ok(diesel::update(
+			access_requests::dsl::access_requests.filter(access_requests::id.eq(self.id)),
+		)
+		.set((
+			access_requests::status.eq(status.as_str()),
+			access_requests::granted_level.eq(level.map(|level| level.as_str())),
+			access_requests::decided_by.eq(decided_by),
+			access_requests::decided_at.eq(SystemTime::now()),
+		))
+		.get_result(conn)?)
+	}
+}
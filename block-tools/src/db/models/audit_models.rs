@@ -0,0 +1,241 @@
+use super::super::schema::audit_events;
+use super::Block;
+use crate::LoopError;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+/// A change to who can access a block. Events are only ever
+/// inserted, so they answer who shared a block and when.
+#[derive(Queryable, Clone)]
+pub struct AuditEvent {
+	pub id: i64,
+	pub block_id: i64,
+	/// The user that made the change, if it is known
+	pub actor_id: Option<i32>,
+	/// One of `user` or `share_link`
+	pub source: String,
+	/// The share link the change was made with or about
+	pub share_link_id: Option<i64>,
+	pub action: String,
+	pub old_public: bool,
+	pub new_public: bool,
+	/// JSON of the block's permissions before the change
+	pub old_perms: String,
+	/// JSON of the block's permissions after the change
+	pub new_perms: String,
+	pub created_at: SystemTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "audit_events"]
+pub struct NewAuditEvent {
+	pub block_id: i64,
+	pub actor_id: Option<i32>,
+	pub source: String,
+	pub share_link_id: Option<i64>,
+	pub action: String,
+	pub old_public: bool,
+	pub new_public: bool,
+	pub old_perms: String,
+	pub new_perms: String,
+	pub created_at: SystemTime,
+}
+
+/// The kinds of changes that are audited
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+	Visibility,
+	Permissions,
+	TeamPermissions,
+	Inheritance,
+	CoOwners,
+	Owner,
+	Revert,
+	ShareLinkCreated,
+	ShareLinkRevoked,
+	ShareLinkRedeemed,
+}
+
+impl AuditAction {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Visibility => "visibility",
+			Self::Permissions => "permissions",
+			Self::TeamPermissions => "team_permissions",
+			Self::Inheritance => "inheritance",
+			Self::CoOwners => "co_owners",
+			Self::Owner => "owner",
+			Self::Revert => "revert",
+			Self::ShareLinkCreated => "share_link_created",
+			Self::ShareLinkRevoked => "share_link_revoked",
+			Self::ShareLinkRedeemed => "share_link_redeemed",
+		}
+	}
+
+	pub fn from_name(action: &str) -> Self {
+		match action {
+			"visibility" => Self::Visibility,
+			"team_permissions" => Self::TeamPermissions,
+			"inheritance" => Self::Inheritance,
+			"co_owners" => Self::CoOwners,
+			"owner" => Self::Owner,
+			"revert" => Self::Revert,
+			"share_link_created" => Self::ShareLinkCreated,
+			"share_link_revoked" => Self::ShareLinkRevoked,
+			"share_link_redeemed" => Self::ShareLinkRedeemed,
+			_ => Self::Permissions,
+		}
+	}
+}
+
+/// Where the access to make a change came from. The API doesn't have tokens
+/// other than the ones users sign in with, so there is no source for API
+/// tokens; changes made with a user's token are theirs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditSource {
+	/// The actor's own permissions on the block
+	User,
+	/// A share link that the actor had the token of
+	ShareLink,
+}
+
+impl AuditSource {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::User => "user",
+			Self::ShareLink => "share_link",
+		}
+	}
+
+	pub fn from_name(source: &str) -> Self {
+		match source {
+			"share_link" => Self::ShareLink,
+			_ => Self::User,
+		}
+	}
+}
+
+/// Everything that decides who can access a block, as it is stored in an event
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PermSnapshot {
+	pub owner_id: i32,
+	pub co_owners: Vec<i32>,
+	pub perm_full: Vec<i32>,
+	pub perm_edit: Vec<i32>,
+	pub perm_comment: Vec<i32>,
+	pub perm_view: Vec<i32>,
+	pub team_full: Vec<i64>,
+	pub team_edit: Vec<i64>,
+	pub team_comment: Vec<i64>,
+	pub team_view: Vec<i64>,
+	pub inherit_perms: bool,
+}
+
+impl From<&Block> for PermSnapshot {
+	fn from(block: &Block) -> Self {
+		PermSnapshot {
+			owner_id: block.owner_id,
+			co_owners: block.co_owners.clone(),
+			perm_full: block.perm_full.clone(),
+			perm_edit: block.perm_edit.clone(),
+			perm_comment: block.perm_comment.clone(),
+			perm_view: block.perm_view.clone(),
+			team_full: block.team_full.clone(),
+			team_edit: block.team_edit.clone(),
+			team_comment: block.team_comment.clone(),
+			team_view: block.team_view.clone(),
+			inherit_perms: block.inherit_perms,
+		}
+	}
+}
+
+impl NewAuditEvent {
+	/// An event for a change a user made with their own permissions. `before`
+	/// and `after` are the block before and after the change.
+	pub fn new(action: AuditAction, before: &Block, after: &Block, actor_id: Option<i32>) -> Self {
+		NewAuditEvent {
+			block_id: after.id,
+			actor_id,
+			source: AuditSource::User.as_str().to_string(),
+			share_link_id: None,
+			action: action.as_str().to_string(),
+			old_public: before.public,
+			new_public: after.public,
+			old_perms: perms_json(before),
+			new_perms: perms_json(after),
+			created_at: SystemTime::now(),
+		}
+	}
+
+	/// Marks the share link that the change was about
+	pub fn share_link(self, link_id: i64) -> Self {
+		NewAuditEvent {
+			share_link_id: Some(link_id),
+			..self
+		}
+	}
+
+	/// Marks the change as made with a share link's access
+	pub fn via_share_link(self, link_id: i64) -> Self {
+		NewAuditEvent {
+			source: AuditSource::ShareLink.as_str().to_string(),
+			..self.share_link(link_id)
+		}
+	}
+
+	pub fn insert(self, conn: &PgConnection) -> Result<AuditEvent, LoopError> {
+		Ok(diesel::insert_into(audit_events::table)
+			.values(self)
+			.get_result(conn)?)
+	}
+}
+
+impl AuditEvent {
+	/// The events for a block, newest first
+	pub fn of_block(
+		block_id: i64,
+		limit: i64,
+		conn: &PgConnection,
+	) -> Result<Vec<Self>, LoopError> {
+		Ok(audit_events::dsl::audit_events
+			.filter(audit_events::block_id.eq(block_id))
+			.order_by(audit_events::id.desc())
+			.limit(limit)
+			.load(conn)?)
+	}
+
+	/// The changes a user made, newest first
+	pub fn by_actor(
+		actor_id: i32,
+		limit: i64,
+		conn: &PgConnection,
+	) -> Result<Vec<Self>, LoopError> {
+		Ok(audit_events::dsl::audit_events
+			.filter(audit_events::actor_id.eq(actor_id))
+			.order_by(audit_events::id.desc())
+			.limit(limit)
+			.load(conn)?)
+	}
+
+	pub fn action(&self) -> AuditAction {
+		AuditAction::from_name(&self.action)
+	}
+
+	pub fn source(&self) -> AuditSource {
+		AuditSource::from_name(&self.source)
+	}
+
+	pub fn old_snapshot(&self) -> PermSnapshot {
+		serde_json::from_str(&self.old_perms).unwrap_or_default()
+	}
+
+	pub fn new_snapshot(&self) -> PermSnapshot {
+		serde_json::from_str(&self.new_perms).unwrap_or_default()
+	}
+}
+
+fn perms_json(block: &Block) -> String {
+	serde_json::to_string(&PermSnapshot::from(block)).unwrap_or_else(|_| "{}".into())
+}
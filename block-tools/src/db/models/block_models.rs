@@ -1,12 +1,17 @@
-use super::{super::schema::blocks, NewProperty};
-use crate::LoopError;
+use super::{
+	super::schema::{blocks, properties},
+	BlockReference, BlockRevision, Formula, NewBlockRevision, NewProperty, Property,
+	PropertySnapshot, RevisionChange,
+};
+use crate::{auth::inheritance::InheritedPerms, LoopError};
 use colors_transform::Color;
-use diesel::prelude::*;
+use diesel::{pg::Pg, prelude::*, Queryable};
 use palette::{Shade, Srgb};
 use rand::Rng;
+use serde_json::Value;
 use std::time::SystemTime;
 
-#[derive(Queryable, Clone)]
+#[derive(Clone)]
 pub struct Block {
 	pub id: i64,
 	pub block_type: String,
@@ -17,10 +22,116 @@ pub struct Block {
 	pub public: bool,
 	pub perm_full: Vec<i32>,
 	pub perm_edit: Vec<i32>,
+	pub perm_comment: Vec<i32>,
 	pub perm_view: Vec<i32>,
 	pub stars: Vec<i32>,
 	pub notif_enabled: Vec<i32>,
 	pub color: Option<String>,
+	/// Whether the block gets the permissions of the blocks it is a property of
+	pub inherit_perms: bool,
+	/// The permissions the block got from the teams it is shared with, and
+	/// from its parents if it inherits them
+	pub inherited: InheritedPerms,
+	/// IDs of the teams whose members have each level
+	pub team_full: Vec<i64>,
+	pub team_edit: Vec<i64>,
+	pub team_comment: Vec<i64>,
+	pub team_view: Vec<i64>,
+	/// Users that have the same permissions as the owner
+	pub co_owners: Vec<i32>,
+}
+
+type BlockRow = (
+	i64,
+	String,
+	SystemTime,
+	SystemTime,
+	Option<Value>,
+	i32,
+	bool,
+	Vec<i32>,
+	Vec<i32>,
+	Vec<i32>,
+	Vec<i32>,
+	Vec<i32>,
+	Option<String>,
+	bool,
+	bool,
+	Vec<i32>,
+	Vec<i32>,
+	Vec<i32>,
+	Vec<i64>,
+	Vec<i64>,
+	Vec<i64>,
+	Vec<i32>,
+	Vec<i32>,
+	Vec<i32>,
+	Vec<i64>,
+	Option<String>,
+);
+
+/// Block data is stored as JSON, but block types use it as a string
+impl Queryable<blocks::SqlType, Pg> for Block {
+	type Row = BlockRow;
+
+	fn build(row: Self::Row) -> Self {
+		Block {
+			id: row.0,
+			block_type: row.1,
+			created_at: row.2,
+			updated_at: row.3,
+			block_data: row.25.or_else(|| row.4.map(data_from_json)),
+			owner_id: row.5,
+			public: row.6,
+			perm_full: row.7,
+			perm_edit: row.8,
+			perm_comment: row.22,
+			perm_view: row.9,
+			stars: row.10,
+			notif_enabled: row.11,
+			color: row.12,
+			inherit_perms: row.13,
+			inherited: InheritedPerms {
+				public: row.14,
+				full: row.15,
+				edit: row.16,
+				comment: row.23,
+				view: row.17,
+			},
+			team_full: row.18,
+			team_edit: row.19,
+			team_comment: row.24,
+			team_view: row.20,
+			co_owners: row.21,
+		}
+	}
+}
+
+/// Turns block data into the JSON that is stored. Data that is a JSON object
+/// or list is kept as it is, and anything else is stored as a JSON string.
+pub fn data_to_json(data: &str) -> Value {
+	match serde_json::from_str::<Value>(data) {
+		Ok(value) if value.is_object() || value.is_array() => value,
+		_ => Value::String(data.to_string()),
+	}
+}
+
+/// The text of block data that is stored as a JSON object or list. The JSON
+/// doesn't keep how it was written, like its spacing and the order of its keys,
+/// so the text is stored too.
+pub fn data_text(data: &str) -> Option<String> {
+	match data_to_json(data) {
+		Value::String(_) => None,
+		_ => Some(data.to_string()),
+	}
+}
+
+/// Turns stored JSON back into the block data string
+pub fn data_from_json(value: Value) -> String {
+	match value {
+		Value::String(data) => data,
+		value => value.to_string(),
+	}
 }
 
 impl Block {
@@ -41,41 +152,91 @@ impl Block {
 			.optional()?)
 	}
 
+	/// Loads every block with one of the IDs. Missing blocks are left out.
+	pub fn by_ids(block_ids: &[i64], conn: &PgConnection) -> Result<Vec<Self>, LoopError> {
+		Ok(blocks::dsl::blocks
+			.filter(blocks::id.eq_any(block_ids))
+			.load(conn)?)
+	}
+
+	/// The properties of the block, in order
+	pub fn children(&self, conn: &PgConnection) -> Result<Vec<Property>, LoopError> {
+		Property::children_of(self.id, conn)
+	}
+
+	/// Updates the block's data as the acting user, see `BlockRevision::act_as`.
+	/// Changes that no user made pass `None` to `update_data_by`.
 	pub fn update_data(&self, new_data: &str, conn: &PgConnection) -> Result<Block, LoopError> {
-		Ok(
-			diesel::update(blocks::dsl::blocks.filter(blocks::id.eq(self.id)))
-				.set((
-					blocks::block_data.eq(Some(new_data)),
-					blocks::updated_at.eq(std::time::SystemTime::now()),
-				))
-				.get_result(conn)?,
-		)
+		self.update_data_by(new_data, BlockRevision::acting_author(), conn)
 	}
 
+	/// Updates the block's data, recording the user that made the change
+	pub fn update_data_by(
+		&self,
+		new_data: &str,
+		author_id: Option<i32>,
+		conn: &PgConnection,
+	) -> Result<Block, LoopError> {
+		let block: Block = diesel::update(blocks::dsl::blocks.filter(blocks::id.eq(self.id)))
+			.set((
+				blocks::block_data.eq(Some(data_to_json(new_data))),
+				blocks::block_data_text.eq(data_text(new_data)),
+				blocks::updated_at.eq(std::time::SystemTime::now()),
+			))
+			.get_result(conn)?;
+		block.record_revision(RevisionChange::Data, author_id, conn)?;
+		BlockReference::index(&block, conn)?;
+		Formula::recompute(block.id, conn)?;
+		Ok(block)
+	}
+
+	/// Updates the block's color as the acting user
 	pub fn update_color(
 		&self,
 		new_color: Option<String>,
 		conn: &PgConnection,
 	) -> Result<Block, LoopError> {
-		Ok(
-			diesel::update(blocks::dsl::blocks.filter(blocks::id.eq(self.id)))
-				.set((
-					blocks::color.eq(new_color),
-					blocks::updated_at.eq(std::time::SystemTime::now()),
-				))
-				.get_result(conn)?,
-		)
+		self.update_color_by(new_color, BlockRevision::acting_author(), conn)
 	}
 
+	/// Updates the block's color, recording the user that made the change
+	pub fn update_color_by(
+		&self,
+		new_color: Option<String>,
+		author_id: Option<i32>,
+		conn: &PgConnection,
+	) -> Result<Block, LoopError> {
+		let block: Block = diesel::update(blocks::dsl::blocks.filter(blocks::id.eq(self.id)))
+			.set((
+				blocks::color.eq(new_color),
+				blocks::updated_at.eq(std::time::SystemTime::now()),
+			))
+			.get_result(conn)?;
+		block.record_revision(RevisionChange::Color, author_id, conn)?;
+		Ok(block)
+	}
+
+	/// Updates the block's visibility as the acting user
 	pub fn update_public(&self, public: bool, conn: &PgConnection) -> Result<Block, LoopError> {
-		Ok(
-			diesel::update(blocks::dsl::blocks.filter(blocks::id.eq(self.id)))
-				.set((
-					blocks::public.eq(public),
-					blocks::updated_at.eq(std::time::SystemTime::now()),
-				))
-				.get_result(conn)?,
-		)
+		self.update_public_by(public, BlockRevision::acting_author(), conn)
+	}
+
+	/// Updates the block's visibility, recording the user that made the change
+	pub fn update_public_by(
+		&self,
+		public: bool,
+		author_id: Option<i32>,
+		conn: &PgConnection,
+	) -> Result<Block, LoopError> {
+		let block: Block = diesel::update(blocks::dsl::blocks.filter(blocks::id.eq(self.id)))
+			.set((
+				blocks::public.eq(public),
+				blocks::updated_at.eq(std::time::SystemTime::now()),
+			))
+			.get_result(conn)?;
+		block.record_revision(RevisionChange::Visibility, author_id, conn)?;
+		Block::propagate_perms(block.id, conn)?;
+		Ok(block)
 	}
 
 	pub fn update_starred(
@@ -123,6 +284,7 @@ impl Block {
 			.get_result(conn)?)
 	}
 
+	/// Updates the full, edit and view lists, keeping the block's commenters
 	pub fn update_perms(
 		&self,
 		perm_full: Vec<i32>,
@@ -130,21 +292,135 @@ impl Block {
 		perm_view: Vec<i32>,
 		conn: &PgConnection,
 	) -> Result<Block, LoopError> {
-		Ok(
-			diesel::update(blocks::dsl::blocks.filter(blocks::id.eq(self.id)))
+		self.update_perms_by(
+			perm_full,
+			perm_edit,
+			self.perm_comment.clone(),
+			perm_view,
+			None,
+			conn,
+		)
+	}
+
+	/// Updates the block's permissions, recording the user that made the change
+	pub fn update_perms_by(
+		&self,
+		perm_full: Vec<i32>,
+		perm_edit: Vec<i32>,
+		perm_comment: Vec<i32>,
+		perm_view: Vec<i32>,
+		author_id: Option<i32>,
+		conn: &PgConnection,
+	) -> Result<Block, LoopError> {
+		let block: Block = diesel::update(blocks::dsl::blocks.filter(blocks::id.eq(self.id)))
+			.set((
+				blocks::perm_full.eq(perm_full),
+				blocks::perm_edit.eq(perm_edit),
+				blocks::perm_comment.eq(perm_comment),
+				blocks::perm_view.eq(perm_view),
+				blocks::updated_at.eq(std::time::SystemTime::now()),
+			))
+			.get_result(conn)?;
+		block.record_revision(RevisionChange::Permissions, author_id, conn)?;
+		Block::propagate_perms(block.id, conn)?;
+		Ok(block)
+	}
+
+	/// Deletes the block. Links to it don't lead anywhere anymore, so
+	/// they are marked as dangling.
+	pub fn delete(&self, conn: &PgConnection) -> Result<(), LoopError> {
+		diesel::delete(blocks::dsl::blocks.filter(blocks::id.eq(self.id))).execute(conn)?;
+		BlockReference::mark_deleted(self.id, conn)?;
+		Ok(())
+	}
+
+	/// Appends a snapshot of the block's current state to its history
+	pub fn record_revision(
+		&self,
+		change: RevisionChange,
+		author_id: Option<i32>,
+		conn: &PgConnection,
+	) -> Result<BlockRevision, LoopError> {
+		NewBlockRevision::snapshot(self, change, author_id, conn)?.insert(conn)
+	}
+
+	/// Restores the block to the state captured in a revision. Visibility and
+	/// permissions are only restored if `restore_perms` is true.
+	pub fn revert_to(
+		&self,
+		revision: &BlockRevision,
+		restore_perms: bool,
+		author_id: Option<i32>,
+		conn: &PgConnection,
+	) -> Result<Block, LoopError> {
+		conn.transaction::<_, LoopError, _>(|| {
+			let filter = blocks::dsl::blocks.filter(blocks::id.eq(self.id));
+			let mut block: Block = diesel::update(filter)
 				.set((
-					blocks::perm_full.eq(perm_full),
-					blocks::perm_edit.eq(perm_edit),
-					blocks::perm_view.eq(perm_view),
+					blocks::block_data.eq(revision.block_data.as_deref().map(data_to_json)),
+					blocks::block_data_text.eq(revision.block_data.as_deref().and_then(data_text)),
+					blocks::color.eq(revision.color.clone()),
 					blocks::updated_at.eq(std::time::SystemTime::now()),
 				))
-				.get_result(conn)?,
-		)
+				.get_result(conn)?;
+			if restore_perms {
+				block = diesel::update(filter)
+					.set((
+						blocks::public.eq(revision.public),
+						blocks::perm_full.eq(revision.perm_full.clone()),
+						blocks::perm_edit.eq(revision.perm_edit.clone()),
+						blocks::perm_comment.eq(revision.perm_comment.clone()),
+						blocks::perm_view.eq(revision.perm_view.clone()),
+					))
+					.get_result(conn)?;
+			}
+
+			// Make the properties match the ones in the revision
+			let wanted = revision.property_list();
+			let current: Vec<Property> = properties::dsl::properties
+				.filter(properties::parent_id.eq(self.id))
+				.load(conn)?;
+			let mut kept: Vec<PropertySnapshot> = vec![];
+			let mut moved_children: Vec<i64> = vec![];
+			for prop in current {
+				let snapshot = PropertySnapshot::from(&prop);
+				if wanted.contains(&snapshot) && !kept.contains(&snapshot) {
+					kept.push(snapshot);
+				} else {
+					diesel::delete(properties::dsl::properties.filter(properties::id.eq(prop.id)))
+						.execute(conn)?;
+					moved_children.push(prop.value_id);
+				}
+			}
+			for prop in wanted.into_iter().filter(|prop| !kept.contains(prop)) {
+				let position = prop.position.clone();
+				moved_children.push(prop.value_id);
+				let inserted = NewProperty {
+					property_name: prop.property_name,
+					parent_id: self.id,
+					value_id: prop.value_id,
+					annotation: prop.annotation,
+				}
+				.insert_without_revision(conn)?;
+				if let Some(position) = position {
+					inserted.set_position(&position, conn)?;
+				}
+			}
+
+			block.record_revision(RevisionChange::Revert, author_id, conn)?;
+			if restore_perms {
+				Block::propagate_perms(block.id, conn)?;
+			}
+			for child_id in moved_children {
+				Block::propagate_perms(child_id, conn)?;
+			}
+			BlockReference::index(&block, conn)?;
+			Formula::recompute(block.id, conn)?;
+			Ok(block)
+		})
 	}
 }
 
-#[derive(Insertable)]
-#[table_name = "blocks"]
 pub struct NewBlock {
 	pub block_type: String,
 	pub created_at: SystemTime,
@@ -179,9 +455,12 @@ impl NewBlock {
 	}
 
 	pub fn insert(self, conn: &PgConnection) -> Result<Block, LoopError> {
-		Ok(diesel::insert_into(blocks::table)
-			.values(self)
-			.get_result(conn)?)
+		let block: Block = diesel::insert_into(blocks::table)
+			.values(NewBlockRow::from(self))
+			.get_result(conn)?;
+		block.record_revision(RevisionChange::Created, Some(block.owner_id), conn)?;
+		BlockReference::index(&block, conn)?;
+		Ok(block)
 	}
 
 	pub fn color_from(&mut self, rgb: String) -> Result<(), LoopError> {
@@ -200,3 +479,79 @@ impl NewBlock {
 		Ok(())
 	}
 }
+
+/// A new block as it is inserted, with its data as JSON
+#[derive(Insertable)]
+#[table_name = "blocks"]
+struct NewBlockRow {
+	block_type: String,
+	created_at: SystemTime,
+	updated_at: SystemTime,
+	block_data: Option<Value>,
+	block_data_text: Option<String>,
+	owner_id: i32,
+	public: bool,
+	perm_full: Vec<i32>,
+	perm_edit: Vec<i32>,
+	perm_view: Vec<i32>,
+	stars: Vec<i32>,
+	notif_enabled: Vec<i32>,
+	color: Option<String>,
+}
+
+impl From<NewBlock> for NewBlockRow {
+	fn from(block: NewBlock) -> Self {
+		NewBlockRow {
+			block_type: block.block_type,
+			created_at: block.created_at,
+			updated_at: block.updated_at,
+			block_data: block.block_data.as_deref().map(data_to_json),
+			block_data_text: block.block_data.as_deref().and_then(data_text),
+			owner_id: block.owner_id,
+			public: block.public,
+			perm_full: block.perm_full,
+			perm_edit: block.perm_edit,
+			perm_view: block.perm_view,
+			stars: block.stars,
+			notif_enabled: block.notif_enabled,
+			color: block.color,
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	/// How data is read back after it is stored, without the JSON keeping
+	/// its key order or spacing
+	fn stored(data: &str) -> String {
+		let json: Value = serde_json::from_str(&data_to_json(data).to_string()).unwrap();
+		data_text(data).unwrap_or_else(|| data_from_json(json))
+	}
+
+	#[test]
+	fn data_reads_back_unchanged() {
+		for data in &[
+			"plain text",
+			"1.0",
+			"\"quoted\"",
+			"true",
+			"null",
+			"",
+			"{ \"b\": 1.0, \"a\": [1, 2] }",
+			"[1e2]",
+			"{not json",
+		] {
+			assert_eq!(*data, stored(data));
+		}
+	}
+
+	#[test]
+	fn only_objects_and_lists_stay_json() {
+		assert!(data_to_json("{\"a\":1}").is_object());
+		assert!(data_to_json("[1]").is_array());
+		assert_eq!(Value::String("1.0".into()), data_to_json("1.0"));
+		assert_eq!(None, data_text("1.0"));
+	}
+}
@@ -0,0 +1,253 @@
+use super::super::schema::{collection_notified, collections, team_members};
+use super::{Block, BlockSearch, SearchFilters, SearchSort};
+use crate::{LoopError, PageQuery};
+use diesel::{
+	dsl::{exists, not},
+	prelude::*,
+};
+use serde_json::Value;
+use std::time::SystemTime;
+
+/// A search that a user saved with a name, so that it can be run again.
+/// Collections belong to a user, and other users can't see them.
+#[derive(Queryable, Clone)]
+pub struct Collection {
+	pub id: i64,
+	pub owner_id: i32,
+	pub name: String,
+	pub query: String,
+	/// The `SearchFilters` of the search, as JSON
+	pub filters: Value,
+	/// Include `data` blocks
+	pub with_data: bool,
+	/// How the results are ordered, as the name from `SearchSort::as_str`
+	pub sort: String,
+	/// When the owner subscribed to new matches, if they did
+	pub subscribed_at: Option<SystemTime>,
+	pub created_at: SystemTime,
+	pub updated_at: SystemTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "collections"]
+pub struct NewCollection {
+	pub owner_id: i32,
+	pub name: String,
+	pub query: String,
+	pub filters: Value,
+	pub with_data: bool,
+	pub sort: String,
+	pub created_at: SystemTime,
+	pub updated_at: SystemTime,
+}
+
+/// Changes to a collection. Fields that are `None` are left as they are.
+#[derive(AsChangeset, Default)]
+#[table_name = "collections"]
+pub struct CollectionChanges {
+	pub name: Option<String>,
+	pub query: Option<String>,
+	pub filters: Option<Value>,
+	pub with_data: Option<bool>,
+	pub sort: Option<String>,
+}
+
+impl NewCollection {
+	pub fn new(owner_id: i32, name: impl ToString, query: impl ToString) -> Self {
+		NewCollection {
+			owner_id,
+			name: name.to_string(),
+			query: query.to_string(),
+			filters: SearchFilters::default().to_json(),
+			with_data: false,
+			sort: SearchSort::Rank.as_str().to_string(),
+			created_at: SystemTime::now(),
+			updated_at: SystemTime::now(),
+		}
+	}
+
+	pub fn insert(self, conn: &PgConnection) -> Result<Collection, LoopError> {
+		Ok(diesel::insert_into(collections::table)
+			.values(self)
+			.get_result(conn)?)
+	}
+}
+
+impl Collection {
+	pub fn by_id(collection_id: i64, conn: &PgConnection) -> Result<Option<Self>, LoopError> {
+		Ok(collections::dsl::collections
+			.filter(collections::id.eq(collection_id))
+			.limit(1)
+			.get_result(conn)
+			.optional()?)
+	}
+
+	/// All the collections a user has saved, sorted by name
+	pub fn of_user(owner_id: i32, conn: &PgConnection) -> Result<Vec<Self>, LoopError> {
+		Ok(collections::dsl::collections
+			.filter(collections::owner_id.eq(owner_id))
+			.order_by(collections::name)
+			.load(conn)?)
+	}
+
+	/// The subscribed collections whose owners could be notified about a block:
+	/// they subscribed before it was made, don't own it, can view it (directly,
+	/// from a parent or through one of its teams) and weren't notified about it
+	/// yet. Whether each search finds the block is left to `is_new_match`.
+	pub fn watching(block: &Block, conn: &PgConnection) -> Result<Vec<Self>, LoopError> {
+		let mut query = collections::dsl::collections
+			.filter(collections::subscribed_at.le(block.created_at))
+			.filter(collections::owner_id.ne(block.owner_id))
+			.filter(not(collections::id.eq_any(
+				collection_notified::table
+					.filter(collection_notified::block_id.eq(block.id))
+					.select(collection_notified::collection_id),
+			)))
+			.into_boxed();
+		if !block.public && !block.inherited.public {
+			let inherited = &block.inherited;
+			let viewers: Vec<i32> = [
+				&block.co_owners,
+				&block.perm_full,
+				&block.perm_edit,
+				&block.perm_comment,
+				&block.perm_view,
+				&inherited.full,
+				&inherited.edit,
+				&inherited.comment,
+				&inherited.view,
+			]
+			.iter()
+			.flat_map(|ids| ids.iter().copied())
+			.collect();
+			// Members of the block's teams, in case its inherited permissions
+			// weren't updated yet
+			let team_ids: Vec<i64> = [
+				&block.team_full,
+				&block.team_edit,
+				&block.team_comment,
+				&block.team_view,
+			]
+			.iter()
+			.flat_map(|ids| ids.iter().copied())
+			.collect();
+			let team_members = team_members::table
+				.filter(team_members::team_id.eq_any(team_ids))
+				.filter(team_members::accepted.eq(true))
+				.select(team_members::user_id);
+			query = query.filter(
+				collections::owner_id
+					.eq_any(viewers)
+					.or(collections::owner_id.eq_any(team_members)),
+			);
+		}
+		Ok(query.load(conn)?)
+	}
+
+	pub fn filters(&self) -> SearchFilters {
+		serde_json::from_value(self.filters.clone()).unwrap_or_default()
+	}
+
+	pub fn sort(&self) -> SearchSort {
+		SearchSort::from_name(&self.sort)
+	}
+
+	/// The saved search, as `user_id` runs it. Tags in the filters are always
+	/// the owner's, since only the owner can see them.
+	pub fn search(&self, user_id: Option<i32>) -> BlockSearch {
+		BlockSearch {
+			with_data: self.with_data,
+			sort: self.sort(),
+			..BlockSearch::filtered(&self.query, user_id, Some(self.owner_id), &self.filters())
+		}
+	}
+
+	pub fn update(
+		&self,
+		changes: CollectionChanges,
+		conn: &PgConnection,
+	) -> Result<Collection, LoopError> {
+		Ok(
+			diesel::update(collections::dsl::collections.filter(collections::id.eq(self.id)))
+				.set((changes, collections::updated_at.eq(SystemTime::now())))
+				.get_result(conn)?,
+		)
+	}
+
+	/// Starts or stops notifying the owner about new matches. Only blocks
+	/// created after subscribing count as new.
+	pub fn set_subscribed(
+		&self,
+		subscribed: bool,
+		conn: &PgConnection,
+	) -> Result<Collection, LoopError> {
+		let subscribed_at = match (subscribed, self.subscribed_at) {
+			(true, Some(time)) => Some(time),
+			(true, None) => Some(SystemTime::now()),
+			(false, _) => None,
+		};
+		if subscribed_at == self.subscribed_at {
+			return Ok(self.clone());
+		}
+		conn.transaction::<_, LoopError, _>(|| {
+			diesel::delete(
+				collection_notified::table.filter(collection_notified::collection_id.eq(self.id)),
+			)
+			.execute(conn)?;
+			Ok(
+				diesel::update(collections::dsl::collections.filter(collections::id.eq(self.id)))
+					.set(collections::subscribed_at.eq(subscribed_at))
+					.get_result(conn)?,
+			)
+		})
+	}
+
+	/// Whether a block is a match that the owner should be notified about:
+	/// it was created after they subscribed, they weren't notified about it
+	/// yet, and the saved search finds it.
+	pub fn is_new_match(&self, block: &Block, conn: &PgConnection) -> Result<bool, LoopError> {
+		let subscribed_at = match self.subscribed_at {
+			Some(time) => time,
+			None => return Ok(false),
+		};
+		if block.created_at < subscribed_at || self.was_notified(block.id, conn)? {
+			return Ok(false);
+		}
+
+		let search = BlockSearch {
+			block_ids: Some(vec![block.id]),
+			..self.search(Some(self.owner_id))
+		};
+		let found = Block::search(&search, &PageQuery::first(1), conn)?;
+		Ok(found.items.iter().any(|hit| hit.id == block.id))
+	}
+
+	/// Whether the owner was already notified about a block
+	pub fn was_notified(&self, block_id: i64, conn: &PgConnection) -> Result<bool, LoopError> {
+		Ok(diesel::select(exists(
+			collection_notified::table
+				.filter(collection_notified::collection_id.eq(self.id))
+				.filter(collection_notified::block_id.eq(block_id)),
+		))
+		.get_result(conn)?)
+	}
+
+	/// Remembers that the owner was notified about a block. False if they
+	/// already were, so that only one caller sends the notification.
+	pub fn mark_notified(&self, block_id: i64, conn: &PgConnection) -> Result<bool, LoopError> {
+		let inserted = diesel::insert_into(collection_notified::table)
+			.values((
+				collection_notified::collection_id.eq(self.id),
+				collection_notified::block_id.eq(block_id),
+			))
+			.on_conflict_do_nothing()
+			.execute(conn)?;
+		Ok(inserted > 0)
+	}
+
+	pub fn delete(&self, conn: &PgConnection) -> Result<(), LoopError> {
+		diesel::delete(collections::dsl::collections.filter(collections::id.eq(self.id)))
+			.execute(conn)?;
+		Ok(())
+	}
+}
@@ -1,6 +1,7 @@
-use super::super::schema::comments;
-use crate::LoopError;
-use diesel::prelude::*;
+use super::super::schema::{blocks, comments};
+use super::Block;
+use crate::{LoopError, Page, PageQuery};
+use diesel::{pg::Pg, prelude::*};
 use std::time::SystemTime;
 
 #[derive(Queryable, Clone)]
@@ -13,6 +14,29 @@ pub struct Comment {
 	pub created_at: SystemTime,
 }
 
+impl Comment {
+	/// A page of the comments on a block, newest first. Only comments with
+	/// content that the user can view are included.
+	pub fn page_on(
+		block_id: i64,
+		user_id: Option<i32>,
+		page: &PageQuery<i64>,
+		conn: &PgConnection,
+	) -> Result<Page<Self>, LoopError> {
+		let on_block = || -> comments::BoxedQuery<'static, Pg> {
+			let visible = blocks::table
+				.select(blocks::id)
+				.filter(Block::visible_to(user_id));
+			comments::dsl::comments
+				.filter(comments::block_id.eq(block_id))
+				.filter(comments::content_id.eq_any(visible))
+				.into_boxed()
+		};
+
+		page.load_desc(on_block, comments::id, conn)
+	}
+}
+
 #[derive(Insertable)]
 #[table_name = "comments"]
 pub struct NewComment {
@@ -0,0 +1,206 @@
+use super::super::schema::blocks;
+use super::Block;
+use crate::LoopError;
+use chrono::DateTime;
+use diesel::{
+	dsl::sql,
+	pg::Pg,
+	prelude::*,
+	sql_types::{Array, Bool, Double, Jsonb, Text},
+};
+use serde_json::{Map, Value};
+
+/// How a value inside block data is compared
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataOp {
+	Equals,
+	NotEquals,
+	/// The value is text that includes the filter's text, ignoring case
+	Contains,
+	GreaterThan,
+	LessThan,
+	/// There is a value at the path, no matter what it is
+	Exists,
+}
+
+/// A condition on the value at a path inside of block data,
+/// like `status` being `"done"`
+#[derive(Debug, Clone)]
+pub struct DataFilter {
+	/// The keys (or list indexes) to follow into the data
+	pub path: Vec<String>,
+	pub op: DataOp,
+	pub value: Value,
+	/// Compares the inline property (or formula) named by the first key
+	/// of the path, instead of the block's data
+	pub inline: bool,
+}
+
+type BoxedBlockQuery<'a> = blocks::BoxedQuery<'a, Pg>;
+
+impl DataFilter {
+	/// Data that has the filter's value at its path, used to check if block
+	/// data contains it. This lets Postgres use the data's index.
+	fn containing(&self) -> Value {
+		self.path
+			.iter()
+			.rev()
+			.fold(self.value.clone(), |inner, key| {
+				let mut map = Map::new();
+				map.insert(key.clone(), inner);
+				Value::Object(map)
+			})
+	}
+
+	/// The filter's value as text, the way Postgres gives it with `#>>`
+	fn value_text(&self) -> String {
+		match &self.value {
+			Value::String(text) => text.clone(),
+			value => value.to_string(),
+		}
+	}
+
+	fn apply<'a>(&self, query: BoxedBlockQuery<'a>) -> BoxedBlockQuery<'a> {
+		if self.inline {
+			return self.apply_inline(query);
+		}
+		let path = self.path.clone();
+		match self.op {
+			DataOp::Equals => {
+				query.filter(sql::<Bool>("block_data @> ").bind::<Jsonb, _>(self.containing()))
+			}
+			DataOp::NotEquals => query.filter(
+				sql::<Bool>("NOT coalesce(block_data @> ")
+					.bind::<Jsonb, _>(self.containing())
+					.sql(", false)"),
+			),
+			DataOp::Contains => query.filter(
+				sql::<Bool>("block_data #>> ")
+					.bind::<Array<Text>, _>(path)
+					.sql(" ILIKE '%' || ")
+					.bind::<Text, _>(escape_like(&self.value_text()))
+					.sql(" || '%'"),
+			),
+			DataOp::GreaterThan => self.compare(query, ">"),
+			DataOp::LessThan => self.compare(query, "<"),
+			DataOp::Exists => query.filter(
+				sql::<Bool>("block_data #> ")
+					.bind::<Array<Text>, _>(path)
+					.sql(" IS NOT NULL"),
+			),
+		}
+	}
+
+	fn apply_inline<'a>(&self, query: BoxedBlockQuery<'a>) -> BoxedBlockQuery<'a> {
+		let name = self.path.first().cloned().unwrap_or_default();
+		let exists = "EXISTS (SELECT 1 FROM inline_properties ip \
+			WHERE ip.parent_id = blocks.id AND ip.property_name = ";
+		let compare = |query: BoxedBlockQuery<'a>, start: &str, operator: &str| {
+			let start = sql::<Bool>(start).bind::<Text, _>(name.clone());
+			match &self.value {
+				Value::Number(number) => query.filter(
+					start
+						.sql(&format!(" AND ip.number_value {} ", operator))
+						.bind::<Double, _>(number.as_f64().unwrap_or_default())
+						.sql(")"),
+				),
+				Value::Bool(boolean) => query.filter(
+					start
+						.sql(&format!(" AND ip.bool_value {} ", operator))
+						.bind::<Bool, _>(*boolean)
+						.sql(")"),
+				),
+				_ if self.is_timestamp() => query.filter(
+					start
+						.sql(&format!(" AND ip.time_value {} ", operator))
+						.bind::<Text, _>(self.value_text())
+						.sql("::timestamptz)"),
+				),
+				_ => query.filter(
+					start
+						.sql(&format!(" AND ip.text_value {} ", operator))
+						.bind::<Text, _>(self.value_text())
+						.sql(")"),
+				),
+			}
+		};
+
+		match self.op {
+			DataOp::Equals => compare(query, exists, "="),
+			DataOp::NotEquals => compare(query, &format!("NOT {}", exists), "="),
+			DataOp::GreaterThan => compare(query, exists, ">"),
+			DataOp::LessThan => compare(query, exists, "<"),
+			DataOp::Contains => query.filter(
+				sql::<Bool>(exists)
+					.bind::<Text, _>(name.clone())
+					.sql(" AND ip.text_value ILIKE '%' || ")
+					.bind::<Text, _>(escape_like(&self.value_text()))
+					.sql(" || '%')"),
+			),
+			DataOp::Exists => {
+				query.filter(sql::<Bool>(exists).bind::<Text, _>(name.clone()).sql(")"))
+			}
+		}
+	}
+
+	/// Whether the value is a date and time, like `2021-07-01T12:00:00Z`
+	fn is_timestamp(&self) -> bool {
+		match &self.value {
+			Value::String(text) => DateTime::parse_from_rfc3339(text).is_ok(),
+			_ => false,
+		}
+	}
+
+	/// Compares numbers as numbers, and anything else (like dates) as text
+	fn compare<'a>(&self, query: BoxedBlockQuery<'a>, operator: &str) -> BoxedBlockQuery<'a> {
+		let path = self.path.clone();
+		if self.value.is_number() {
+			query.filter(
+				sql::<Bool>("jsonb_typeof(block_data #> ")
+					.bind::<Array<Text>, _>(path.clone())
+					.sql(") = 'number' AND (block_data #>> ")
+					.bind::<Array<Text>, _>(path)
+					.sql(&format!(")::numeric {} ", operator))
+					.bind::<Text, _>(self.value_text())
+					.sql("::numeric"),
+			)
+		} else {
+			query.filter(
+				sql::<Bool>("block_data #>> ")
+					.bind::<Array<Text>, _>(path)
+					.sql(&format!(" {} ", operator))
+					.bind::<Text, _>(self.value_text()),
+			)
+		}
+	}
+}
+
+impl Block {
+	/// Finds blocks, newest first, whose data matches every filter. Only the
+	/// blocks the user can view are included, and at most `limit` of them.
+	pub fn query_data(
+		block_type: Option<&str>,
+		filters: &[DataFilter],
+		user_id: Option<i32>,
+		limit: i64,
+		conn: &PgConnection,
+	) -> Result<Vec<Block>, LoopError> {
+		let mut query = blocks::table
+			.filter(Block::visible_to(user_id))
+			.into_boxed();
+		if let Some(block_type) = block_type {
+			query = query.filter(blocks::block_type.eq(block_type.to_string()));
+		}
+		for filter in filters {
+			query = filter.apply(query);
+		}
+		Ok(query.order_by(blocks::id.desc()).limit(limit).load(conn)?)
+	}
+}
+
+/// Makes `%` and `_` match themselves in an `ILIKE` pattern
+fn escape_like(text: &str) -> String {
+	text.replace('\\', "\\\\")
+		.replace('%', "\\%")
+		.replace('_', "\\_")
+}
@@ -0,0 +1,268 @@
+use super::super::schema::{blocks, formulas, inline_properties, properties};
+use super::{InlineProperty, InlineValue, NewInlineProperty, Property};
+use crate::{
+	formulas::{evaluate, evaluation_order, parse, Expr, FormulaError, FormulaScope},
+	BlockError, LoopError,
+};
+use diesel::prelude::*;
+use std::time::SystemTime;
+
+/// A value of a block that is computed from its other values and its
+/// children. The result is stored as an inline property with the same name.
+#[derive(Queryable, Clone)]
+pub struct Formula {
+	pub id: i64,
+	pub block_id: i64,
+	pub name: String,
+	/// The formula as it was written, like `price * quantity`
+	pub expression: String,
+	/// Why the formula couldn't be computed the last time it was tried
+	pub error: Option<String>,
+	pub author_id: Option<i32>,
+	pub updated_at: SystemTime,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "formulas"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct NewFormula {
+	pub block_id: i64,
+	pub name: String,
+	pub expression: String,
+	pub error: Option<String>,
+	pub author_id: Option<i32>,
+	pub updated_at: SystemTime,
+}
+
+impl Formula {
+	/// All the formulas on a block, sorted by name
+	pub fn of_block(block_id: i64, conn: &PgConnection) -> Result<Vec<Self>, LoopError> {
+		Ok(formulas::dsl::formulas
+			.filter(formulas::block_id.eq(block_id))
+			.order_by(formulas::name)
+			.load(conn)?)
+	}
+
+	/// Adds a formula to a block, or replaces the one with the same name. Fails if
+	/// the formula can't be parsed or would refer to itself through other formulas.
+	pub fn define(
+		block_id: i64,
+		name: &str,
+		expression: &str,
+		author_id: Option<i32>,
+		conn: &PgConnection,
+	) -> Result<Formula, LoopError> {
+		let expr = parse(expression).map_err(BlockError::FormulaParse)?;
+
+		let mut exprs: Vec<(String, Expr)> = Formula::of_block(block_id, conn)?
+			.into_iter()
+			.filter(|formula| formula.name != name)
+			.filter_map(|formula| Some((formula.name, parse(&formula.expression).ok()?)))
+			.collect();
+		exprs.push((name.to_string(), expr));
+		evaluation_order(&exprs).map_err(BlockError::FormulaCycle)?;
+
+		let new = NewFormula {
+			block_id,
+			name: name.to_string(),
+			expression: expression.to_string(),
+			error: None,
+			author_id,
+			updated_at: SystemTime::now(),
+		};
+		diesel::insert_into(formulas::table)
+			.values(&new)
+			.on_conflict((formulas::block_id, formulas::name))
+			.do_update()
+			.set(&new)
+			.execute(conn)?;
+
+		Formula::recompute(block_id, conn)?;
+		Ok(formulas::dsl::formulas
+			.filter(formulas::block_id.eq(block_id))
+			.filter(formulas::name.eq(name))
+			.get_result(conn)?)
+	}
+
+	/// Removes a formula from a block, along with its value
+	pub fn remove(block_id: i64, name: &str, conn: &PgConnection) -> Result<(), LoopError> {
+		diesel::delete(
+			formulas::dsl::formulas
+				.filter(formulas::block_id.eq(block_id))
+				.filter(formulas::name.eq(name)),
+		)
+		.execute(conn)?;
+		remove_value(block_id, name, conn)?;
+		Formula::recompute(block_id, conn)
+	}
+
+	/// Updates the formulas that could use a block's values, after they change.
+	/// Formulas can read the values of children and their data children, so
+	/// the block's parents and grandparents are updated too. When a formula
+	/// is updated, the same happens for its block.
+	pub fn recompute(block_id: i64, conn: &PgConnection) -> Result<(), LoopError> {
+		let mut computed: Vec<i64> = vec![];
+		let mut changed = vec![block_id];
+		while let Some(block_id) = changed.pop() {
+			let parents = parent_ids(&[block_id], conn)?;
+			let grandparents = parent_ids(&parents, conn)?;
+			let mut candidates = vec![block_id];
+			candidates.extend(parents);
+			candidates.extend(grandparents);
+
+			let with_formulas: Vec<i64> = formulas::dsl::formulas
+				.filter(formulas::block_id.eq_any(&candidates))
+				.select(formulas::block_id)
+				.load(conn)?;
+			// Closest blocks first, so that parents see their children's new values
+			for candidate in candidates {
+				if computed.contains(&candidate) || !with_formulas.contains(&candidate) {
+					continue;
+				}
+				computed.push(candidate);
+				Formula::recompute_block(candidate, conn)?;
+				if candidate != block_id {
+					changed.push(candidate);
+				}
+			}
+		}
+		Ok(())
+	}
+
+	/// Computes every formula of a block and stores their values
+	fn recompute_block(block_id: i64, conn: &PgConnection) -> Result<(), LoopError> {
+		let mut formulas = vec![];
+		let mut exprs = vec![];
+		for formula in Formula::of_block(block_id, conn)? {
+			match parse(&formula.expression) {
+				Ok(expr) => {
+					exprs.push((formula.name.clone(), expr));
+					formulas.push(formula);
+				}
+				Err(err) => {
+					remove_value(block_id, &formula.name, conn)?;
+					formula.set_error(Some(err), conn)?;
+				}
+			}
+		}
+
+		let order = match evaluation_order(&exprs) {
+			Ok(order) => order,
+			Err(name) => {
+				let err = format!("Refers to itself through '{}'", name);
+				for formula in formulas {
+					remove_value(block_id, &formula.name, conn)?;
+					formula.set_error(Some(err.clone()), conn)?;
+				}
+				return Ok(());
+			}
+		};
+
+		let mut scope = BlockScope { block_id, conn };
+		for index in order {
+			let formula = &formulas[index];
+			let error = match evaluate(&exprs[index].1, &mut scope) {
+				Ok(Some(value)) => {
+					NewInlineProperty::new(block_id, &formula.name, value).insert(conn)?;
+					None
+				}
+				Ok(None) => {
+					remove_value(block_id, &formula.name, conn)?;
+					None
+				}
+				Err(FormulaError::Invalid(err)) => {
+					remove_value(block_id, &formula.name, conn)?;
+					Some(err)
+				}
+				Err(FormulaError::Database(err)) => return Err(err),
+			};
+			if error != formula.error {
+				formula.set_error(error, conn)?;
+			}
+		}
+		Ok(())
+	}
+
+	fn set_error(&self, error: Option<String>, conn: &PgConnection) -> Result<(), LoopError> {
+		diesel::update(formulas::dsl::formulas.filter(formulas::id.eq(self.id)))
+			.set(formulas::error.eq(error))
+			.execute(conn)?;
+		Ok(())
+	}
+}
+
+/// Reads the values that a block's formulas refer to
+struct BlockScope<'a> {
+	block_id: i64,
+	conn: &'a PgConnection,
+}
+
+impl FormulaScope for BlockScope<'_> {
+	fn value(&mut self, name: &str) -> Result<Option<InlineValue>, LoopError> {
+		if let Some(value) = InlineProperty::value_of(self.block_id, name, self.conn)? {
+			return Ok(Some(value));
+		}
+		// Values that haven't been made inline are kept in data blocks
+		let child = Property::children_of(self.block_id, self.conn)?
+			.into_iter()
+			.find(|prop| prop.property_name == name);
+		match child {
+			Some(child) => data_value(child.value_id, self.conn),
+			None => Ok(None),
+		}
+	}
+
+	fn child_values(
+		&mut self,
+		name: &str,
+		field: Option<&str>,
+	) -> Result<Vec<Option<InlineValue>>, LoopError> {
+		let children: Vec<Property> = Property::children_of(self.block_id, self.conn)?
+			.into_iter()
+			.filter(|prop| prop.property_name == name)
+			.collect();
+		let mut values = vec![];
+		for child in children {
+			values.push(match field {
+				Some(field) => BlockScope {
+					block_id: child.value_id,
+					conn: self.conn,
+				}
+				.value(field)?,
+				None => data_value(child.value_id, self.conn)?,
+			});
+		}
+		Ok(values)
+	}
+}
+
+/// The data of a block as a value. Data that looks like a number is a number.
+fn data_value(block_id: i64, conn: &PgConnection) -> Result<Option<InlineValue>, LoopError> {
+	let data: Option<(Option<serde_json::Value>, Option<String>)> = blocks::dsl::blocks
+		.filter(blocks::id.eq(block_id))
+		.select((blocks::block_data, blocks::block_data_text))
+		.get_result(conn)
+		.optional()?;
+	let data = data.and_then(|(json, text)| text.or_else(|| json.map(super::data_from_json)));
+	Ok(data.map(|data| match data.trim().parse() {
+		Ok(number) => InlineValue::Number(number),
+		Err(_) => InlineValue::Text(data),
+	}))
+}
+
+fn parent_ids(block_ids: &[i64], conn: &PgConnection) -> Result<Vec<i64>, LoopError> {
+	Ok(properties::dsl::properties
+		.filter(properties::value_id.eq_any(block_ids))
+		.select(properties::parent_id)
+		.load(conn)?)
+}
+
+fn remove_value(block_id: i64, name: &str, conn: &PgConnection) -> Result<(), LoopError> {
+	diesel::delete(
+		inline_properties::dsl::inline_properties
+			.filter(inline_properties::parent_id.eq(block_id))
+			.filter(inline_properties::property_name.eq(name)),
+	)
+	.execute(conn)?;
+	Ok(())
+}
@@ -0,0 +1,210 @@
+use super::super::schema::blocks;
+use super::{Block, Property, RevisionChange, Team};
+use crate::{
+	auth::inheritance::{resolve_inheritance, InheritedPerms, PermNode},
+	LoopError, Page, PageQuery,
+};
+use diesel::{
+	pg::Pg,
+	prelude::*,
+	sql_types::{BigInt, Bool},
+};
+use std::{
+	collections::{HashMap, HashSet},
+	time::SystemTime,
+};
+
+impl Block {
+	/// The permissions the block grants by itself, without what it inherits
+	pub fn own_perms(&self) -> InheritedPerms {
+		let mut owner_ids = self.co_owners.clone();
+		owner_ids.push(self.owner_id);
+		InheritedPerms::granted(
+			&owner_ids,
+			self.public,
+			&self.perm_full,
+			&self.perm_edit,
+			&self.perm_comment,
+			&self.perm_view,
+		)
+	}
+
+	/// Every user with full permissions or higher on the block, whether
+	/// they have them directly, through a team or from a parent
+	pub fn full_user_ids(&self) -> Vec<i32> {
+		let mut perms = self.own_perms();
+		perms.merge(&self.inherited);
+		perms.full
+	}
+
+	/// A condition for the blocks that a user can view, which decides the
+	/// same way `can_view` does. Without a user, only public blocks match.
+	pub fn visible_to(
+		user_id: Option<i32>,
+	) -> Box<dyn BoxableExpression<blocks::table, Pg, SqlType = Bool>> {
+		let public = blocks::public.or(blocks::inherited_public);
+		let user_id = match user_id {
+			Some(user_id) => user_id,
+			None => return Box::new(public),
+		};
+		let ids = || vec![user_id];
+		Box::new(
+			public
+				.or(blocks::owner_id.eq(user_id))
+				.or(blocks::co_owners.contains(ids()))
+				.or(blocks::perm_full.contains(ids()))
+				.or(blocks::perm_edit.contains(ids()))
+				.or(blocks::perm_comment.contains(ids()))
+				.or(blocks::perm_view.contains(ids()))
+				.or(blocks::inherited_full.contains(ids()))
+				.or(blocks::inherited_edit.contains(ids()))
+				.or(blocks::inherited_comment.contains(ids()))
+				.or(blocks::inherited_view.contains(ids())),
+		)
+	}
+
+	/// A page of the blocks a user owns that another user (or no user) can
+	/// view, newest first
+	pub fn page_owned_by(
+		owner_id: i32,
+		viewer_id: Option<i32>,
+		page: &PageQuery<i64>,
+		conn: &PgConnection,
+	) -> Result<Page<Self>, LoopError> {
+		let owned = || -> blocks::BoxedQuery<'static, Pg> {
+			blocks::dsl::blocks
+				.filter(blocks::owner_id.eq(owner_id))
+				.filter(Block::visible_to(viewer_id))
+				.into_boxed()
+		};
+
+		page.load_desc(owned, blocks::id, conn)
+	}
+
+	/// All the blocks that are shared with a team at any level
+	pub fn shared_with_team(team_id: i64, conn: &PgConnection) -> Result<Vec<Block>, LoopError> {
+		Ok(blocks::dsl::blocks
+			.filter(
+				blocks::team_full
+					.contains(vec![team_id])
+					.or(blocks::team_edit.contains(vec![team_id]))
+					.or(blocks::team_comment.contains(vec![team_id]))
+					.or(blocks::team_view.contains(vec![team_id])),
+			)
+			.load(conn)?)
+	}
+
+	/// Sets which teams have each level on the block, recording the user that made the change
+	pub fn update_team_perms_by(
+		&self,
+		team_full: Vec<i64>,
+		team_edit: Vec<i64>,
+		team_comment: Vec<i64>,
+		team_view: Vec<i64>,
+		author_id: Option<i32>,
+		conn: &PgConnection,
+	) -> Result<Block, LoopError> {
+		diesel::update(blocks::dsl::blocks.filter(blocks::id.eq(self.id)))
+			.set((
+				blocks::team_full.eq(team_full),
+				blocks::team_edit.eq(team_edit),
+				blocks::team_comment.eq(team_comment),
+				blocks::team_view.eq(team_view),
+				blocks::updated_at.eq(SystemTime::now()),
+			))
+			.execute(conn)?;
+		Block::propagate_perms(self.id, conn)?;
+		let block = Block::by_id(self.id, conn)?.unwrap_or_else(|| self.clone());
+		block.record_revision(RevisionChange::Permissions, author_id, conn)?;
+		Ok(block)
+	}
+
+	/// Turns inheriting permissions from the block's parents on or off
+	pub fn set_inherit_perms(
+		&self,
+		inherit: bool,
+		conn: &PgConnection,
+	) -> Result<Block, LoopError> {
+		diesel::update(blocks::dsl::blocks.filter(blocks::id.eq(self.id)))
+			.set(blocks::inherit_perms.eq(inherit))
+			.execute(conn)?;
+		Block::propagate_perms(self.id, conn)?;
+		Ok(Block::by_id(self.id, conn)?.unwrap_or_else(|| self.clone()))
+	}
+
+	/// Updates what a block and the blocks that inherit from it (directly or
+	/// through other blocks) inherit, after its permissions or parents change.
+	pub fn propagate_perms(block_id: i64, conn: &PgConnection) -> Result<(), LoopError> {
+		let parent_props: Vec<Property> = diesel::sql_query(HEIR_PARENT_PROPERTIES)
+			.bind::<BigInt, _>(block_id)
+			.load(conn)?;
+
+		let mut dirty: HashSet<i64> = parent_props.iter().map(|prop| prop.value_id).collect();
+		dirty.insert(block_id);
+		let mut ids: Vec<i64> = dirty.iter().copied().collect();
+		ids.extend(parent_props.iter().map(|prop| prop.parent_id));
+
+		let mut nodes: HashMap<i64, PermNode> = HashMap::new();
+		for block in Block::by_ids(&ids, conn)? {
+			let parents = parent_props
+				.iter()
+				.filter(|prop| prop.value_id == block.id)
+				.map(|prop| prop.parent_id)
+				.collect();
+			nodes.insert(
+				block.id,
+				PermNode {
+					inherit: block.inherit_perms,
+					parents,
+					own: block.own_perms(),
+					teams: Team::granted_perms(
+						&block.team_full,
+						&block.team_edit,
+						&block.team_comment,
+						&block.team_view,
+						conn,
+					)?,
+					inherited: block.inherited,
+				},
+			);
+		}
+
+		for (id, inherited) in resolve_inheritance(&nodes, &dirty) {
+			let unchanged = nodes
+				.get(&id)
+				.map_or(true, |node| node.inherited == inherited);
+			if unchanged {
+				continue;
+			}
+			diesel::update(blocks::dsl::blocks.filter(blocks::id.eq(id)))
+				.set((
+					blocks::inherited_public.eq(inherited.public),
+					blocks::inherited_full.eq(inherited.full),
+					blocks::inherited_edit.eq(inherited.edit),
+					blocks::inherited_comment.eq(inherited.comment),
+					blocks::inherited_view.eq(inherited.view),
+				))
+				.execute(conn)?;
+		}
+		Ok(())
+	}
+}
+
+/// Finds every block that inherits permissions from a block, directly or
+/// through other inheriting blocks, and returns the properties that point to
+/// them and to the block itself. `UNION` drops blocks that were already
+/// found, so cycles end.
+const HEIR_PARENT_PROPERTIES: &str = "
+WITH RECURSIVE heirs(id) AS (
+	SELECT $1::BIGINT
+	UNION
+	SELECT p.value_id
+	FROM properties p
+	INNER JOIN heirs h ON p.parent_id = h.id
+	INNER JOIN blocks b ON b.id = p.value_id
+	WHERE b.inherit_perms
+)
+SELECT * FROM properties
+WHERE value_id IN (SELECT id FROM heirs)
+ORDER BY id
+";
@@ -0,0 +1,245 @@
+use super::super::schema::{blocks, inline_properties, properties};
+use super::{Block, Formula, Property, RevisionChange, User};
+use crate::{LoopError, UserError};
+use diesel::prelude::*;
+use std::time::SystemTime;
+
+/// The most characters the name of an inline property can have
+pub const MAX_INLINE_NAME: usize = 36;
+
+/// A property whose value is stored with it, instead of in a block of its own
+#[derive(Queryable, Clone)]
+pub struct InlineProperty {
+	pub id: i64,
+	pub parent_id: i64,
+	pub property_name: String,
+	/// Which of the values is used, from `InlineValue::type_name`
+	pub value_type: String,
+	pub text_value: Option<String>,
+	pub number_value: Option<f64>,
+	pub bool_value: Option<bool>,
+	pub time_value: Option<SystemTime>,
+	pub user_value: Option<i32>,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "inline_properties"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct NewInlineProperty {
+	pub parent_id: i64,
+	pub property_name: String,
+	pub value_type: String,
+	pub text_value: Option<String>,
+	pub number_value: Option<f64>,
+	pub bool_value: Option<bool>,
+	pub time_value: Option<SystemTime>,
+	pub user_value: Option<i32>,
+}
+
+/// A primitive value that a property can hold without a block
+#[derive(Debug, Clone, PartialEq)]
+pub enum InlineValue {
+	Text(String),
+	Number(f64),
+	Boolean(bool),
+	Timestamp(SystemTime),
+	/// The ID of a user
+	User(i32),
+}
+
+impl InlineValue {
+	pub fn type_name(&self) -> &'static str {
+		match self {
+			Self::Text(_) => "text",
+			Self::Number(_) => "number",
+			Self::Boolean(_) => "boolean",
+			Self::Timestamp(_) => "timestamp",
+			Self::User(_) => "user",
+		}
+	}
+}
+
+impl InlineProperty {
+	/// The property's value, or `None` if it has no value of its type
+	pub fn value(&self) -> Option<InlineValue> {
+		match self.value_type.as_str() {
+			"text" => self.text_value.clone().map(InlineValue::Text),
+			"number" => self.number_value.map(InlineValue::Number),
+			"boolean" => self.bool_value.map(InlineValue::Boolean),
+			"timestamp" => self.time_value.map(InlineValue::Timestamp),
+			"user" => self.user_value.map(InlineValue::User),
+			_ => None,
+		}
+	}
+
+	/// The value of a block's inline property, if it has one with the name
+	pub fn value_of(
+		parent_id: i64,
+		property_name: &str,
+		conn: &PgConnection,
+	) -> Result<Option<InlineValue>, LoopError> {
+		let prop: Option<InlineProperty> = inline_properties::dsl::inline_properties
+			.filter(inline_properties::parent_id.eq(parent_id))
+			.filter(inline_properties::property_name.eq(property_name))
+			.limit(1)
+			.get_result(conn)
+			.optional()?;
+		Ok(prop.and_then(|prop| prop.value()))
+	}
+
+	/// Turns every `data` block that is the only child of a property into an inline
+	/// text value, then deletes the data block. Only properties called `property_name`
+	/// on blocks of `block_type` are changed, so block types can move over one at a
+	/// time. Data blocks that are used anywhere else are left alone. Returns how
+	/// many properties were changed.
+	pub fn inline_data_children(
+		block_type: &str,
+		property_name: &str,
+		conn: &PgConnection,
+	) -> Result<usize, LoopError> {
+		conn.transaction::<_, LoopError, _>(|| {
+			let candidates: Vec<Property> = properties::table
+				.inner_join(blocks::table.on(blocks::id.eq(properties::parent_id)))
+				.filter(blocks::block_type.eq(block_type))
+				.filter(properties::property_name.eq(property_name))
+				.select(properties::all_columns)
+				.load(conn)?;
+
+			let mut changed = 0;
+			for prop in candidates {
+				let data = match Block::by_id(prop.value_id, conn)? {
+					Some(data) if data.block_type == "data" => data,
+					_ => continue,
+				};
+				let uses: i64 = properties::dsl::properties
+					.filter(
+						properties::value_id
+							.eq(data.id)
+							.or(properties::parent_id.eq(data.id)),
+					)
+					.count()
+					.get_result(conn)?;
+				if uses != 1 {
+					continue;
+				}
+				// Only one inline value can have the name
+				let taken: bool = diesel::select(diesel::dsl::exists(
+					inline_properties::dsl::inline_properties
+						.filter(inline_properties::parent_id.eq(prop.parent_id))
+						.filter(inline_properties::property_name.eq(property_name)),
+				))
+				.get_result(conn)?;
+				if taken {
+					continue;
+				}
+
+				let value = InlineValue::Text(data.block_data.clone().unwrap_or_default());
+				NewInlineProperty::new(prop.parent_id, property_name, value).insert(conn)?;
+				// Removing the property records the change in the parent's history
+				prop.delete(conn)?;
+				data.delete(conn)?;
+				changed += 1;
+			}
+			Ok(changed)
+		})
+	}
+}
+
+impl NewInlineProperty {
+	pub fn new(parent_id: i64, property_name: impl ToString, value: InlineValue) -> Self {
+		let mut prop = NewInlineProperty {
+			parent_id,
+			property_name: property_name.to_string(),
+			value_type: value.type_name().to_string(),
+			text_value: None,
+			number_value: None,
+			bool_value: None,
+			time_value: None,
+			user_value: None,
+		};
+		match value {
+			InlineValue::Text(text) => prop.text_value = Some(text),
+			InlineValue::Number(number) => prop.number_value = Some(number),
+			InlineValue::Boolean(boolean) => prop.bool_value = Some(boolean),
+			InlineValue::Timestamp(time) => prop.time_value = Some(time),
+			InlineValue::User(user_id) => prop.user_value = Some(user_id),
+		}
+		prop
+	}
+
+	/// Saves the value, replacing the block's old value with the same name
+	pub fn insert(self, conn: &PgConnection) -> Result<InlineProperty, LoopError> {
+		self.check(conn)?;
+		Ok(diesel::insert_into(inline_properties::table)
+			.values(&self)
+			.on_conflict((
+				inline_properties::parent_id,
+				inline_properties::property_name,
+			))
+			.do_update()
+			.set(&self)
+			.get_result(conn)?)
+	}
+
+	/// Makes sure the name fits, and that a user value is a user that exists
+	fn check(&self, conn: &PgConnection) -> Result<(), LoopError> {
+		let length = self.property_name.chars().count();
+		if length == 0 || length > MAX_INLINE_NAME {
+			return Err(UserError::PropertyNameLength(self.property_name.clone()).into());
+		}
+		if let Some(user_id) = self.user_value {
+			if User::by_id(user_id, conn)?.is_none() {
+				return Err(UserError::IdNonexist(user_id).into());
+			}
+		}
+		Ok(())
+	}
+}
+
+impl Block {
+	/// All the inline values of the block, sorted by name
+	pub fn inline_properties(&self, conn: &PgConnection) -> Result<Vec<InlineProperty>, LoopError> {
+		Ok(inline_properties::dsl::inline_properties
+			.filter(inline_properties::parent_id.eq(self.id))
+			.order_by(inline_properties::property_name)
+			.load(conn)?)
+	}
+
+	/// The block's inline value with a name, if it has one
+	pub fn inline_value(
+		&self,
+		property_name: &str,
+		conn: &PgConnection,
+	) -> Result<Option<InlineValue>, LoopError> {
+		InlineProperty::value_of(self.id, property_name, conn)
+	}
+
+	/// Sets one of the block's inline values, or removes it if there is no value.
+	/// Formulas that use the value are updated, and the change is recorded in
+	/// the block's history.
+	pub fn set_inline_value(
+		&self,
+		property_name: &str,
+		value: Option<InlineValue>,
+		author_id: Option<i32>,
+		conn: &PgConnection,
+	) -> Result<Option<InlineProperty>, LoopError> {
+		let prop = match value {
+			Some(value) => {
+				Some(NewInlineProperty::new(self.id, property_name, value).insert(conn)?)
+			}
+			None => {
+				diesel::delete(
+					inline_properties::dsl::inline_properties
+						.filter(inline_properties::parent_id.eq(self.id))
+						.filter(inline_properties::property_name.eq(property_name)),
+				)
+				.execute(conn)?;
+				None
+			}
+		};
+		self.record_revision(RevisionChange::Properties, author_id, conn)?;
+		Formula::recompute(self.id, conn)?;
+		Ok(prop)
+	}
+}
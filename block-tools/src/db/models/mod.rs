@@ -1,13 +1,45 @@
+mod access_request_models;
+mod audit_models;
 mod block_models;
+mod collection_models;
 mod comment_models;
+mod data_query_models;
 pub mod email_models;
+mod formula_models;
+mod inheritance_models;
+mod inline_property_models;
 mod notification_models;
+mod ownership_models;
 mod property_models;
+mod reference_models;
+mod relation_models;
+mod revision_models;
+mod search_filter_models;
+mod search_models;
+mod share_link_models;
+mod tag_models;
+mod team_models;
 pub mod update_models;
 mod user_models;
+pub use access_request_models::*;
+pub use audit_models::*;
 pub use block_models::*;
+pub use collection_models::*;
 pub use comment_models::*;
+pub use data_query_models::*;
 pub use email_models::*;
+pub use formula_models::*;
+pub use inheritance_models::*;
+pub use inline_property_models::*;
 pub use notification_models::*;
+pub use ownership_models::*;
 pub use property_models::*;
+pub use reference_models::*;
+pub use relation_models::*;
+pub use revision_models::*;
+pub use search_filter_models::*;
+pub use search_models::*;
+pub use share_link_models::*;
+pub use tag_models::*;
+pub use team_models::*;
 pub use user_models::*;
@@ -1,8 +1,14 @@
 use super::super::schema::{notifications, users};
-use crate::{notifications::broker::Broker, LoopError};
-use diesel::prelude::*;
+use crate::{notifications::broker::Broker, LoopError, Page, PageQuery};
+use diesel::{pg::Pg, prelude::*};
 use expo_server_sdk::*;
-use std::{str::FromStr, time::SystemTime};
+use std::{cell::RefCell, str::FromStr, time::SystemTime};
+
+thread_local! {
+	/// Notifications sent inside of a shared transaction on this thread,
+	/// which wait for it to be committed. `None` outside of one.
+	static HELD: RefCell<Option<Vec<Notification>>> = RefCell::new(None);
+}
 
 #[derive(Queryable, Clone)]
 pub struct Notification {
@@ -14,6 +20,57 @@ pub struct Notification {
 	pub time: Option<SystemTime>,
 }
 
+impl Notification {
+	/// A page of the notifications that the user has not cleared, newest first
+	pub fn page_for(
+		user_id: i32,
+		page: &PageQuery<i64>,
+		conn: &PgConnection,
+	) -> Result<Page<Self>, LoopError> {
+		let of_user = || -> notifications::BoxedQuery<'static, Pg> {
+			notifications::dsl::notifications
+				.filter(notifications::recipients.contains(vec![user_id]))
+				.into_boxed()
+		};
+
+		page.load_desc(of_user, notifications::id, conn)
+	}
+
+	/// Publishes the notification to subscriptions, and pushes it to the
+	/// devices of its recipients
+	pub fn deliver(&self, conn: &PgConnection) -> Result<(), LoopError> {
+		Broker::publish(self.clone());
+		let mut tokens: Vec<String> = vec![];
+		for user_id in &self.recipients {
+			let mut user_tokens: Vec<String> = users::dsl::users
+				.select(users::dsl::expo_tokens)
+				.filter(users::dsl::id.eq(user_id))
+				.first(conn)?;
+			tokens.append(&mut user_tokens);
+		}
+		for token in tokens {
+			let token = PushToken::from_str(token.as_str()).unwrap();
+			let msg = PushMessage::new(token)
+				.body(&self.description)
+				.title(&self.name);
+
+			let push_notifier = PushNotifier::new().gzip_policy(GzipPolicy::Always);
+			push_notifier.send_push_notification(&msg).unwrap();
+		}
+		Ok(())
+	}
+
+	/// Starts holding back the notifications sent on this thread
+	pub(crate) fn hold() {
+		HELD.with(|held| *held.borrow_mut() = Some(vec![]));
+	}
+
+	/// Stops holding back notifications, and returns the ones that were held
+	pub(crate) fn release() -> Vec<Notification> {
+		HELD.with(|held| held.borrow_mut().take().unwrap_or_default())
+	}
+}
+
 #[derive(Insertable)]
 #[table_name = "notifications"]
 pub struct NewNotification {
@@ -25,27 +82,21 @@ pub struct NewNotification {
 }
 
 impl NewNotification {
+	/// Saves the notification, and delivers it unless it is being held
+	/// back until a transaction is committed
 	pub fn send(self, conn: &PgConnection) -> Result<Notification, LoopError> {
 		let notif: Notification = diesel::insert_into(notifications::table)
 			.values(&self)
 			.get_result(conn)?;
-		Broker::publish(notif.clone());
-		let mut tokens: Vec<String> = vec![];
-		for user_id in &notif.recipients {
-			let mut user_tokens: Vec<String> = users::dsl::users
-				.select(users::dsl::expo_tokens)
-				.filter(users::dsl::id.eq(user_id))
-				.first(conn)?;
-			tokens.append(&mut user_tokens);
-		}
-		for token in tokens {
-			let token = PushToken::from_str(token.as_str()).unwrap();
-			let msg = PushMessage::new(token)
-				.body(&notif.description)
-				.title(&notif.name);
-
-			let push_notifier = PushNotifier::new().gzip_policy(GzipPolicy::Always);
-			push_notifier.send_push_notification(&msg).unwrap();
+		let held = HELD.with(|held| match held.borrow_mut().as_mut() {
+			Some(held) => {
+				held.push(notif.clone());
+				true
+			}
+			None => false,
+		});
+		if !held {
+			notif.deliver(conn)?;
 		}
 		Ok(notif)
 	}
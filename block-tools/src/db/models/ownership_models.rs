@@ -0,0 +1,255 @@
+use super::super::schema::{blocks, ownership_transfers};
+use super::{AuditAction, Block, NewAuditEvent, RevisionChange};
+use crate::LoopError;
+use diesel::{
+	prelude::*,
+	sql_types::{BigInt, Integer},
+};
+use std::time::SystemTime;
+
+/// An offer to make another user the owner of a block. The block
+/// only changes owners once the recipient accepts.
+#[derive(Queryable, Clone)]
+pub struct OwnershipTransfer {
+	pub id: i64,
+	pub block_id: i64,
+	/// The owner when the transfer was offered
+	pub from_id: i32,
+	pub to_id: i32,
+	/// Whether the blocks under the block that have the same owner are transferred too
+	pub subtree: bool,
+	/// One of `pending`, `accepted`, `declined` or `cancelled`
+	pub status: String,
+	pub created_at: SystemTime,
+	pub decided_at: Option<SystemTime>,
+}
+
+#[derive(Insertable)]
+#[table_name = "ownership_transfers"]
+pub struct NewOwnershipTransfer {
+	pub block_id: i64,
+	pub from_id: i32,
+	pub to_id: i32,
+	pub subtree: bool,
+	pub created_at: SystemTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferStatus {
+	Pending,
+	Accepted,
+	Declined,
+	Cancelled,
+}
+
+impl TransferStatus {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Pending => "pending",
+			Self::Accepted => "accepted",
+			Self::Declined => "declined",
+			Self::Cancelled => "cancelled",
+		}
+	}
+
+	pub fn from_name(status: &str) -> Self {
+		match status {
+			"accepted" => Self::Accepted,
+			"declined" => Self::Declined,
+			"cancelled" => Self::Cancelled,
+			_ => Self::Pending,
+		}
+	}
+}
+
+#[derive(QueryableByName)]
+struct BlockId {
+	#[sql_type = "BigInt"]
+	id: i64,
+}
+
+impl NewOwnershipTransfer {
+	pub fn new(block: &Block, to_id: i32, subtree: bool) -> Self {
+		NewOwnershipTransfer {
+			block_id: block.id,
+			from_id: block.owner_id,
+			to_id,
+			subtree,
+			created_at: SystemTime::now(),
+		}
+	}
+
+	/// Inserts the transfer, cancelling any other pending transfer of the block
+	pub fn insert(self, conn: &PgConnection) -> Result<OwnershipTransfer, LoopError> {
+		conn.transaction::<_, LoopError, _>(|| {
+			for pending in OwnershipTransfer::pending_for_block(self.block_id, conn)? {
+				pending.decide(TransferStatus::Cancelled, conn)?;
+			}
+			Ok(diesel::insert_into(ownership_transfers::table)
+				.values(self)
+				.get_result(conn)?)
+		})
+	}
+}
+
+impl OwnershipTransfer {
+	pub fn by_id(transfer_id: i64, conn: &PgConnection) -> Result<Option<Self>, LoopError> {
+		Ok(ownership_transfers::dsl::ownership_transfers
+			.filter(ownership_transfers::id.eq(transfer_id))
+			.limit(1)
+			.get_result(conn)
+			.optional()?)
+	}
+
+	/// Transfers that a user hasn't answered yet, oldest first
+	pub fn pending_to(to_id: i32, conn: &PgConnection) -> Result<Vec<Self>, LoopError> {
+		Ok(ownership_transfers::dsl::ownership_transfers
+			.filter(ownership_transfers::to_id.eq(to_id))
+			.filter(ownership_transfers::status.eq(TransferStatus::Pending.as_str()))
+			.order_by(ownership_transfers::id)
+			.load(conn)?)
+	}
+
+	pub fn pending_for_block(block_id: i64, conn: &PgConnection) -> Result<Vec<Self>, LoopError> {
+		Ok(ownership_transfers::dsl::ownership_transfers
+			.filter(ownership_transfers::block_id.eq(block_id))
+			.filter(ownership_transfers::status.eq(TransferStatus::Pending.as_str()))
+			.order_by(ownership_transfers::id)
+			.load(conn)?)
+	}
+
+	pub fn status(&self) -> TransferStatus {
+		TransferStatus::from_name(&self.status)
+	}
+
+	/// Makes the recipient the owner of the block, and of the blocks under it
+	/// with the same owner if the transfer includes the subtree. The previous
+	/// owner keeps full permissions. Each block that changes owners gets an
+	/// audit event. Returns the block.
+	pub fn accept(&self, conn: &PgConnection) -> Result<Block, LoopError> {
+		conn.transaction::<_, LoopError, _>(|| {
+			let block_ids = if self.subtree {
+				Block::owned_subtree(self.block_id, self.from_id, conn)?
+			} else {
+				vec![self.block_id]
+			};
+			for block in Block::by_ids(&block_ids, conn)? {
+				if block.owner_id == self.from_id {
+					let changed = block.change_owner(self.to_id, Some(self.to_id), conn)?;
+					NewAuditEvent::new(AuditAction::Owner, &block, &changed, Some(self.to_id))
+						.insert(conn)?;
+				}
+			}
+			self.decide(TransferStatus::Accepted, conn)?;
+			Ok(Block::by_id(self.block_id, conn)?.ok_or(LoopError::GenericError)?)
+		})
+	}
+
+	pub fn decline(&self, conn: &PgConnection) -> Result<OwnershipTransfer, LoopError> {
+		self.decide(TransferStatus::Declined, conn)
+	}
+
+	pub fn cancel(&self, conn: &PgConnection) -> Result<OwnershipTransfer, LoopError> {
+		self.decide(TransferStatus::Cancelled, conn)
+	}
+
+	fn decide(
+		&self,
+		status: TransferStatus,
+		conn: &PgConnection,
+	) -> Result<OwnershipTransfer, LoopError> {
+		Ok(diesel::update(
+			ownership_transfers::dsl::ownership_transfers
+				.filter(ownership_transfers::id.eq(self.id)),
+		)
+		.set((
+			ownership_transfers::status.eq(status.as_str()),
+			ownership_transfers::decided_at.eq(SystemTime::now()),
+		))
+		.get_result(conn)?)
+	}
+}
+
+impl Block {
+	/// IDs of a block and every block under it (through properties) that has
+	/// the same owner. Blocks with other owners end the walk.
+	pub fn owned_subtree(
+		root_id: i64,
+		owner_id: i32,
+		conn: &PgConnection,
+	) -> Result<Vec<i64>, LoopError> {
+		let ids: Vec<BlockId> = diesel::sql_query(OWNED_SUBTREE)
+			.bind::<BigInt, _>(root_id)
+			.bind::<Integer, _>(owner_id)
+			.load(conn)?;
+		Ok(ids.into_iter().map(|block| block.id).collect())
+	}
+
+	/// Makes another user the owner. The previous owner drops to full
+	/// permissions, and the new owner is taken out of the other lists.
+	pub fn change_owner(
+		&self,
+		owner_id: i32,
+		author_id: Option<i32>,
+		conn: &PgConnection,
+	) -> Result<Block, LoopError> {
+		let others = |ids: &[i32]| -> Vec<i32> {
+			ids.iter().copied().filter(|id| *id != owner_id).collect()
+		};
+		let mut perm_full = others(&self.perm_full);
+		if self.owner_id != owner_id && !perm_full.contains(&self.owner_id) {
+			perm_full.push(self.owner_id);
+		}
+		let block: Block = diesel::update(blocks::dsl::blocks.filter(blocks::id.eq(self.id)))
+			.set((
+				blocks::owner_id.eq(owner_id),
+				blocks::co_owners.eq(others(&self.co_owners)),
+				blocks::perm_full.eq(perm_full),
+				blocks::perm_edit.eq(others(&self.perm_edit)),
+				blocks::perm_comment.eq(others(&self.perm_comment)),
+				blocks::perm_view.eq(others(&self.perm_view)),
+				blocks::updated_at.eq(SystemTime::now()),
+			))
+			.get_result(conn)?;
+		block.record_revision(RevisionChange::Permissions, author_id, conn)?;
+		Block::propagate_perms(block.id, conn)?;
+		Ok(block)
+	}
+
+	/// Sets the users that have the same permissions as the owner
+	pub fn update_co_owners_by(
+		&self,
+		co_owners: Vec<i32>,
+		author_id: Option<i32>,
+		conn: &PgConnection,
+	) -> Result<Block, LoopError> {
+		let co_owners: Vec<i32> = co_owners
+			.into_iter()
+			.filter(|id| *id != self.owner_id)
+			.collect();
+		let block: Block = diesel::update(blocks::dsl::blocks.filter(blocks::id.eq(self.id)))
+			.set((
+				blocks::co_owners.eq(co_owners),
+				blocks::updated_at.eq(SystemTime::now()),
+			))
+			.get_result(conn)?;
+		block.record_revision(RevisionChange::Permissions, author_id, conn)?;
+		Block::propagate_perms(block.id, conn)?;
+		Ok(block)
+	}
+}
+
+/// Walks down the properties from a block, only through blocks with the given
+/// owner. `UNION` drops blocks that were already found, so cycles end.
+const OWNED_SUBTREE: &str = "
+WITH RECURSIVE subtree(id) AS (
+	SELECT id FROM blocks WHERE id = $1 AND owner_id = $2
+	UNION
+	SELECT p.value_id
+	FROM properties p
+	INNER JOIN subtree s ON p.parent_id = s.id
+	INNER JOIN blocks b ON b.id = p.value_id
+	WHERE b.owner_id = $2
+)
+SELECT id FROM subtree
+";
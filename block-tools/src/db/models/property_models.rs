@@ -1,15 +1,26 @@
-use diesel::{prelude::*, PgConnection};
+use diesel::{
+	prelude::*,
+	sql_types::{Array, BigInt, Integer, Nullable, Text},
+	PgConnection,
+};
 
 use super::super::schema::properties;
+use super::{Block, BlockRevision, Formula, RevisionChange};
 use crate::LoopError;
 
-#[derive(Queryable)]
+#[derive(Queryable, QueryableByName)]
+#[table_name = "properties"]
 pub struct Property {
 	pub id: i64,
 	pub property_name: String,
 	pub parent_id: i64,
 	pub value_id: i64,
 	pub annotation: Option<String>,
+	/// Whether the parent is the one used for the value's breadcrumb
+	pub is_primary: bool,
+	/// Where the property goes among its siblings. Positions are compared as
+	/// strings, and properties without one go after the rest.
+	pub position: Option<String>,
 }
 
 #[derive(Insertable)]
@@ -29,9 +40,450 @@ impl NewProperty {
 		}
 	}
 
+	/// Inserts the property as the acting user, see `BlockRevision::act_as`
 	pub fn insert(self, conn: &PgConnection) -> Result<Property, LoopError> {
+		self.insert_by(BlockRevision::acting_author(), conn)
+	}
+
+	/// Inserts the property, recording the change in the parent's history
+	pub fn insert_by(
+		self,
+		author_id: Option<i32>,
+		conn: &PgConnection,
+	) -> Result<Property, LoopError> {
+		let property = self.insert_without_revision(conn)?;
+		if let Some(parent) = Block::by_id(property.parent_id, conn)? {
+			parent.record_revision(RevisionChange::Properties, author_id, conn)?;
+		}
+		Formula::recompute(property.parent_id, conn)?;
+		Block::propagate_perms(property.value_id, conn)?;
+		Ok(property)
+	}
+
+	/// Inserts the property right before one of its siblings
+	pub fn insert_before(
+		self,
+		sibling: &Property,
+		author_id: Option<i32>,
+		conn: &PgConnection,
+	) -> Result<Property, LoopError> {
+		self.insert_next_to(sibling, true, author_id, conn)
+	}
+
+	/// Inserts the property right after one of its siblings
+	pub fn insert_after(
+		self,
+		sibling: &Property,
+		author_id: Option<i32>,
+		conn: &PgConnection,
+	) -> Result<Property, LoopError> {
+		self.insert_next_to(sibling, false, author_id, conn)
+	}
+
+	fn insert_next_to(
+		self,
+		sibling: &Property,
+		before: bool,
+		author_id: Option<i32>,
+		conn: &PgConnection,
+	) -> Result<Property, LoopError> {
+		conn.transaction::<_, LoopError, _>(|| {
+			let children = Property::rank_children(sibling.parent_id, conn)?;
+			let index = children
+				.iter()
+				.position(|child| child.id == sibling.id)
+				.unwrap_or(children.len());
+			let (lower, upper) = if before {
+				(index.checked_sub(1), Some(index))
+			} else {
+				(Some(index), Some(index + 1))
+			};
+			let rank_at = |index: Option<usize>| {
+				index
+					.and_then(|index| children.get(index))
+					.and_then(|child| child.position.as_deref())
+			};
+			let position = rank_between(rank_at(lower), rank_at(upper));
+
+			let property = self
+				.insert_without_revision(conn)?
+				.set_position(&position, conn)?;
+			if let Some(parent) = Block::by_id(property.parent_id, conn)? {
+				parent.record_revision(RevisionChange::Properties, author_id, conn)?;
+			}
+			Formula::recompute(property.parent_id, conn)?;
+			Block::propagate_perms(property.value_id, conn)?;
+			Ok(property)
+		})
+	}
+
+	/// Inserts the property without touching the parent's history
+	pub fn insert_without_revision(self, conn: &PgConnection) -> Result<Property, LoopError> {
 		Ok(diesel::insert_into(properties::table)
 			.values(&self)
 			.get_result(conn)?)
 	}
 }
+
+impl Property {
+	/// All the properties that can be reached from a block by following
+	/// properties at most `depth` times. If `names` is provided, only
+	/// properties with one of those names are followed.
+	pub fn reachable_from(
+		root_id: i64,
+		depth: i32,
+		names: Option<Vec<String>>,
+		conn: &PgConnection,
+	) -> Result<Vec<Property>, LoopError> {
+		Ok(diesel::sql_query(REACHABLE_PROPERTIES)
+			.bind::<BigInt, _>(root_id)
+			.bind::<Integer, _>(depth)
+			.bind::<Nullable<Array<Text>>, _>(names)
+			.load(conn)?)
+	}
+
+	/// Every property that leads up to one of the blocks, or to any of their
+	/// ancestors. Loads the parents of all the blocks in one query.
+	pub fn ancestors_of(
+		block_ids: &[i64],
+		conn: &PgConnection,
+	) -> Result<Vec<Property>, LoopError> {
+		Ok(diesel::sql_query(ANCESTOR_PROPERTIES)
+			.bind::<Array<BigInt>, _>(block_ids)
+			.load(conn)?)
+	}
+
+	/// Makes `parent_id` the primary parent of a block, or clears the
+	/// primary parent if there is none. Returns how many properties
+	/// from the parent to the block there are.
+	pub fn set_primary_parent(
+		block_id: i64,
+		parent_id: Option<i64>,
+		conn: &PgConnection,
+	) -> Result<usize, LoopError> {
+		conn.transaction::<_, LoopError, _>(|| {
+			let to_block = properties::dsl::properties.filter(properties::value_id.eq(block_id));
+			diesel::update(to_block)
+				.set(properties::is_primary.eq(false))
+				.execute(conn)?;
+			let parent_id = match parent_id {
+				Some(parent_id) => parent_id,
+				None => return Ok(0),
+			};
+			Ok(
+				diesel::update(to_block.filter(properties::parent_id.eq(parent_id)))
+					.set(properties::is_primary.eq(true))
+					.execute(conn)?,
+			)
+		})
+	}
+
+	/// The properties of a block, in order
+	pub fn children_of(parent_id: i64, conn: &PgConnection) -> Result<Vec<Property>, LoopError> {
+		let mut children: Vec<Property> = properties::dsl::properties
+			.filter(properties::parent_id.eq(parent_id))
+			.load(conn)?;
+		children.sort_by(|a, b| {
+			(a.position.is_none(), &a.position, a.id).cmp(&(
+				b.position.is_none(),
+				&b.position,
+				b.id,
+			))
+		});
+		Ok(children)
+	}
+
+	/// The properties of a block in order, after giving a position to
+	/// every one that doesn't have one yet
+	pub fn rank_children(parent_id: i64, conn: &PgConnection) -> Result<Vec<Property>, LoopError> {
+		let mut children = Property::children_of(parent_id, conn)?;
+		let mut previous: Option<String> = None;
+		for child in children.iter_mut() {
+			let in_order = match (&previous, &child.position) {
+				(_, None) => false,
+				(None, Some(_)) => true,
+				(Some(previous), Some(position)) => position > previous,
+			};
+			if !in_order {
+				let position = rank_between(previous.as_deref(), None);
+				*child = child.set_position(&position, conn)?;
+			}
+			previous = child.position.clone();
+		}
+		Ok(children)
+	}
+
+	/// Puts the properties of a block in the order of `property_ids`. Properties
+	/// that aren't listed keep their order, after the listed ones. Only the
+	/// properties that have to move get new positions.
+	pub fn reorder(
+		parent_id: i64,
+		property_ids: &[i64],
+		author_id: Option<i32>,
+		conn: &PgConnection,
+	) -> Result<Vec<Property>, LoopError> {
+		conn.transaction::<_, LoopError, _>(|| {
+			let mut children = Property::children_of(parent_id, conn)?;
+			children.sort_by_key(|child| {
+				property_ids
+					.iter()
+					.position(|id| *id == child.id)
+					.unwrap_or(property_ids.len())
+			});
+
+			let kept = longest_ordered(
+				&children
+					.iter()
+					.map(|child| child.position.as_deref())
+					.collect::<Vec<_>>(),
+			);
+			// The position of the next property that stays, for each property
+			let mut upper: Vec<Option<String>> = vec![None; children.len()];
+			let mut next: Option<String> = None;
+			for index in (0..children.len()).rev() {
+				upper[index] = next.clone();
+				if kept[index] {
+					next = children[index].position.clone();
+				}
+			}
+
+			let mut ordered = vec![];
+			let mut previous: Option<String> = None;
+			for (index, child) in children.into_iter().enumerate() {
+				let child = if kept[index] {
+					child
+				} else {
+					let position = rank_between(previous.as_deref(), upper[index].as_deref());
+					child.set_position(&position, conn)?
+				};
+				previous = child.position.clone();
+				ordered.push(child);
+			}
+
+			if kept.contains(&false) {
+				if let Some(parent) = Block::by_id(parent_id, conn)? {
+					parent.record_revision(RevisionChange::Properties, author_id, conn)?;
+				}
+				Formula::recompute(parent_id, conn)?;
+			}
+			Ok(ordered)
+		})
+	}
+
+	pub fn set_position(&self, position: &str, conn: &PgConnection) -> Result<Property, LoopError> {
+		Ok(
+			diesel::update(properties::dsl::properties.filter(properties::id.eq(self.id)))
+				.set(properties::position.eq(position))
+				.get_result(conn)?,
+		)
+	}
+
+	/// Deletes the property as the acting user, see `BlockRevision::act_as`
+	pub fn delete(&self, conn: &PgConnection) -> Result<(), LoopError> {
+		self.delete_by(BlockRevision::acting_author(), conn)
+	}
+
+	/// Deletes the property, recording the change in the parent's history
+	pub fn delete_by(&self, author_id: Option<i32>, conn: &PgConnection) -> Result<(), LoopError> {
+		diesel::delete(properties::dsl::properties.filter(properties::id.eq(self.id)))
+			.execute(conn)?;
+		if let Some(parent) = Block::by_id(self.parent_id, conn)? {
+			parent.record_revision(RevisionChange::Properties, author_id, conn)?;
+		}
+		Formula::recompute(self.parent_id, conn)?;
+		Block::propagate_perms(self.value_id, conn)?;
+		Ok(())
+	}
+}
+
+/// Walks the property graph outward from `root_id` in a single recursive
+/// query. Each block is walked from the fewest steps it can be reached in,
+/// and every property found within `depth` steps is returned once.
+/// `UNION` leaves out blocks that were already reached in as many steps,
+/// so the walk ends on cycles and doesn't follow every path of a DAG.
+const REACHABLE_PROPERTIES: &str = "
+WITH RECURSIVE reached(id, depth) AS (
+	SELECT $1::BIGINT, 0
+	UNION
+	SELECT p.value_id, r.depth + 1
+	FROM properties p
+	INNER JOIN reached r ON p.parent_id = r.id
+	WHERE r.depth + 1 < $2
+		AND ($3::varchar[] IS NULL OR p.property_name = ANY($3))
+),
+nearest(id, depth) AS (
+	SELECT id, MIN(depth) FROM reached GROUP BY id
+)
+SELECT p.* FROM properties p
+INNER JOIN nearest n ON p.parent_id = n.id
+WHERE n.depth < $2
+	AND ($3::varchar[] IS NULL OR p.property_name = ANY($3))
+ORDER BY p.id
+";
+
+/// Walks the property graph upward from each of the blocks in `$1`,
+/// finding every property that leads to one of their ancestors. `UNION`
+/// leaves out blocks that were already found, so cycles end.
+const ANCESTOR_PROPERTIES: &str = "
+WITH RECURSIVE ancestors(id) AS (
+	SELECT unnest($1::BIGINT[])
+	UNION
+	SELECT p.parent_id
+	FROM properties p
+	INNER JOIN ancestors a ON p.value_id = a.id
+)
+SELECT * FROM properties
+WHERE value_id IN (SELECT id FROM ancestors)
+ORDER BY id
+";
+
+/// Which positions can stay as they are so that as few as possible change.
+/// These are the most positions that are already in order, even if others
+/// are between them. Missing positions never stay.
+fn longest_ordered(positions: &[Option<&str>]) -> Vec<bool> {
+	// The last position of the longest ordered run of each length so far
+	let mut ends: Vec<(usize, &str)> = vec![];
+	// The position before each one in its run
+	let mut previous: Vec<Option<usize>> = vec![None; positions.len()];
+	for (index, position) in positions.iter().enumerate() {
+		let position = match position {
+			Some(position) => *position,
+			None => continue,
+		};
+		let length = ends.partition_point(|(_, end)| *end < position);
+		previous[index] = length.checked_sub(1).map(|before| ends[before].0);
+		if length == ends.len() {
+			ends.push((index, position));
+		} else {
+			ends[length] = (index, position);
+		}
+	}
+
+	let mut kept = vec![false; positions.len()];
+	let mut current = ends.last().map(|(index, _)| *index);
+	while let Some(index) = current {
+		kept[index] = true;
+		current = previous[index];
+	}
+	kept
+}
+
+/// The characters used in positions, in the order they sort
+const RANK_DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+/// Makes a position that sorts between two others. Either side can be left
+/// out to make a position before or after everything. The result never ends
+/// with `0`, so there is always room for another position before it.
+pub fn rank_between(before: Option<&str>, after: Option<&str>) -> String {
+	let base = RANK_DIGITS.len();
+	let digit = |rank: Option<&str>, index: usize| {
+		rank.and_then(|rank| rank.as_bytes().get(index))
+			.and_then(|byte| RANK_DIGITS.iter().position(|digit| digit == byte))
+	};
+	// A position that isn't before the next one can't be placed between
+	let after = match (before, after) {
+		(Some(before), Some(after)) if before >= after => None,
+		_ => after,
+	};
+
+	let mut rank = String::new();
+	// Once the rank sorts before `after`, any digit can follow
+	let mut bounded = after.is_some();
+	let mut index = 0;
+	loop {
+		let low = digit(before, index).unwrap_or(0);
+		let high = match (bounded, digit(after, index)) {
+			(true, Some(high)) => high,
+			_ => {
+				bounded = false;
+				base
+			}
+		};
+		if high > low + 1 {
+			rank.push(RANK_DIGITS[(low + high) / 2] as char);
+			return rank;
+		}
+		rank.push(RANK_DIGITS[low] as char);
+		if high == low + 1 {
+			bounded = false;
+		}
+		index += 1;
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn between(before: Option<&str>, after: Option<&str>) -> String {
+		let rank = rank_between(before, after);
+		if let Some(before) = before {
+			assert!(
+				rank.as_str() > before,
+				"{} should be after {}",
+				rank,
+				before
+			);
+		}
+		if let Some(after) = after {
+			if before.map_or(true, |before| before < after) {
+				assert!(rank.as_str() < after, "{} should be before {}", rank, after);
+			}
+		}
+		assert!(!rank.ends_with('0'));
+		rank
+	}
+
+	#[test]
+	fn ranks_without_bounds() {
+		assert_eq!("i", between(None, None));
+		between(Some("i"), None);
+		between(None, Some("i"));
+		between(None, Some("01"));
+		between(Some("zz"), None);
+	}
+
+	#[test]
+	fn ranks_between_adjacent_digits() {
+		assert_eq!("ai", between(Some("a"), Some("b")));
+		between(Some("a"), Some("a1"));
+		between(Some("az"), Some("b"));
+		between(Some("azzz"), Some("b"));
+	}
+
+	#[test]
+	fn ranks_after_when_out_of_order() {
+		between(Some("b"), Some("a"));
+		between(Some("b"), Some("b"));
+	}
+
+	#[test]
+	fn repeated_inserts_stay_ordered() {
+		// Always right after the same property
+		let mut after = "b".to_string();
+		for _ in 0..100 {
+			after = between(Some("a"), Some(&after));
+		}
+		// Always right before the same property
+		let mut before = "a".to_string();
+		for _ in 0..100 {
+			before = between(Some(&before), Some("b"));
+		}
+		// Always first
+		let mut first = "i".to_string();
+		for _ in 0..100 {
+			first = between(None, Some(&first));
+		}
+	}
+
+	#[test]
+	fn only_moved_positions_change() {
+		// The last property was moved to the start
+		let kept = longest_ordered(&[Some("d"), Some("a"), Some("b"), Some("c")]);
+		assert_eq!(vec![false, true, true, true], kept);
+		let kept = longest_ordered(&[Some("a"), Some("c"), Some("b"), None]);
+		assert_eq!(2, kept.iter().filter(|kept| **kept).count());
+		assert!(!kept[3]);
+		assert_eq!(vec![false; 0], longest_ordered(&[]));
+	}
+}
@@ -0,0 +1,257 @@
+use super::super::schema::{block_references, blocks};
+use super::Block;
+use crate::LoopError;
+use diesel::prelude::*;
+use serde_json::Value;
+
+/// The start of the app path that links to a block, such as `/b/12`
+pub const BLOCK_PATH_PREFIX: &str = "/b/";
+
+/// A link from inside one block's content to another block
+#[derive(Queryable, Clone)]
+pub struct BlockReference {
+	pub id: i64,
+	/// The block whose content has the link
+	pub source_id: i64,
+	/// The block that is linked to
+	pub target_id: i64,
+	/// The text that was shown for the link
+	pub link_text: Option<String>,
+	/// True if the target was deleted, or was renamed so the link text is outdated
+	pub dangling: bool,
+}
+
+#[derive(Insertable)]
+#[table_name = "block_references"]
+pub struct NewBlockReference {
+	pub source_id: i64,
+	pub target_id: i64,
+	pub link_text: Option<String>,
+	pub dangling: bool,
+}
+
+impl BlockReference {
+	/// All the references that point to a block
+	pub fn to_block(target_id: i64, conn: &PgConnection) -> Result<Vec<Self>, LoopError> {
+		Ok(block_references::dsl::block_references
+			.filter(block_references::target_id.eq(target_id))
+			.order_by(block_references::id)
+			.load(conn)?)
+	}
+
+	/// All the references inside of a block's content
+	pub fn from_block(source_id: i64, conn: &PgConnection) -> Result<Vec<Self>, LoopError> {
+		Ok(block_references::dsl::block_references
+			.filter(block_references::source_id.eq(source_id))
+			.order_by(block_references::id)
+			.load(conn)?)
+	}
+
+	/// Replaces the references stored for a block with the ones found in its
+	/// data. Links that were already there stay outdated if they were.
+	pub fn index(block: &Block, conn: &PgConnection) -> Result<Vec<Self>, LoopError> {
+		let existing = BlockReference::from_block(block.id, conn)?;
+		diesel::delete(
+			block_references::dsl::block_references
+				.filter(block_references::source_id.eq(block.id)),
+		)
+		.execute(conn)?;
+
+		let found = match &block.block_data {
+			Some(data) => extract_references(data),
+			None => return Ok(vec![]),
+		};
+		let mut references = vec![];
+		for (target_id, link_text) in found {
+			let target_exists: bool = diesel::select(diesel::dsl::exists(
+				blocks::dsl::blocks.filter(blocks::id.eq(target_id)),
+			))
+			.get_result(conn)?;
+			references.push(NewBlockReference {
+				source_id: block.id,
+				target_id,
+				dangling: !target_exists || was_dangling(&existing, target_id, &link_text),
+				link_text,
+			});
+		}
+		if references.is_empty() {
+			return Ok(vec![]);
+		}
+		Ok(diesel::insert_into(block_references::table)
+			.values(references)
+			.get_results(conn)?)
+	}
+
+	/// Marks every reference to a block as dangling, for when it is deleted
+	pub fn mark_deleted(target_id: i64, conn: &PgConnection) -> Result<usize, LoopError> {
+		Ok(diesel::update(
+			block_references::dsl::block_references
+				.filter(block_references::target_id.eq(target_id)),
+		)
+		.set(block_references::dangling.eq(true))
+		.execute(conn)?)
+	}
+
+	/// Updates the references to a block after it was renamed. Links that
+	/// showed the old name are outdated, and links that show the new name
+	/// aren't anymore. Links with any other text were given their own text,
+	/// so they are left alone.
+	pub fn mark_renamed(
+		target_id: i64,
+		old_name: &str,
+		new_name: &str,
+		conn: &PgConnection,
+	) -> Result<(), LoopError> {
+		for reference in BlockReference::to_block(target_id, conn)? {
+			let link_text = reference.link_text.as_deref();
+			match renamed_dangling(link_text, old_name, new_name) {
+				Some(dangling) if dangling != reference.dangling => {
+					diesel::update(
+						block_references::dsl::block_references
+							.filter(block_references::id.eq(reference.id)),
+					)
+					.set(block_references::dangling.eq(dangling))
+					.execute(conn)?;
+				}
+				_ => {}
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Whether the same link was stored as outdated before the block was indexed again
+fn was_dangling(existing: &[BlockReference], target_id: i64, link_text: &Option<String>) -> bool {
+	existing.iter().any(|reference| {
+		reference.dangling && reference.target_id == target_id && &reference.link_text == link_text
+	})
+}
+
+/// Whether a link is outdated after its target was renamed, or `None` if
+/// the rename doesn't change that
+fn renamed_dangling(link_text: Option<&str>, old_name: &str, new_name: &str) -> Option<bool> {
+	match link_text {
+		_ if old_name == new_name => None,
+		Some(text) if text == old_name => Some(true),
+		Some(text) if text == new_name => Some(false),
+		_ => None,
+	}
+}
+
+/// Parses the ID of a block from an app path like `/b/12`
+pub fn block_id_from_path(path: &str) -> Option<i64> {
+	path.strip_prefix(BLOCK_PATH_PREFIX)?
+		.trim_end_matches('/')
+		.parse()
+		.ok()
+}
+
+/// Finds every link to a block in a block's data, along with the link's
+/// text. The data is expected to be JSON, like serialized display components.
+pub fn extract_references(data: &str) -> Vec<(i64, Option<String>)> {
+	let mut found = vec![];
+	if let Ok(value) = serde_json::from_str::<Value>(data) {
+		walk_references(&value, &mut found);
+	}
+	found
+}
+
+fn walk_references(value: &Value, found: &mut Vec<(i64, Option<String>)>) {
+	match value {
+		Value::Object(map) => {
+			let target = map
+				.get("app_path")
+				.and_then(Value::as_str)
+				.and_then(block_id_from_path);
+			if let Some(target_id) = target {
+				// Link components keep their text in a text component
+				let link_text = map
+					.get("text")
+					.and_then(|text| text.get("text").or(Some(text)))
+					.and_then(Value::as_str)
+					.map(String::from);
+				found.push((target_id, link_text));
+			}
+			for child in map.values() {
+				walk_references(child, found);
+			}
+		}
+		Value::Array(list) => {
+			for child in list {
+				walk_references(child, found);
+			}
+		}
+		_ => {}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn reference(target_id: i64, link_text: Option<&str>, dangling: bool) -> BlockReference {
+		BlockReference {
+			id: 1,
+			source_id: 1,
+			target_id,
+			link_text: link_text.map(String::from),
+			dangling,
+		}
+	}
+
+	#[test]
+	fn paths_are_parsed() {
+		assert_eq!(Some(12), block_id_from_path("/b/12"));
+		assert_eq!(Some(12), block_id_from_path("/b/12/"));
+		assert_eq!(None, block_id_from_path("/b/abc"));
+		assert_eq!(None, block_id_from_path("/u/12"));
+	}
+
+	#[test]
+	fn nested_links_are_found() {
+		let data = r#"{
+			"cid": "stack",
+			"items": [
+				{"cid": "link", "app_path": "/b/2", "text": {"cid": "text", "text": "Two"}},
+				{"cid": "button", "app_path": "/b/3", "text": "Three"},
+				{"cid": "link", "app_path": "/b/4"},
+				{"cid": "link", "app_path": "https://example.com", "text": "Away"}
+			]
+		}"#;
+		assert_eq!(
+			vec![
+				(2, Some("Two".to_string())),
+				(3, Some("Three".to_string())),
+				(4, None),
+			],
+			extract_references(data)
+		);
+	}
+
+	#[test]
+	fn plain_text_has_no_links() {
+		assert!(extract_references("see /b/2").is_empty());
+		assert!(extract_references("").is_empty());
+	}
+
+	#[test]
+	fn renames_outdate_links_with_the_old_name() {
+		assert_eq!(Some(true), renamed_dangling(Some("Old"), "Old", "New"));
+		assert_eq!(Some(false), renamed_dangling(Some("New"), "Old", "New"));
+		assert_eq!(None, renamed_dangling(Some("Custom"), "Old", "New"));
+		assert_eq!(None, renamed_dangling(None, "Old", "New"));
+		assert_eq!(None, renamed_dangling(Some("Old"), "Old", "Old"));
+	}
+
+	#[test]
+	fn unchanged_links_stay_dangling() {
+		let existing = vec![
+			reference(2, Some("Old"), true),
+			reference(3, Some("Three"), false),
+		];
+		assert!(was_dangling(&existing, 2, &Some("Old".to_string())));
+		assert!(!was_dangling(&existing, 2, &Some("New".to_string())));
+		assert!(!was_dangling(&existing, 3, &Some("Three".to_string())));
+		assert!(!was_dangling(&existing, 4, &None));
+	}
+}
@@ -0,0 +1,178 @@
+use super::super::schema::{block_relations, relation_types};
+use crate::LoopError;
+use diesel::prelude::*;
+use std::time::SystemTime;
+
+/// A kind of link between two blocks that isn't a parent and child, like
+/// "blocks" or "duplicates". Each has a name for both directions.
+#[derive(Queryable, Clone)]
+pub struct RelationType {
+	pub id: i64,
+	/// The user that made the relation type, or `None` if it is built in
+	pub owner_id: Option<i32>,
+	/// What the source is to the target, like "blocks"
+	pub name: String,
+	/// What the target is to the source, like "blocked by"
+	pub inverse_name: String,
+	pub created_at: SystemTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "relation_types"]
+pub struct NewRelationType {
+	pub owner_id: Option<i32>,
+	pub name: String,
+	pub inverse_name: String,
+	pub created_at: SystemTime,
+}
+
+/// A link of a certain relation type from one block to another. These are
+/// kept apart from properties, so they never change breadcrumbs.
+#[derive(Queryable, Clone)]
+pub struct BlockRelation {
+	pub id: i64,
+	pub relation_type_id: i64,
+	pub source_id: i64,
+	pub target_id: i64,
+	/// The user that added the relation, if it is known
+	pub author_id: Option<i32>,
+	pub created_at: SystemTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "block_relations"]
+pub struct NewBlockRelation {
+	pub relation_type_id: i64,
+	pub source_id: i64,
+	pub target_id: i64,
+	pub author_id: Option<i32>,
+	pub created_at: SystemTime,
+}
+
+impl NewRelationType {
+	pub fn new(owner_id: i32, name: impl ToString, inverse_name: impl ToString) -> Self {
+		NewRelationType {
+			owner_id: Some(owner_id),
+			name: name.to_string(),
+			inverse_name: inverse_name.to_string(),
+			created_at: SystemTime::now(),
+		}
+	}
+
+	pub fn insert(self, conn: &PgConnection) -> Result<RelationType, LoopError> {
+		Ok(diesel::insert_into(relation_types::table)
+			.values(self)
+			.get_result(conn)?)
+	}
+}
+
+impl RelationType {
+	pub fn by_id(type_id: i64, conn: &PgConnection) -> Result<Option<Self>, LoopError> {
+		Ok(relation_types::dsl::relation_types
+			.filter(relation_types::id.eq(type_id))
+			.limit(1)
+			.get_result(conn)
+			.optional()?)
+	}
+
+	/// The relation types a user can use: the built in ones and their own
+	pub fn available_to(user_id: i32, conn: &PgConnection) -> Result<Vec<Self>, LoopError> {
+		Ok(relation_types::dsl::relation_types
+			.filter(
+				relation_types::owner_id
+					.is_null()
+					.or(relation_types::owner_id.eq(user_id)),
+			)
+			.order_by(relation_types::id)
+			.load(conn)?)
+	}
+
+	/// Whether a user can add relations of this type
+	pub fn usable_by(&self, user_id: i32) -> bool {
+		match self.owner_id {
+			Some(owner_id) => owner_id == user_id,
+			None => true,
+		}
+	}
+
+	/// Finds one of the relation types a user can use by its name
+	pub fn by_name(
+		user_id: i32,
+		name: &str,
+		conn: &PgConnection,
+	) -> Result<Option<Self>, LoopError> {
+		Ok(RelationType::available_to(user_id, conn)?
+			.into_iter()
+			.find(|relation_type| relation_type.name == name))
+	}
+
+	/// Deletes the relation type, along with every relation of its type
+	pub fn delete(&self, conn: &PgConnection) -> Result<(), LoopError> {
+		diesel::delete(relation_types::dsl::relation_types.filter(relation_types::id.eq(self.id)))
+			.execute(conn)?;
+		Ok(())
+	}
+}
+
+impl NewBlockRelation {
+	pub fn new(relation_type_id: i64, source_id: i64, target_id: i64) -> Self {
+		NewBlockRelation {
+			relation_type_id,
+			source_id,
+			target_id,
+			author_id: None,
+			created_at: SystemTime::now(),
+		}
+	}
+
+	/// Adds the relation, or finds the same relation if it already exists
+	pub fn insert(self, conn: &PgConnection) -> Result<BlockRelation, LoopError> {
+		let existing = block_relations::dsl::block_relations
+			.filter(block_relations::relation_type_id.eq(self.relation_type_id))
+			.filter(block_relations::source_id.eq(self.source_id))
+			.filter(block_relations::target_id.eq(self.target_id))
+			.limit(1)
+			.get_result(conn)
+			.optional()?;
+		if let Some(existing) = existing {
+			return Ok(existing);
+		}
+		Ok(diesel::insert_into(block_relations::table)
+			.values(self)
+			.get_result(conn)?)
+	}
+}
+
+impl BlockRelation {
+	pub fn by_id(relation_id: i64, conn: &PgConnection) -> Result<Option<Self>, LoopError> {
+		Ok(block_relations::dsl::block_relations
+			.filter(block_relations::id.eq(relation_id))
+			.limit(1)
+			.get_result(conn)
+			.optional()?)
+	}
+
+	/// The relations that a block has to other blocks
+	pub fn from_block(source_id: i64, conn: &PgConnection) -> Result<Vec<Self>, LoopError> {
+		Ok(block_relations::dsl::block_relations
+			.filter(block_relations::source_id.eq(source_id))
+			.order_by(block_relations::id)
+			.load(conn)?)
+	}
+
+	/// The relations that other blocks have to a block
+	pub fn to_block(target_id: i64, conn: &PgConnection) -> Result<Vec<Self>, LoopError> {
+		Ok(block_relations::dsl::block_relations
+			.filter(block_relations::target_id.eq(target_id))
+			.order_by(block_relations::id)
+			.load(conn)?)
+	}
+
+	pub fn delete(&self, conn: &PgConnection) -> Result<(), LoopError> {
+		diesel::delete(
+			block_relations::dsl::block_relations.filter(block_relations::id.eq(self.id)),
+		)
+		.execute(conn)?;
+		Ok(())
+	}
+}
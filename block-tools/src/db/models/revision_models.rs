@@ -0,0 +1,330 @@
+use super::super::schema::{block_revisions, properties};
+use super::{Block, Property};
+use crate::LoopError;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{cell::Cell, time::SystemTime};
+
+thread_local! {
+	/// The user whose request runs block type code on this thread, which
+	/// the unauthored changes it makes are recorded as. `None` outside of one.
+	static ACTING: Cell<Option<i32>> = Cell::new(None);
+}
+
+/// An immutable snapshot of a block, taken every time one of
+/// its data, color, visibility, permissions or properties change
+#[derive(Queryable, Clone)]
+pub struct BlockRevision {
+	pub id: i64,
+	pub block_id: i64,
+	/// The user that made the change, if it is known
+	pub author_id: Option<i32>,
+	pub created_at: SystemTime,
+	/// What kind of change created this revision
+	pub change: String,
+	pub block_data: Option<String>,
+	pub color: Option<String>,
+	pub public: bool,
+	pub perm_full: Vec<i32>,
+	pub perm_edit: Vec<i32>,
+	pub perm_view: Vec<i32>,
+	/// JSON list of the block's properties at the time
+	pub properties: String,
+	pub perm_comment: Vec<i32>,
+}
+
+#[derive(Insertable)]
+#[table_name = "block_revisions"]
+pub struct NewBlockRevision {
+	pub block_id: i64,
+	pub author_id: Option<i32>,
+	pub created_at: SystemTime,
+	pub change: String,
+	pub block_data: Option<String>,
+	pub color: Option<String>,
+	pub public: bool,
+	pub perm_full: Vec<i32>,
+	pub perm_edit: Vec<i32>,
+	pub perm_view: Vec<i32>,
+	pub properties: String,
+	pub perm_comment: Vec<i32>,
+}
+
+/// The kinds of changes that are recorded as revisions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RevisionChange {
+	Created,
+	Data,
+	Color,
+	Visibility,
+	Permissions,
+	Properties,
+	Revert,
+}
+
+/// A property as it is stored inside of a revision
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PropertySnapshot {
+	pub property_name: String,
+	pub value_id: i64,
+	pub annotation: Option<String>,
+	#[serde(default)]
+	pub position: Option<String>,
+}
+
+/// A single field that differs between two revisions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RevisionFieldChange {
+	pub field: String,
+	pub old: Option<String>,
+	pub new: Option<String>,
+}
+
+impl RevisionChange {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Created => "created",
+			Self::Data => "data",
+			Self::Color => "color",
+			Self::Visibility => "visibility",
+			Self::Permissions => "permissions",
+			Self::Properties => "properties",
+			Self::Revert => "revert",
+		}
+	}
+}
+
+impl From<&Property> for PropertySnapshot {
+	fn from(prop: &Property) -> Self {
+		PropertySnapshot {
+			property_name: prop.property_name.clone(),
+			value_id: prop.value_id,
+			annotation: prop.annotation.clone(),
+			position: prop.position.clone(),
+		}
+	}
+}
+
+impl BlockRevision {
+	/// Runs `f`, recording the changes it makes without an author as made
+	/// by the user
+	pub fn act_as<T>(user_id: Option<i32>, f: impl FnOnce() -> T) -> T {
+		let previous = ACTING.with(|acting| acting.replace(user_id));
+		let result = f();
+		ACTING.with(|acting| acting.set(previous));
+		result
+	}
+
+	/// The user that changes without an author are made by, see `act_as`
+	pub(crate) fn acting_author() -> Option<i32> {
+		ACTING.with(|acting| acting.get())
+	}
+
+	pub fn by_id(revision_id: i64, conn: &PgConnection) -> Result<Option<Self>, LoopError> {
+		Ok(block_revisions::dsl::block_revisions
+			.filter(block_revisions::id.eq(revision_id))
+			.limit(1)
+			.get_result(conn)
+			.optional()?)
+	}
+
+	/// All the revisions of a block, newest first
+	pub fn of_block(block_id: i64, conn: &PgConnection) -> Result<Vec<Self>, LoopError> {
+		Ok(block_revisions::dsl::block_revisions
+			.filter(block_revisions::block_id.eq(block_id))
+			.order_by(block_revisions::id.desc())
+			.load(conn)?)
+	}
+
+	pub fn property_list(&self) -> Vec<PropertySnapshot> {
+		serde_json::from_str(&self.properties).unwrap_or_default()
+	}
+
+	/// Lists the fields that changed going from this revision to `other`
+	pub fn diff(&self, other: &BlockRevision) -> Vec<RevisionFieldChange> {
+		let mut changes = vec![];
+		let mut compare = |field: &str, old: Option<String>, new: Option<String>| {
+			if old != new {
+				changes.push(RevisionFieldChange {
+					field: field.to_string(),
+					old,
+					new,
+				});
+			}
+		};
+		compare("data", self.block_data.clone(), other.block_data.clone());
+		compare("color", self.color.clone(), other.color.clone());
+		compare(
+			"public",
+			Some(self.public.to_string()),
+			Some(other.public.to_string()),
+		);
+		compare(
+			"permFull",
+			id_list(&self.perm_full),
+			id_list(&other.perm_full),
+		);
+		compare(
+			"permEdit",
+			id_list(&self.perm_edit),
+			id_list(&other.perm_edit),
+		);
+		compare(
+			"permComment",
+			id_list(&self.perm_comment),
+			id_list(&other.perm_comment),
+		);
+		compare(
+			"permView",
+			id_list(&self.perm_view),
+			id_list(&other.perm_view),
+		);
+
+		let old_props = self.property_list();
+		let new_props = other.property_list();
+		for prop in &old_props {
+			if !new_props.contains(prop) {
+				compare("property", property_string(prop), None);
+			}
+		}
+		for prop in &new_props {
+			if !old_props.contains(prop) {
+				compare("property", None, property_string(prop));
+			}
+		}
+		changes
+	}
+}
+
+impl NewBlockRevision {
+	/// Captures the current state of a block (including its properties)
+	pub fn snapshot(
+		block: &Block,
+		change: RevisionChange,
+		author_id: Option<i32>,
+		conn: &PgConnection,
+	) -> Result<Self, LoopError> {
+		let props: Vec<PropertySnapshot> = properties::dsl::properties
+			.filter(properties::parent_id.eq(block.id))
+			.order_by(properties::id)
+			.load::<Property>(conn)?
+			.iter()
+			.map(PropertySnapshot::from)
+			.collect();
+		Ok(NewBlockRevision {
+			block_id: block.id,
+			author_id,
+			created_at: SystemTime::now(),
+			change: change.as_str().to_string(),
+			block_data: block.block_data.clone(),
+			color: block.color.clone(),
+			public: block.public,
+			perm_full: block.perm_full.clone(),
+			perm_edit: block.perm_edit.clone(),
+			perm_view: block.perm_view.clone(),
+			properties: serde_json::to_string(&props).unwrap_or_else(|_| "[]".into()),
+			perm_comment: block.perm_comment.clone(),
+		})
+	}
+
+	pub fn insert(self, conn: &PgConnection) -> Result<BlockRevision, LoopError> {
+		Ok(diesel::insert_into(block_revisions::table)
+			.values(self)
+			.get_result(conn)?)
+	}
+}
+
+fn id_list(ids: &[i32]) -> Option<String> {
+	serde_json::to_string(ids).ok()
+}
+
+fn property_string(prop: &PropertySnapshot) -> Option<String> {
+	serde_json::to_string(prop).ok()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn revision(block_data: &str, properties: &[PropertySnapshot]) -> BlockRevision {
+		BlockRevision {
+			id: 1,
+			block_id: 1,
+			author_id: None,
+			created_at: SystemTime::UNIX_EPOCH,
+			change: RevisionChange::Data.as_str().to_string(),
+			block_data: Some(block_data.to_string()),
+			color: None,
+			public: false,
+			perm_full: vec![],
+			perm_edit: vec![],
+			perm_view: vec![],
+			properties: serde_json::to_string(properties).unwrap(),
+			perm_comment: vec![],
+		}
+	}
+
+	fn property(value_id: i64) -> PropertySnapshot {
+		PropertySnapshot {
+			property_name: "child".to_string(),
+			value_id,
+			annotation: None,
+			position: None,
+		}
+	}
+
+	fn fields(changes: &[RevisionFieldChange]) -> Vec<&str> {
+		changes.iter().map(|change| change.field.as_str()).collect()
+	}
+
+	#[test]
+	fn same_revisions_have_no_changes() {
+		let old = revision("hello", &[property(2)]);
+		assert!(old.diff(&old.clone()).is_empty());
+	}
+
+	#[test]
+	fn changed_fields_are_listed() {
+		let old = revision("hello", &[]);
+		let mut new = revision("goodbye", &[]);
+		new.color = Some("rgb(1,2,3)".to_string());
+		new.public = true;
+		new.perm_edit = vec![4];
+
+		let changes = old.diff(&new);
+		assert_eq!(
+			vec!["data", "color", "public", "permEdit"],
+			fields(&changes)
+		);
+		assert_eq!(
+			RevisionFieldChange {
+				field: "data".to_string(),
+				old: Some("hello".to_string()),
+				new: Some("goodbye".to_string()),
+			},
+			changes[0]
+		);
+		assert_eq!(Some("[4]".to_string()), changes[3].new);
+	}
+
+	#[test]
+	fn properties_are_added_and_removed() {
+		let old = revision("hello", &[property(2), property(3)]);
+		let new = revision("hello", &[property(3), property(4)]);
+
+		let changes = old.diff(&new);
+		assert_eq!(vec!["property", "property"], fields(&changes));
+		assert_eq!(property_string(&property(2)), changes[0].old);
+		assert_eq!(None, changes[0].new);
+		assert_eq!(None, changes[1].old);
+		assert_eq!(property_string(&property(4)), changes[1].new);
+	}
+
+	#[test]
+	fn unreadable_properties_count_as_none() {
+		let mut old = revision("hello", &[]);
+		old.properties = "not json".to_string();
+		assert!(old.property_list().is_empty());
+		assert!(old.diff(&revision("hello", &[])).is_empty());
+	}
+}
@@ -0,0 +1,409 @@
+use super::TagMatch;
+use crate::{auth::permissions::PermLevel, UserError};
+use diesel::{
+	pg::Pg,
+	query_builder::{BoxedSqlQuery, SqlQuery},
+	sql_types::{Array, BigInt, Integer, Text},
+};
+use serde::{Deserialize, Serialize};
+
+/// How deep groups of filters can be nested, counting the filters themselves
+pub const MAX_FILTER_DEPTH: usize = 8;
+/// The most groups of filters a search can have, counting the filters themselves
+pub const MAX_FILTER_GROUPS: usize = 64;
+
+/// The filters of a search, in the form they are saved in. A block has to
+/// pass every filter that is set, and every group in `all`, at least one
+/// group in `any` and none of the groups in `none`. Groups are filters
+/// themselves, so they can be nested.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SearchFilters {
+	/// Only include blocks the user has starred
+	pub starred: bool,
+	pub block_type: Option<String>,
+	pub owner_id: Option<i32>,
+	/// Only include blocks with these tags. An empty list doesn't filter anything.
+	pub tags: Option<Vec<i64>>,
+	pub tag_match: TagMatch,
+	/// Only include blocks created at or after this time, in seconds since the epoch
+	pub created_after: Option<i64>,
+	pub created_before: Option<i64>,
+	/// Only include blocks updated at or after this time, in seconds since the epoch
+	pub updated_after: Option<i64>,
+	pub updated_before: Option<i64>,
+	pub color: Option<String>,
+	/// Only include public blocks, or only private ones
+	pub public: Option<bool>,
+	/// Only include blocks the user has at least this level on
+	pub perm_level: Option<PermLevel>,
+	/// Only include blocks with comments, or only blocks without
+	pub has_comments: Option<bool>,
+	/// Only include blocks that are under this block, at any depth
+	pub parent_id: Option<i64>,
+	/// Only include blocks the user gets notifications for, or only blocks they don't
+	pub notif_enabled: Option<bool>,
+	pub all: Vec<SearchFilters>,
+	pub any: Vec<SearchFilters>,
+	pub none: Vec<SearchFilters>,
+}
+
+impl SearchFilters {
+	/// The filters as they are saved in a collection
+	pub fn to_json(&self) -> serde_json::Value {
+		serde_json::to_value(self).unwrap_or_default()
+	}
+
+	/// Checks that the groups aren't nested too deep and that there aren't
+	/// too many of them, which keeps the SQL of the filters small
+	pub fn check(&self) -> Result<(), UserError> {
+		self.check_groups(1, &mut 0)
+	}
+
+	fn check_groups(&self, depth: usize, groups: &mut usize) -> Result<(), UserError> {
+		*groups += 1;
+		if depth > MAX_FILTER_DEPTH || *groups > MAX_FILTER_GROUPS {
+			return Err(UserError::TooManyFilters);
+		}
+		for group in self.all.iter().chain(&self.any).chain(&self.none) {
+			group.check_groups(depth + 1, groups)?;
+		}
+		Ok(())
+	}
+}
+
+/// A value that the SQL of a filter binds
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterValue {
+	Int(i32),
+	BigInt(i64),
+	BigInts(Vec<i64>),
+	Text(String),
+}
+
+/// Turns filters into a condition on the block `b`, with `$1` as the user
+/// searching. Values are bound as parameters, numbered from `first_param`.
+pub struct FilterSql {
+	first_param: usize,
+	/// The user whose tags the filters use
+	tag_owner: Option<i32>,
+	pub values: Vec<FilterValue>,
+}
+
+impl FilterSql {
+	pub fn new(first_param: usize, tag_owner: Option<i32>) -> Self {
+		FilterSql {
+			first_param,
+			tag_owner,
+			values: vec![],
+		}
+	}
+
+	/// The condition that blocks have to meet to pass the filters. Errors if
+	/// the filters don't pass `SearchFilters::check`.
+	pub fn condition(&mut self, filters: &SearchFilters) -> Result<String, UserError> {
+		filters.check()?;
+		Ok(self.group(filters))
+	}
+
+	fn group(&mut self, filters: &SearchFilters) -> String {
+		let mut parts: Vec<String> = vec![];
+
+		if filters.starred {
+			parts.push("$1 = ANY(b.stars)".into());
+		}
+		if let Some(block_type) = &filters.block_type {
+			let param = self.bind(FilterValue::Text(block_type.clone()));
+			parts.push(format!("b.block_type = {}", param));
+		}
+		if let Some(owner_id) = filters.owner_id {
+			let param = self.bind(FilterValue::Int(owner_id));
+			parts.push(format!("b.owner_id = {}", param));
+		}
+		// An empty list of tags doesn't filter anything
+		if let Some(tag_ids) = filters.tags.as_ref().filter(|ids| !ids.is_empty()) {
+			parts.push(self.tags(tag_ids, filters.tag_match));
+		}
+		let times = [
+			("b.created_at", ">=", filters.created_after),
+			("b.created_at", "<", filters.created_before),
+			("b.updated_at", ">=", filters.updated_after),
+			("b.updated_at", "<", filters.updated_before),
+		];
+		for (column, operator, time) in times.iter() {
+			if let Some(time) = time {
+				let param = self.bind(FilterValue::BigInt(*time));
+				parts.push(format!(
+					"{} {} (to_timestamp({}) AT TIME ZONE 'UTC')",
+					column, operator, param
+				));
+			}
+		}
+		if let Some(color) = &filters.color {
+			let param = self.bind(FilterValue::Text(color.clone()));
+			parts.push(format!("b.color = {}", param));
+		}
+		if let Some(public) = filters.public {
+			parts.push(negate_unless(public, "(b.public OR b.inherited_public)"));
+		}
+		if let Some(level) = filters.perm_level {
+			parts.push(self.perm_level(level));
+		}
+		if let Some(has_comments) = filters.has_comments {
+			parts.push(negate_unless(
+				has_comments,
+				"EXISTS (SELECT 1 FROM comments c WHERE c.block_id = b.id)",
+			));
+		}
+		if let Some(parent_id) = filters.parent_id {
+			let param = self.bind(FilterValue::BigInt(parent_id));
+			parts.push(SUBTREE.replace("{parent}", &param));
+		}
+		if let Some(enabled) = filters.notif_enabled {
+			parts.push(negate_unless(enabled, "$1 = ANY(b.notif_enabled)"));
+		}
+
+		for group in &filters.all {
+			parts.push(self.group(group));
+		}
+		if !filters.any.is_empty() {
+			let any: Vec<String> = filters.any.iter().map(|group| self.group(group)).collect();
+			parts.push(format!("({})", any.join(" OR ")));
+		}
+		for group in &filters.none {
+			parts.push(format!("NOT {}", self.group(group)));
+		}
+
+		if parts.is_empty() {
+			"TRUE".into()
+		} else {
+			// Conditions on the user are null without one, and that
+			// shouldn't turn into a match when they are negated
+			let parts: Vec<String> = parts
+				.into_iter()
+				.map(|part| format!("COALESCE({}, FALSE)", part))
+				.collect();
+			format!("({})", parts.join(" AND "))
+		}
+	}
+
+	/// Blocks need every tag, or any of them. Only the owner's tags count.
+	fn tags(&mut self, tag_ids: &[i64], tag_match: TagMatch) -> String {
+		let mut tag_ids = tag_ids.to_vec();
+		tag_ids.sort_unstable();
+		tag_ids.dedup();
+		let count = tag_ids.len();
+		let owner_id = self.tag_owner.unwrap_or_default();
+		let owner = self.bind(FilterValue::Int(owner_id));
+		let ids = self.bind(FilterValue::BigInts(tag_ids));
+		let tagged = format!(
+			"SELECT count(*) FROM block_tags bt INNER JOIN tags t ON t.id = bt.tag_id \
+			WHERE bt.block_id = b.id AND t.owner_id = {} AND bt.tag_id = ANY({})",
+			owner, ids
+		);
+		match tag_match {
+			TagMatch::All => format!("({}) = {}", tagged, count),
+			TagMatch::Any => format!("({}) > 0", tagged),
+		}
+	}
+
+	/// The same levels as `has_perm_level`, where each level includes the
+	/// ones above it
+	fn perm_level(&mut self, level: PermLevel) -> String {
+		let param = self.bind(FilterValue::Int(level as i32));
+		PERM_LEVEL.replace("{level}", &param)
+	}
+
+	/// Adds a value, and returns the parameter it is bound to
+	fn bind(&mut self, value: FilterValue) -> String {
+		self.values.push(value);
+		format!("${}", self.first_param + self.values.len() - 1)
+	}
+
+	/// Binds the values to a query, in the order of their parameters
+	pub fn bind_to<'a>(
+		&self,
+		mut query: BoxedSqlQuery<'a, Pg, SqlQuery>,
+	) -> BoxedSqlQuery<'a, Pg, SqlQuery> {
+		for value in &self.values {
+			query = match value.clone() {
+				FilterValue::Int(value) => query.bind::<Integer, _>(value),
+				FilterValue::BigInt(value) => query.bind::<BigInt, _>(value),
+				FilterValue::BigInts(value) => query.bind::<Array<BigInt>, _>(value),
+				FilterValue::Text(value) => query.bind::<Text, _>(value),
+			};
+		}
+		query
+	}
+}
+
+fn negate_unless(keep: bool, condition: &str) -> String {
+	if keep {
+		condition.to_string()
+	} else {
+		format!("NOT {}", condition)
+	}
+}
+
+/// Whether the user has at least the level bound to `{level}`, counting
+/// levels from 0 for view to 4 for owner
+const PERM_LEVEL: &str = "(
+	b.owner_id = $1
+	OR $1 = ANY(b.co_owners)
+	OR ({level} <= 3 AND $1 = ANY(b.perm_full || b.inherited_full))
+	OR ({level} <= 2 AND $1 = ANY(b.perm_edit || b.inherited_edit))
+	OR ({level} <= 1 AND $1 = ANY(b.perm_comment || b.inherited_comment))
+	OR ({level} <= 0 AND (
+		b.public
+		OR b.inherited_public
+		OR $1 = ANY(b.perm_view || b.inherited_view)
+	))
+)";
+
+/// Whether the block can be reached from `{parent}` by following properties.
+/// `UNION` leaves out blocks that were already found, so cycles end.
+const SUBTREE: &str = "b.id IN (
+	WITH RECURSIVE subtree(id) AS (
+		SELECT value_id FROM properties WHERE parent_id = {parent}
+		UNION
+		SELECT p.value_id FROM properties p INNER JOIN subtree s ON p.parent_id = s.id
+	)
+	SELECT id FROM subtree
+)";
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn no_filters_match_everything() {
+		let mut sql = FilterSql::new(7, None);
+		assert_eq!("TRUE", sql.condition(&SearchFilters::default()).unwrap());
+		assert!(sql.values.is_empty());
+	}
+
+	#[test]
+	fn filters_are_bound_in_order() {
+		let filters = SearchFilters {
+			block_type: Some("task".into()),
+			owner_id: Some(4),
+			..Default::default()
+		};
+		let mut sql = FilterSql::new(7, None);
+		assert_eq!(
+			"(COALESCE(b.block_type = $7, FALSE) AND COALESCE(b.owner_id = $8, FALSE))",
+			sql.condition(&filters).unwrap()
+		);
+		assert_eq!(
+			vec![FilterValue::Text("task".into()), FilterValue::Int(4)],
+			sql.values
+		);
+	}
+
+	#[test]
+	fn groups_nest() {
+		let task = SearchFilters {
+			block_type: Some("task".into()),
+			..Default::default()
+		};
+		let filters = SearchFilters {
+			any: vec![
+				task.clone(),
+				SearchFilters {
+					starred: true,
+					..Default::default()
+				},
+			],
+			none: vec![task],
+			..Default::default()
+		};
+		let mut sql = FilterSql::new(14, None);
+		assert_eq!(
+			"(COALESCE(((COALESCE(b.block_type = $14, FALSE)) OR (COALESCE($1 = ANY(b.stars), FALSE))), FALSE) \
+			AND COALESCE(NOT (COALESCE(b.block_type = $15, FALSE)), FALSE))",
+			sql.condition(&filters).unwrap()
+		);
+		assert_eq!(2, sql.values.len());
+	}
+
+	#[test]
+	fn tags_are_deduplicated() {
+		let filters = SearchFilters {
+			tags: Some(vec![3, 2, 3]),
+			..Default::default()
+		};
+		let mut sql = FilterSql::new(1, Some(9));
+		let condition = sql.condition(&filters).unwrap();
+		assert!(condition.contains(") = 2"));
+		assert_eq!(
+			vec![FilterValue::Int(9), FilterValue::BigInts(vec![2, 3])],
+			sql.values
+		);
+	}
+
+	#[test]
+	fn no_tags_dont_filter() {
+		for tag_match in &[TagMatch::All, TagMatch::Any] {
+			let filters = SearchFilters {
+				tags: Some(vec![]),
+				tag_match: *tag_match,
+				..Default::default()
+			};
+			let mut sql = FilterSql::new(1, Some(9));
+			assert_eq!("TRUE", sql.condition(&filters).unwrap());
+		}
+	}
+
+	#[test]
+	fn nesting_is_limited() {
+		let mut filters = SearchFilters::default();
+		for _ in 1..MAX_FILTER_DEPTH {
+			filters = SearchFilters {
+				all: vec![filters],
+				..Default::default()
+			};
+		}
+		assert!(FilterSql::new(1, None).condition(&filters).is_ok());
+		let filters = SearchFilters {
+			none: vec![filters],
+			..Default::default()
+		};
+		assert!(matches!(
+			FilterSql::new(1, None).condition(&filters),
+			Err(UserError::TooManyFilters)
+		));
+	}
+
+	#[test]
+	fn groups_are_limited() {
+		let filters = SearchFilters {
+			any: vec![SearchFilters::default(); MAX_FILTER_GROUPS - 1],
+			..Default::default()
+		};
+		assert!(filters.check().is_ok());
+		let filters = SearchFilters {
+			any: vec![SearchFilters::default(); MAX_FILTER_GROUPS],
+			..Default::default()
+		};
+		assert!(matches!(filters.check(), Err(UserError::TooManyFilters)));
+	}
+
+	#[test]
+	fn saved_filters_read_back() {
+		let filters = SearchFilters {
+			block_type: Some("task".into()),
+			tags: Some(vec![3, 4]),
+			tag_match: TagMatch::Any,
+			perm_level: Some(PermLevel::Edit),
+			none: vec![SearchFilters {
+				has_comments: Some(true),
+				..Default::default()
+			}],
+			..Default::default()
+		};
+		let saved = filters.to_json();
+		assert_eq!(filters, serde_json::from_value(saved).unwrap());
+		let empty: SearchFilters = serde_json::from_str("{}").unwrap();
+		assert_eq!(SearchFilters::default(), empty);
+	}
+}
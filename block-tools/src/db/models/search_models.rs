@@ -0,0 +1,422 @@
+use super::{Block, FilterSql, SearchFilters};
+use crate::{LoopError, Page, PageQuery, SortKey};
+use diesel::{
+	pg::Pg,
+	prelude::*,
+	sql_types::{Array, BigInt, Bool, Double, Integer, Nullable, Text},
+};
+
+/// What to look for in a search of blocks, and how to order what is found
+#[derive(Debug, Clone, Default)]
+pub struct BlockSearch {
+	/// The text to match against block names, the text of their data
+	/// children and their comments. Every word is matched as a prefix, so
+	/// results show up while the last word is still being typed. Words in
+	/// double quotes have to appear together, in that order.
+	pub query: String,
+	/// The user searching, who can only find blocks they can view
+	pub user_id: Option<i32>,
+	/// Include `data` blocks
+	pub with_data: bool,
+	pub filters: SearchFilters,
+	/// The user whose tags the filters use, since users can only see their own
+	pub tag_owner: Option<i32>,
+	/// Only include these blocks
+	pub block_ids: Option<Vec<i64>>,
+	pub sort: SearchSort,
+}
+
+impl BlockSearch {
+	/// A search for the query with the filters provided. The tags in the
+	/// filters are those of `tag_owner`, which is usually the user searching.
+	pub fn filtered(
+		query: impl ToString,
+		user_id: Option<i32>,
+		tag_owner: Option<i32>,
+		filters: &SearchFilters,
+	) -> Self {
+		BlockSearch {
+			query: query.to_string(),
+			user_id,
+			filters: filters.clone(),
+			tag_owner,
+			..Default::default()
+		}
+	}
+}
+
+/// How search results are ordered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchSort {
+	/// By how well the block matches, with starred blocks a little higher
+	Rank,
+	Stars,
+	Updated,
+	Created,
+}
+
+impl Default for SearchSort {
+	fn default() -> Self {
+		Self::Rank
+	}
+}
+
+impl SearchSort {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Rank => "rank",
+			Self::Stars => "stars",
+			Self::Updated => "updated",
+			Self::Created => "created",
+		}
+	}
+
+	pub fn from_name(sort: &str) -> Self {
+		match sort {
+			"stars" => Self::Stars,
+			"updated" => Self::Updated,
+			"created" => Self::Created,
+			_ => Self::Rank,
+		}
+	}
+}
+
+/// A block found by a search
+#[derive(QueryableByName, Debug, Clone, Copy)]
+pub struct SearchHit {
+	#[sql_type = "BigInt"]
+	pub id: i64,
+	/// How well the block matches the query
+	#[sql_type = "Double"]
+	pub rank: f64,
+	/// What the results are ordered by, which is the rank unless the
+	/// search sorts them another way
+	#[sql_type = "Double"]
+	pub sort_key: f64,
+	/// The parts of the block's content and comments that match, marked the
+	/// way `parse_headline` reads them
+	#[sql_type = "Text"]
+	pub headline: String,
+}
+
+/// A part of a block's text that matches a search
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snippet {
+	pub text: String,
+	/// The ranges of the text that match the query, as the character
+	/// (not byte) where each starts and the one after it ends
+	pub highlights: Vec<(usize, usize)>,
+}
+
+/// Marks the start of a match in a headline
+const HIGHLIGHT_START: char = '\u{E000}';
+/// Marks the end of a match in a headline
+const HIGHLIGHT_STOP: char = '\u{E001}';
+/// Separates the fragments of a headline
+const FRAGMENT_DELIMITER: char = '\u{E002}';
+
+impl SearchHit {
+	pub fn key(&self) -> SortKey {
+		SortKey {
+			value: self.sort_key,
+			id: self.id,
+		}
+	}
+
+	/// The parts of the block's text that match the search
+	pub fn snippets(&self) -> Vec<Snippet> {
+		parse_headline(&self.headline)
+	}
+}
+
+#[derive(QueryableByName)]
+struct Count {
+	#[sql_type = "BigInt"]
+	count: i64,
+}
+
+/// Binds the search to `$1` through `$6` of a query. The filters are bound
+/// after every other parameter, since how many there are changes.
+macro_rules! bind_search {
+	($query:expr, $search:expr) => {
+		$query
+			.bind::<Nullable<Integer>, _>($search.user_id)
+			.bind::<Nullable<Text>, _>(search_tsquery(&$search.query))
+			.bind::<Text, _>($search.query.trim())
+			.bind::<Bool, _>($search.with_data)
+			.bind::<Nullable<Array<BigInt>>, _>($search.block_ids.clone())
+			.bind::<Text, _>($search.sort.as_str())
+	};
+}
+
+impl Block {
+	/// Finds a page of the blocks that match a search, in the order the search asks for
+	pub fn search(
+		search: &BlockSearch,
+		page: &PageQuery<SortKey>,
+		conn: &PgConnection,
+	) -> Result<Page<SearchHit>, LoopError> {
+		let mut filters = FilterSql::new(14, search.tag_owner);
+		let condition = filters.condition(&search.filters)?;
+		let query = bind_search!(
+			diesel::sql_query(format!("{}{}", search_matches(&condition), MATCHES_PAGE))
+				.into_boxed::<Pg>(),
+			search
+		)
+		.bind::<Nullable<Double>, _>(page.after.map(|key| key.value))
+		.bind::<Nullable<BigInt>, _>(page.after.map(|key| key.id))
+		.bind::<Nullable<Double>, _>(page.before.map(|key| key.value))
+		.bind::<Nullable<BigInt>, _>(page.before.map(|key| key.id))
+		.bind::<Bool, _>(page.from_end)
+		.bind::<BigInt, _>(page.fetch())
+		.bind::<Text, _>(headline_options());
+		let hits: Vec<SearchHit> = filters.bind_to(query).load(conn)?;
+
+		let mut filters = FilterSql::new(7, search.tag_owner);
+		let condition = filters.condition(&search.filters)?;
+		let query = bind_search!(
+			diesel::sql_query(format!("{}{}", search_matches(&condition), MATCHES_COUNT))
+				.into_boxed::<Pg>(),
+			search
+		);
+		let total: Count = filters.bind_to(query).get_result(conn)?;
+		Ok(page.page(hits, total.count))
+	}
+
+	/// Sets the name the block is found by. Data blocks are named by their
+	/// data, which the database keeps up to date by itself.
+	pub fn set_search_name(
+		block_id: i64,
+		name: &str,
+		conn: &PgConnection,
+	) -> Result<(), LoopError> {
+		diesel::sql_query(SET_SEARCH_NAME)
+			.bind::<BigInt, _>(block_id)
+			.bind::<Text, _>(name)
+			.execute(conn)?;
+		Ok(())
+	}
+}
+
+/// Turns text into a query that matches every word of it as a prefix, like
+/// `to:* & do:*`. Words in double quotes have to follow each other instead,
+/// like `(to <-> do)`, and aren't prefixes. Characters that aren't letters
+/// or numbers only split words, so they can't be read as query operators.
+/// There is no query if the text has no words.
+pub fn search_tsquery(text: &str) -> Option<String> {
+	let mut terms: Vec<String> = vec![];
+	// Every other part is inside of quotes. A quote that isn't closed
+	// lasts until the end.
+	for (index, part) in text.split('"').enumerate() {
+		let words = words(part);
+		if index % 2 == 1 {
+			if !words.is_empty() {
+				terms.push(format!("({})", words.join(" <-> ")));
+			}
+		} else {
+			terms.extend(words.into_iter().map(|word| format!("{}:*", word)));
+		}
+	}
+	if terms.is_empty() {
+		None
+	} else {
+		Some(terms.join(" & "))
+	}
+}
+
+fn words(text: &str) -> Vec<&str> {
+	text.split(|c: char| !c.is_alphanumeric())
+		.filter(|word| !word.is_empty())
+		.collect()
+}
+
+/// How Postgres marks the matches in headlines, so that `parse_headline`
+/// can find them
+fn headline_options() -> String {
+	format!(
+		"StartSel={}, StopSel={}, FragmentDelimiter={}, MaxFragments=3, MaxWords=20, MinWords=8",
+		HIGHLIGHT_START, HIGHLIGHT_STOP, FRAGMENT_DELIMITER
+	)
+}
+
+/// Splits a headline from Postgres into snippets, and finds where the
+/// matches in each are. Fragments without a match are left out, since they
+/// are only the start of the text.
+pub fn parse_headline(headline: &str) -> Vec<Snippet> {
+	headline
+		.split(FRAGMENT_DELIMITER)
+		.filter_map(|fragment| {
+			let mut text = String::new();
+			let mut highlights = vec![];
+			let mut length = 0;
+			let mut start = None;
+			for c in fragment.chars() {
+				match c {
+					HIGHLIGHT_START => start = Some(length),
+					HIGHLIGHT_STOP => {
+						if let Some(start) = start.take() {
+							highlights.push((start, length));
+						}
+					}
+					c => {
+						text.push(c);
+						length += 1;
+					}
+				}
+			}
+			if highlights.is_empty() {
+				None
+			} else {
+				Some(Snippet { text, highlights })
+			}
+		})
+		.collect()
+}
+
+/// The blocks that match a search, with `condition` as the SQL of its filters
+fn search_matches(condition: &str) -> String {
+	SEARCH_MATCHES.replace("{filters}", condition)
+}
+
+/// Ranks blocks by how well their search document matches the query, plus
+/// how similar their name is to it, which forgives typos. More stars raise
+/// the rank a little, and data blocks count half. Parameters that are not
+/// set are null and so match every block. Blocks are only included if the
+/// user can view them, the same way `can_view` decides. Timestamps are sorted
+/// by as seconds, so that every way of sorting has a number as its key.
+const SEARCH_MATCHES: &str = "
+WITH matches AS (
+	SELECT
+		id,
+		rank,
+		CASE $6::TEXT
+			WHEN 'stars' THEN cardinality(stars)::FLOAT8
+			WHEN 'updated' THEN extract(epoch FROM updated_at)::FLOAT8
+			WHEN 'created' THEN extract(epoch FROM created_at)::FLOAT8
+			ELSE rank
+		END AS sort_key
+	FROM (
+		SELECT
+			b.id,
+			b.stars,
+			b.updated_at,
+			b.created_at,
+			(
+				COALESCE(ts_rank(b.search_document, to_tsquery('simple', $2)), 0)
+				+ similarity(b.search_name, $3)
+			)::FLOAT8
+			* (1 + ln(1 + cardinality(b.stars)) / 10)
+			* (CASE WHEN b.block_type = 'data' THEN 0.5 ELSE 1 END) AS rank
+		FROM blocks b
+		WHERE (
+			b.public
+			OR b.inherited_public
+			OR b.owner_id = $1
+			OR $1 = ANY(b.co_owners)
+			OR $1 = ANY(b.perm_full || b.perm_edit || b.perm_comment || b.perm_view)
+			OR $1 = ANY(b.inherited_full || b.inherited_edit || b.inherited_comment || b.inherited_view)
+		)
+		AND ($2::TEXT IS NULL OR b.search_document @@ to_tsquery('simple', $2) OR b.search_name % $3)
+		AND ($4 OR b.block_type <> 'data')
+		AND ($5::BIGINT[] IS NULL OR b.id = ANY($5))
+		AND {filters}
+	) ranked
+)
+";
+
+/// The matches between the cursors, highest key first. Pages from the end
+/// are loaded lowest key first. Headlines are only made for the page, since
+/// they need the text of each block.
+const MATCHES_PAGE: &str = "
+SELECT
+	p.id,
+	p.rank,
+	p.sort_key,
+	CASE WHEN $2::TEXT IS NULL THEN '' ELSE ts_headline(
+		'simple',
+		concat_ws(' ', b.search_content, b.search_comments),
+		to_tsquery('simple', $2),
+		$13
+	) END AS headline
+FROM (
+	SELECT id, rank, sort_key FROM matches
+	WHERE ($7::FLOAT8 IS NULL OR (sort_key, id) < ($7, $8))
+	AND ($9::FLOAT8 IS NULL OR (sort_key, id) > ($9, $10))
+	ORDER BY
+		CASE WHEN $11 THEN sort_key END,
+		CASE WHEN $11 THEN id END,
+		sort_key DESC,
+		id DESC
+	LIMIT $12
+) p
+INNER JOIN blocks b ON b.id = p.id
+ORDER BY
+	CASE WHEN $11 THEN p.sort_key END,
+	CASE WHEN $11 THEN p.id END,
+	p.sort_key DESC,
+	p.id DESC
+";
+
+const MATCHES_COUNT: &str = "SELECT count(*) AS count FROM matches";
+
+const SET_SEARCH_NAME: &str = "
+UPDATE blocks SET search_name = $2
+WHERE id = $1 AND block_type <> 'data' AND search_name <> $2
+";
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn words_become_prefixes() {
+		assert_eq!(
+			Some("quarterly:* & plan:*".into()),
+			search_tsquery("quarterly plan")
+		);
+	}
+
+	#[test]
+	fn operators_are_dropped() {
+		assert_eq!(
+			Some("a:* & b:* & c:*".into()),
+			search_tsquery("a & !b | (c:*)")
+		);
+	}
+
+	#[test]
+	fn no_words_no_query() {
+		assert_eq!(None, search_tsquery("  - \"\""));
+	}
+
+	#[test]
+	fn quotes_make_phrases() {
+		assert_eq!(
+			Some("(the <-> quick <-> fox) & jump:*".into()),
+			search_tsquery("\"the quick fox\" jump")
+		);
+		assert_eq!(Some("a:* & (b <-> c)".into()), search_tsquery("a \"b c"));
+	}
+
+	#[test]
+	fn headline_highlights() {
+		let (start, stop, delimiter) = (HIGHLIGHT_START, HIGHLIGHT_STOP, FRAGMENT_DELIMITER);
+		let headline = format!(
+			"the {}quick{} fox{}no match{}{}jümp{}s",
+			start, stop, delimiter, delimiter, start, stop
+		);
+		assert_eq!(
+			vec![
+				Snippet {
+					text: "the quick fox".into(),
+					highlights: vec![(4, 9)],
+				},
+				Snippet {
+					text: "jümps".into(),
+					highlights: vec![(0, 4)],
+				},
+			],
+			parse_headline(&headline)
+		);
+	}
+}
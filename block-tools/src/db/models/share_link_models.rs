@@ -0,0 +1,178 @@
+use super::super::schema::share_links;
+use crate::{auth::permissions::PermLevel, LoopError};
+use diesel::prelude::*;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use std::time::SystemTime;
+
+/// How many characters are in a share link's token
+const TOKEN_LENGTH: usize = 32;
+
+/// A link that gives access to a block to whoever has its token, without
+/// needing to be one of the users in the block's permissions
+#[derive(Queryable, Clone)]
+pub struct ShareLink {
+	pub id: i64,
+	pub block_id: i64,
+	pub token: String,
+	/// Either `view` or `edit`
+	pub perm_level: String,
+	pub expires_at: Option<SystemTime>,
+	/// Single use links have to be redeemed by a user, and then stop working
+	pub single_use: bool,
+	pub used_by: Option<i32>,
+	pub used_at: Option<SystemTime>,
+	pub revoked: bool,
+	pub created_by: i32,
+	pub created_at: SystemTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "share_links"]
+pub struct NewShareLink {
+	pub block_id: i64,
+	pub token: String,
+	pub perm_level: String,
+	pub expires_at: Option<SystemTime>,
+	pub single_use: bool,
+	pub created_by: i32,
+	pub created_at: SystemTime,
+}
+
+/// The levels of access a share link can give
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareLevel {
+	View,
+	Edit,
+}
+
+impl ShareLevel {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::View => "view",
+			Self::Edit => "edit",
+		}
+	}
+
+	pub fn from_name(level: &str) -> Self {
+		match level {
+			"edit" => Self::Edit,
+			_ => Self::View,
+		}
+	}
+
+	pub fn perm_level(&self) -> PermLevel {
+		match self {
+			Self::View => PermLevel::View,
+			Self::Edit => PermLevel::Edit,
+		}
+	}
+}
+
+impl NewShareLink {
+	/// A link to a block with a new random token
+	pub fn new(block_id: i64, level: ShareLevel, created_by: i32) -> Self {
+		let token = thread_rng()
+			.sample_iter(&Alphanumeric)
+			.take(TOKEN_LENGTH)
+			.map(char::from)
+			.collect();
+		NewShareLink {
+			block_id,
+			token,
+			perm_level: level.as_str().to_string(),
+			expires_at: None,
+			single_use: false,
+			created_by,
+			created_at: SystemTime::now(),
+		}
+	}
+
+	pub fn insert(self, conn: &PgConnection) -> Result<ShareLink, LoopError> {
+		Ok(diesel::insert_into(share_links::table)
+			.values(self)
+			.get_result(conn)?)
+	}
+}
+
+impl ShareLink {
+	pub fn by_id(link_id: i64, conn: &PgConnection) -> Result<Option<Self>, LoopError> {
+		Ok(share_links::dsl::share_links
+			.filter(share_links::id.eq(link_id))
+			.limit(1)
+			.get_result(conn)
+			.optional()?)
+	}
+
+	pub fn by_token(token: &str, conn: &PgConnection) -> Result<Option<Self>, LoopError> {
+		Ok(share_links::dsl::share_links
+			.filter(share_links::token.eq(token))
+			.limit(1)
+			.get_result(conn)
+			.optional()?)
+	}
+
+	/// All the links to a block, newest first
+	pub fn of_block(block_id: i64, conn: &PgConnection) -> Result<Vec<Self>, LoopError> {
+		Ok(share_links::dsl::share_links
+			.filter(share_links::block_id.eq(block_id))
+			.order_by(share_links::id.desc())
+			.load(conn)?)
+	}
+
+	pub fn level(&self) -> ShareLevel {
+		ShareLevel::from_name(&self.perm_level)
+	}
+
+	/// False if the link was revoked, has expired or was already used up
+	pub fn is_usable(&self) -> bool {
+		let expired = self
+			.expires_at
+			.map_or(false, |expires_at| expires_at <= SystemTime::now());
+		let used_up = self.single_use && self.used_at.is_some();
+		!self.revoked && !expired && !used_up
+	}
+
+	/// The level the link gives on a block to requests that carry its token.
+	/// Single use links only work once redeemed by a user.
+	pub fn grants(&self, block_id: i64) -> Option<PermLevel> {
+		if self.block_id != block_id || self.single_use || !self.is_usable() {
+			return None;
+		}
+		Some(self.level().perm_level())
+	}
+
+	/// Records that a user redeemed the link, unless it can't be used anymore.
+	/// The check and the change are one statement, so two requests can't
+	/// both redeem a single use link.
+	pub fn claim(&self, user_id: i32, conn: &PgConnection) -> Result<Option<ShareLink>, LoopError> {
+		let now = SystemTime::now();
+		let usable = share_links::dsl::share_links
+			.filter(share_links::id.eq(self.id))
+			.filter(share_links::revoked.eq(false))
+			.filter(
+				share_links::expires_at
+					.is_null()
+					.or(share_links::expires_at.gt(now)),
+			)
+			.filter(
+				share_links::single_use
+					.eq(false)
+					.or(share_links::used_at.is_null()),
+			);
+		Ok(diesel::update(usable)
+			.set((
+				share_links::used_by.eq(user_id),
+				share_links::used_at.eq(now),
+			))
+			.get_result(conn)
+			.optional()?)
+	}
+
+	pub fn revoke(&self, conn: &PgConnection) -> Result<ShareLink, LoopError> {
+		Ok(
+			diesel::update(share_links::dsl::share_links.filter(share_links::id.eq(self.id)))
+				.set(share_links::revoked.eq(true))
+				.get_result(conn)?,
+		)
+	}
+}
@@ -0,0 +1,228 @@
+use super::super::schema::{block_tags, tags};
+use crate::{LoopError, UserError};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+/// The most characters a tag's name can have
+pub const MAX_TAG_NAME: usize = 64;
+/// The most characters a color can have, like `rgb(255,255,255)`
+const MAX_COLOR: usize = 17;
+
+/// A label that a user can put on any block they can view.
+/// Tags belong to a user, and other users can't see them.
+#[derive(Queryable, Clone)]
+pub struct Tag {
+	pub id: i64,
+	pub owner_id: i32,
+	pub name: String,
+	pub color: Option<String>,
+	pub created_at: SystemTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "tags"]
+pub struct NewTag {
+	pub owner_id: i32,
+	pub name: String,
+	pub color: Option<String>,
+	pub created_at: SystemTime,
+}
+
+#[derive(Queryable, Clone)]
+pub struct BlockTag {
+	pub id: i64,
+	pub tag_id: i64,
+	pub block_id: i64,
+}
+
+#[derive(Insertable)]
+#[table_name = "block_tags"]
+pub struct NewBlockTag {
+	pub tag_id: i64,
+	pub block_id: i64,
+}
+
+/// How multiple tags in a filter are combined
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum TagMatch {
+	/// Blocks need to have every tag
+	All,
+	/// Blocks need to have at least one of the tags
+	Any,
+}
+
+impl Default for TagMatch {
+	fn default() -> Self {
+		Self::All
+	}
+}
+
+impl NewTag {
+	pub fn new(owner_id: i32, name: impl ToString) -> Self {
+		NewTag {
+			owner_id,
+			name: name.to_string(),
+			color: None,
+			created_at: SystemTime::now(),
+		}
+	}
+
+	pub fn insert(self, conn: &PgConnection) -> Result<Tag, LoopError> {
+		Ok(diesel::insert_into(tags::table)
+			.values(self)
+			.get_result(conn)?)
+	}
+}
+
+impl Tag {
+	/// Makes sure a name fits in a tag
+	pub fn check_name(name: &str) -> Result<(), UserError> {
+		let length = name.trim().chars().count();
+		if length == 0 || name.chars().count() > MAX_TAG_NAME {
+			return Err(UserError::TagNameLength(name.to_string()));
+		}
+		Ok(())
+	}
+
+	/// Makes sure a color is an RGB string like `rgb(123,123,123)`, like block colors
+	pub fn check_color(color: Option<&str>) -> Result<(), UserError> {
+		match color {
+			Some(color)
+				if color.chars().count() > MAX_COLOR
+					|| color.parse::<colors_transform::Rgb>().is_err() =>
+			{
+				Err(UserError::InvalidColor(color.to_string()))
+			}
+			_ => Ok(()),
+		}
+	}
+
+	pub fn by_id(tag_id: i64, conn: &PgConnection) -> Result<Option<Self>, LoopError> {
+		Ok(tags::dsl::tags
+			.filter(tags::id.eq(tag_id))
+			.limit(1)
+			.get_result(conn)
+			.optional()?)
+	}
+
+	pub fn by_name(
+		owner_id: i32,
+		name: &str,
+		conn: &PgConnection,
+	) -> Result<Option<Self>, LoopError> {
+		Ok(tags::dsl::tags
+			.filter(tags::owner_id.eq(owner_id))
+			.filter(tags::name.eq(name))
+			.limit(1)
+			.get_result(conn)
+			.optional()?)
+	}
+
+	/// All the tags a user has made, sorted by name
+	pub fn of_user(owner_id: i32, conn: &PgConnection) -> Result<Vec<Self>, LoopError> {
+		Ok(tags::dsl::tags
+			.filter(tags::owner_id.eq(owner_id))
+			.order_by(tags::name)
+			.load(conn)?)
+	}
+
+	/// The tags a user has put on a block
+	pub fn on_block(
+		block_id: i64,
+		owner_id: i32,
+		conn: &PgConnection,
+	) -> Result<Vec<Self>, LoopError> {
+		Ok(tags::table
+			.inner_join(block_tags::table)
+			.filter(block_tags::block_id.eq(block_id))
+			.filter(tags::owner_id.eq(owner_id))
+			.order_by(tags::name)
+			.select(tags::all_columns)
+			.load(conn)?)
+	}
+
+	/// IDs of the blocks that have this tag
+	pub fn block_ids(&self, conn: &PgConnection) -> Result<Vec<i64>, LoopError> {
+		Ok(block_tags::dsl::block_tags
+			.filter(block_tags::tag_id.eq(self.id))
+			.select(block_tags::block_id)
+			.load(conn)?)
+	}
+
+	pub fn rename(&self, name: &str, conn: &PgConnection) -> Result<Tag, LoopError> {
+		Ok(diesel::update(tags::dsl::tags.filter(tags::id.eq(self.id)))
+			.set(tags::name.eq(name))
+			.get_result(conn)?)
+	}
+
+	pub fn update_color(
+		&self,
+		color: Option<String>,
+		conn: &PgConnection,
+	) -> Result<Tag, LoopError> {
+		Ok(diesel::update(tags::dsl::tags.filter(tags::id.eq(self.id)))
+			.set(tags::color.eq(color))
+			.get_result(conn)?)
+	}
+
+	/// Puts the tag on a block. Does nothing if the block already has it.
+	pub fn attach(&self, block_id: i64, conn: &PgConnection) -> Result<(), LoopError> {
+		diesel::insert_into(block_tags::table)
+			.values(NewBlockTag {
+				tag_id: self.id,
+				block_id,
+			})
+			.on_conflict_do_nothing()
+			.execute(conn)?;
+		Ok(())
+	}
+
+	pub fn detach(&self, block_id: i64, conn: &PgConnection) -> Result<(), LoopError> {
+		diesel::delete(
+			block_tags::dsl::block_tags
+				.filter(block_tags::tag_id.eq(self.id))
+				.filter(block_tags::block_id.eq(block_id)),
+		)
+		.execute(conn)?;
+		Ok(())
+	}
+
+	/// Moves all the blocks with this tag to another tag, then deletes this one
+	pub fn merge_into(&self, other: &Tag, conn: &PgConnection) -> Result<Tag, LoopError> {
+		conn.transaction::<_, LoopError, _>(|| {
+			for block_id in self.block_ids(conn)? {
+				other.attach(block_id, conn)?;
+			}
+			self.delete(conn)?;
+			Ok(other.clone())
+		})
+	}
+
+	pub fn delete(&self, conn: &PgConnection) -> Result<(), LoopError> {
+		diesel::delete(tags::dsl::tags.filter(tags::id.eq(self.id))).execute(conn)?;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn tag_names_fit() {
+		assert!(Tag::check_name("reading list").is_ok());
+		assert!(Tag::check_name(&"é".repeat(MAX_TAG_NAME)).is_ok());
+		assert!(Tag::check_name(&"é".repeat(MAX_TAG_NAME + 1)).is_err());
+		assert!(Tag::check_name("  ").is_err());
+	}
+
+	#[test]
+	fn tag_colors_are_rgb() {
+		assert!(Tag::check_color(None).is_ok());
+		assert!(Tag::check_color(Some("rgb(255,255,255)")).is_ok());
+		assert!(Tag::check_color(Some("blue")).is_err());
+		assert!(Tag::check_color(Some("rgb(255, 255, 255, 255)")).is_err());
+	}
+}
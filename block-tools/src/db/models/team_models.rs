@@ -0,0 +1,297 @@
+use super::super::schema::{blocks, team_members, teams};
+use super::Block;
+use crate::{auth::inheritance::InheritedPerms, LoopError};
+use diesel::prelude::*;
+use std::time::SystemTime;
+
+/// A named group of users that blocks can be shared with. Sharing a block
+/// with a team gives every member that accepted their invitation access.
+#[derive(Queryable, Clone)]
+pub struct Team {
+	pub id: i64,
+	pub name: String,
+	pub owner_id: i32,
+	pub created_at: SystemTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "teams"]
+pub struct NewTeam {
+	pub name: String,
+	pub owner_id: i32,
+	pub created_at: SystemTime,
+}
+
+#[derive(Queryable, Clone)]
+pub struct TeamMember {
+	pub id: i64,
+	pub team_id: i64,
+	pub user_id: i32,
+	/// One of `owner`, `admin` or `member`
+	pub role: String,
+	/// False while the user hasn't accepted their invitation
+	pub accepted: bool,
+	pub invited_by: Option<i32>,
+	pub created_at: SystemTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "team_members"]
+pub struct NewTeamMember {
+	pub team_id: i64,
+	pub user_id: i32,
+	pub role: String,
+	pub accepted: bool,
+	pub invited_by: Option<i32>,
+	pub created_at: SystemTime,
+}
+
+/// What a member can do in a team. Admins can invite and remove members,
+/// and only the owner can change roles or delete the team.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TeamRole {
+	Member,
+	Admin,
+	Owner,
+}
+
+impl TeamRole {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Member => "member",
+			Self::Admin => "admin",
+			Self::Owner => "owner",
+		}
+	}
+
+	pub fn from_name(role: &str) -> Self {
+		match role {
+			"owner" => Self::Owner,
+			"admin" => Self::Admin,
+			_ => Self::Member,
+		}
+	}
+}
+
+impl NewTeam {
+	pub fn new(owner_id: i32, name: impl ToString) -> Self {
+		NewTeam {
+			name: name.to_string(),
+			owner_id,
+			created_at: SystemTime::now(),
+		}
+	}
+
+	/// Inserts the team, with its owner as the first member
+	pub fn insert(self, conn: &PgConnection) -> Result<Team, LoopError> {
+		conn.transaction::<_, LoopError, _>(|| {
+			let team: Team = diesel::insert_into(teams::table)
+				.values(&self)
+				.get_result(conn)?;
+			diesel::insert_into(team_members::table)
+				.values(NewTeamMember {
+					team_id: team.id,
+					user_id: team.owner_id,
+					role: TeamRole::Owner.as_str().to_string(),
+					accepted: true,
+					invited_by: None,
+					created_at: SystemTime::now(),
+				})
+				.execute(conn)?;
+			Ok(team)
+		})
+	}
+}
+
+impl Team {
+	pub fn by_id(team_id: i64, conn: &PgConnection) -> Result<Option<Self>, LoopError> {
+		Ok(teams::dsl::teams
+			.filter(teams::id.eq(team_id))
+			.limit(1)
+			.get_result(conn)
+			.optional()?)
+	}
+
+	/// The teams a user is a member of, sorted by name
+	pub fn of_user(user_id: i32, conn: &PgConnection) -> Result<Vec<Self>, LoopError> {
+		Ok(teams::table
+			.inner_join(team_members::table)
+			.filter(team_members::user_id.eq(user_id))
+			.filter(team_members::accepted.eq(true))
+			.order_by(teams::name)
+			.select(teams::all_columns)
+			.load(conn)?)
+	}
+
+	/// Every member of the team, including ones that were only invited
+	pub fn members(&self, conn: &PgConnection) -> Result<Vec<TeamMember>, LoopError> {
+		Ok(team_members::dsl::team_members
+			.filter(team_members::team_id.eq(self.id))
+			.order_by(team_members::id)
+			.load(conn)?)
+	}
+
+	/// The role of a user in the team, if they accepted an invitation
+	pub fn role_of(
+		&self,
+		user_id: i32,
+		conn: &PgConnection,
+	) -> Result<Option<TeamRole>, LoopError> {
+		Ok(self
+			.member(user_id, conn)?
+			.filter(|member| member.accepted)
+			.map(|member| member.role()))
+	}
+
+	pub fn member(
+		&self,
+		user_id: i32,
+		conn: &PgConnection,
+	) -> Result<Option<TeamMember>, LoopError> {
+		Ok(team_members::dsl::team_members
+			.filter(team_members::team_id.eq(self.id))
+			.filter(team_members::user_id.eq(user_id))
+			.limit(1)
+			.get_result(conn)
+			.optional()?)
+	}
+
+	/// Invites a user to the team. If they were already invited or are a
+	/// member, their membership is returned as it is.
+	pub fn invite(
+		&self,
+		user_id: i32,
+		role: TeamRole,
+		invited_by: i32,
+		conn: &PgConnection,
+	) -> Result<TeamMember, LoopError> {
+		if let Some(member) = self.member(user_id, conn)? {
+			return Ok(member);
+		}
+		Ok(diesel::insert_into(team_members::table)
+			.values(NewTeamMember {
+				team_id: self.id,
+				user_id,
+				role: role.as_str().to_string(),
+				accepted: false,
+				invited_by: Some(invited_by),
+				created_at: SystemTime::now(),
+			})
+			.get_result(conn)?)
+	}
+
+	pub fn rename(&self, name: &str, conn: &PgConnection) -> Result<Team, LoopError> {
+		Ok(
+			diesel::update(teams::dsl::teams.filter(teams::id.eq(self.id)))
+				.set(teams::name.eq(name))
+				.get_result(conn)?,
+		)
+	}
+
+	/// Removes a user from the team, along with any pending invitation
+	pub fn remove_member(&self, user_id: i32, conn: &PgConnection) -> Result<(), LoopError> {
+		diesel::delete(
+			team_members::dsl::team_members
+				.filter(team_members::team_id.eq(self.id))
+				.filter(team_members::user_id.eq(user_id)),
+		)
+		.execute(conn)?;
+		self.propagate_perms(conn)
+	}
+
+	/// Deletes the team, taking away the access its members had through it
+	pub fn delete(&self, conn: &PgConnection) -> Result<(), LoopError> {
+		let shared = Block::shared_with_team(self.id, conn)?;
+		diesel::delete(teams::dsl::teams.filter(teams::id.eq(self.id))).execute(conn)?;
+		for block in shared {
+			let filter = blocks::dsl::blocks.filter(blocks::id.eq(block.id));
+			let without = |ids: &[i64]| -> Vec<i64> {
+				ids.iter().copied().filter(|id| *id != self.id).collect()
+			};
+			diesel::update(filter)
+				.set((
+					blocks::team_full.eq(without(&block.team_full)),
+					blocks::team_edit.eq(without(&block.team_edit)),
+					blocks::team_comment.eq(without(&block.team_comment)),
+					blocks::team_view.eq(without(&block.team_view)),
+				))
+				.execute(conn)?;
+			Block::propagate_perms(block.id, conn)?;
+		}
+		Ok(())
+	}
+
+	/// Updates the permissions of every block shared with the
+	/// team, after its members change
+	pub fn propagate_perms(&self, conn: &PgConnection) -> Result<(), LoopError> {
+		for block in Block::shared_with_team(self.id, conn)? {
+			Block::propagate_perms(block.id, conn)?;
+		}
+		Ok(())
+	}
+
+	/// The permissions that sharing with teams gives, from the accepted
+	/// members of each team
+	pub fn granted_perms(
+		team_full: &[i64],
+		team_edit: &[i64],
+		team_comment: &[i64],
+		team_view: &[i64],
+		conn: &PgConnection,
+	) -> Result<InheritedPerms, LoopError> {
+		let members_of = |team_ids: &[i64]| -> Result<Vec<i32>, LoopError> {
+			if team_ids.is_empty() {
+				return Ok(vec![]);
+			}
+			Ok(team_members::dsl::team_members
+				.filter(team_members::team_id.eq_any(team_ids))
+				.filter(team_members::accepted.eq(true))
+				.select(team_members::user_id)
+				.load(conn)?)
+		};
+		let mut perms = InheritedPerms::default();
+		perms.merge(&InheritedPerms {
+			public: false,
+			full: members_of(team_full)?,
+			edit: members_of(team_edit)?,
+			comment: members_of(team_comment)?,
+			view: members_of(team_view)?,
+		});
+		Ok(perms)
+	}
+}
+
+impl TeamMember {
+	pub fn role(&self) -> TeamRole {
+		TeamRole::from_name(&self.role)
+	}
+
+	/// Accepts the invitation, giving the user access to what the team can access
+	pub fn accept(&self, conn: &PgConnection) -> Result<TeamMember, LoopError> {
+		let member: TeamMember =
+			diesel::update(team_members::dsl::team_members.filter(team_members::id.eq(self.id)))
+				.set(team_members::accepted.eq(true))
+				.get_result(conn)?;
+		if let Some(team) = Team::by_id(member.team_id, conn)? {
+			team.propagate_perms(conn)?;
+		}
+		Ok(member)
+	}
+
+	pub fn set_role(&self, role: TeamRole, conn: &PgConnection) -> Result<TeamMember, LoopError> {
+		Ok(
+			diesel::update(team_members::dsl::team_members.filter(team_members::id.eq(self.id)))
+				.set(team_members::role.eq(role.as_str()))
+				.get_result(conn)?,
+		)
+	}
+
+	/// The invitations a user hasn't answered yet
+	pub fn pending_for(user_id: i32, conn: &PgConnection) -> Result<Vec<Self>, LoopError> {
+		Ok(team_members::dsl::team_members
+			.filter(team_members::user_id.eq(user_id))
+			.filter(team_members::accepted.eq(false))
+			.order_by(team_members::id)
+			.load(conn)?)
+	}
+}
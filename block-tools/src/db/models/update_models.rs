@@ -1,3 +1,6 @@
+use super::super::schema::updates;
+use crate::{LoopError, Page, PageQuery};
+use diesel::{pg::Pg, prelude::*};
 use std::time::SystemTime;
 
 #[derive(Queryable, Clone)]
@@ -6,3 +9,11 @@ pub struct UpdateModel {
 	pub created_at: SystemTime,
 	pub display: String,
 }
+
+impl UpdateModel {
+	/// A page of the updates that have been published, newest first
+	pub fn page(page: &PageQuery<i32>, conn: &PgConnection) -> Result<Page<Self>, LoopError> {
+		let all = || -> updates::BoxedQuery<'static, Pg> { updates::dsl::updates.into_boxed() };
+		page.load_desc(all, updates::id, conn)
+	}
+}
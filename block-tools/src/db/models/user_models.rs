@@ -2,7 +2,8 @@ use diesel::PgConnection;
 
 use super::super::schema::{potential_users, users};
 use crate::diesel::*;
-use crate::LoopError;
+use crate::{LoopError, Page, PageQuery, SortKey};
+use diesel::sql_types::{BigInt, Bool, Double, Nullable, Text};
 use std::time::SystemTime;
 
 #[derive(Queryable, Clone)]
@@ -22,6 +23,8 @@ pub struct User {
 	pub expo_tokens: Vec<String>,
 	// The latest update that the user has seen
 	pub latest_update_seen_id: Option<i32>,
+	/// Admins can see everything in the audit log
+	pub admin: bool,
 }
 
 impl User {
@@ -33,6 +36,44 @@ impl User {
 			.optional()?)
 	}
 
+	/// Loads every user with one of the IDs. Missing users are left out.
+	pub fn by_ids(user_ids: &[i32], conn: &PgConnection) -> Result<Vec<Self>, LoopError> {
+		Ok(users::dsl::users
+			.filter(users::id.eq_any(user_ids))
+			.load(conn)?)
+	}
+
+	/// Finds a page of the users whose username or display name is similar to
+	/// the query, or starts with it. The most similar users come first.
+	pub fn search(
+		query: &str,
+		page: &PageQuery<SortKey>,
+		conn: &PgConnection,
+	) -> Result<Page<UserHit>, LoopError> {
+		let hits: Vec<UserHit> =
+			diesel::sql_query(format!("{}{}", USER_MATCHES, USER_MATCHES_PAGE))
+				.bind::<Text, _>(query)
+				.bind::<Nullable<Double>, _>(page.after.map(|key| key.value))
+				.bind::<Nullable<BigInt>, _>(page.after.map(|key| key.id))
+				.bind::<Nullable<Double>, _>(page.before.map(|key| key.value))
+				.bind::<Nullable<BigInt>, _>(page.before.map(|key| key.id))
+				.bind::<Bool, _>(page.from_end)
+				.bind::<BigInt, _>(page.fetch())
+				.load(conn)?;
+		let total: UserCount = diesel::sql_query(format!("{}{}", USER_MATCHES, USER_MATCHES_COUNT))
+			.bind::<Text, _>(query)
+			.get_result(conn)?;
+		Ok(page.page(hits, total.count))
+	}
+
+	/// The IDs out of a list that belong to a user
+	pub fn existing_ids(user_ids: &[i32], conn: &PgConnection) -> Result<Vec<i32>, LoopError> {
+		Ok(users::dsl::users
+			.filter(users::id.eq_any(user_ids))
+			.select(users::id)
+			.load(conn)?)
+	}
+
 	pub fn update_username(
 		&self,
 		new_username: &str,
@@ -118,6 +159,65 @@ impl User {
 	}
 }
 
+/// A user found by a search
+#[derive(QueryableByName, Debug, Clone, Copy)]
+pub struct UserHit {
+	#[sql_type = "BigInt"]
+	pub id: i64,
+	/// How similar the user's names are to the query
+	#[sql_type = "Double"]
+	pub rank: f64,
+}
+
+impl UserHit {
+	pub fn key(&self) -> SortKey {
+		SortKey {
+			value: self.rank,
+			id: self.id,
+		}
+	}
+}
+
+#[derive(QueryableByName)]
+struct UserCount {
+	#[sql_type = "BigInt"]
+	count: i64,
+}
+
+/// Users with a username or display name that is similar to `$1` or starts
+/// with it, and how similar the closest of the two is
+const USER_MATCHES: &str = "
+WITH matches AS (
+	SELECT
+		id::BIGINT AS id,
+		GREATEST(
+			similarity(username, $1),
+			similarity(COALESCE(display_name, ''), $1)
+		)::FLOAT8 AS rank
+	FROM users
+	WHERE username % $1
+		OR display_name % $1
+		OR starts_with(lower(username), lower($1))
+		OR starts_with(lower(display_name), lower($1))
+)
+";
+
+/// The matches between the cursors, most similar first. Pages from the end
+/// are loaded least similar first.
+const USER_MATCHES_PAGE: &str = "
+SELECT id, rank FROM matches
+WHERE ($2::FLOAT8 IS NULL OR (rank, id) < ($2, $3))
+AND ($4::FLOAT8 IS NULL OR (rank, id) > ($4, $5))
+ORDER BY
+	CASE WHEN $6 THEN rank END,
+	CASE WHEN $6 THEN id END,
+	rank DESC,
+	id DESC
+LIMIT $7
+";
+
+const USER_MATCHES_COUNT: &str = "SELECT count(*) AS count FROM matches";
+
 #[derive(Insertable)]
 #[table_name = "users"]
 pub struct NewUser {
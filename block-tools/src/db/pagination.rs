@@ -0,0 +1,186 @@
+use crate::LoopError;
+use diesel::{
+	dsl::{count_star, Asc, Desc, Gt, Lt},
+	expression::{count::CountStar, AsExpression},
+	query_dsl::{
+		methods::{FilterDsl, LimitDsl, OrderDsl, SelectDsl},
+		LoadQuery,
+	},
+	ExpressionMethods, PgConnection, RunQueryDsl,
+};
+
+/// Which part of a list to load. Lists are ordered by a key that is unique
+/// for each row, so a key keeps pointing at the same place in the list when
+/// rows are added or removed.
+#[derive(Debug, Clone)]
+pub struct PageQuery<K> {
+	/// Only rows that come after this key
+	pub after: Option<K>,
+	/// Only rows that come before this key
+	pub before: Option<K>,
+	/// The most rows to load
+	pub limit: i64,
+	/// Load the rows closest to the end of the list (or to `before`)
+	/// instead of the ones closest to the start (or to `after`)
+	pub from_end: bool,
+}
+
+/// A part of a list, in the list's order
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+	pub items: Vec<T>,
+	/// Whether the list has rows before this page
+	pub has_previous: bool,
+	/// Whether the list has rows after this page
+	pub has_next: bool,
+	/// How many rows the whole list has
+	pub total_count: i64,
+}
+
+/// The key of a row in a list that is ordered by a number, like how well
+/// the row matches a search. The ID breaks ties, so keys are unique.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SortKey {
+	pub value: f64,
+	pub id: i64,
+}
+
+impl<K> PageQuery<K> {
+	/// The first rows of the list
+	pub fn first(limit: i64) -> Self {
+		PageQuery {
+			after: None,
+			before: None,
+			limit,
+			from_end: false,
+		}
+	}
+
+	/// How many rows to load for the page. One more than the limit is loaded
+	/// to tell if there are more rows past the page.
+	pub fn fetch(&self) -> i64 {
+		self.limit + 1
+	}
+
+	/// Makes a page out of rows loaded with `fetch` as their limit. Rows loaded
+	/// from the end are in reverse, and are put back in the list's order.
+	pub fn page<T>(&self, mut rows: Vec<T>, total_count: i64) -> Page<T> {
+		let more = rows.len() as i64 > self.limit;
+		rows.truncate(self.limit.max(0) as usize);
+		if self.from_end {
+			rows.reverse();
+			Page {
+				items: rows,
+				has_previous: more,
+				has_next: self.before.is_some(),
+				total_count,
+			}
+		} else {
+			Page {
+				items: rows,
+				has_previous: self.after.is_some(),
+				has_next: more,
+				total_count,
+			}
+		}
+	}
+}
+
+impl<K: Copy> PageQuery<K> {
+	/// Loads a page of a list that is ordered by a unique column, highest
+	/// first, like the IDs of rows that are added over time. `list` makes the
+	/// query for the whole list, and is called again to count it.
+	pub fn load_desc<Q, C, T>(
+		&self,
+		list: impl Fn() -> Q,
+		key: C,
+		conn: &PgConnection,
+	) -> Result<Page<T>, LoopError>
+	where
+		C: ExpressionMethods + Copy,
+		K: AsExpression<C::SqlType>,
+		Q: FilterDsl<Lt<C, K>, Output = Q>
+			+ FilterDsl<Gt<C, K>, Output = Q>
+			+ OrderDsl<Asc<C>, Output = Q>
+			+ OrderDsl<Desc<C>, Output = Q>
+			+ LimitDsl<Output = Q>
+			+ SelectDsl<CountStar>
+			+ RunQueryDsl<PgConnection>
+			+ LoadQuery<PgConnection, T>,
+		<Q as SelectDsl<CountStar>>::Output:
+			RunQueryDsl<PgConnection> + LoadQuery<PgConnection, i64>,
+	{
+		let mut query = list();
+		if let Some(after) = self.after {
+			query = FilterDsl::filter(query, key.lt(after));
+		}
+		if let Some(before) = self.before {
+			query = FilterDsl::filter(query, key.gt(before));
+		}
+		query = if self.from_end {
+			OrderDsl::order(query, key.asc())
+		} else {
+			OrderDsl::order(query, key.desc())
+		};
+
+		let rows = LimitDsl::limit(query, self.fetch()).load(conn)?;
+		let total_count = SelectDsl::select(list(), count_star()).get_result(conn)?;
+		Ok(self.page(rows, total_count))
+	}
+}
+
+impl<T> Page<T> {
+	/// Changes every item of the page, keeping where the page is in the list
+	pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+		Page {
+			items: self.items.into_iter().map(f).collect(),
+			has_previous: self.has_previous,
+			has_next: self.has_next,
+			total_count: self.total_count,
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn query(
+		after: Option<i64>,
+		before: Option<i64>,
+		limit: i64,
+		from_end: bool,
+	) -> PageQuery<i64> {
+		PageQuery {
+			after,
+			before,
+			limit,
+			from_end,
+		}
+	}
+
+	#[test]
+	fn first_page_with_more() {
+		let page = query(None, None, 2, false).page(vec![9, 8, 7], 3);
+		assert_eq!(vec![9, 8], page.items);
+		assert!(!page.has_previous);
+		assert!(page.has_next);
+		assert_eq!(3, page.total_count);
+	}
+
+	#[test]
+	fn last_rows_after_cursor() {
+		let page = query(Some(8), None, 2, false).page(vec![7], 3);
+		assert_eq!(vec![7], page.items);
+		assert!(page.has_previous);
+		assert!(!page.has_next);
+	}
+
+	#[test]
+	fn from_end_is_put_back_in_order() {
+		let page = query(None, Some(7), 1, true).page(vec![8, 9], 3);
+		assert_eq!(vec![8], page.items);
+		assert!(page.has_previous);
+		assert!(page.has_next);
+	}
+}
@@ -1,9 +1,11 @@
+use crate::{models::Notification, LoopError};
+use diesel::connection::{Connection, TransactionManager};
 use diesel::pg::PgConnection;
 use diesel::r2d2::ConnectionManager;
 use dotenv::dotenv;
 use log::error;
 use r2d2::{Pool, PooledConnection};
-use std::env;
+use std::{env, ops::Deref};
 
 /// Type for the access to the DB
 pub type PostgresPool = Pool<ConnectionManager<PgConnection>>;
@@ -18,6 +20,87 @@ pub fn get_pool(url: &str) -> PostgresPool {
 		.expect("could not build connection pool")
 }
 
+/// A connection that a context gives out. Inside of a transaction, every
+/// caller gets the transaction's connection, even while another holds it.
+pub enum ContextConn<'a> {
+	Pooled(PgConnect),
+	Shared(&'a PgConnect),
+}
+
+impl Deref for ContextConn<'_> {
+	type Target = PgConnect;
+
+	fn deref(&self) -> &PgConnect {
+		match self {
+			ContextConn::Pooled(conn) => conn,
+			ContextConn::Shared(conn) => conn,
+		}
+	}
+}
+
+/// A connection from the pool with an open transaction. Everything done
+/// through it is committed or rolled back together, and notifications
+/// sent in the meantime are only delivered once it is committed.
+pub struct SharedTransaction {
+	conn: PgConnect,
+	finished: bool,
+}
+
+impl SharedTransaction {
+	/// Takes a connection from the pool and starts a transaction on it
+	pub fn begin(pool: &PostgresPool) -> Result<Self, LoopError> {
+		let conn = pool.get()?;
+		conn.transaction_manager().begin_transaction(&*conn)?;
+		Notification::hold();
+		Ok(SharedTransaction {
+			conn,
+			finished: false,
+		})
+	}
+
+	pub fn conn(&self) -> &PgConnect {
+		&self.conn
+	}
+
+	/// Makes every change done with the connection permanent, then
+	/// delivers the notifications that were held back. If the commit
+	/// fails, dropping the transaction rolls it back.
+	pub fn commit(mut self) -> Result<(), LoopError> {
+		self.conn
+			.transaction_manager()
+			.commit_transaction(&*self.conn)?;
+		self.finished = true;
+		for notif in Notification::release() {
+			notif.deliver(&self.conn)?;
+		}
+		Ok(())
+	}
+
+	/// Throws away every change done with the connection
+	pub fn rollback(mut self) -> Result<(), LoopError> {
+		Notification::release();
+		self.conn
+			.transaction_manager()
+			.rollback_transaction(&*self.conn)?;
+		self.finished = true;
+		Ok(())
+	}
+}
+
+impl Drop for SharedTransaction {
+	// A transaction that was never finished, like after a panic, is rolled
+	// back so that the connection goes back to the pool without it
+	fn drop(&mut self) {
+		if !self.finished {
+			Notification::release();
+			let manager = self.conn.transaction_manager();
+			if let Err(err) = manager.rollback_transaction(&*self.conn) {
+				error!("Couldn't roll back an unfinished transaction: {}", err);
+			}
+		}
+	}
+}
+
 /// Gets the DATABASE_URL from the environment
 pub fn env_db() -> String {
 	// Load the environment from .env file
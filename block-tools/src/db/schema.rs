@@ -1,10 +1,88 @@
+table! {
+	access_requests (id) {
+		id -> Int8,
+		block_id -> Int8,
+		requester_id -> Int4,
+		perm_level -> Varchar,
+		message -> Nullable<Text>,
+		status -> Varchar,
+		granted_level -> Nullable<Varchar>,
+		decided_by -> Nullable<Int4>,
+		created_at -> Timestamp,
+		decided_at -> Nullable<Timestamp>,
+	}
+}
+
+table! {
+	audit_events (id) {
+		id -> Int8,
+		block_id -> Int8,
+		actor_id -> Nullable<Int4>,
+		source -> Varchar,
+		share_link_id -> Nullable<Int8>,
+		action -> Varchar,
+		old_public -> Bool,
+		new_public -> Bool,
+		old_perms -> Text,
+		new_perms -> Text,
+		created_at -> Timestamp,
+	}
+}
+
+table! {
+	block_references (id) {
+		id -> Int8,
+		source_id -> Int8,
+		target_id -> Int8,
+		link_text -> Nullable<Text>,
+		dangling -> Bool,
+	}
+}
+
+table! {
+	block_relations (id) {
+		id -> Int8,
+		relation_type_id -> Int8,
+		source_id -> Int8,
+		target_id -> Int8,
+		author_id -> Nullable<Int4>,
+		created_at -> Timestamp,
+	}
+}
+
+table! {
+	block_revisions (id) {
+		id -> Int8,
+		block_id -> Int8,
+		author_id -> Nullable<Int4>,
+		created_at -> Timestamp,
+		change -> Varchar,
+		block_data -> Nullable<Text>,
+		color -> Nullable<Varchar>,
+		public -> Bool,
+		perm_full -> Array<Int4>,
+		perm_edit -> Array<Int4>,
+		perm_view -> Array<Int4>,
+		properties -> Text,
+		perm_comment -> Array<Int4>,
+	}
+}
+
+table! {
+	block_tags (id) {
+		id -> Int8,
+		tag_id -> Int8,
+		block_id -> Int8,
+	}
+}
+
 table! {
 	blocks (id) {
 		id -> Int8,
 		block_type -> Varchar,
 		created_at -> Timestamp,
 		updated_at -> Timestamp,
-		block_data -> Nullable<Text>,
+		block_data -> Nullable<Jsonb>,
 		owner_id -> Int4,
 		public -> Bool,
 		perm_full -> Array<Int4>,
@@ -13,6 +91,42 @@ table! {
 		stars -> Array<Int4>,
 		notif_enabled -> Array<Int4>,
 		color -> Nullable<Varchar>,
+		inherit_perms -> Bool,
+		inherited_public -> Bool,
+		inherited_full -> Array<Int4>,
+		inherited_edit -> Array<Int4>,
+		inherited_view -> Array<Int4>,
+		team_full -> Array<Int8>,
+		team_edit -> Array<Int8>,
+		team_view -> Array<Int8>,
+		co_owners -> Array<Int4>,
+		perm_comment -> Array<Int4>,
+		inherited_comment -> Array<Int4>,
+		team_comment -> Array<Int8>,
+		block_data_text -> Nullable<Text>,
+	}
+}
+
+table! {
+	collection_notified (id) {
+		id -> Int8,
+		collection_id -> Int8,
+		block_id -> Int8,
+	}
+}
+
+table! {
+	collections (id) {
+		id -> Int8,
+		owner_id -> Int4,
+		name -> Varchar,
+		query -> Text,
+		filters -> Jsonb,
+		with_data -> Bool,
+		sort -> Varchar,
+		subscribed_at -> Nullable<Timestamp>,
+		created_at -> Timestamp,
+		updated_at -> Timestamp,
 	}
 }
 
@@ -38,6 +152,32 @@ table! {
 	}
 }
 
+table! {
+	formulas (id) {
+		id -> Int8,
+		block_id -> Int8,
+		name -> Varchar,
+		expression -> Text,
+		error -> Nullable<Text>,
+		author_id -> Nullable<Int4>,
+		updated_at -> Timestamp,
+	}
+}
+
+table! {
+	inline_properties (id) {
+		id -> Int8,
+		parent_id -> Int8,
+		property_name -> Varchar,
+		value_type -> Varchar,
+		text_value -> Nullable<Text>,
+		number_value -> Nullable<Float8>,
+		bool_value -> Nullable<Bool>,
+		time_value -> Nullable<Timestamp>,
+		user_value -> Nullable<Int4>,
+	}
+}
+
 table! {
 	notifications (id) {
 		id -> Int8,
@@ -49,6 +189,19 @@ table! {
 	}
 }
 
+table! {
+	ownership_transfers (id) {
+		id -> Int8,
+		block_id -> Int8,
+		from_id -> Int4,
+		to_id -> Int4,
+		subtree -> Bool,
+		status -> Varchar,
+		created_at -> Timestamp,
+		decided_at -> Nullable<Timestamp>,
+	}
+}
+
 table! {
 	potential_users (id) {
 		id -> Int4,
@@ -69,6 +222,65 @@ table! {
 		parent_id -> Int8,
 		value_id -> Int8,
 		annotation -> Nullable<Varchar>,
+		is_primary -> Bool,
+		position -> Nullable<Varchar>,
+	}
+}
+
+table! {
+	relation_types (id) {
+		id -> Int8,
+		owner_id -> Nullable<Int4>,
+		name -> Varchar,
+		inverse_name -> Varchar,
+		created_at -> Timestamp,
+	}
+}
+
+table! {
+	share_links (id) {
+		id -> Int8,
+		block_id -> Int8,
+		token -> Varchar,
+		perm_level -> Varchar,
+		expires_at -> Nullable<Timestamp>,
+		single_use -> Bool,
+		used_by -> Nullable<Int4>,
+		used_at -> Nullable<Timestamp>,
+		revoked -> Bool,
+		created_by -> Int4,
+		created_at -> Timestamp,
+	}
+}
+
+table! {
+	tags (id) {
+		id -> Int8,
+		owner_id -> Int4,
+		name -> Varchar,
+		color -> Nullable<Varchar>,
+		created_at -> Timestamp,
+	}
+}
+
+table! {
+	team_members (id) {
+		id -> Int8,
+		team_id -> Int8,
+		user_id -> Int4,
+		role -> Varchar,
+		accepted -> Bool,
+		invited_by -> Nullable<Int4>,
+		created_at -> Timestamp,
+	}
+}
+
+table! {
+	teams (id) {
+		id -> Int8,
+		name -> Varchar,
+		owner_id -> Int4,
+		created_at -> Timestamp,
 	}
 }
 
@@ -93,21 +305,58 @@ table! {
 		featured_id -> Nullable<Int8>,
 		expo_tokens -> Array<Text>,
 		latest_update_seen_id -> Nullable<Int4>,
+		admin -> Bool,
 	}
 }
 
+joinable!(access_requests -> blocks (block_id));
+joinable!(block_references -> blocks (source_id));
+joinable!(block_relations -> blocks (source_id));
+joinable!(block_relations -> relation_types (relation_type_id));
+joinable!(block_revisions -> blocks (block_id));
+joinable!(block_revisions -> users (author_id));
+joinable!(block_tags -> blocks (block_id));
+joinable!(block_tags -> tags (tag_id));
+joinable!(collection_notified -> blocks (block_id));
+joinable!(collection_notified -> collections (collection_id));
+joinable!(collections -> users (owner_id));
 joinable!(comments -> blocks (content_id));
 joinable!(comments -> users (author_id));
 joinable!(email_confirm -> users (user_id));
+joinable!(formulas -> blocks (block_id));
+joinable!(inline_properties -> blocks (parent_id));
+joinable!(inline_properties -> users (user_value));
+joinable!(ownership_transfers -> blocks (block_id));
+joinable!(relation_types -> users (owner_id));
+joinable!(share_links -> blocks (block_id));
+joinable!(tags -> users (owner_id));
+joinable!(team_members -> teams (team_id));
+joinable!(teams -> users (owner_id));
 joinable!(users -> updates (latest_update_seen_id));
 
 allow_tables_to_appear_in_same_query!(
+	access_requests,
+	audit_events,
+	block_references,
+	block_relations,
+	block_revisions,
+	block_tags,
 	blocks,
+	collection_notified,
+	collections,
 	comments,
 	email_confirm,
+	formulas,
+	inline_properties,
 	notifications,
+	ownership_transfers,
 	potential_users,
 	properties,
+	relation_types,
+	share_links,
+	tags,
+	team_members,
+	teams,
 	updates,
 	users,
 );
@@ -2,7 +2,7 @@ use crate::{
 	auth::permissions::{has_perm_level, PermLevel},
 	db::schema::comments,
 	display_api::{component::atomic::icon::Icon, ActionObject},
-	models::Block,
+	models::{Block, Tag},
 	LoopError, PgConnect,
 };
 use diesel::prelude::*;
@@ -18,6 +18,7 @@ pub struct MenuComponent {
 	pub star_button: Option<StarButton>,
 	pub custom: Option<Vec<CustomMenuItem>>,
 	pub comment_count: Option<i64>,
+	pub tags: Option<Vec<MenuTag>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -30,10 +31,18 @@ pub struct StarButton {
 pub struct PermissionsList {
 	pub full: usize,
 	pub edit: usize,
+	pub comment: usize,
 	pub view: usize,
 	pub public: Option<bool>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MenuTag {
+	pub id: i64,
+	pub name: String,
+	pub color: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CustomMenuItem {
 	pub icon: Icon,
@@ -66,6 +75,7 @@ impl MenuComponent {
 			star_button: None,
 			custom: None,
 			comment_count: None,
+			tags: None,
 		}
 	}
 }
@@ -89,6 +99,7 @@ impl MenuComponent {
 				public,
 				full: block.perm_full.len(),
 				edit: block.perm_edit.len(),
+				comment: block.perm_comment.len(),
 				view: block.perm_view.len(),
 			});
 		}
@@ -111,4 +122,19 @@ impl MenuComponent {
 		self.comment_count = Some(count);
 		Ok(count)
 	}
+
+	/// Adds the tags that the user has put on the block to the menu
+	pub fn load_tags(&mut self, user_id: i32, conn: &PgConnect) -> Result<usize, LoopError> {
+		let tags: Vec<MenuTag> = Tag::on_block(self.block_id, user_id, conn)?
+			.into_iter()
+			.map(|tag| MenuTag {
+				id: tag.id,
+				name: tag.name,
+				color: tag.color,
+			})
+			.collect();
+		let count = tags.len();
+		self.tags = Some(tags);
+		Ok(count)
+	}
 }
@@ -28,6 +28,19 @@ pub enum BlockError {
 	/// Error for when a block method does not exist
 	/// for a certain block type. (Name, Type)
 	MethodExist(String, String),
+	/// Error for when a block method is called with a different
+	/// type than the block has. (Block ID, Type)
+	TypeMismatch(i64, String),
+	/// Error for when an operation in a batch (by index) has no single
+	/// action or references an operation that does not come before it
+	InvalidBatchOperation(usize),
+	/// Error for when a block (second) has no property
+	/// from another block (first). (Parent, Child)
+	NotParent(i64, i64),
+	/// Error for when a formula can't be parsed. (Reason)
+	FormulaParse(String),
+	/// Error for when a formula refers to itself. (Formula name)
+	FormulaCycle(String),
 }
 
 impl fmt::Display for BlockError {
@@ -43,12 +56,43 @@ impl fmt::Display for BlockError {
 					name, block_type
 				)
 			}
+			BlockError::TypeMismatch(block_id, block_type) => {
+				write!(
+					f,
+					"[btm] Block {} is not a block of the {} type.",
+					block_id, block_type
+				)
+			}
 			BlockError::TypeGenericError(err) => {
 				write!(f, "[btg] {}", err)
 			}
 			BlockError::InputParse => {
 				write!(f, "[bip] The input string could not be parsed properly.")
 			}
+			BlockError::InvalidBatchOperation(index) => {
+				write!(
+					f,
+					"[bbi] Batch operation {} needs exactly one action, and can only reference operations before it.",
+					index
+				)
+			}
+			BlockError::NotParent(parent_id, block_id) => {
+				write!(
+					f,
+					"[bnp] Block {} is not a parent of block {}.",
+					parent_id, block_id
+				)
+			}
+			BlockError::FormulaParse(reason) => {
+				write!(f, "[bfp] The formula could not be parsed: {}", reason)
+			}
+			BlockError::FormulaCycle(name) => {
+				write!(
+					f,
+					"[bfc] The formula '{}' refers to itself through other formulas.",
+					name
+				)
+			}
 		}
 	}
 }
@@ -59,12 +103,35 @@ pub enum UserError {
 	PasswordMatch,
 	EmailConfirmError(EmailConfirmError),
 	NameNonexist(String),
+	IdNonexist(i32),
 	NameConflict(String),
 	NameTooShort(String),
 	JwtGeneric,
 	NoAccess(NoAccessSubject),
 	NeedAuth,
 	InsufficientFunds(i32),
+	TagConflict(String),
+	/// Error for when a tag's name is empty or too long. (Name)
+	TagNameLength(String),
+	/// Error for when a color isn't an RGB string. (Color)
+	InvalidColor(String),
+	/// Error for when an inline property's name is empty or too long. (Name)
+	PropertyNameLength(String),
+	RelationTypeConflict(String),
+	ShareLinkInvalid,
+	AlreadyHasAccess(i64),
+	AccessRequestDecided(i64),
+	TransferDecided(i64),
+	/// Error for when a cursor can't be read. (Cursor)
+	InvalidCursor(String),
+	/// Error for when a page asks for both `first` and `last`, or a negative count
+	InvalidPage,
+	/// Error for when search filters are nested too deep or have too many groups
+	TooManyFilters,
+	/// Error for when a revision doesn't exist. (Revision ID)
+	RevisionNonexist(i64),
+	/// Error for when two revisions aren't of the same block. (Revision IDs)
+	RevisionMismatch(i64, i64),
 }
 
 impl fmt::Display for UserError {
@@ -80,6 +147,9 @@ impl fmt::Display for UserError {
 					name
 				)
 			}
+			UserError::IdNonexist(id) => {
+				write!(f, "[uie] A user with the ID {} was not found.", id)
+			}
 			UserError::PasswordTooShort => write!(f, "[ups] The password provided was too short."),
 			UserError::NameTooShort(name) => {
 				write!(f, "[uns] The username `{}` provided was too short.", name)
@@ -99,33 +169,131 @@ impl fmt::Display for UserError {
 				"[uci] Insuffecient funds. {} credits are needed.",
 				needed
 			),
+			UserError::TagConflict(name) => {
+				write!(f, "[utc] A tag called '{}' already exists.", name)
+			}
+			UserError::TagNameLength(name) => write!(
+				f,
+				"[utl] The tag name '{}' needs between 1 and {} characters.",
+				name,
+				crate::models::MAX_TAG_NAME
+			),
+			UserError::InvalidColor(color) => write!(
+				f,
+				"[uic] '{}' is not an RGB color like `rgb(123,123,123)`.",
+				color
+			),
+			UserError::PropertyNameLength(name) => write!(
+				f,
+				"[upn] The property name '{}' needs between 1 and {} characters.",
+				name,
+				crate::models::MAX_INLINE_NAME
+			),
+			UserError::RelationTypeConflict(name) => {
+				write!(f, "[urc] A relation type called '{}' already exists.", name)
+			}
+			UserError::AlreadyHasAccess(id) => write!(
+				f,
+				"[uha] You already have that level of access to block {}.",
+				id
+			),
+			UserError::AccessRequestDecided(id) => {
+				write!(f, "[urd] Access request {} was already decided.", id)
+			}
+			UserError::TransferDecided(id) => write!(
+				f,
+				"[utd] Ownership transfer {} was already answered or is outdated.",
+				id
+			),
+			UserError::InvalidCursor(cursor) => {
+				write!(f, "[ucu] The cursor '{}' is not valid.", cursor)
+			}
+			UserError::InvalidPage => write!(
+				f,
+				"[upg] Only one of `first` and `last` can be given, and it can't be negative."
+			),
+			UserError::TooManyFilters => write!(
+				f,
+				"[utf] Search filters can have up to {} groups, nested up to {} deep.",
+				crate::models::MAX_FILTER_GROUPS,
+				crate::models::MAX_FILTER_DEPTH
+			),
+			UserError::RevisionNonexist(id) => {
+				write!(f, "[urn] A revision with the ID {} was not found.", id)
+			}
+			UserError::RevisionMismatch(from, to) => write!(
+				f,
+				"[urm] Revisions {} and {} aren't of the same block.",
+				from, to
+			),
+			UserError::ShareLinkInvalid => write!(
+				f,
+				"[usl] The share link was not found, or it expired, was revoked or was already used."
+			),
 		}
 	}
 }
 
 #[derive(Debug, Clone)]
 pub enum NoAccessSubject {
+	AccessRequest(i64),
+	BlockAudit(i64),
+	/// Calling a block type's method on a block. (Block ID, method name)
+	BlockMethod(i64, String),
+	CommentBlock(i64),
 	DeleteBlock(i64),
+	EditCollection(i64),
 	EditColor(i64),
+	EditParents(i64),
+	EditRelations(i64),
+	EditRelationType(i64),
+	EditTag(i64),
+	EditTeam(i64),
 	NotifBlock(i64),
 	OtherUserCredits,
+	ReindexSearch,
+	RevertBlock(i64),
+	TransferOwnership(i64),
 	UpdatePermissions(i64),
+	UserAudit(i32),
 	ViewBlock(i64),
 	ViewComment(i64),
+	ViewTeam(i64),
 }
 
 impl fmt::Display for NoAccessSubject {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		match self {
 			NoAccessSubject::OtherUserCredits => write!(f, "another user's credits"),
+			NoAccessSubject::ReindexSearch => write!(f, "rebuilding the search index"),
+			NoAccessSubject::AccessRequest(id) => write!(f, "deciding access request {}", id),
 			NoAccessSubject::UpdatePermissions(id) => {
 				write!(f, "updating block {}'s permissions", id)
 			}
+			NoAccessSubject::BlockAudit(id) => write!(f, "viewing block {}'s audit log", id),
+			NoAccessSubject::UserAudit(id) => write!(f, "viewing user {}'s audit log", id),
+			NoAccessSubject::BlockMethod(id, name) => {
+				write!(f, "calling method '{}' on block {}", name, id)
+			}
+			NoAccessSubject::CommentBlock(id) => write!(f, "commenting on block {}", id),
 			NoAccessSubject::DeleteBlock(id) => write!(f, "deleting block {}", id),
 			NoAccessSubject::ViewBlock(id) => write!(f, "viewing block {}", id),
 			NoAccessSubject::ViewComment(id) => write!(f, "viewing comment {}", id),
 			NoAccessSubject::NotifBlock(id) => write!(f, "setting block {}'s notifications", id),
+			NoAccessSubject::EditCollection(id) => write!(f, "changing collection {}", id),
 			NoAccessSubject::EditColor(id) => write!(f, "changing block {}'s color", id),
+			NoAccessSubject::RevertBlock(id) => write!(f, "reverting block {}", id),
+			NoAccessSubject::TransferOwnership(id) => {
+				write!(f, "ownership transfer {}", id)
+			}
+			NoAccessSubject::EditTag(id) => write!(f, "changing tag {}", id),
+			NoAccessSubject::EditTeam(id) => write!(f, "changing team {}", id),
+			NoAccessSubject::ViewTeam(id) => write!(f, "viewing team {}", id),
+			NoAccessSubject::EditRelations(id) => write!(f, "changing block {}'s relations", id),
+			NoAccessSubject::EditRelationType(id) => write!(f, "changing relation type {}", id),
+			NoAccessSubject::EditParents(id) => {
+				write!(f, "changing block {}'s parents or properties", id)
+			}
 		}
 	}
 }
@@ -0,0 +1,470 @@
+//! Formulas compute a block's values from its other values and its children.
+//! They are written like `price * quantity`, `"Total: " + sum(item, cost)`
+//! or `days(start, now())`.
+mod parse;
+use crate::{models::InlineValue, LoopError};
+use chrono::{DateTime, Utc};
+pub use parse::parse;
+use std::time::{Duration, SystemTime};
+
+const SECONDS_PER_DAY: f64 = 86400.;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+	Number(f64),
+	Text(String),
+	Boolean(bool),
+	/// Another value of the same block, by name
+	Ref(String),
+	Negate(Box<Expr>),
+	Binary(BinaryOp, Box<Expr>, Box<Expr>),
+	/// A function like `sum(item, cost)`, with its name and arguments
+	Call(String, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+	Add,
+	Subtract,
+	Multiply,
+	Divide,
+}
+
+/// Where a formula gets the values it refers to
+pub trait FormulaScope {
+	/// The value called `name` on the block the formula is on
+	fn value(&mut self, name: &str) -> Result<Option<InlineValue>, LoopError>;
+	/// The value called `field` on each child under the property `name`. If
+	/// there is no field, the data of each child is used instead.
+	fn child_values(
+		&mut self,
+		name: &str,
+		field: Option<&str>,
+	) -> Result<Vec<Option<InlineValue>>, LoopError>;
+}
+
+#[derive(Debug, Clone)]
+pub enum FormulaError {
+	/// The formula can't be computed, like when dividing text
+	Invalid(String),
+	Database(LoopError),
+}
+
+impl From<LoopError> for FormulaError {
+	fn from(err: LoopError) -> Self {
+		FormulaError::Database(err)
+	}
+}
+
+impl Expr {
+	/// The names of the block's values that the formula refers to. The property
+	/// names given to `count` and `sum` refer to children, so they aren't included.
+	pub fn refs(&self) -> Vec<String> {
+		let mut refs = vec![];
+		self.collect_refs(&mut refs);
+		refs.sort();
+		refs.dedup();
+		refs
+	}
+
+	fn collect_refs(&self, refs: &mut Vec<String>) {
+		match self {
+			Expr::Ref(name) => refs.push(name.clone()),
+			Expr::Negate(inner) => inner.collect_refs(refs),
+			Expr::Binary(_, left, right) => {
+				left.collect_refs(refs);
+				right.collect_refs(refs);
+			}
+			Expr::Call(function, args) => {
+				let skip = match function.as_str() {
+					"count" | "sum" => args.len(),
+					_ => 0,
+				};
+				for arg in args.iter().skip(skip) {
+					arg.collect_refs(refs);
+				}
+			}
+			_ => {}
+		}
+	}
+}
+
+/// Orders formulas so that each one comes after the formulas it refers to.
+/// If formulas refer to themselves (even through others), the name of
+/// one of them is returned as the error.
+pub fn evaluation_order(formulas: &[(String, Expr)]) -> Result<Vec<usize>, String> {
+	#[derive(Clone, Copy, PartialEq)]
+	enum Visit {
+		New,
+		Visiting,
+		Done,
+	}
+
+	fn visit(
+		index: usize,
+		formulas: &[(String, Expr)],
+		visits: &mut Vec<Visit>,
+		order: &mut Vec<usize>,
+	) -> Result<(), String> {
+		match visits[index] {
+			Visit::Done => return Ok(()),
+			Visit::Visiting => return Err(formulas[index].0.clone()),
+			Visit::New => {}
+		}
+		visits[index] = Visit::Visiting;
+		for name in formulas[index].1.refs() {
+			if let Some(dependency) = formulas.iter().position(|(other, _)| *other == name) {
+				visit(dependency, formulas, visits, order)?;
+			}
+		}
+		visits[index] = Visit::Done;
+		order.push(index);
+		Ok(())
+	}
+
+	let mut visits = vec![Visit::New; formulas.len()];
+	let mut order = vec![];
+	for index in 0..formulas.len() {
+		visit(index, formulas, &mut visits, &mut order)?;
+	}
+	Ok(order)
+}
+
+/// Computes a formula. The result is `None` if a value it needs is missing.
+pub fn evaluate(
+	expr: &Expr,
+	scope: &mut impl FormulaScope,
+) -> Result<Option<InlineValue>, FormulaError> {
+	Ok(match expr {
+		Expr::Number(number) => Some(InlineValue::Number(*number)),
+		Expr::Text(text) => Some(InlineValue::Text(text.clone())),
+		Expr::Boolean(boolean) => Some(InlineValue::Boolean(*boolean)),
+		Expr::Ref(name) => scope.value(name)?,
+		Expr::Negate(inner) => match evaluate(inner, scope)? {
+			Some(value) => Some(InlineValue::Number(-number(&value, "-")?)),
+			None => None,
+		},
+		Expr::Binary(op, left, right) => match (evaluate(left, scope)?, evaluate(right, scope)?) {
+			(Some(left), Some(right)) => Some(binary(*op, left, right)?),
+			_ => None,
+		},
+		Expr::Call(function, args) => call(function, args, scope)?,
+	})
+}
+
+fn binary(
+	op: BinaryOp,
+	left: InlineValue,
+	right: InlineValue,
+) -> Result<InlineValue, FormulaError> {
+	use InlineValue::*;
+	Ok(match (op, left, right) {
+		(BinaryOp::Divide, Number(_), Number(b)) if b == 0. => {
+			return Err(FormulaError::Invalid("Can't divide by zero".into()))
+		}
+		(op, Number(a), Number(b)) => Number(match op {
+			BinaryOp::Add => a + b,
+			BinaryOp::Subtract => a - b,
+			BinaryOp::Multiply => a * b,
+			BinaryOp::Divide => a / b,
+		}),
+		(BinaryOp::Add, Text(a), b) => Text(a + &display(&b)),
+		(BinaryOp::Add, a, Text(b)) => Text(display(&a) + &b),
+		(BinaryOp::Subtract, Timestamp(a), Timestamp(b)) => Number(days_between(b, a)),
+		(BinaryOp::Add, Timestamp(time), Number(days))
+		| (BinaryOp::Add, Number(days), Timestamp(time)) => Timestamp(add_days(time, days)?),
+		(BinaryOp::Subtract, Timestamp(time), Number(days)) => Timestamp(add_days(time, -days)?),
+		(op, a, b) => {
+			return Err(FormulaError::Invalid(format!(
+				"Can't use {:?} with {} and {}",
+				op,
+				a.type_name(),
+				b.type_name()
+			)))
+		}
+	})
+}
+
+fn call(
+	function: &str,
+	args: &[Expr],
+	scope: &mut impl FormulaScope,
+) -> Result<Option<InlineValue>, FormulaError> {
+	let arg_count = |count: usize| {
+		if args.len() == count {
+			Ok(())
+		} else {
+			Err(FormulaError::Invalid(format!(
+				"{} needs {} arguments",
+				function, count
+			)))
+		}
+	};
+
+	Ok(match function {
+		"now" => {
+			arg_count(0)?;
+			Some(InlineValue::Timestamp(SystemTime::now()))
+		}
+		"count" => {
+			arg_count(1)?;
+			let children = scope.child_values(&name_arg(&args[0])?, None)?;
+			Some(InlineValue::Number(children.len() as f64))
+		}
+		"sum" => {
+			if args.is_empty() || args.len() > 2 {
+				return Err(FormulaError::Invalid("sum needs 1 or 2 arguments".into()));
+			}
+			let field = match args.get(1) {
+				Some(field) => Some(name_arg(field)?),
+				None => None,
+			};
+			let total = scope
+				.child_values(&name_arg(&args[0])?, field.as_deref())?
+				.iter()
+				.flatten()
+				.filter_map(|value| number(value, "sum").ok())
+				.sum();
+			Some(InlineValue::Number(total))
+		}
+		"days" => {
+			arg_count(2)?;
+			match (evaluate(&args[0], scope)?, evaluate(&args[1], scope)?) {
+				(Some(start), Some(end)) => Some(InlineValue::Number(days_between(
+					timestamp(&start)?,
+					timestamp(&end)?,
+				))),
+				_ => None,
+			}
+		}
+		"concat" => {
+			let mut text = String::new();
+			for arg in args {
+				if let Some(value) = evaluate(arg, scope)? {
+					text += &display(&value);
+				}
+			}
+			Some(InlineValue::Text(text))
+		}
+		_ => {
+			return Err(FormulaError::Invalid(format!(
+				"There is no function called {}",
+				function
+			)))
+		}
+	})
+}
+
+/// A property name given to a function, like `item` in `count(item)`
+fn name_arg(arg: &Expr) -> Result<String, FormulaError> {
+	match arg {
+		Expr::Ref(name) | Expr::Text(name) => Ok(name.clone()),
+		_ => Err(FormulaError::Invalid(
+			"Expected the name of a property".into(),
+		)),
+	}
+}
+
+fn number(value: &InlineValue, used_by: &str) -> Result<f64, FormulaError> {
+	match value {
+		InlineValue::Number(number) => Ok(*number),
+		InlineValue::Text(text) => text
+			.trim()
+			.parse()
+			.map_err(|_| FormulaError::Invalid(format!("{} needs a number", used_by))),
+		other => Err(FormulaError::Invalid(format!(
+			"{} needs a number, not {}",
+			used_by,
+			other.type_name()
+		))),
+	}
+}
+
+fn timestamp(value: &InlineValue) -> Result<SystemTime, FormulaError> {
+	match value {
+		InlineValue::Timestamp(time) => Ok(*time),
+		InlineValue::Text(text) => DateTime::parse_from_rfc3339(text.trim())
+			.map(SystemTime::from)
+			.map_err(|_| FormulaError::Invalid(format!("'{}' is not a date", text))),
+		other => Err(FormulaError::Invalid(format!(
+			"Expected a date, not {}",
+			other.type_name()
+		))),
+	}
+}
+
+/// How many days are from `start` to `end`. Negative if `end` is earlier.
+fn days_between(start: SystemTime, end: SystemTime) -> f64 {
+	match end.duration_since(start) {
+		Ok(duration) => duration.as_secs_f64() / SECONDS_PER_DAY,
+		Err(err) => -err.duration().as_secs_f64() / SECONDS_PER_DAY,
+	}
+}
+
+/// Moves a time by a number of days, which fails if the days aren't a
+/// number or the time would be out of range
+fn add_days(time: SystemTime, days: f64) -> Result<SystemTime, FormulaError> {
+	let moved = match Duration::try_from_secs_f64(days.abs() * SECONDS_PER_DAY) {
+		Ok(duration) if days >= 0. => time.checked_add(duration),
+		Ok(duration) => time.checked_sub(duration),
+		Err(_) => None,
+	};
+	moved.ok_or_else(|| FormulaError::Invalid(format!("Can't move a date by {} days", days)))
+}
+
+/// How a value looks when it is put into text
+pub fn display(value: &InlineValue) -> String {
+	match value {
+		InlineValue::Text(text) => text.clone(),
+		InlineValue::Number(number) => number.to_string(),
+		InlineValue::Boolean(boolean) => boolean.to_string(),
+		InlineValue::Timestamp(time) => DateTime::<Utc>::from(*time).to_rfc3339(),
+		InlineValue::User(user_id) => user_id.to_string(),
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use std::collections::HashMap;
+
+	#[derive(Default)]
+	struct TestScope {
+		values: HashMap<String, InlineValue>,
+		children: HashMap<String, Vec<InlineValue>>,
+	}
+
+	impl FormulaScope for TestScope {
+		fn value(&mut self, name: &str) -> Result<Option<InlineValue>, LoopError> {
+			Ok(self.values.get(name).cloned())
+		}
+
+		fn child_values(
+			&mut self,
+			name: &str,
+			_: Option<&str>,
+		) -> Result<Vec<Option<InlineValue>>, LoopError> {
+			Ok(self
+				.children
+				.get(name)
+				.map(|values| values.iter().cloned().map(Some).collect())
+				.unwrap_or_default())
+		}
+	}
+
+	fn eval(source: &str, scope: &mut TestScope) -> Option<InlineValue> {
+		evaluate(&parse(source).unwrap(), scope).unwrap()
+	}
+
+	#[test]
+	fn arithmetic_precedence() {
+		let mut scope = TestScope::default();
+		assert_eq!(Some(InlineValue::Number(7.)), eval("1 + 2 * 3", &mut scope));
+		assert_eq!(
+			Some(InlineValue::Number(-9.)),
+			eval("-(1 + 2) * 3", &mut scope)
+		);
+	}
+
+	#[test]
+	fn concat_text_with_values() {
+		let mut scope = TestScope::default();
+		scope
+			.values
+			.insert("due date".into(), InlineValue::Text("today".into()));
+		assert_eq!(
+			Some(InlineValue::Text("Due today: 2".into())),
+			eval("\"Due \" + `due date` + \": \" + 2", &mut scope)
+		);
+	}
+
+	#[test]
+	fn count_and_sum_children() {
+		let mut scope = TestScope::default();
+		scope.children.insert(
+			"item".into(),
+			vec![InlineValue::Number(2.), InlineValue::Text("3.5".into())],
+		);
+		assert_eq!(
+			Some(InlineValue::Number(2.)),
+			eval("count(item)", &mut scope)
+		);
+		assert_eq!(
+			Some(InlineValue::Number(5.5)),
+			eval("sum(item)", &mut scope)
+		);
+	}
+
+	#[test]
+	fn missing_values_are_empty() {
+		let mut scope = TestScope::default();
+		assert_eq!(None, eval("price * 2", &mut scope));
+	}
+
+	#[test]
+	fn date_differences() {
+		let mut scope = TestScope::default();
+		assert_eq!(
+			Some(InlineValue::Number(2.)),
+			eval(
+				"days(\"2021-07-01T00:00:00Z\", \"2021-07-03T00:00:00Z\")",
+				&mut scope
+			)
+		);
+	}
+
+	#[test]
+	fn dates_out_of_range() {
+		let mut scope = TestScope::default();
+		// Too many digits for a float, so it is infinite
+		let huge = "9".repeat(400);
+		for source in &[
+			format!("now() + {} * 10", huge),
+			format!("now() - {}", huge),
+			format!("now() + ({} - {})", huge, huge),
+			"now() + 150000000000000".to_string(),
+			"now() - 150000000000000".to_string(),
+		] {
+			assert!(evaluate(&parse(source).unwrap(), &mut scope).is_err());
+		}
+		assert!(eval("now() + 1.5", &mut scope).is_some());
+	}
+
+	#[test]
+	fn parse_errors() {
+		assert!(parse("1 +").is_err());
+		assert!(parse("(1").is_err());
+		assert!(parse("\"open").is_err());
+	}
+
+	#[test]
+	fn nesting_is_limited() {
+		assert!(parse(&format!("{}1{}", "(".repeat(40), ")".repeat(40))).is_err());
+		assert!(parse(&format!("{}1", "-".repeat(40))).is_err());
+		assert!(parse(&format!("{}1{}", "sum(".repeat(40), ")".repeat(40))).is_err());
+		// Too long, before it is even parsed
+		assert!(parse(&format!("{}1", "(".repeat(100000))).is_err());
+		assert!(parse(&format!("{}1{}", "(".repeat(20), ")".repeat(20))).is_ok());
+	}
+
+	#[test]
+	fn order_follows_refs() {
+		let formulas = vec![
+			("total".to_string(), parse("subtotal + tax").unwrap()),
+			("tax".to_string(), parse("subtotal * 0.1").unwrap()),
+			("subtotal".to_string(), parse("sum(item)").unwrap()),
+		];
+		assert_eq!(Ok(vec![2, 1, 0]), evaluation_order(&formulas));
+	}
+
+	#[test]
+	fn cycles_are_found() {
+		let formulas = vec![
+			("a".to_string(), parse("b + 1").unwrap()),
+			("b".to_string(), parse("a + 1").unwrap()),
+		];
+		assert!(evaluation_order(&formulas).is_err());
+		let formulas = vec![("a".to_string(), parse("a + 1").unwrap())];
+		assert_eq!(Err("a".to_string()), evaluation_order(&formulas));
+	}
+}
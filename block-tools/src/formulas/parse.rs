@@ -0,0 +1,226 @@
+use super::{BinaryOp, Expr};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+	Number(f64),
+	Text(String),
+	Name(String),
+	Op(char),
+	Open,
+	Close,
+	Comma,
+}
+
+/// The longest formula that can be parsed, in characters
+const MAX_LENGTH: usize = 2000;
+/// How deeply parentheses, negation and function calls can be nested
+const MAX_DEPTH: usize = 32;
+
+/// Parses a formula like `price * count(item)`. The error describes
+/// what was wrong with the formula.
+pub fn parse(source: &str) -> Result<Expr, String> {
+	if source.chars().count() > MAX_LENGTH {
+		return Err(format!(
+			"Formulas can't be longer than {} characters",
+			MAX_LENGTH
+		));
+	}
+	let tokens = tokenize(source)?;
+	let mut parser = Parser {
+		tokens,
+		index: 0,
+		depth: 0,
+	};
+	let expr = parser.expr()?;
+	match parser.next() {
+		None => Ok(expr),
+		Some(token) => Err(format!(
+			"Unexpected {:?} after the end of the formula",
+			token
+		)),
+	}
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+	let mut tokens = vec![];
+	let mut chars = source.chars().peekable();
+	while let Some(&c) = chars.peek() {
+		match c {
+			c if c.is_whitespace() => {
+				chars.next();
+			}
+			'0'..='9' | '.' => {
+				let mut number = String::new();
+				while let Some(&c) = chars.peek() {
+					if !(c.is_ascii_digit() || c == '.') {
+						break;
+					}
+					number.push(c);
+					chars.next();
+				}
+				let number = number
+					.parse()
+					.map_err(|_| format!("'{}' is not a number", number))?;
+				tokens.push(Token::Number(number));
+			}
+			'"' => {
+				chars.next();
+				tokens.push(Token::Text(read_until(&mut chars, '"')?));
+			}
+			// Names with spaces can be written like `due date`
+			'`' => {
+				chars.next();
+				tokens.push(Token::Name(read_until(&mut chars, '`')?));
+			}
+			c if c.is_alphabetic() || c == '_' => {
+				let mut name = String::new();
+				while let Some(&c) = chars.peek() {
+					if !(c.is_alphanumeric() || c == '_') {
+						break;
+					}
+					name.push(c);
+					chars.next();
+				}
+				tokens.push(Token::Name(name));
+			}
+			'+' | '-' | '*' | '/' => {
+				tokens.push(Token::Op(c));
+				chars.next();
+			}
+			'(' => {
+				tokens.push(Token::Open);
+				chars.next();
+			}
+			')' => {
+				tokens.push(Token::Close);
+				chars.next();
+			}
+			',' => {
+				tokens.push(Token::Comma);
+				chars.next();
+			}
+			c => return Err(format!("Unexpected character '{}'", c)),
+		}
+	}
+	Ok(tokens)
+}
+
+fn read_until(chars: &mut impl Iterator<Item = char>, end: char) -> Result<String, String> {
+	let mut text = String::new();
+	for c in chars {
+		if c == end {
+			return Ok(text);
+		}
+		text.push(c);
+	}
+	Err(format!("Missing a closing {}", end))
+}
+
+struct Parser {
+	tokens: Vec<Token>,
+	index: usize,
+	/// How many expressions the parser is inside of
+	depth: usize,
+}
+
+impl Parser {
+	fn peek(&self) -> Option<&Token> {
+		self.tokens.get(self.index)
+	}
+
+	fn next(&mut self) -> Option<Token> {
+		let token = self.tokens.get(self.index).cloned();
+		self.index += 1;
+		token
+	}
+
+	fn expect(&mut self, expected: Token) -> Result<(), String> {
+		match self.next() {
+			Some(token) if token == expected => Ok(()),
+			Some(token) => Err(format!("Expected {:?} but found {:?}", expected, token)),
+			None => Err(format!("Expected {:?} at the end of the formula", expected)),
+		}
+	}
+
+	fn expr(&mut self) -> Result<Expr, String> {
+		let mut expr = self.term()?;
+		while let Some(Token::Op(op @ ('+' | '-'))) = self.peek().cloned() {
+			self.next();
+			let op = if op == '+' {
+				BinaryOp::Add
+			} else {
+				BinaryOp::Subtract
+			};
+			expr = Expr::Binary(op, Box::new(expr), Box::new(self.term()?));
+		}
+		Ok(expr)
+	}
+
+	fn term(&mut self) -> Result<Expr, String> {
+		let mut expr = self.unary()?;
+		while let Some(Token::Op(op @ ('*' | '/'))) = self.peek().cloned() {
+			self.next();
+			let op = if op == '*' {
+				BinaryOp::Multiply
+			} else {
+				BinaryOp::Divide
+			};
+			expr = Expr::Binary(op, Box::new(expr), Box::new(self.unary()?));
+		}
+		Ok(expr)
+	}
+
+	/// Every nested expression is parsed through here, so this is where
+	/// the depth is limited
+	fn unary(&mut self) -> Result<Expr, String> {
+		if self.depth >= MAX_DEPTH {
+			return Err(format!(
+				"Formulas can't be nested more than {} levels deep",
+				MAX_DEPTH
+			));
+		}
+		self.depth += 1;
+		let expr = if let Some(Token::Op('-')) = self.peek() {
+			self.next();
+			self.unary().map(|inner| Expr::Negate(Box::new(inner)))
+		} else {
+			self.primary()
+		};
+		self.depth -= 1;
+		expr
+	}
+
+	fn primary(&mut self) -> Result<Expr, String> {
+		match self.next() {
+			Some(Token::Number(number)) => Ok(Expr::Number(number)),
+			Some(Token::Text(text)) => Ok(Expr::Text(text)),
+			Some(Token::Open) => {
+				let expr = self.expr()?;
+				self.expect(Token::Close)?;
+				Ok(expr)
+			}
+			Some(Token::Name(name)) => {
+				if self.peek() != Some(&Token::Open) {
+					return Ok(match name.as_str() {
+						"true" => Expr::Boolean(true),
+						"false" => Expr::Boolean(false),
+						_ => Expr::Ref(name),
+					});
+				}
+				self.next();
+				let mut args = vec![];
+				if self.peek() != Some(&Token::Close) {
+					args.push(self.expr()?);
+					while self.peek() == Some(&Token::Comma) {
+						self.next();
+						args.push(self.expr()?);
+					}
+				}
+				self.expect(Token::Close)?;
+				Ok(Expr::Call(name, args))
+			}
+			Some(token) => Err(format!("Unexpected {:?}", token)),
+			None => Err("The formula ended too early".into()),
+		}
+	}
+}
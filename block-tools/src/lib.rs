@@ -7,6 +7,7 @@ pub use errors::*;
 pub mod auth;
 pub mod blocks;
 pub mod display_api;
+pub mod formulas;
 pub mod notifications;
 pub use sentry;
 
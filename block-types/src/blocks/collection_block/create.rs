@@ -0,0 +1,65 @@
+use super::{CollectionBlock, CollectionBlockData, BLOCK_NAME};
+use block_tools::{
+	blocks::Context,
+	display_api::{
+		component::{
+			atomic::text::TextComponent,
+			form::input::{InputComponent, InputType},
+			layout::{
+				displaylist::{DisplayListComponent, DisplayListItem},
+				stack::StackComponent,
+			},
+		},
+		CreationObject,
+	},
+	models::{Block, Collection, NewBlock},
+	BlockError, LoopError,
+};
+
+pub fn create(input: String, context: &Context, user_id: i32) -> Result<Block, LoopError> {
+	let conn = &context.shared_conn()?;
+	let data: CollectionBlockData = serde_json::from_str(&input).map_err(BlockError::from)?;
+
+	CollectionBlock::check_owned(data.collection_id, user_id, conn)?;
+
+	NewBlock {
+		block_data: serde_json::to_string(&data).ok(),
+		..NewBlock::new(BLOCK_NAME, user_id)
+	}
+	.insert(conn)
+}
+
+pub fn create_display(context: &Context, user_id: i32) -> Result<CreationObject, LoopError> {
+	let conn = &context.conn()?;
+
+	let mut main = StackComponent::vertical();
+	let collections = Collection::of_user(user_id, conn)?;
+	if collections.is_empty() {
+		main.push(TextComponent::info(
+			"You don't have any collections yet. Save a search first.",
+		));
+	} else {
+		main.push(DisplayListComponent {
+			items: collections
+				.into_iter()
+				.map(|collection| {
+					let text = format!("{} (#{})", collection.name, collection.id);
+					DisplayListItem::new(TextComponent::new(text).into())
+				})
+				.collect(),
+			..Default::default()
+		});
+	}
+	main.push(InputComponent {
+		label: Some("Collection ID".to_string()),
+		name: Some("COLLECTION".to_string()),
+		input_type: Some(InputType::Number),
+		..Default::default()
+	});
+
+	Ok(CreationObject {
+		header_component: TextComponent::heading("New Collection Block").into(),
+		main_component: main.into(),
+		input_template: r#"{"collection_id": $[COLLECTION]$}"#.to_string(),
+	})
+}
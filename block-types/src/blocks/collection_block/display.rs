@@ -0,0 +1,113 @@
+use super::CollectionBlock;
+use crate::delegation::display::{delegate_block_icon, delegate_block_name};
+use block_tools::{
+	auth::{optional_token, optional_validate_token},
+	blocks::{BlockType, Context},
+	display_api::{
+		component::{
+			atomic::{
+				icon::{Icon, IconComponent},
+				text::TextComponent,
+			},
+			interact::link::LinkComponent,
+			layout::{
+				card::{CardComponent, CardHeader},
+				displaylist::{DisplayListComponent, DisplayListItem},
+				stack::StackComponent,
+			},
+			DisplayComponent,
+		},
+		DisplayMeta, DisplayObject, PageMeta,
+	},
+	models::{Block, Collection, BLOCK_PATH_PREFIX},
+	LoopError, PageQuery,
+};
+
+/// How many results the page shows
+const PAGE_RESULTS: i64 = 50;
+/// How many results an embed shows
+const EMBED_RESULTS: i64 = 5;
+
+pub fn page_display(block: &Block, context: &Context) -> Result<DisplayObject, LoopError> {
+	let collection = match CollectionBlock::collection(block, context)? {
+		Some(collection) => collection,
+		None => return Ok(DisplayObject::new(missing())),
+	};
+
+	let results = results(&collection, context, PAGE_RESULTS)?;
+	Ok(DisplayObject {
+		meta: Some(DisplayMeta {
+			page: Some(PageMeta {
+				title: Some(collection.name),
+				..Default::default()
+			}),
+			..Default::default()
+		}),
+		..DisplayObject::new(results)
+	})
+}
+
+pub fn embed_display(block: &Block, context: &Context) -> DisplayComponent {
+	let (collection, results) = match CollectionBlock::collection(block, context) {
+		Ok(Some(collection)) => match results(&collection, context, EMBED_RESULTS) {
+			Ok(results) => (collection, results),
+			Err(err) => return CardComponent::error_card(err).into(),
+		},
+		Ok(None) => return missing(),
+		Err(err) => return CardComponent::error_card(err).into(),
+	};
+
+	CardComponent {
+		color: block.color.clone(),
+		header: Some(CardHeader {
+			icon: Some(CollectionBlock::info().icon),
+			block_id: Some(block.id.to_string()),
+			..CardHeader::new(collection.name)
+		}),
+		..CardComponent::new(results)
+	}
+	.into()
+}
+
+fn missing() -> DisplayComponent {
+	TextComponent::info("This collection was deleted.").into()
+}
+
+/// Runs the collection's search as the viewer, and links to the blocks it finds
+fn results(
+	collection: &Collection,
+	context: &Context,
+	size: i64,
+) -> Result<DisplayComponent, LoopError> {
+	let conn = &context.conn()?;
+	let user_id = optional_validate_token(optional_token(context))?;
+
+	let search = collection.search(user_id);
+	let hits = Block::search(&search, &PageQuery::first(size), conn)?;
+	let ids: Vec<i64> = hits.items.iter().map(|hit| hit.id).collect();
+	let mut blocks = Block::by_ids(&ids, conn)?;
+	// Keep the order of the search
+	blocks.sort_by_key(|block| ids.iter().position(|id| *id == block.id));
+
+	if blocks.is_empty() {
+		return Ok(TextComponent::info("No blocks match this collection yet.").into());
+	}
+
+	let mut items = vec![];
+	for block in blocks {
+		let name = delegate_block_name(context, &block.block_type, &block)?;
+		let icon = delegate_block_icon(&block.block_type).unwrap_or(Icon::Box);
+		let mut row = StackComponent::horizontal();
+		row.push(IconComponent::new(icon));
+		row.push(LinkComponent {
+			app_path: Some(format!("{}{}", BLOCK_PATH_PREFIX, block.id)),
+			..LinkComponent::new(TextComponent::new(name))
+		});
+		items.push(DisplayListItem::new(row.into()));
+	}
+	Ok(DisplayListComponent {
+		items,
+		..Default::default()
+	}
+	.into())
+}
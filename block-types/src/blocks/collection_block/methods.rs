@@ -0,0 +1,19 @@
+use super::{CollectionBlock, CollectionBlockData};
+use block_tools::{
+	auth::{require_token, validate_token},
+	blocks::Context,
+	models::Block,
+	BlockError, LoopError,
+};
+
+/// Makes the block show another collection, which the user has to own
+pub fn set_collection(context: &Context, block_id: i64, args: String) -> Result<Block, LoopError> {
+	let user_id = validate_token(&require_token(context)?)?;
+	let data: CollectionBlockData = serde_json::from_str(&args).map_err(BlockError::from)?;
+	let conn = &context.shared_conn()?;
+	CollectionBlock::check_owned(data.collection_id, user_id, conn)?;
+
+	let block = Block::by_id(block_id, conn)?.ok_or(LoopError::GenericError)?;
+	let data = serde_json::to_string(&data).map_err(BlockError::from)?;
+	block.update_data_by(&data, Some(user_id), conn)
+}
@@ -0,0 +1,105 @@
+use block_tools::{
+	auth::permissions::PermLevel,
+	blocks::{BlockType, Context, TypeInfo},
+	display_api::{
+		component::{atomic::icon::Icon, DisplayComponent},
+		CreationObject, DisplayObject,
+	},
+	models::{Block, Collection},
+	BlockError, LoopError, NoAccessSubject, PgConnect, UserError,
+};
+use serde::{Deserialize, Serialize};
+
+mod create;
+mod display;
+mod methods;
+
+pub const BLOCK_NAME: &str = "collection";
+
+/// A block that shows the results of one of its owner's collections. The
+/// search is run again every time the block is viewed, as the viewer.
+pub struct CollectionBlock;
+
+/// What a collection block stores as its data
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CollectionBlockData {
+	pub collection_id: i64,
+}
+
+impl CollectionBlock {
+	/// The collection that the block shows, unless it was deleted
+	pub fn collection(block: &Block, context: &Context) -> Result<Option<Collection>, LoopError> {
+		let data: Option<CollectionBlockData> = block
+			.block_data
+			.as_ref()
+			.and_then(|data| serde_json::from_str(data).ok());
+		match data {
+			Some(data) => Collection::by_id(data.collection_id, &context.conn()?),
+			None => Ok(None),
+		}
+	}
+
+	/// Only the owner can show a collection, since it can filter by their tags
+	fn check_owned(collection_id: i64, user_id: i32, conn: &PgConnect) -> Result<(), LoopError> {
+		let owned = Collection::by_id(collection_id, conn)?
+			.map_or(false, |collection| collection.owner_id == user_id);
+		if owned {
+			Ok(())
+		} else {
+			Err(UserError::NoAccess(NoAccessSubject::EditCollection(collection_id)).into())
+		}
+	}
+}
+
+impl BlockType for CollectionBlock {
+	fn name() -> String {
+		BLOCK_NAME.to_string()
+	}
+
+	fn info() -> TypeInfo {
+		TypeInfo {
+			name: Self::name(),
+			icon: Icon::Filter,
+			desc: "Shows the blocks that match one of your saved searches".to_string(),
+		}
+	}
+
+	fn create(input: String, context: &Context, user_id: i32) -> Result<Block, LoopError> {
+		create::create(input, context, user_id)
+	}
+
+	fn page_display(block: &Block, context: &Context) -> Result<DisplayObject, LoopError> {
+		display::page_display(block, context)
+	}
+
+	fn embed_display(block: &Block, context: &Context) -> DisplayComponent {
+		display::embed_display(block, context)
+	}
+
+	fn create_display(context: &Context, user_id: i32) -> Result<CreationObject, LoopError> {
+		create::create_display(context, user_id)
+	}
+
+	fn method_delegate(
+		context: &Context,
+		name: String,
+		block_id: i64,
+		args: String,
+	) -> Result<Block, LoopError> {
+		match name.as_str() {
+			"set_collection" => methods::set_collection(context, block_id, args),
+			_ => Err(BlockError::MethodExist(name, Self::name()).into()),
+		}
+	}
+
+	fn methods() -> Vec<(String, PermLevel)> {
+		vec![("set_collection".to_string(), PermLevel::Edit)]
+	}
+
+	fn block_name(block: &Block, context: &Context) -> Result<String, LoopError> {
+		Ok(match Self::collection(block, context)? {
+			Some(collection) => collection.name,
+			None => "Collection".to_string(),
+		})
+	}
+}
@@ -1,3 +1,4 @@
+pub mod collection_block;
 pub mod data_block;
 pub mod document_block;
 pub mod group_block;
@@ -18,6 +18,7 @@ use block_tools::{
 pub fn delegate_page_display(block: &Block, context: &Context) -> Result<DisplayObject, LoopError> {
 	let block_type: BlockTypes = block.block_type.clone().into();
 	match block_type {
+		BlockTypes::Collection => collection_block::CollectionBlock::page_display(block, context),
 		BlockTypes::Data => data_block::DataBlock::page_display(block, context),
 		BlockTypes::Text => text_block::TextBlock::page_display(block, context),
 		BlockTypes::Group => group_block::GroupBlock::page_display(block, context),
@@ -35,6 +36,7 @@ pub fn delegate_embed_display(block: &Block, context: &Context) -> DisplayCompon
 	let block_type: BlockTypes = block.block_type.clone().into();
 	let user_id = optional_validate_token(optional_token(context)).unwrap();
 	match block_type {
+		BlockTypes::Collection => collection_block::CollectionBlock::embed_display(block, context),
 		BlockTypes::Data => data_block::DataBlock::embed_display(block, context),
 		BlockTypes::Text => text_block::TextBlock::embed_display(block, context),
 		BlockTypes::Group => group_block::GroupBlock::embed_display(block, context),
@@ -62,6 +64,9 @@ pub fn delegate_creation_display(
 ) -> Result<CreationObject, LoopError> {
 	let block_type: BlockTypes = block_type.to_string().into();
 	match block_type {
+		BlockTypes::Collection => {
+			collection_block::CollectionBlock::create_display(context, user_id)
+		}
 		BlockTypes::Data => data_block::DataBlock::create_display(context, user_id),
 		BlockTypes::Text => text_block::TextBlock::create_display(context, user_id),
 		BlockTypes::Group => group_block::GroupBlock::create_display(context, user_id),
@@ -79,6 +84,7 @@ pub fn delegate_block_name(
 ) -> Result<String, LoopError> {
 	let block_type: BlockTypes = block_type.to_string().into();
 	match block_type {
+		BlockTypes::Collection => collection_block::CollectionBlock::block_name(block, context),
 		BlockTypes::Data => data_block::DataBlock::block_name(block, context),
 		BlockTypes::Text => text_block::TextBlock::block_name(block, context),
 		BlockTypes::Group => group_block::GroupBlock::block_name(block, context),
@@ -92,6 +98,7 @@ pub fn delegate_block_name(
 pub fn delegate_block_icon(block_type: impl ToString) -> Option<Icon> {
 	let block_type: BlockTypes = block_type.to_string().into();
 	Some(match block_type {
+		BlockTypes::Collection => collection_block::CollectionBlock::info().icon,
 		BlockTypes::Data => data_block::DataBlock::info().icon,
 		BlockTypes::Text => text_block::TextBlock::info().icon,
 		BlockTypes::Group => group_block::GroupBlock::info().icon,
@@ -1,7 +1,15 @@
 use crate::blocks::*;
 use crate::types::BlockTypes;
 use block_tools::blocks::BlockType;
-use block_tools::{blocks::Context, models::Block, BlockError, LoopError};
+use block_tools::{
+	auth::permissions::PermLevel,
+	blocks::Context,
+	models::{Block, BlockRevision},
+	BlockError, LoopError,
+};
+
+/// Methods change blocks, so those that a type doesn't list need edit permissions
+pub const DEFAULT_METHOD_LEVEL: PermLevel = PermLevel::Edit;
 
 pub fn delegate_create(
 	block_type: &str,
@@ -10,7 +18,10 @@ pub fn delegate_create(
 	user_id: i32,
 ) -> Result<Block, LoopError> {
 	let block_type: BlockTypes = block_type.to_string().into();
-	match block_type {
+	BlockRevision::act_as(Some(user_id), || match block_type {
+		BlockTypes::Collection => {
+			collection_block::CollectionBlock::create(input, context, user_id)
+		}
 		BlockTypes::Data => data_block::DataBlock::create(input, context, user_id),
 		BlockTypes::Text => text_block::TextBlock::create(input, context, user_id),
 		BlockTypes::Group => group_block::GroupBlock::create(input, context, user_id),
@@ -18,7 +29,7 @@ pub fn delegate_create(
 		BlockTypes::Habit => habit_block::HabitBlock::create(input, context, user_id),
 		BlockTypes::Task => task_block::TaskBlock::create(input, context, user_id),
 		BlockTypes::Invalid(name) => Err(BlockError::TypeExist(name).into()),
-	}
+	})
 }
 
 pub fn delegate_method(
@@ -29,7 +40,10 @@ pub fn delegate_method(
 	block_id: i64,
 ) -> Result<Block, LoopError> {
 	let block_type: BlockTypes = block_type.into();
-	match block_type {
+	context.acting(|| match block_type {
+		BlockTypes::Collection => {
+			collection_block::CollectionBlock::method_delegate(context, name, block_id, args)
+		}
 		BlockTypes::Data => data_block::DataBlock::method_delegate(context, name, block_id, args),
 		BlockTypes::Text => text_block::TextBlock::method_delegate(context, name, block_id, args),
 		BlockTypes::Habit => {
@@ -43,9 +57,55 @@ pub fn delegate_method(
 		}
 		BlockTypes::Task => task_block::TaskBlock::method_delegate(context, name, block_id, args),
 		BlockTypes::Invalid(name) => Err(BlockError::TypeExist(name).into()),
+	})
+}
+
+/// The methods a block type lists, with the level needed to call each one
+pub fn delegate_methods(block_type: &str) -> Vec<(String, PermLevel)> {
+	let block_type: BlockTypes = block_type.to_string().into();
+	match block_type {
+		BlockTypes::Collection => collection_block::CollectionBlock::methods(),
+		BlockTypes::Data => data_block::DataBlock::methods(),
+		BlockTypes::Text => text_block::TextBlock::methods(),
+		BlockTypes::Group => group_block::GroupBlock::methods(),
+		BlockTypes::Habit => habit_block::HabitBlock::methods(),
+		BlockTypes::Task => task_block::TaskBlock::methods(),
+		BlockTypes::Document => document_block::DocumentBlock::methods(),
+		BlockTypes::Invalid(_) => vec![],
 	}
 }
 
+/// The level needed to call a method of a block type
+pub fn method_level(block_type: &str, name: &str) -> PermLevel {
+	delegate_methods(block_type)
+		.into_iter()
+		.find(|(method, _)| method == name)
+		.map_or(DEFAULT_METHOD_LEVEL, |(_, level)| level)
+}
+
+/// The names of every block type
+pub const BLOCK_TYPES: [&str; 7] = [
+	collection_block::BLOCK_NAME,
+	data_block::BLOCK_NAME,
+	document_block::BLOCK_NAME,
+	group_block::BLOCK_NAME,
+	habit_block::BLOCK_NAME,
+	task_block::BLOCK_NAME,
+	text_block::BLOCK_NAME,
+];
+
+/// Every method that the block types list, as its type, name and level
+pub fn registered_methods() -> Vec<(String, String, PermLevel)> {
+	BLOCK_TYPES
+		.iter()
+		.flat_map(|block_type| {
+			delegate_methods(block_type)
+				.into_iter()
+				.map(move |(name, level)| (block_type.to_string(), name, level))
+		})
+		.collect()
+}
+
 pub fn delegate_visibility_update(
 	context: &Context,
 	block_type: &str,
@@ -53,7 +113,10 @@ pub fn delegate_visibility_update(
 	public: bool,
 ) -> Result<(), LoopError> {
 	let block_type: BlockTypes = block_type.to_string().into();
-	match block_type {
+	context.acting(|| match block_type {
+		BlockTypes::Collection => {
+			collection_block::CollectionBlock::visibility_update(context, block_id, public)
+		}
 		BlockTypes::Data => data_block::DataBlock::visibility_update(context, block_id, public),
 		BlockTypes::Text => text_block::TextBlock::visibility_update(context, block_id, public),
 		BlockTypes::Group => group_block::GroupBlock::visibility_update(context, block_id, public),
@@ -63,7 +126,7 @@ pub fn delegate_visibility_update(
 			document_block::DocumentBlock::visibility_update(context, block_id, public)
 		}
 		BlockTypes::Invalid(name) => Err(BlockError::TypeExist(name).into()),
-	}
+	})
 }
 
 pub fn delegate_general_perm_update(
@@ -75,7 +138,10 @@ pub fn delegate_general_perm_update(
 	perm_view: Vec<i32>,
 ) -> Result<(), LoopError> {
 	let block_type: BlockTypes = block_type.to_string().into();
-	match block_type {
+	context.acting(|| match block_type {
+		BlockTypes::Collection => collection_block::CollectionBlock::general_perm_update(
+			context, block_id, perm_full, perm_edit, perm_view,
+		),
 		BlockTypes::Data => data_block::DataBlock::general_perm_update(
 			context, block_id, perm_full, perm_edit, perm_view,
 		),
@@ -95,5 +161,38 @@ pub fn delegate_general_perm_update(
 			context, block_id, perm_full, perm_edit, perm_view,
 		),
 		BlockTypes::Invalid(name) => Err(BlockError::TypeExist(name).into()),
-	}
+	})
+}
+
+pub fn delegate_comment_perm_update(
+	context: &Context,
+	block_type: &str,
+	block_id: i64,
+	perm_comment: Vec<i32>,
+) -> Result<(), LoopError> {
+	let block_type: BlockTypes = block_type.to_string().into();
+	context.acting(|| match block_type {
+		BlockTypes::Collection => {
+			collection_block::CollectionBlock::comment_perm_update(context, block_id, perm_comment)
+		}
+		BlockTypes::Data => {
+			data_block::DataBlock::comment_perm_update(context, block_id, perm_comment)
+		}
+		BlockTypes::Text => {
+			text_block::TextBlock::comment_perm_update(context, block_id, perm_comment)
+		}
+		BlockTypes::Group => {
+			group_block::GroupBlock::comment_perm_update(context, block_id, perm_comment)
+		}
+		BlockTypes::Habit => {
+			habit_block::HabitBlock::comment_perm_update(context, block_id, perm_comment)
+		}
+		BlockTypes::Task => {
+			task_block::TaskBlock::comment_perm_update(context, block_id, perm_comment)
+		}
+		BlockTypes::Document => {
+			document_block::DocumentBlock::comment_perm_update(context, block_id, perm_comment)
+		}
+		BlockTypes::Invalid(name) => Err(BlockError::TypeExist(name).into()),
+	})
 }
@@ -1,6 +1,7 @@
 use crate::blocks::*;
 
 pub enum BlockTypes {
+	Collection,
 	Data,
 	Document,
 	Group,
@@ -13,6 +14,7 @@ pub enum BlockTypes {
 impl From<String> for BlockTypes {
 	fn from(s: String) -> Self {
 		match s.as_str() {
+			collection_block::BLOCK_NAME => BlockTypes::Collection,
 			data_block::BLOCK_NAME => BlockTypes::Data,
 			document_block::BLOCK_NAME => BlockTypes::Document,
 			group_block::BLOCK_NAME => BlockTypes::Group,